@@ -9,6 +9,16 @@ export CARGO_HOME=/ci/cache/cargo
 export CARGO_TARGET_DIR=/ci/cache/target
 
 cargo test --manifest-path ./embassy-futures/Cargo.toml
+cargo test --manifest-path ./embassy-executor/Cargo.toml
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features join-handles
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features alloc
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features alloc,join-handles
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features task-priorities,introspection,poll-monitor,alloc
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features supervisor,integrated-timers --test supervisor
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features task-local --test task_local
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features arch-std,executor-thread,join-handles --test std
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features arch-std,executor-thread,task-priorities,task-local,alloc --test std
+cargo test --manifest-path ./embassy-executor/Cargo.toml --features arch-std,executor-thread,shuffle-run-queue --test std
 cargo test --manifest-path ./embassy-sync/Cargo.toml
 cargo test --manifest-path ./embassy-embedded-hal/Cargo.toml
 cargo test --manifest-path ./embassy-hal-internal/Cargo.toml
@@ -27,4 +37,12 @@ cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --fe
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f732ze,exti,time-driver-any,exti
 cargo test --manifest-path ./embassy-stm32/Cargo.toml --no-default-features --features stm32f769ni,exti,time-driver-any,exti
 
+cargo test --manifest-path ./embassy-net/Cargo.toml --features std,proto-ipv4,medium-ethernet,udp,dhcpv4-server,packet-capture
+cargo test --manifest-path ./embassy-net/Cargo.toml --features udp,proto-ipv4,proto-ipv6,proto-ipv6-fragmentation,medium-ip,packet-timestamps
+cargo test --manifest-path ./cyw43/Cargo.toml --lib
 cargo test --manifest-path ./embassy-net-adin1110/Cargo.toml
+cargo test --manifest-path ./embassy-net-driver-channel/Cargo.toml
+cargo test --manifest-path ./embassy-net-enc28j60/Cargo.toml
+cargo test --manifest-path ./embassy-net-loopback/Cargo.toml
+cargo test --manifest-path ./embassy-net-ppp/Cargo.toml
+cargo test --manifest-path ./embassy-net-wiznet/Cargo.toml
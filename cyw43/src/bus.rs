@@ -105,6 +105,25 @@ where
         self.status = self.spi.cmd_write(&cmd_buf[..buf.len() + 1]).await;
     }
 
+    /// Write `buf[4..]` to the WLAN function, using the first word of `buf` for the command.
+    ///
+    /// `buf` is sent as is if it's word-aligned, and copied otherwise.
+    pub async fn wlan_write_in_place(&mut self, buf: &mut [u8]) {
+        assert!(buf.len() % 4 == 0);
+        let cmd = cmd_word(WRITE, INC_ADDR, FUNC_WLAN, 0, buf.len() as u32 - 4);
+        buf[..4].copy_from_slice(&cmd.to_ne_bytes());
+
+        // Safety: all bit patterns are valid u32s.
+        match unsafe { buf.align_to::<u32>() } {
+            ([], words, []) => self.status = self.spi.cmd_write(words).await,
+            _ => {
+                let mut cmd_buf = [0_u32; 513];
+                slice8_mut(&mut cmd_buf)[..buf.len()].copy_from_slice(buf);
+                self.status = self.spi.cmd_write(&cmd_buf[..buf.len() / 4]).await;
+            }
+        }
+    }
+
     #[allow(unused)]
     pub async fn bp_read(&mut self, mut addr: u32, mut data: &mut [u8]) {
         // It seems the HW force-aligns the addr
@@ -115,9 +115,28 @@ pub(crate) const FIXED_ADDR: bool = false;
 
 pub(crate) const AES_ENABLED: u32 = 0x0004;
 pub(crate) const WPA2_SECURITY: u32 = 0x00400000;
+pub(crate) const WPA3_SECURITY: u32 = 0x01000000;
+
+// Values for the 802.11 authentication algorithm (WLC_SET_AUTH).
+pub(crate) const AUTH_OPEN: u32 = 0;
+pub(crate) const AUTH_SAE: u32 = 3;
+
+// Values for the "mfp" iovar (management frame protection).
+pub(crate) const MFP_NONE: u32 = 0;
+pub(crate) const MFP_CAPABLE: u32 = 1;
+pub(crate) const MFP_REQUIRED: u32 = 2;
+
+// Key management bits for WLC_SET_WPA_AUTH and the "bsscfg:wpa_auth" iovar.
+pub(crate) const WPA_AUTH_WPA_PSK: u32 = 0x0004;
+pub(crate) const WPA_AUTH_WPA2_PSK: u32 = 0x0080;
+pub(crate) const WPA_AUTH_WPA3_SAE_PSK: u32 = 0x40000;
+
+// Supplicant status of the PSK_SUP event, when the key exchange completed.
+pub(crate) const SUP_KEYED: u32 = 6;
 
 pub(crate) const MIN_PSK_LEN: usize = 8;
 pub(crate) const MAX_PSK_LEN: usize = 64;
+pub(crate) const MAX_SAE_PASSWORD_LEN: usize = 128;
 
 // Security type (authentication and encryption types are combined using bit mask)
 #[allow(non_camel_case_types)]
@@ -126,6 +145,8 @@ pub(crate) const MAX_PSK_LEN: usize = 64;
 pub(crate) enum Security {
     OPEN = 0,
     WPA2_AES_PSK = WPA2_SECURITY | AES_ENABLED,
+    WPA3_SAE = WPA3_SECURITY | AES_ENABLED,
+    WPA2_WPA3_PSK = WPA3_SECURITY | WPA2_SECURITY | AES_ENABLED,
 }
 
 #[allow(non_camel_case_types)]
@@ -6,7 +6,7 @@ use embassy_net_driver_channel::driver::{HardwareAddress, LinkState};
 use embassy_time::{Duration, Timer};
 
 use crate::consts::*;
-use crate::events::{Event, EventSubscriber, Events};
+use crate::events::{Event, EventSubscriber, Events, Status};
 use crate::fmt::Bytes;
 use crate::ioctl::{IoctlState, IoctlType};
 use crate::structs::*;
@@ -17,6 +17,37 @@ use crate::{countries, events, PowerManagementMode};
 pub struct Error {
     /// Status code.
     pub status: u32,
+    kind: ErrorKind,
+    reason: u32,
+}
+
+impl Error {
+    /// What failed.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// 802.11 reason code reported with the failure, 0 if none.
+    pub fn reason(&self) -> u32 {
+        self.reason
+    }
+}
+
+/// Kind of [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// No network with the requested SSID was found.
+    NoNetwork,
+    /// The network rejected the authentication. With WPA3, this usually means a wrong passphrase.
+    AuthRejected,
+    /// The key exchange after associating failed. With WPA2, this usually means a wrong passphrase.
+    KeyExchange,
+    /// The operation timed out.
+    Timeout,
+    /// Any other failure, see the status code.
+    Other,
 }
 
 /// Multicast errors.
@@ -28,7 +59,27 @@ pub enum AddMulticastAddressError {
     NoFreeSlots,
 }
 
+/// A specific BSS to join, instead of letting the firmware pick any BSS with the requested SSID.
+#[derive(Clone, Copy)]
+pub(crate) struct JoinTarget {
+    pub bssid: [u8; 6],
+    pub chanspec: u16,
+}
+
+/// Disables all events when dropped, so that a cancelled operation doesn't leave events
+/// enabled that would then confuse the next one.
+struct EventMaskGuard<'a>(&'a Events);
+
+impl Drop for EventMaskGuard<'_> {
+    fn drop(&mut self) {
+        self.0.mask.disable_all();
+    }
+}
+
 /// Control driver.
+///
+/// Networks can be joined, and access points started, with open, WPA2, WPA3-SAE or WPA2/WPA3 transition
+/// mode security. WPA2/WPA3-Enterprise (802.1X) is not supported: the firmware has no EAP supplicant.
 pub struct Control<'a> {
     state_ch: ch::StateRunner<'a>,
     events: &'a Events,
@@ -212,109 +263,239 @@ impl<'a> Control<'a> {
 
     /// Join an unprotected network with the provided ssid.
     pub async fn join_open(&mut self, ssid: &str) -> Result<(), Error> {
+        self.join_open_to(ssid, None).await
+    }
+
+    pub(crate) async fn join_open_to(&mut self, ssid: &str, target: Option<JoinTarget>) -> Result<(), Error> {
         self.set_iovar_u32("ampdu_ba_wsize", 8).await;
 
         self.ioctl_set_u32(134, 0, 0).await; // wsec = open
         self.set_iovar_u32x2("bsscfg:sup_wpa", 0, 0).await;
+        self.set_iovar_u32("mfp", MFP_NONE).await;
         self.ioctl_set_u32(20, 0, 1).await; // set_infra = 1
-        self.ioctl_set_u32(22, 0, 0).await; // set_auth = open (0)
-
-        let mut i = SsidInfo {
-            len: ssid.len() as _,
-            ssid: [0; 32],
-        };
-        i.ssid[..ssid.len()].copy_from_slice(ssid.as_bytes());
+        self.ioctl_set_u32(22, 0, AUTH_OPEN).await; // set_auth
 
-        self.wait_for_join(i).await
+        self.wait_for_join(ssid, target, false).await
     }
 
-    /// Join a protected network with the provided ssid and [`PassphraseInfo`].
-    async fn join_wpa2_passphrase_info(&mut self, ssid: &str, passphrase_info: &PassphraseInfo) -> Result<(), Error> {
+    /// Join a protected network: WPA2 with `wpa`, WPA3 with `sae`, or WPA2/WPA3 transition
+    /// mode with both.
+    async fn join_secured(
+        &mut self,
+        ssid: &str,
+        wpa: Option<&PassphraseInfo>,
+        sae: Option<&SaePassphraseInfo>,
+        target: Option<JoinTarget>,
+    ) -> Result<(), Error> {
         self.set_iovar_u32("ampdu_ba_wsize", 8).await;
 
-        self.ioctl_set_u32(134, 0, 4).await; // wsec = wpa2
+        self.ioctl_set_u32(134, 0, AES_ENABLED).await; // wsec = aes
         self.set_iovar_u32x2("bsscfg:sup_wpa", 0, 1).await;
         self.set_iovar_u32x2("bsscfg:sup_wpa2_eapver", 0, 0xFFFF_FFFF).await;
         self.set_iovar_u32x2("bsscfg:sup_wpa_tmo", 0, 2500).await;
 
         Timer::after_millis(100).await;
 
-        self.ioctl(
-            IoctlType::Set,
-            IOCTL_CMD_SET_PASSPHRASE,
-            0,
-            &mut passphrase_info.to_bytes(),
-        )
-        .await; // WLC_SET_WSEC_PMK
-
-        self.ioctl_set_u32(20, 0, 1).await; // set_infra = 1
-        self.ioctl_set_u32(22, 0, 0).await; // set_auth = 0 (open)
-        self.ioctl_set_u32(165, 0, 0x80).await; // set_wpa_auth
+        if let Some(wpa) = wpa {
+            self.ioctl(IoctlType::Set, IOCTL_CMD_SET_PASSPHRASE, 0, &mut wpa.to_bytes())
+                .await; // WLC_SET_WSEC_PMK
+        }
+        if let Some(sae) = sae {
+            self.set_iovar_v::<256>("sae_password", &sae.to_bytes()).await;
+        }
 
-        let mut i = SsidInfo {
-            len: ssid.len() as _,
-            ssid: [0; 32],
+        let (auth, mfp, wpa_auth) = match sae {
+            None => (AUTH_OPEN, MFP_CAPABLE, WPA_AUTH_WPA2_PSK),
+            Some(_) if wpa.is_none() => (AUTH_SAE, MFP_REQUIRED, WPA_AUTH_WPA3_SAE_PSK),
+            // Transition mode: SAE is used when the AP supports it.
+            Some(_) => (AUTH_SAE, MFP_CAPABLE, WPA_AUTH_WPA3_SAE_PSK),
         };
-        i.ssid[..ssid.len()].copy_from_slice(ssid.as_bytes());
 
-        self.wait_for_join(i).await
+        self.ioctl_set_u32(20, 0, 1).await; // set_infra = 1
+        self.ioctl_set_u32(22, 0, auth).await; // set_auth
+        self.set_iovar_u32("mfp", mfp).await;
+        self.ioctl_set_u32(165, 0, wpa_auth).await; // set_wpa_auth
+
+        self.wait_for_join(ssid, target, true).await
     }
 
     /// Join a protected network with the provided ssid and passphrase.
     pub async fn join_wpa2(&mut self, ssid: &str, passphrase: &str) -> Result<(), Error> {
-        let mut pfi = PassphraseInfo {
-            len: passphrase.len() as _,
-            flags: 1,
-            passphrase: [0; 64],
-        };
-        pfi.passphrase[..passphrase.len()].copy_from_slice(passphrase.as_bytes());
-        self.join_wpa2_passphrase_info(ssid, &pfi).await
+        self.join_wpa2_to(ssid, passphrase, None).await
+    }
+
+    pub(crate) async fn join_wpa2_to(
+        &mut self,
+        ssid: &str,
+        passphrase: &str,
+        target: Option<JoinTarget>,
+    ) -> Result<(), Error> {
+        let pfi = passphrase_info(passphrase);
+        self.join_secured(ssid, Some(&pfi), None, target).await
     }
 
     /// Join a protected network with the provided ssid and precomputed PSK.
     pub async fn join_wpa2_psk(&mut self, ssid: &str, psk: &[u8; 32]) -> Result<(), Error> {
+        self.join_wpa2_psk_to(ssid, psk, None).await
+    }
+
+    pub(crate) async fn join_wpa2_psk_to(
+        &mut self,
+        ssid: &str,
+        psk: &[u8; 32],
+        target: Option<JoinTarget>,
+    ) -> Result<(), Error> {
         let mut pfi = PassphraseInfo {
             len: psk.len() as _,
             flags: 0,
             passphrase: [0; 64],
         };
         pfi.passphrase[..psk.len()].copy_from_slice(psk);
-        self.join_wpa2_passphrase_info(ssid, &pfi).await
+        self.join_secured(ssid, Some(&pfi), None, target).await
+    }
+
+    /// Join a WPA3-SAE protected network with the provided ssid and passphrase.
+    pub async fn join_wpa3(&mut self, ssid: &str, passphrase: &str) -> Result<(), Error> {
+        self.join_wpa3_to(ssid, passphrase, None).await
     }
 
-    async fn wait_for_join(&mut self, i: SsidInfo) -> Result<(), Error> {
-        self.events.mask.enable(&[Event::SET_SSID, Event::AUTH]);
+    pub(crate) async fn join_wpa3_to(
+        &mut self,
+        ssid: &str,
+        passphrase: &str,
+        target: Option<JoinTarget>,
+    ) -> Result<(), Error> {
+        let sae = sae_passphrase_info(passphrase);
+        self.join_secured(ssid, None, Some(&sae), target).await
+    }
+
+    /// Join a network in WPA2/WPA3 transition mode with the provided ssid and passphrase.
+    ///
+    /// WPA3-SAE is used if the network supports it, WPA2 otherwise.
+    pub async fn join_wpa2_wpa3(&mut self, ssid: &str, passphrase: &str) -> Result<(), Error> {
+        self.join_wpa2_wpa3_to(ssid, passphrase, None).await
+    }
+
+    pub(crate) async fn join_wpa2_wpa3_to(
+        &mut self,
+        ssid: &str,
+        passphrase: &str,
+        target: Option<JoinTarget>,
+    ) -> Result<(), Error> {
+        let pfi = passphrase_info(passphrase);
+        let sae = sae_passphrase_info(passphrase);
+        self.join_secured(ssid, Some(&pfi), Some(&sae), target).await
+    }
+
+    async fn wait_for_join(&mut self, ssid: &str, target: Option<JoinTarget>, secured: bool) -> Result<(), Error> {
+        let mut i = SsidInfo {
+            len: ssid.len() as _,
+            ssid: [0; 32],
+        };
+        i.ssid[..ssid.len()].copy_from_slice(ssid.as_bytes());
+
+        self.events
+            .mask
+            .enable(&[Event::SET_SSID, Event::AUTH, Event::LINK, Event::PSK_SUP]);
+        let events = EventMaskGuard(self.events);
         let mut subscriber = self.events.queue.subscriber().unwrap();
         // the actual join operation starts here
         // we make sure to enable events before so we don't miss any
 
         // set_ssid
-        self.ioctl(IoctlType::Set, IOCTL_CMD_SET_SSID, 0, &mut i.to_bytes())
-            .await;
+        match target {
+            None => {
+                self.ioctl(IoctlType::Set, IOCTL_CMD_SET_SSID, 0, &mut i.to_bytes())
+                    .await;
+            }
+            Some(target) => {
+                // Restrict the join to a single BSSID on a single channel.
+                let params = JoinParams {
+                    ssid: i,
+                    params: AssocParams {
+                        bssid: target.bssid,
+                        bssid_cnt: 0,
+                        chanspec_num: 1,
+                        chanspec_list: [target.chanspec, 0],
+                    },
+                };
+                self.ioctl(IoctlType::Set, IOCTL_CMD_SET_SSID, 0, &mut params.to_bytes())
+                    .await;
+            }
+        }
 
-        // to complete the join, we wait for a SET_SSID event
-        // we also save the AUTH status for the user, it may be interesting
-        let mut auth_status = 0;
-        let status = loop {
-            let msg = subscriber.next_message_pure().await;
-            if msg.header.event_type == Event::AUTH && msg.header.status != EStatus::SUCCESS {
-                auth_status = msg.header.status;
-            } else if msg.header.event_type == Event::SET_SSID {
-                // join operation ends with SET_SSID event
-                break msg.header.status;
+        // The join operation ends with a SET_SSID event. On protected networks, the key exchange
+        // then has to complete, which the supplicant reports with a PSK_SUP event once the link
+        // is up. AUTH failures are saved to explain a failed SET_SSID.
+        let mut auth_failure = None;
+        let mut associated = false;
+        let mut link_up = false;
+        let mut keyed = !secured;
+        let result = loop {
+            let status = subscriber.next_message_pure().await.header;
+            match status.event_type {
+                Event::AUTH if status.status != EStatus::SUCCESS => auth_failure = Some(status.reason),
+                Event::SET_SSID if status.status != EStatus::SUCCESS => {
+                    let (kind, reason) = match auth_failure {
+                        _ if status.status == EStatus::NO_NETWORKS => (ErrorKind::NoNetwork, status.reason),
+                        Some(reason) => (ErrorKind::AuthRejected, reason),
+                        None if status.status == EStatus::TIMEOUT => (ErrorKind::Timeout, status.reason),
+                        None => (ErrorKind::Other, status.reason),
+                    };
+                    break Err(Error {
+                        status: status.status,
+                        kind,
+                        reason,
+                    });
+                }
+                Event::SET_SSID => associated = true,
+                // Bit 0 of the flags is set for link up.
+                Event::LINK => link_up = status.flags & 1 != 0,
+                // Supplicant states reported before the link is up aren't final.
+                Event::PSK_SUP if !link_up => {}
+                Event::PSK_SUP if status.status == SUP_KEYED => keyed = true,
+                Event::PSK_SUP => {
+                    break Err(Error {
+                        status: status.status,
+                        kind: ErrorKind::KeyExchange,
+                        reason: status.reason,
+                    })
+                }
+                _ => {}
+            }
+            if associated && keyed {
+                break Ok(());
             }
         };
 
-        self.events.mask.disable_all();
-        if status == EStatus::SUCCESS {
-            // successful join
-            self.state_ch.set_link_state(LinkState::Up);
-            debug!("JOINED");
-            Ok(())
-        } else {
-            warn!("JOIN failed with status={} auth={}", status, auth_status);
-            Err(Error { status })
+        drop(subscriber);
+        drop(events);
+        match result {
+            Ok(()) => {
+                // successful join
+                self.events.link_lost.reset();
+                self.state_ch.set_link_state(LinkState::Up);
+                debug!("JOINED");
+            }
+            Err(ref e) => warn!(
+                "JOIN failed with status={} kind={:?} reason={}",
+                e.status, e.kind, e.reason
+            ),
         }
+        result
+    }
+
+    /// Wait until the link to the AP is lost, because the AP deauthenticated or disassociated
+    /// us, or went out of range. Sets the link state down and returns the event that reported it.
+    ///
+    /// The runner records link loss since the last join, so a loss is returned even if it happened
+    /// while this wasn't awaited.
+    pub(crate) async fn wait_for_link_loss(&mut self) -> Status {
+        let status = self.events.link_lost.wait().await;
+
+        self.state_ch.set_link_state(LinkState::Down);
+        debug!("link lost: event={:?} reason={}", status.event_type, status.reason);
+        status
     }
 
     /// Set GPIO pin on WiFi chip.
@@ -334,9 +515,23 @@ impl<'a> Control<'a> {
         self.start_ap(ssid, passphrase, Security::WPA2_AES_PSK, channel).await;
     }
 
+    /// Start WPA3-SAE protected access point.
+    pub async fn start_ap_wpa3(&mut self, ssid: &str, passphrase: &str, channel: u8) {
+        self.start_ap(ssid, passphrase, Security::WPA3_SAE, channel).await;
+    }
+
+    /// Start access point in WPA2/WPA3 transition mode, accepting both WPA2 and WPA3-SAE clients.
+    pub async fn start_ap_wpa2_wpa3(&mut self, ssid: &str, passphrase: &str, channel: u8) {
+        self.start_ap(ssid, passphrase, Security::WPA2_WPA3_PSK, channel).await;
+    }
+
     async fn start_ap(&mut self, ssid: &str, passphrase: &str, security: Security, channel: u8) {
+        let max_len = match security {
+            Security::WPA3_SAE => MAX_SAE_PASSWORD_LEN,
+            _ => MAX_PSK_LEN,
+        };
         if security != Security::OPEN
-            && (passphrase.as_bytes().len() < MIN_PSK_LEN || passphrase.as_bytes().len() > MAX_PSK_LEN)
+            && (passphrase.as_bytes().len() < MIN_PSK_LEN || passphrase.as_bytes().len() > max_len)
         {
             panic!("Passphrase is too short or too long");
         }
@@ -370,20 +565,29 @@ impl<'a> Control<'a> {
         // Set security
         self.set_iovar_u32x2("bsscfg:wsec", 0, (security as u32) & 0xFF).await;
 
+        let (wpa_auth, mfp) = match security {
+            Security::OPEN => (0, MFP_NONE),
+            Security::WPA2_AES_PSK => (WPA_AUTH_WPA2_PSK | WPA_AUTH_WPA_PSK, MFP_NONE),
+            Security::WPA3_SAE => (WPA_AUTH_WPA3_SAE_PSK, MFP_REQUIRED),
+            Security::WPA2_WPA3_PSK => (WPA_AUTH_WPA3_SAE_PSK | WPA_AUTH_WPA2_PSK, MFP_CAPABLE),
+        };
+        self.set_iovar_u32("mfp", mfp).await;
+
         if security != Security::OPEN {
-            self.set_iovar_u32x2("bsscfg:wpa_auth", 0, 0x0084).await; // wpa_auth = WPA2_AUTH_PSK | WPA_AUTH_PSK
+            self.set_iovar_u32x2("bsscfg:wpa_auth", 0, wpa_auth).await;
 
             Timer::after_millis(100).await;
 
             // Set passphrase
-            let mut pfi = PassphraseInfo {
-                len: passphrase.as_bytes().len() as _,
-                flags: 1, // WSEC_PASSPHRASE
-                passphrase: [0; 64],
-            };
-            pfi.passphrase[..passphrase.as_bytes().len()].copy_from_slice(passphrase.as_bytes());
-            self.ioctl(IoctlType::Set, IOCTL_CMD_SET_PASSPHRASE, 0, &mut pfi.to_bytes())
-                .await;
+            if security != Security::WPA3_SAE {
+                let pfi = passphrase_info(passphrase);
+                self.ioctl(IoctlType::Set, IOCTL_CMD_SET_PASSPHRASE, 0, &mut pfi.to_bytes())
+                    .await;
+            }
+            if security != Security::WPA2_AES_PSK {
+                let sae = sae_passphrase_info(passphrase);
+                self.set_iovar_v::<256>("sae_password", &sae.to_bytes()).await;
+            }
         }
 
         // Change mutlicast rate from 1 Mbps to 11 Mbps
@@ -592,6 +796,7 @@ impl<'a> Control<'a> {
     /// Leave the wifi, with which we are currently associated.
     pub async fn leave(&mut self) {
         self.ioctl(IoctlType::Set, IOCTL_CMD_DISASSOC, 0, &mut []).await;
+        self.state_ch.set_link_state(LinkState::Down);
         info!("Disassociated")
     }
 
@@ -603,6 +808,25 @@ impl<'a> Control<'a> {
     }
 }
 
+fn passphrase_info(passphrase: &str) -> PassphraseInfo {
+    let mut pfi = PassphraseInfo {
+        len: passphrase.len() as _,
+        flags: 1, // WSEC_PASSPHRASE
+        passphrase: [0; 64],
+    };
+    pfi.passphrase[..passphrase.len()].copy_from_slice(passphrase.as_bytes());
+    pfi
+}
+
+fn sae_passphrase_info(passphrase: &str) -> SaePassphraseInfo {
+    let mut sae = SaePassphraseInfo {
+        len: passphrase.len() as _,
+        passphrase: [0; MAX_SAE_PASSWORD_LEN],
+    };
+    sae.passphrase[..passphrase.len()].copy_from_slice(passphrase.as_bytes());
+    sae
+}
+
 /// WiFi network scanner.
 pub struct Scanner<'a> {
     subscriber: EventSubscriber<'a>,
@@ -0,0 +1,406 @@
+//! Known networks and their persistent storage.
+
+use embedded_storage_async::nor_flash::NorFlash;
+
+/// Authentication used to join a known network.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Auth {
+    /// Unprotected network.
+    Open,
+    /// WPA2 with a passphrase.
+    Wpa2(heapless::String<64>),
+    /// WPA2 with a precomputed PSK.
+    Wpa2Psk([u8; 32]),
+    /// WPA3-SAE with a passphrase.
+    Wpa3(heapless::String<64>),
+    /// WPA2/WPA3 transition mode with a passphrase.
+    Wpa2Wpa3(heapless::String<64>),
+}
+
+/// A network the connection manager may join.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KnownNetwork {
+    /// Network SSID.
+    pub ssid: heapless::String<32>,
+    /// Authentication.
+    pub auth: Auth,
+}
+
+impl KnownNetwork {
+    /// Create a known network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the SSID is longer than 32 bytes.
+    pub fn new(ssid: &str, auth: Auth) -> Self {
+        Self {
+            ssid: unwrap!(heapless::String::try_from(ssid), "SSID is too long"),
+            auth,
+        }
+    }
+
+    /// Create an unprotected known network.
+    pub fn open(ssid: &str) -> Self {
+        Self::new(ssid, Auth::Open)
+    }
+
+    /// Create a WPA2 protected known network.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the SSID is longer than 32 bytes, or the passphrase longer than 64 bytes.
+    pub fn wpa2(ssid: &str, passphrase: &str) -> Self {
+        let passphrase = unwrap!(heapless::String::try_from(passphrase), "Passphrase is too long");
+        Self::new(ssid, Auth::Wpa2(passphrase))
+    }
+}
+
+/// Persistent storage for known networks.
+pub trait CredentialStore {
+    /// Storage error.
+    type Error: core::fmt::Debug;
+
+    /// Load the stored networks into `networks`, which is empty when called.
+    ///
+    /// Fails if the stored networks don't fit in `networks`, rather than dropping some: they would
+    /// be deleted by the next [`store`](Self::store).
+    async fn load<const N: usize>(&mut self, networks: &mut heapless::Vec<KnownNetwork, N>) -> Result<(), Self::Error>;
+
+    /// Replace the stored networks with `networks`.
+    async fn store(&mut self, networks: &[KnownNetwork]) -> Result<(), Self::Error>;
+}
+
+/// Credential store that doesn't store anything, for known networks that are only
+/// configured at runtime.
+pub struct NoStore;
+
+impl CredentialStore for NoStore {
+    type Error = core::convert::Infallible;
+
+    async fn load<const N: usize>(
+        &mut self,
+        _networks: &mut heapless::Vec<KnownNetwork, N>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn store(&mut self, _networks: &[KnownNetwork]) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Size of the serialized networks in flash.
+///
+/// This fits 10 networks with the longest SSIDs and passphrases.
+pub const FLASH_STORE_SIZE: usize = 1024;
+
+const MAGIC: [u8; 4] = *b"CYWN";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 6;
+const CRC_LEN: usize = 4;
+
+const AUTH_OPEN: u8 = 0;
+const AUTH_WPA2: u8 = 1;
+const AUTH_WPA2_PSK: u8 = 2;
+const AUTH_WPA3: u8 = 3;
+const AUTH_WPA2_WPA3: u8 = 4;
+
+/// Error returned by [`FlashStore`].
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FlashStoreError<E> {
+    /// Flash error.
+    Flash(E),
+    /// The stored networks failed the integrity check.
+    Corrupted,
+    /// The networks don't fit in [`FLASH_STORE_SIZE`] bytes.
+    TooLarge,
+    /// More networks are stored than fit in the list they're loaded into.
+    TooMany,
+}
+
+/// Credential store keeping the known networks in a region of NOR flash.
+///
+/// The region starts at `offset`, which must be aligned to the flash erase size, and spans
+/// [`FLASH_STORE_SIZE`] bytes rounded up to the erase size. It is erased on every store.
+/// Erased flash loads as no networks.
+pub struct FlashStore<F> {
+    flash: F,
+    offset: u32,
+    buf: [u8; FLASH_STORE_SIZE],
+}
+
+impl<F: NorFlash> FlashStore<F> {
+    /// Create a new flash credential store.
+    pub fn new(flash: F, offset: u32) -> Self {
+        assert!(offset as usize % F::ERASE_SIZE == 0);
+        Self {
+            flash,
+            offset,
+            buf: [0; FLASH_STORE_SIZE],
+        }
+    }
+
+    /// Release the flash.
+    pub fn release(self) -> F {
+        self.flash
+    }
+}
+
+impl<F: NorFlash> CredentialStore for FlashStore<F> {
+    type Error = FlashStoreError<F::Error>;
+
+    async fn load<const N: usize>(&mut self, networks: &mut heapless::Vec<KnownNetwork, N>) -> Result<(), Self::Error> {
+        self.flash
+            .read(self.offset, &mut self.buf)
+            .await
+            .map_err(FlashStoreError::Flash)?;
+        let res = decode(&self.buf, networks);
+        if res.is_err() {
+            networks.clear();
+        }
+        res
+    }
+
+    async fn store(&mut self, networks: &[KnownNetwork]) -> Result<(), Self::Error> {
+        let len = encode(networks, &mut self.buf)?;
+        // Pad to the write size with the erased value.
+        let padded = (len + F::WRITE_SIZE - 1) / F::WRITE_SIZE * F::WRITE_SIZE;
+        self.buf[len..padded].fill(0xFF);
+
+        let erase_len = (FLASH_STORE_SIZE + F::ERASE_SIZE - 1) / F::ERASE_SIZE * F::ERASE_SIZE;
+        self.flash
+            .erase(self.offset, self.offset + erase_len as u32)
+            .await
+            .map_err(FlashStoreError::Flash)?;
+        self.flash
+            .write(self.offset, &self.buf[..padded])
+            .await
+            .map_err(FlashStoreError::Flash)
+    }
+}
+
+/// Serialize `networks` into `buf`, returning the length written.
+///
+/// The layout is a header (magic, version, count), then for each network the SSID length and
+/// bytes, the auth kind, the secret length and bytes, and finally a CRC32 of everything before.
+fn encode<E>(networks: &[KnownNetwork], buf: &mut [u8]) -> Result<usize, FlashStoreError<E>> {
+    let count = u8::try_from(networks.len()).map_err(|_| FlashStoreError::TooLarge)?;
+    buf[..4].copy_from_slice(&MAGIC);
+    buf[4] = VERSION;
+    buf[5] = count;
+
+    let mut pos = HEADER_LEN;
+    for network in networks {
+        let (kind, secret): (u8, &[u8]) = match &network.auth {
+            Auth::Open => (AUTH_OPEN, &[]),
+            Auth::Wpa2(passphrase) => (AUTH_WPA2, passphrase.as_bytes()),
+            Auth::Wpa2Psk(psk) => (AUTH_WPA2_PSK, psk),
+            Auth::Wpa3(passphrase) => (AUTH_WPA3, passphrase.as_bytes()),
+            Auth::Wpa2Wpa3(passphrase) => (AUTH_WPA2_WPA3, passphrase.as_bytes()),
+        };
+        let ssid = network.ssid.as_bytes();
+        let entry_len = 3 + ssid.len() + secret.len();
+        if pos + entry_len + CRC_LEN > buf.len() {
+            return Err(FlashStoreError::TooLarge);
+        }
+
+        buf[pos] = ssid.len() as u8;
+        buf[pos + 1..][..ssid.len()].copy_from_slice(ssid);
+        pos += 1 + ssid.len();
+        buf[pos] = kind;
+        buf[pos + 1] = secret.len() as u8;
+        buf[pos + 2..][..secret.len()].copy_from_slice(secret);
+        pos += 2 + secret.len();
+    }
+
+    let crc = crc32(&buf[..pos]);
+    buf[pos..][..CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+    Ok(pos + CRC_LEN)
+}
+
+fn decode<E, const N: usize>(
+    buf: &[u8],
+    networks: &mut heapless::Vec<KnownNetwork, N>,
+) -> Result<(), FlashStoreError<E>> {
+    if buf[..4] != MAGIC {
+        // Nothing stored yet.
+        return Ok(());
+    }
+    if buf[4] != VERSION {
+        return Err(FlashStoreError::Corrupted);
+    }
+
+    let mut pos = HEADER_LEN;
+    let mut too_many = false;
+    for _ in 0..buf[5] {
+        // Length-prefixed SSID, auth kind, then length-prefixed secret.
+        let ssid = field(buf, &mut pos)?;
+        let kind = *buf.get(pos).ok_or(FlashStoreError::Corrupted)?;
+        pos += 1;
+        let secret = field(buf, &mut pos)?;
+
+        let ssid = core::str::from_utf8(ssid).map_err(|_| FlashStoreError::Corrupted)?;
+        let ssid = heapless::String::try_from(ssid).map_err(|_| FlashStoreError::Corrupted)?;
+        let passphrase = || {
+            let passphrase = core::str::from_utf8(secret).map_err(|_| FlashStoreError::Corrupted)?;
+            heapless::String::try_from(passphrase).map_err(|_| FlashStoreError::Corrupted)
+        };
+        let auth = match kind {
+            AUTH_OPEN => Auth::Open,
+            AUTH_WPA2 => Auth::Wpa2(passphrase()?),
+            AUTH_WPA2_PSK => Auth::Wpa2Psk(secret.try_into().map_err(|_| FlashStoreError::Corrupted)?),
+            AUTH_WPA3 => Auth::Wpa3(passphrase()?),
+            AUTH_WPA2_WPA3 => Auth::Wpa2Wpa3(passphrase()?),
+            _ => return Err(FlashStoreError::Corrupted),
+        };
+
+        too_many |= networks.push(KnownNetwork { ssid, auth }).is_err();
+    }
+
+    let crc = buf.get(pos..pos + CRC_LEN).ok_or(FlashStoreError::Corrupted)?;
+    if crc != crc32(&buf[..pos]).to_le_bytes() {
+        return Err(FlashStoreError::Corrupted);
+    }
+    if too_many {
+        return Err(FlashStoreError::TooMany);
+    }
+    Ok(())
+}
+
+fn field<'a, E>(buf: &'a [u8], pos: &mut usize) -> Result<&'a [u8], FlashStoreError<E>> {
+    let len = *buf.get(*pos).ok_or(FlashStoreError::Corrupted)? as usize;
+    let data = buf.get(*pos + 1..*pos + 1 + len).ok_or(FlashStoreError::Corrupted)?;
+    *pos += 1 + len;
+    Ok(data)
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+
+    type Error = FlashStoreError<()>;
+
+    fn networks() -> [KnownNetwork; 5] {
+        [
+            KnownNetwork::open("open"),
+            KnownNetwork::wpa2("wpa2", "passphrase"),
+            KnownNetwork::new("psk", Auth::Wpa2Psk([0x5a; 32])),
+            KnownNetwork::new("wpa3", Auth::Wpa3(heapless::String::try_from("sae").unwrap())),
+            KnownNetwork::new("mixed", Auth::Wpa2Wpa3(heapless::String::try_from("both").unwrap())),
+        ]
+    }
+
+    fn encoded(networks: &[KnownNetwork]) -> ([u8; FLASH_STORE_SIZE], usize) {
+        let mut buf = [0xFF; FLASH_STORE_SIZE];
+        let len = encode::<()>(networks, &mut buf).unwrap();
+        (buf, len)
+    }
+
+    #[test]
+    fn round_trip() {
+        for network in networks() {
+            let (buf, _) = encoded(core::slice::from_ref(&network));
+            let mut loaded = heapless::Vec::<_, 1>::new();
+            decode::<(), 1>(&buf, &mut loaded).unwrap();
+            assert_eq!(loaded, [network]);
+        }
+
+        let (buf, _) = encoded(&networks());
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        decode::<(), 5>(&buf, &mut loaded).unwrap();
+        assert_eq!(loaded, networks());
+    }
+
+    #[test]
+    fn erased() {
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        decode::<(), 5>(&[0xFF; FLASH_STORE_SIZE], &mut loaded).unwrap();
+        assert!(loaded.is_empty());
+    }
+
+    #[test]
+    fn corrupted_crc() {
+        let (mut buf, len) = encoded(&networks());
+        buf[len - 1] ^= 1;
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        assert!(matches!(decode::<(), 5>(&buf, &mut loaded), Err(Error::Corrupted)));
+
+        let (mut buf, _) = encoded(&networks());
+        buf[HEADER_LEN + 1] ^= 1;
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        assert!(matches!(decode::<(), 5>(&buf, &mut loaded), Err(Error::Corrupted)));
+    }
+
+    #[test]
+    fn wrong_version() {
+        let (mut buf, _) = encoded(&networks());
+        buf[4] = VERSION + 1;
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        assert!(matches!(decode::<(), 5>(&buf, &mut loaded), Err(Error::Corrupted)));
+    }
+
+    #[test]
+    fn truncated() {
+        let (buf, len) = encoded(&networks());
+        let mut loaded = heapless::Vec::<_, 5>::new();
+        assert!(matches!(
+            decode::<(), 5>(&buf[..len - 1], &mut loaded),
+            Err(Error::Corrupted)
+        ));
+    }
+
+    #[test]
+    fn too_many() {
+        let (buf, _) = encoded(&networks());
+        let mut loaded = heapless::Vec::<_, 4>::new();
+        assert!(matches!(decode::<(), 4>(&buf, &mut loaded), Err(Error::TooMany)));
+    }
+
+    #[test]
+    fn too_large() {
+        let passphrase = "p".repeat(64);
+        let networks: std::vec::Vec<_> = (0..20)
+            .map(|i| KnownNetwork::wpa2(&std::format!("{:032}", i), &passphrase))
+            .collect();
+        let mut buf = [0; FLASH_STORE_SIZE];
+        assert!(matches!(encode::<()>(&networks, &mut buf), Err(Error::TooLarge)));
+        // The documented capacity fits.
+        assert!(encode::<()>(&networks[..10], &mut buf).is_ok());
+    }
+
+    #[test]
+    fn fields() {
+        let buf = [3, 1, 2, 3, 0, 2];
+        let mut pos = 0;
+        assert_eq!(field::<()>(&buf, &mut pos).unwrap(), &[1, 2, 3]);
+        assert_eq!(pos, 4);
+        assert_eq!(field::<()>(&buf, &mut pos).unwrap(), &[]);
+        assert_eq!(pos, 5);
+        assert!(matches!(field::<()>(&buf, &mut pos), Err(Error::Corrupted)));
+        pos = 6;
+        assert!(matches!(field::<()>(&buf, &mut pos), Err(Error::Corrupted)));
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
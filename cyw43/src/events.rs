@@ -5,6 +5,7 @@ use core::cell::RefCell;
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::pubsub::{PubSubChannel, Subscriber};
+use embassy_sync::signal::Signal;
 
 use crate::structs::BssInfo;
 
@@ -293,6 +294,8 @@ pub type EventSubscriber<'a> = Subscriber<'a, NoopRawMutex, Message, 2, 1, 1>;
 pub struct Events {
     pub queue: EventQueue,
     pub mask: SharedEventMask,
+    /// Last link loss reported since the link came up, whatever the mask.
+    pub link_lost: Signal<NoopRawMutex, Status>,
 }
 
 impl Events {
@@ -300,6 +303,7 @@ impl Events {
         Self {
             queue: EventQueue::new(),
             mask: SharedEventMask::default(),
+            link_lost: Signal::new(),
         }
     }
 }
@@ -309,6 +313,8 @@ impl Events {
 pub struct Status {
     pub event_type: Event,
     pub status: u32,
+    pub flags: u16,
+    pub reason: u32,
 }
 
 #[derive(Copy, Clone)]
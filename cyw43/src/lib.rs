@@ -1,5 +1,5 @@
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 #![allow(async_fn_in_trait)]
 #![deny(unused_must_use)]
 #![doc = include_str!("../README.md")]
@@ -16,6 +16,8 @@ mod ioctl;
 mod structs;
 
 mod control;
+pub mod credentials;
+pub mod manager;
 mod nvram;
 mod runner;
 
@@ -28,11 +30,20 @@ use ioctl::IoctlState;
 
 use crate::bus::Bus;
 pub use crate::bus::SpiBusCyw43;
-pub use crate::control::{AddMulticastAddressError, Control, Error as ControlError, Scanner};
+pub use crate::control::{
+    AddMulticastAddressError, Control, Error as ControlError, ErrorKind as ControlErrorKind, Scanner,
+};
 pub use crate::runner::Runner;
 pub use crate::structs::BssInfo;
 
 const MTU: usize = 1514;
+/// Room before outbound frames for the gSPI command word, the SDPCM header, the padding and the
+/// BDC header, so the runner can send frames from the channel buffer.
+const TX_HEADROOM: usize = 4 + structs::SdpcmHeader::SIZE + runner::PADDING_SIZE + structs::BdcHeader::SIZE;
+/// Room after outbound frames to round transfers up to whole words.
+const TX_TAILROOM: usize = 3;
+/// Size of the channel packet buffers.
+const BUF_SIZE: usize = MTU + TX_HEADROOM + TX_TAILROOM;
 
 #[allow(unused)]
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -107,7 +118,7 @@ const CHIP: Chip = Chip {
 /// Driver state.
 pub struct State {
     ioctl_state: IoctlState,
-    ch: ch::State<MTU, 4, 4>,
+    ch: ch::State<BUF_SIZE, 4, 4>,
     events: Events,
 }
 
@@ -116,7 +127,7 @@ impl State {
     pub fn new() -> Self {
         Self {
             ioctl_state: IoctlState::new(),
-            ch: ch::State::new(),
+            ch: ch::State::with_room(TX_HEADROOM, TX_TAILROOM),
             events: Events::new(),
         }
     }
@@ -209,7 +220,7 @@ impl PowerManagementMode {
 }
 
 /// Embassy-net driver.
-pub type NetDriver<'a> = ch::Device<'a, MTU>;
+pub type NetDriver<'a> = ch::Device<'a, BUF_SIZE>;
 
 /// Create a new instance of the CYW43 driver.
 ///
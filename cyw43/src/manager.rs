@@ -0,0 +1,626 @@
+//! Connection manager, keeping the device connected to one of a list of known networks.
+//!
+//! The [`Manager`] owns the [`Control`] and runs in its own task. It scans for known networks,
+//! joins the one with the strongest signal, and when the link is lost it reconnects, waiting
+//! longer after every failed attempt. Optionally it periodically scans for a stronger access
+//! point while connected, and roams to it.
+//!
+//! The rest of the application talks to it through a [`Handle`], to follow the connection
+//! [`Status`] and to change the list of known networks, which is kept in a [`CredentialStore`].
+
+use core::cell::RefCell;
+use core::future::{pending, poll_fn};
+use core::task::Poll;
+
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use embassy_sync::waitqueue::MultiWakerRegistration;
+use embassy_time::{with_timeout, Duration, Timer};
+
+use crate::control::{JoinTarget, ScanOptions};
+use crate::credentials::{Auth, CredentialStore, KnownNetwork};
+use crate::fmt::Bytes;
+use crate::{Control, ControlError};
+
+/// Connection manager configuration.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Config {
+    /// Delay before the first reconnection attempt. It doubles on every failed attempt.
+    pub backoff_min: Duration,
+    /// Maximum delay between reconnection attempts.
+    pub backoff_max: Duration,
+    /// Time allowed for joining a network before the attempt is considered failed.
+    pub join_timeout: Duration,
+    /// Interval between scans for a stronger access point while connected.
+    ///
+    /// `None` disables roaming.
+    pub roam_interval: Option<Duration>,
+    /// How much stronger, in dB, another access point must be to roam to it.
+    pub roam_threshold: i16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            backoff_min: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(60),
+            join_timeout: Duration::from_secs(15),
+            roam_interval: None,
+            roam_threshold: 8,
+        }
+    }
+}
+
+/// Connection status.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    /// Not connected, and not trying to: there are no known networks, or
+    /// [`Handle::disconnect`] was called.
+    Idle,
+    /// Scanning for known networks.
+    Scanning,
+    /// Joining a network.
+    Connecting {
+        /// Network SSID.
+        ssid: heapless::String<32>,
+        /// BSSID of the access point.
+        bssid: [u8; 6],
+    },
+    /// Connected to a network.
+    Connected {
+        /// Network SSID.
+        ssid: heapless::String<32>,
+        /// BSSID of the access point.
+        bssid: [u8; 6],
+        /// Signal strength of the access point when last scanned, in dBm.
+        rssi: i16,
+    },
+    /// Waiting before trying to connect again, after a failed attempt.
+    Backoff {
+        /// Number of consecutive failed attempts.
+        attempt: u32,
+        /// Delay before the next attempt.
+        retry_in: Duration,
+    },
+}
+
+impl Status {
+    /// Whether the device is connected to a network.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Status::Connected { .. })
+    }
+}
+
+/// Error returned by [`Handle`] requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The list of known networks is full.
+    Full,
+    /// The credential store failed to store the known networks.
+    ///
+    /// The change is still applied until the device restarts.
+    Storage,
+}
+
+enum Command {
+    Add(KnownNetwork),
+    Remove(heapless::String<32>),
+    Disconnect,
+    Reconnect,
+}
+
+struct Shared {
+    status: Status,
+    version: u32,
+    wakers: MultiWakerRegistration<4>,
+}
+
+/// Connection manager state, shared between the [`Manager`] and its [`Handle`]s.
+pub struct State {
+    shared: Mutex<NoopRawMutex, RefCell<Shared>>,
+    commands: Channel<NoopRawMutex, Command, 1>,
+    responses: Signal<NoopRawMutex, Result<(), Error>>,
+    request_lock: AsyncMutex<NoopRawMutex, ()>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    /// Create a new connection manager state.
+    pub const fn new() -> Self {
+        Self {
+            shared: Mutex::new(RefCell::new(Shared {
+                status: Status::Idle,
+                version: 0,
+                wakers: MultiWakerRegistration::new(),
+            })),
+            commands: Channel::new(),
+            responses: Signal::new(),
+            request_lock: AsyncMutex::new(()),
+        }
+    }
+
+    fn set_status(&self, status: Status) {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            if s.status != status {
+                s.status = status;
+                s.version = s.version.wrapping_add(1);
+                s.wakers.wake();
+            }
+        })
+    }
+}
+
+/// Create a connection manager.
+///
+/// The known networks are loaded from `store`, which must hold at most `N`; if loading fails the
+/// manager starts without any. Run the returned [`Manager`] in its own task, and use the
+/// [`Handle`] to follow and control it.
+pub async fn new<'a, S: CredentialStore, const N: usize>(
+    control: Control<'a>,
+    state: &'a mut State,
+    mut store: S,
+    config: Config,
+) -> (Manager<'a, S, N>, Handle<'a>) {
+    let mut networks = heapless::Vec::new();
+    if store.load(&mut networks).await.is_err() {
+        warn!("loading known networks failed, starting without any");
+        networks.clear();
+    }
+    debug!("loaded {} known networks", networks.len());
+
+    let state = &*state;
+    let manager = Manager {
+        control,
+        state,
+        store,
+        config,
+        networks,
+        enabled: true,
+        attempt: 0,
+    };
+    (manager, Handle::new(state))
+}
+
+/// Connection manager runner.
+pub struct Manager<'a, S: CredentialStore, const N: usize> {
+    control: Control<'a>,
+    state: &'a State,
+    store: S,
+    config: Config,
+    networks: heapless::Vec<KnownNetwork, N>,
+    enabled: bool,
+    attempt: u32,
+}
+
+/// A found access point of a known network.
+struct Candidate {
+    index: usize,
+    bssid: [u8; 6],
+    chanspec: u16,
+    rssi: i16,
+}
+
+/// The access point we're connected to.
+struct Link {
+    ssid: heapless::String<32>,
+    bssid: [u8; 6],
+    rssi: i16,
+}
+
+impl<'a, S: CredentialStore, const N: usize> Manager<'a, S, N> {
+    /// Run the connection manager.
+    ///
+    /// Requests from [`Handle`]s are handled between scans and join attempts.
+    pub async fn run(mut self) -> ! {
+        loop {
+            if !self.enabled || self.networks.is_empty() {
+                self.set_status(Status::Idle);
+                let command = self.state.commands.receive().await;
+                self.handle(command, None).await;
+                continue;
+            }
+
+            self.set_status(Status::Scanning);
+            let (candidate, _) = self.scan(None).await;
+            let link = match candidate {
+                Some(candidate) => self.join(&candidate).await,
+                None => {
+                    debug!("no known network found");
+                    None
+                }
+            };
+
+            match link {
+                Some(link) => {
+                    self.attempt = 0;
+                    self.stay_connected(link).await;
+                }
+                None => self.backoff().await,
+            }
+        }
+    }
+
+    /// Scan for known networks, and return the strongest access point found, along with the
+    /// signal strength of `current` if it was found.
+    async fn scan(&mut self, current: Option<[u8; 6]>) -> (Option<Candidate>, Option<i16>) {
+        let mut best: Option<Candidate> = None;
+        let mut current_rssi = None;
+
+        let mut scanner = self.control.scan(ScanOptions::default()).await;
+        while let Some(bss) = scanner.next().await {
+            let ssid = &bss.ssid[..(bss.ssid_len as usize).min(32)];
+            let Some(index) = self.networks.iter().position(|n| n.ssid.as_bytes() == ssid) else {
+                continue;
+            };
+
+            let (bssid, rssi) = (bss.bssid, bss.rssi);
+            if current == Some(bssid) {
+                current_rssi = Some(rssi);
+            }
+            if best.as_ref().map_or(true, |b| rssi > b.rssi) {
+                best = Some(Candidate {
+                    index,
+                    bssid,
+                    chanspec: bss.chanspec,
+                    rssi,
+                });
+            }
+        }
+
+        (best, current_rssi)
+    }
+
+    async fn join(&mut self, candidate: &Candidate) -> Option<Link> {
+        let network = &self.networks[candidate.index];
+        info!(
+            "joining {} at {:02x} ({} dBm)",
+            network.ssid.as_str(),
+            Bytes(&candidate.bssid),
+            candidate.rssi
+        );
+        self.set_status(Status::Connecting {
+            ssid: network.ssid.clone(),
+            bssid: candidate.bssid,
+        });
+
+        let target = Some(JoinTarget {
+            bssid: candidate.bssid,
+            chanspec: candidate.chanspec,
+        });
+        let join = join_network(&mut self.control, network, target);
+        match with_timeout(self.config.join_timeout, join).await {
+            Ok(Ok(())) => Some(Link {
+                ssid: network.ssid.clone(),
+                bssid: candidate.bssid,
+                rssi: candidate.rssi,
+            }),
+            Ok(Err(e)) => {
+                warn!("join failed: {:?} (status={} reason={})", e.kind(), e.status, e.reason());
+                None
+            }
+            Err(_) => {
+                warn!("join timed out");
+                self.control.leave().await;
+                None
+            }
+        }
+    }
+
+    async fn stay_connected(&mut self, mut link: Link) {
+        loop {
+            self.set_status(Status::Connected {
+                ssid: link.ssid.clone(),
+                bssid: link.bssid,
+                rssi: link.rssi,
+            });
+
+            let roam_interval = self.config.roam_interval;
+            let roam_timer = async move {
+                match roam_interval {
+                    Some(interval) => Timer::after(interval).await,
+                    None => pending().await,
+                }
+            };
+
+            // Link loss is recorded by the runner, so it's not missed while scanning or handling a
+            // request: it's returned on the next iteration.
+            match select3(
+                self.control.wait_for_link_loss(),
+                self.state.commands.receive(),
+                roam_timer,
+            )
+            .await
+            {
+                Either3::First(status) => {
+                    warn!("link to {} lost, reason={}", link.ssid.as_str(), status.reason);
+                    return;
+                }
+                Either3::Second(command) => {
+                    if self.handle(command, Some(&link.ssid)).await {
+                        self.control.leave().await;
+                        return;
+                    }
+                }
+                Either3::Third(()) => {
+                    let (best, current_rssi) = self.scan(Some(link.bssid)).await;
+                    link.rssi = current_rssi.unwrap_or(link.rssi);
+
+                    let Some(best) = best else { continue };
+                    if !should_roam(&link, &best, self.config.roam_threshold) {
+                        continue;
+                    }
+
+                    info!("roaming from {:02x} ({} dBm)", Bytes(&link.bssid), link.rssi);
+                    self.control.leave().await;
+                    match self.join(&best).await {
+                        Some(new_link) => link = new_link,
+                        None => return,
+                    }
+                }
+            }
+        }
+    }
+
+    async fn backoff(&mut self) {
+        let delay = backoff_delay(&self.config, self.attempt);
+        self.attempt = self.attempt.saturating_add(1);
+
+        debug!("retrying in {} ms", delay.as_millis());
+        self.set_status(Status::Backoff {
+            attempt: self.attempt,
+            retry_in: delay,
+        });
+
+        // Any request ends the wait, so that changes to the known networks apply right away.
+        if let Either::Second(command) = select(Timer::after(delay), self.state.commands.receive()).await {
+            self.handle(command, None).await;
+        }
+    }
+
+    /// Handle a request, and return whether the current connection, if any, must be dropped.
+    async fn handle(&mut self, command: Command, current: Option<&str>) -> bool {
+        let (result, restart) = match command {
+            Command::Add(network) => {
+                let restart = current == Some(network.ssid.as_str());
+                (self.add(network).await, restart)
+            }
+            Command::Remove(ssid) => {
+                let restart = current == Some(ssid.as_str());
+                (self.remove(&ssid).await, restart)
+            }
+            Command::Disconnect => {
+                self.enabled = false;
+                (Ok(()), true)
+            }
+            Command::Reconnect => {
+                self.enabled = true;
+                self.attempt = 0;
+                (Ok(()), true)
+            }
+        };
+        self.state.responses.signal(result);
+        restart
+    }
+
+    async fn add(&mut self, network: KnownNetwork) -> Result<(), Error> {
+        match self.networks.iter_mut().find(|n| n.ssid == network.ssid) {
+            Some(existing) => *existing = network,
+            None => self.networks.push(network).map_err(|_| Error::Full)?,
+        }
+        self.save().await
+    }
+
+    async fn remove(&mut self, ssid: &str) -> Result<(), Error> {
+        let len = self.networks.len();
+        self.networks.retain(|n| n.ssid != ssid);
+        if self.networks.len() == len {
+            return Ok(());
+        }
+        self.save().await
+    }
+
+    async fn save(&mut self) -> Result<(), Error> {
+        self.store.store(&self.networks).await.map_err(|_| {
+            warn!("storing known networks failed");
+            Error::Storage
+        })
+    }
+
+    fn set_status(&self, status: Status) {
+        self.state.set_status(status)
+    }
+}
+
+/// Delay before the next attempt after `attempt` consecutive failed ones.
+fn backoff_delay(config: &Config, attempt: u32) -> Duration {
+    let factor = 1u64.checked_shl(attempt).unwrap_or(u64::MAX);
+    Duration::from_ticks(config.backoff_min.as_ticks().saturating_mul(factor)).min(config.backoff_max)
+}
+
+/// Whether `best` is a different access point, at least `threshold` dB stronger than `link`.
+fn should_roam(link: &Link, best: &Candidate, threshold: i16) -> bool {
+    best.bssid != link.bssid && best.rssi >= link.rssi.saturating_add(threshold)
+}
+
+async fn join_network(
+    control: &mut Control<'_>,
+    network: &KnownNetwork,
+    target: Option<JoinTarget>,
+) -> Result<(), ControlError> {
+    match &network.auth {
+        Auth::Open => control.join_open_to(&network.ssid, target).await,
+        Auth::Wpa2(passphrase) => control.join_wpa2_to(&network.ssid, passphrase, target).await,
+        Auth::Wpa2Psk(psk) => control.join_wpa2_psk_to(&network.ssid, psk, target).await,
+        Auth::Wpa3(passphrase) => control.join_wpa3_to(&network.ssid, passphrase, target).await,
+        Auth::Wpa2Wpa3(passphrase) => control.join_wpa2_wpa3_to(&network.ssid, passphrase, target).await,
+    }
+}
+
+/// Handle to follow and control a connection manager.
+#[derive(Clone)]
+pub struct Handle<'a> {
+    state: &'a State,
+    seen: u32,
+}
+
+impl<'a> Handle<'a> {
+    fn new(state: &'a State) -> Self {
+        let seen = state.shared.lock(|s| s.borrow().version);
+        Self { state, seen }
+    }
+
+    /// Get the current connection status.
+    pub fn status(&self) -> Status {
+        self.state.shared.lock(|s| s.borrow().status.clone())
+    }
+
+    /// Wait for the connection status to change, and return the new status.
+    ///
+    /// Returns right away if the status changed since this handle last saw it. Intermediate
+    /// statuses may be skipped if the status changes faster than this is called.
+    pub async fn changed(&mut self) -> Status {
+        poll_fn(|cx| {
+            self.state.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                if s.version != self.seen {
+                    self.seen = s.version;
+                    Poll::Ready(s.status.clone())
+                } else {
+                    s.wakers.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Wait until the device is connected to a network.
+    pub async fn wait_connected(&mut self) {
+        while !self.status().is_connected() {
+            self.changed().await;
+        }
+    }
+
+    /// Add a known network, or replace the credentials of a known network with the same SSID.
+    ///
+    /// If connected to that network, the manager reconnects with the new credentials.
+    pub async fn add_network(&self, network: KnownNetwork) -> Result<(), Error> {
+        self.request(Command::Add(network)).await
+    }
+
+    /// Remove a known network. If connected to it, the manager disconnects from it.
+    pub async fn remove_network(&self, ssid: &str) -> Result<(), Error> {
+        match heapless::String::try_from(ssid) {
+            Ok(ssid) => self.request(Command::Remove(ssid)).await,
+            // Too long to be a known network.
+            Err(()) => Ok(()),
+        }
+    }
+
+    /// Disconnect, and stop connecting until [`reconnect`](Self::reconnect) is called.
+    pub async fn disconnect(&self) {
+        unwrap!(self.request(Command::Disconnect).await);
+    }
+
+    /// Drop the current connection if any, and connect again to the strongest known network.
+    pub async fn reconnect(&self) {
+        unwrap!(self.request(Command::Reconnect).await);
+    }
+
+    async fn request(&self, command: Command) -> Result<(), Error> {
+        let _lock = self.state.request_lock.lock().await;
+        // Clear the response of a previous request that was cancelled.
+        self.state.responses.reset();
+        self.state.commands.send(command).await;
+        self.state.responses.wait().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_futures::select::{select, Either};
+    use embassy_futures::yield_now;
+
+    use super::*;
+
+    fn link(bssid: u8, rssi: i16) -> Link {
+        Link {
+            ssid: heapless::String::try_from("net").unwrap(),
+            bssid: [bssid; 6],
+            rssi,
+        }
+    }
+
+    fn candidate(bssid: u8, rssi: i16) -> Candidate {
+        Candidate {
+            index: 0,
+            bssid: [bssid; 6],
+            chanspec: 0,
+            rssi,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = Config::default();
+        assert_eq!(backoff_delay(&config, 0), Duration::from_secs(1));
+        assert_eq!(backoff_delay(&config, 1), Duration::from_secs(2));
+        assert_eq!(backoff_delay(&config, 5), Duration::from_secs(32));
+        assert_eq!(backoff_delay(&config, 6), Duration::from_secs(60));
+        assert_eq!(backoff_delay(&config, 64), Duration::from_secs(60));
+        assert_eq!(backoff_delay(&config, u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn roam_threshold() {
+        assert!(!should_roam(&link(1, -70), &candidate(1, -40), 8));
+        assert!(!should_roam(&link(1, -70), &candidate(2, -63), 8));
+        assert!(should_roam(&link(1, -70), &candidate(2, -62), 8));
+    }
+
+    #[test]
+    fn handle_sees_status_changes() {
+        let state = State::new();
+        let mut handle = Handle::new(&state);
+        assert_eq!(handle.status(), Status::Idle);
+
+        // Setting the same status isn't a change.
+        state.set_status(Status::Idle);
+        let changed = block_on(select(handle.changed(), yield_now()));
+        assert!(matches!(changed, Either::Second(())));
+
+        state.set_status(Status::Scanning);
+        state.set_status(Status::Backoff {
+            attempt: 1,
+            retry_in: Duration::from_secs(1),
+        });
+        // Only the latest status is returned.
+        let status = block_on(handle.changed());
+        assert!(matches!(status, Status::Backoff { attempt: 1, .. }));
+        assert!(!status.is_connected());
+
+        let connected = Status::Connected {
+            ssid: heapless::String::try_from("net").unwrap(),
+            bssid: [1; 6],
+            rssi: -60,
+        };
+        state.set_status(connected.clone());
+        block_on(handle.wait_connected());
+        assert_eq!(handle.status(), connected);
+    }
+}
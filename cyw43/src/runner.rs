@@ -11,7 +11,17 @@ use crate::fmt::Bytes;
 use crate::ioctl::{IoctlState, IoctlType, PendingIoctl};
 use crate::nvram::NVRAM;
 use crate::structs::*;
-use crate::{events, slice8_mut, Core, CHIP, MTU};
+use crate::{events, slice8_mut, Core, BUF_SIZE, CHIP, TX_HEADROOM};
+
+/// There MUST be 2 bytes of padding between the SDPCM and BDC headers.
+/// And ONLY for data packets!
+/// No idea why, but the firmware will append two zero bytes to the tx'd packets
+/// otherwise. If the packet is exactly 1514 bytes (the max MTU), this makes it
+/// be oversized and get dropped.
+/// WHD adds it here https://github.com/Infineon/wifi-host-driver/blob/c04fcbb6b0d049304f376cf483fd7b1b570c8cd5/WiFi_Host_Driver/src/include/whd_sdpcm.h#L90
+/// and adds it to the header size her https://github.com/Infineon/wifi-host-driver/blob/c04fcbb6b0d049304f376cf483fd7b1b570c8cd5/WiFi_Host_Driver/src/whd_sdpcm.c#L597
+/// ¯\_(ツ)_/¯
+pub(crate) const PADDING_SIZE: usize = 2;
 
 #[cfg(feature = "firmware-logs")]
 struct LogState {
@@ -35,7 +45,7 @@ impl Default for LogState {
 
 /// Driver communicating with the WiFi chip.
 pub struct Runner<'a, PWR, SPI> {
-    ch: ch::Runner<'a, MTU>,
+    ch: ch::Runner<'a, BUF_SIZE>,
     bus: Bus<PWR, SPI>,
 
     ioctl_state: &'a IoctlState,
@@ -55,7 +65,7 @@ where
     SPI: SpiBusCyw43,
 {
     pub(crate) fn new(
-        ch: ch::Runner<'a, MTU>,
+        ch: ch::Runner<'a, BUF_SIZE>,
         bus: Bus<PWR, SPI>,
         ioctl_state: &'a IoctlState,
         events: &'a Events,
@@ -231,7 +241,7 @@ where
 
             if self.has_credit() {
                 let ioctl = self.ioctl_state.wait_pending();
-                let tx = self.ch.tx_buf();
+                let tx = self.ch.tx_packet();
                 let ev = self.bus.wait_for_event();
 
                 match select3(ioctl, tx, ev).await {
@@ -244,21 +254,11 @@ where
                         self.send_ioctl(kind, cmd, iface, unsafe { &*iobuf }, &mut buf).await;
                         self.check_status(&mut buf).await;
                     }
-                    Either3::Second(packet) => {
-                        trace!("tx pkt {:02x}", Bytes(&packet[..packet.len().min(48)]));
-
-                        let buf8 = slice8_mut(&mut buf);
-
-                        // There MUST be 2 bytes of padding between the SDPCM and BDC headers.
-                        // And ONLY for data packets!
-                        // No idea why, but the firmware will append two zero bytes to the tx'd packets
-                        // otherwise. If the packet is exactly 1514 bytes (the max MTU), this makes it
-                        // be oversized and get dropped.
-                        // WHD adds it here https://github.com/Infineon/wifi-host-driver/blob/c04fcbb6b0d049304f376cf483fd7b1b570c8cd5/WiFi_Host_Driver/src/include/whd_sdpcm.h#L90
-                        // and adds it to the header size her https://github.com/Infineon/wifi-host-driver/blob/c04fcbb6b0d049304f376cf483fd7b1b570c8cd5/WiFi_Host_Driver/src/whd_sdpcm.c#L597
-                        // ¯\_(ツ)_/¯
-                        const PADDING_SIZE: usize = 2;
-                        let total_len = SdpcmHeader::SIZE + PADDING_SIZE + BdcHeader::SIZE + packet.len();
+                    Either3::Second(mut packet) => {
+                        let len = packet.len();
+                        trace!("tx pkt {:02x}", Bytes(&packet.frame()[..len.min(48)]));
+
+                        let total_len = SdpcmHeader::SIZE + PADDING_SIZE + BdcHeader::SIZE + len;
 
                         let seq = self.sdpcm_seq;
                         self.sdpcm_seq = self.sdpcm_seq.wrapping_add(1);
@@ -284,17 +284,21 @@ where
                         trace!("tx {:?}", sdpcm_header);
                         trace!("    {:?}", bdc_header);
 
-                        buf8[0..SdpcmHeader::SIZE].copy_from_slice(&sdpcm_header.to_bytes());
-                        buf8[SdpcmHeader::SIZE + PADDING_SIZE..][..BdcHeader::SIZE]
-                            .copy_from_slice(&bdc_header.to_bytes());
-                        buf8[SdpcmHeader::SIZE + PADDING_SIZE + BdcHeader::SIZE..][..packet.len()]
-                            .copy_from_slice(packet);
+                        let padded_len = (total_len + 3) & !3; // round up to 4byte
 
-                        let total_len = (total_len + 3) & !3; // round up to 4byte
+                        // The headers go into the packet's headroom, after the word reserved for the
+                        // gSPI command, and the frame is sent from the channel buffer.
+                        let buf8 = packet.with_room(TX_HEADROOM, padded_len - total_len);
+                        let data = &mut buf8[4..];
+                        data[0..SdpcmHeader::SIZE].copy_from_slice(&sdpcm_header.to_bytes());
+                        data[SdpcmHeader::SIZE..][..PADDING_SIZE].fill(0);
+                        data[SdpcmHeader::SIZE + PADDING_SIZE..][..BdcHeader::SIZE]
+                            .copy_from_slice(&bdc_header.to_bytes());
+                        data[total_len..].fill(0);
 
-                        trace!("    {:02x}", Bytes(&buf8[..total_len.min(48)]));
+                        trace!("    {:02x}", Bytes(&data[..padded_len.min(48)]));
 
-                        self.bus.wlan_write(&buf[..(total_len / 4)]).await;
+                        self.bus.wlan_write_in_place(buf8).await;
                         self.ch.tx_done();
                         self.check_status(&mut buf).await;
                     }
@@ -417,10 +421,22 @@ where
                     Bytes(evt_data)
                 );
 
+                let status = Status {
+                    event_type: evt_type,
+                    status: event_packet.msg.status,
+                    flags: event_packet.msg.flags,
+                    reason: event_packet.msg.reason,
+                };
+                match evt_type {
+                    // Bit 0 of the flags is set for link up.
+                    Event::LINK if status.flags & 1 != 0 => {}
+                    Event::LINK | Event::DEAUTH_IND | Event::DISASSOC_IND => self.events.link_lost.signal(status),
+                    _ => {}
+                }
+
                 if self.events.mask.is_enabled(evt_type) {
-                    let status = event_packet.msg.status;
                     let event_payload = match evt_type {
-                        Event::ESCAN_RESULT if status == EStatus::PARTIAL => {
+                        Event::ESCAN_RESULT if status.status == EStatus::PARTIAL => {
                             let Some((_, bss_info)) = ScanResults::parse(evt_data) else {
                                 return;
                             };
@@ -440,13 +456,7 @@ where
                     self.events
                         .queue
                         .immediate_publisher()
-                        .publish_immediate(events::Message::new(
-                            Status {
-                                event_type: evt_type,
-                                status,
-                            },
-                            event_payload,
-                        ));
+                        .publish_immediate(events::Message::new(status, event_payload));
                 }
             }
             CHANNEL_TYPE_DATA => {
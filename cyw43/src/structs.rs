@@ -384,6 +384,27 @@ pub struct SsidInfo {
 }
 impl_bytes!(SsidInfo);
 
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct AssocParams {
+    pub bssid: [u8; 6],
+    pub bssid_cnt: u16,
+    pub chanspec_num: u32,
+    // Only one channel is used, the second entry pads the struct to a multiple of 4 bytes.
+    pub chanspec_list: [u16; 2],
+}
+impl_bytes!(AssocParams);
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct JoinParams {
+    pub ssid: SsidInfo,
+    pub params: AssocParams,
+}
+impl_bytes!(JoinParams);
+
 #[derive(Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
@@ -394,6 +415,15 @@ pub struct PassphraseInfo {
 }
 impl_bytes!(PassphraseInfo);
 
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(C)]
+pub struct SaePassphraseInfo {
+    pub len: u16,
+    pub passphrase: [u8; 128],
+}
+impl_bytes!(SaePassphraseInfo);
+
 #[derive(Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[repr(C)]
@@ -25,7 +25,10 @@ impl Parse for Args {
 }
 
 /// Declares an async task that can be run by `embassy-executor`. The optional `pool_size` parameter can be used to specify how
-/// many concurrent tasks can be spawned (default is 1) for the function.
+/// many concurrent tasks can be spawned (default is 1) for the function. The optional `name` parameter sets the task name
+/// reported by the executor's introspection (default is the function name). The optional `priority` parameter sets
+/// the task priority, which requires the `task-priorities` feature of `embassy-executor` and must be lower than
+/// `PRIORITY_LEVELS`; both are checked at compile time.
 ///
 ///
 /// The following restrictions apply:
@@ -54,6 +57,15 @@ impl Parse for Args {
 ///     // Function body
 /// }
 /// ```
+///
+/// Declaring a named task:
+///
+/// ``` rust
+/// #[embassy_executor::task(name = "blinky")]
+/// async fn mytask() {
+///     // Function body
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn task(args: TokenStream, item: TokenStream) -> TokenStream {
     let args = syn::parse_macro_input!(args as Args);
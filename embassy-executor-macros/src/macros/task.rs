@@ -10,6 +10,10 @@ use crate::util::ctxt::Ctxt;
 struct Args {
     #[darling(default)]
     pool_size: Option<syn::Expr>,
+    #[darling(default)]
+    name: Option<String>,
+    #[darling(default)]
+    priority: Option<syn::Expr>,
 }
 
 pub fn run(args: &[NestedMeta], f: syn::ItemFn) -> Result<TokenStream, TokenStream> {
@@ -20,6 +24,12 @@ pub fn run(args: &[NestedMeta], f: syn::ItemFn) -> Result<TokenStream, TokenStre
         lit: Lit::Int(LitInt::new("1", Span::call_site())),
     }));
 
+    let task_name = args.name.unwrap_or_else(|| f.sig.ident.to_string());
+    let token = match args.priority {
+        Some(priority) => quote!(::embassy_executor::__task_priority!(token, #priority)),
+        None => quote!(token),
+    };
+
     let ctxt = Ctxt::new();
 
     if f.sig.asyncness.is_none() {
@@ -37,17 +47,16 @@ pub fn run(args: &[NestedMeta], f: syn::ItemFn) -> Result<TokenStream, TokenStre
     if !f.sig.variadic.is_none() {
         ctxt.error_spanned_by(&f.sig, "task functions must not be variadic");
     }
-    match &f.sig.output {
-        ReturnType::Default => {}
-        ReturnType::Type(_, ty) => match &**ty {
-            Type::Tuple(tuple) if tuple.elems.is_empty() => {}
-            Type::Never(_) => {}
-            _ => ctxt.error_spanned_by(
-                &f.sig,
-                "task functions must either not return a value, return `()` or return `!`",
-            ),
-        },
-    }
+    // `!` can't be named on stable. Tasks returning it never finish, so their tokens are turned
+    // into tokens of tasks returning `()`, which can be spawned with `Spawner::spawn()`.
+    let (output, never_returns) = match &f.sig.output {
+        ReturnType::Type(_, ty) if matches!(**ty, Type::Never(_)) => (
+            quote!(()),
+            Some(quote!(let token = unsafe { token.__never_returns() };)),
+        ),
+        ReturnType::Type(_, ty) => (quote!(#ty), None),
+        ReturnType::Default => (quote!(()), None),
+    };
 
     let mut args = Vec::new();
     let mut fargs = f.sig.inputs.clone();
@@ -90,16 +99,21 @@ pub fn run(args: &[NestedMeta], f: syn::ItemFn) -> Result<TokenStream, TokenStre
         ));
     }
 
+    #[cfg(feature = "nightly")]
+    let future = match never_returns {
+        Some(_) => quote!(::core::future::Future),
+        None => quote!(::core::future::Future<Output = #output>),
+    };
     #[cfg(feature = "nightly")]
     let mut task_outer: ItemFn = parse_quote! {
-        #visibility fn #task_ident(#fargs) -> ::embassy_executor::SpawnToken<impl Sized> {
+        #visibility fn #task_ident(#fargs) -> ::embassy_executor::SpawnToken<impl Sized, #output> {
             trait _EmbassyInternalTaskTrait {
                 type Fut: ::core::future::Future + 'static;
                 fn construct(#fargs) -> Self::Fut;
             }
 
             impl _EmbassyInternalTaskTrait for () {
-                type Fut = impl core::future::Future + 'static;
+                type Fut = impl #future + 'static;
                 fn construct(#fargs) -> Self::Fut {
                     #task_inner_ident(#(#full_args,)*)
                 }
@@ -107,15 +121,19 @@ pub fn run(args: &[NestedMeta], f: syn::ItemFn) -> Result<TokenStream, TokenStre
 
             const POOL_SIZE: usize = #pool_size;
             static POOL: ::embassy_executor::raw::TaskPool<<() as _EmbassyInternalTaskTrait>::Fut, POOL_SIZE> = ::embassy_executor::raw::TaskPool::new();
-            unsafe { POOL._spawn_async_fn(move || <() as _EmbassyInternalTaskTrait>::construct(#(#full_args,)*)) }
+            let token = unsafe { POOL._spawn_async_fn(#task_name, move || <() as _EmbassyInternalTaskTrait>::construct(#(#full_args,)*)) };
+            #never_returns
+            #token
         }
     };
     #[cfg(not(feature = "nightly"))]
     let mut task_outer: ItemFn = parse_quote! {
-        #visibility fn #task_ident(#fargs) -> ::embassy_executor::SpawnToken<impl Sized> {
+        #visibility fn #task_ident(#fargs) -> ::embassy_executor::SpawnToken<impl Sized, #output> {
             const POOL_SIZE: usize = #pool_size;
             static POOL: ::embassy_executor::_export::TaskPoolRef = ::embassy_executor::_export::TaskPoolRef::new();
-            unsafe { POOL.get::<_, POOL_SIZE>()._spawn_async_fn(move || #task_inner_ident(#(#full_args,)*)) }
+            let token = unsafe { POOL.get::<_, POOL_SIZE>()._spawn_async_fn(#task_name, move || #task_inner_ident(#(#full_args,)*)) };
+            #never_returns
+            #token
         }
     };
 
@@ -1,6 +1,9 @@
 #[cfg(feature = "executor-interrupt")]
 compile_error!("`executor-interrupt` is not supported with `arch-avr`.");
 
+#[cfg(feature = "executor-multicore")]
+compile_error!("`executor-multicore` is not supported with `arch-avr`.");
+
 #[cfg(feature = "executor-thread")]
 pub use thread::*;
 #[cfg(feature = "executor-thread")]
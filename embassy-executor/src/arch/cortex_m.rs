@@ -2,11 +2,18 @@
 #[cfg(any(feature = "executor-thread", feature = "executor-interrupt"))]
 fn __pender(context: *mut ()) {
     unsafe {
-        // Safety: `context` is either `usize::MAX` created by `Executor::run`, or a valid interrupt
-        // request number given to `InterruptExecutor::start`.
+        // Safety: `context` is either `usize::MAX` created by `Executor::run`, a core context
+        // created by `Executor::new_for_core`, or a valid interrupt request number given to
+        // `InterruptExecutor::start`.
 
         let context = context as usize;
 
+        #[cfg(feature = "executor-multicore")]
+        if let Some(core_id) = crate::multicore::core_id(context) {
+            crate::multicore::pend(core_id);
+            return;
+        }
+
         #[cfg(feature = "executor-thread")]
         // Try to make Rust optimize the branching away if we only use thread mode.
         if !cfg!(feature = "executor-interrupt") || context == THREAD_PENDER {
@@ -79,6 +86,44 @@ mod thread {
             }
         }
 
+        /// Create a new Executor, running on core `core_id` of a multi-core chip.
+        ///
+        /// Tasks of this executor can be woken from any core, for example after spawning them with
+        /// a [`SendSpawner`](crate::SendSpawner) from another core. The executor then calls a hook you must
+        /// provide to wake up the core, instead of executing `SEV`:
+        ///
+        /// ```rust,ignore
+        /// #[export_name = "__core_pender"]
+        /// fn core_pender(core_id: usize) {
+        ///     // Make the `WFE` of core `core_id` return.
+        /// }
+        /// ```
+        ///
+        /// The hook may be called from any core, including `core_id` itself, and from interrupts.
+        /// On chips where `SEV` reaches all cores, like the RP2040, it can just execute `SEV`. Otherwise,
+        /// it must send an event or an inter-processor interrupt to core `core_id`.
+        ///
+        /// With the `task-local` feature, you also provide a hook returning the id of the core it's
+        /// called from, so task-local values are looked up in the task polled by that core:
+        ///
+        /// ```rust,ignore
+        /// #[export_name = "__core_id"]
+        /// fn core_id() -> usize {
+        ///     // Read the core id, for example from the RP2040's SIO `CPUID` register.
+        /// }
+        /// ```
+        ///
+        /// # Panics
+        ///
+        /// Panics if `core_id` is 256 or more.
+        #[cfg(feature = "executor-multicore")]
+        pub fn new_for_core(core_id: usize) -> Self {
+            Self {
+                inner: raw::Executor::new(crate::multicore::context(core_id)),
+                not_send: PhantomData,
+            }
+        }
+
         /// Run the executor.
         ///
         /// The `init` closure is called with a [`Spawner`] that spawns tasks on
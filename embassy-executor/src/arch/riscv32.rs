@@ -1,10 +1,39 @@
-#[cfg(feature = "executor-interrupt")]
-compile_error!("`executor-interrupt` is not supported with `arch-riscv32`.");
+#[export_name = "__pender"]
+#[cfg(any(feature = "executor-thread", feature = "executor-interrupt"))]
+#[allow(clippy::needless_return)] // if only the thread executor is enabled.
+fn __pender(context: *mut ()) {
+    // Safety: `context` is either `usize::MAX` created by `Executor::new`, a core context created
+    // by `Executor::new_for_core`, or the pend function of the interrupt given to
+    // `InterruptExecutor::start`.
+
+    let context = context as usize;
+
+    #[cfg(feature = "executor-multicore")]
+    if let Some(core_id) = crate::multicore::core_id(context) {
+        crate::multicore::pend(core_id);
+        return;
+    }
+
+    #[cfg(feature = "executor-thread")]
+    // Try to make Rust optimize the branching away if we only use thread mode.
+    if !cfg!(feature = "executor-interrupt") || context == THREAD_PENDER {
+        SIGNAL_WORK_THREAD_MODE.store(true, core::sync::atomic::Ordering::SeqCst);
+        return;
+    }
+
+    #[cfg(feature = "executor-interrupt")]
+    {
+        let pend: fn() = unsafe { core::mem::transmute(context) };
+        pend();
+    }
+}
 
 #[cfg(feature = "executor-thread")]
 pub use thread::*;
 #[cfg(feature = "executor-thread")]
 mod thread {
+    pub(super) const THREAD_PENDER: usize = usize::MAX;
+
     use core::marker::PhantomData;
     use core::sync::atomic::{AtomicBool, Ordering};
 
@@ -13,25 +42,63 @@ mod thread {
     use crate::{raw, Spawner};
 
     /// global atomic used to keep track of whether there is work to do since sev() is not available on RISCV
-    static SIGNAL_WORK_THREAD_MODE: AtomicBool = AtomicBool::new(false);
-
-    #[export_name = "__pender"]
-    fn __pender(_context: *mut ()) {
-        SIGNAL_WORK_THREAD_MODE.store(true, Ordering::SeqCst);
-    }
+    pub(super) static SIGNAL_WORK_THREAD_MODE: AtomicBool = AtomicBool::new(false);
 
     /// RISCV32 Executor
     pub struct Executor {
         inner: raw::Executor,
         not_send: PhantomData<*mut ()>,
+        #[cfg(feature = "executor-multicore")]
+        multicore: bool,
     }
 
     impl Executor {
         /// Create a new Executor.
         pub fn new() -> Self {
             Self {
-                inner: raw::Executor::new(core::ptr::null_mut()),
+                inner: raw::Executor::new(THREAD_PENDER as *mut ()),
+                not_send: PhantomData,
+                #[cfg(feature = "executor-multicore")]
+                multicore: false,
+            }
+        }
+
+        /// Create a new Executor, running on hart `core_id` of a multi-core chip.
+        ///
+        /// Tasks of this executor can be woken from any hart, for example after spawning them with
+        /// a [`SendSpawner`](crate::SendSpawner) from another hart. The executor then calls a hook you must
+        /// provide to wake up the hart:
+        ///
+        /// ```rust,ignore
+        /// #[export_name = "__core_pender"]
+        /// fn core_pender(core_id: usize) {
+        ///     // Pend an interrupt on hart `core_id`, for example its CLINT machine software interrupt.
+        /// }
+        /// ```
+        ///
+        /// The hook may be called from any hart, including `core_id` itself, and from interrupts.
+        /// The executor relies on the pended interrupt alone to make its `WFI` return, so the
+        /// interrupt must be enabled, and its handler must clear it.
+        ///
+        /// With the `task-local` feature, you also provide a hook returning the id of the core it's
+        /// called from, so task-local values are looked up in the task polled by that core:
+        ///
+        /// ```rust,ignore
+        /// #[export_name = "__core_id"]
+        /// fn core_id() -> usize {
+        ///     // Read the hart id, for example from the `mhartid` CSR.
+        /// }
+        /// ```
+        ///
+        /// # Panics
+        ///
+        /// Panics if `core_id` is 256 or more.
+        #[cfg(feature = "executor-multicore")]
+        pub fn new_for_core(core_id: usize) -> Self {
+            Self {
+                inner: raw::Executor::new(crate::multicore::context(core_id)),
                 not_send: PhantomData,
+                multicore: true,
             }
         }
 
@@ -56,6 +123,18 @@ mod thread {
         pub fn run(&'static mut self, init: impl FnOnce(Spawner)) -> ! {
             init(self.inner.spawner());
 
+            #[cfg(feature = "executor-multicore")]
+            if self.multicore {
+                loop {
+                    unsafe {
+                        self.inner.poll();
+                        // Interrupts are disabled, so an interrupt pended by the hook since the poll
+                        // makes `wfi` return immediately, instead of being handled and lost.
+                        critical_section::with(|_| core::arch::asm!("wfi"));
+                    }
+                }
+            }
+
             loop {
                 unsafe {
                     self.inner.poll();
@@ -78,3 +157,144 @@ mod thread {
         }
     }
 }
+
+#[cfg(feature = "executor-interrupt")]
+pub use interrupt::*;
+#[cfg(feature = "executor-interrupt")]
+mod interrupt {
+    use core::cell::{Cell, UnsafeCell};
+    use core::mem::MaybeUninit;
+
+    use critical_section::Mutex;
+
+    use crate::raw;
+
+    /// Interrupt that can be triggered from software, to run an [`InterruptExecutor`].
+    ///
+    /// RISC-V doesn't specify how software triggers an interrupt, so this is implemented for the
+    /// chip at hand. Depending on the interrupt controller, this is done by setting the interrupt's
+    /// `clicintip` bit with a CLIC, by writing a hart's `msip` register with a CLINT, or through a
+    /// vendor-specific peripheral with a PLIC.
+    ///
+    /// # Safety
+    ///
+    /// [`pend()`](Self::pend) must make the interrupt handler calling
+    /// [`InterruptExecutor::on_interrupt()`] run, on the hart the executor was started on.
+    pub unsafe trait SoftwareInterrupt {
+        /// Trigger the interrupt.
+        ///
+        /// This may be called from any hart, and from any interrupt.
+        fn pend();
+
+        /// Enable (unmask) the interrupt.
+        ///
+        /// # Safety
+        ///
+        /// This may break critical sections relying on the interrupt being masked.
+        unsafe fn enable();
+    }
+
+    /// Interrupt mode executor.
+    ///
+    /// This executor runs tasks in interrupt mode. The interrupt handler is set up
+    /// to poll tasks, and when a task is woken the interrupt is pended from software.
+    ///
+    /// This allows running async tasks at a priority higher than thread mode. One
+    /// use case is to leave thread mode free for non-async tasks. Another use case is
+    /// to run multiple executors: one in thread mode for low priority tasks and another in
+    /// interrupt mode for higher priority tasks. Higher priority tasks will preempt lower
+    /// priority ones, if the interrupt controller supports preemption.
+    ///
+    /// To use it, you have to pick an interrupt that can be triggered from software, see
+    /// [`SoftwareInterrupt`].
+    ///
+    /// It is somewhat more complex to use, it's recommended to use the thread-mode
+    /// [`Executor`](crate::Executor) instead, if it works for your use case.
+    pub struct InterruptExecutor {
+        started: Mutex<Cell<bool>>,
+        executor: UnsafeCell<MaybeUninit<raw::Executor>>,
+    }
+
+    unsafe impl Send for InterruptExecutor {}
+    unsafe impl Sync for InterruptExecutor {}
+
+    impl InterruptExecutor {
+        /// Create a new, not started `InterruptExecutor`.
+        #[inline]
+        pub const fn new() -> Self {
+            Self {
+                started: Mutex::new(Cell::new(false)),
+                executor: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+
+        /// Executor interrupt callback.
+        ///
+        /// # Safety
+        ///
+        /// - You MUST call this from the interrupt handler, and from nowhere else.
+        /// - You must not call this before calling `start()`.
+        pub unsafe fn on_interrupt(&'static self) {
+            let executor = unsafe { (*self.executor.get()).assume_init_ref() };
+            executor.poll();
+        }
+
+        /// Start the executor.
+        ///
+        /// This initializes the executor, enables the interrupt, and returns.
+        /// The executor keeps running in the background through the interrupt.
+        ///
+        /// This returns a [`SendSpawner`](crate::SendSpawner) you can use to spawn tasks on it. A
+        /// [`SendSpawner`](crate::SendSpawner) is returned instead of a [`Spawner`](crate::Spawner)
+        /// because the executor effectively runs in a different "thread" (the interrupt), so
+        /// spawning tasks on it is effectively sending them.
+        ///
+        /// To obtain a [`Spawner`](crate::Spawner) for this executor, use
+        /// [`Spawner::for_current_executor()`](crate::Spawner::for_current_executor()) from a task
+        /// running in it.
+        ///
+        /// # Interrupt requirements
+        ///
+        /// You must write the interrupt handler yourself, and make it clear the interrupt and call
+        /// [`on_interrupt()`](Self::on_interrupt).
+        ///
+        /// This method already enables the interrupt with [`SoftwareInterrupt::enable()`], you
+        /// must NOT do it yourself.
+        ///
+        /// You must set the interrupt priority before calling this method. You MUST NOT
+        /// do it after.
+        pub fn start<I: SoftwareInterrupt>(&'static self, _irq: I) -> crate::SendSpawner {
+            if critical_section::with(|cs| self.started.borrow(cs).replace(true)) {
+                panic!("InterruptExecutor::start() called multiple times on the same executor.");
+            }
+
+            let pend: fn() = I::pend;
+            unsafe {
+                (*self.executor.get())
+                    .as_mut_ptr()
+                    .write(raw::Executor::new(pend as *mut ()))
+            }
+
+            let executor = unsafe { (*self.executor.get()).assume_init_ref() };
+
+            unsafe { I::enable() }
+
+            executor.spawner().make_send()
+        }
+
+        /// Get a SendSpawner for this executor
+        ///
+        /// This returns a [`SendSpawner`](crate::SendSpawner) you can use to spawn tasks on this
+        /// executor.
+        ///
+        /// This MUST only be called on an executor that has already been started.
+        /// The function will panic otherwise.
+        pub fn spawner(&'static self) -> crate::SendSpawner {
+            if !critical_section::with(|cs| self.started.borrow(cs).get()) {
+                panic!("InterruptExecutor::spawner() called on uninitialized executor.");
+            }
+            let executor = unsafe { (*self.executor.get()).assume_init_ref() };
+            executor.spawner().make_send()
+        }
+    }
+}
@@ -1,16 +1,26 @@
 #[cfg(feature = "executor-interrupt")]
 compile_error!("`executor-interrupt` is not supported with `arch-std`.");
 
+#[cfg(feature = "executor-multicore")]
+compile_error!("`executor-multicore` is not supported with `arch-std`.");
+
 #[cfg(feature = "executor-thread")]
 pub use thread::*;
 #[cfg(feature = "executor-thread")]
 mod thread {
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::future::Future;
     use std::marker::PhantomData;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Condvar, Mutex};
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
 
     pub use embassy_executor_macros::main_std as main;
 
-    use crate::{raw, Spawner};
+    use crate::raw::TaskRef;
+    use crate::{raw, SendSpawner, Spawner};
 
     #[export_name = "__pender"]
     fn __pender(context: *mut ()) {
@@ -23,6 +33,7 @@ mod thread {
         inner: raw::Executor,
         not_send: PhantomData<*mut ()>,
         signaler: &'static Signaler,
+        polling: Cell<bool>,
     }
 
     impl Executor {
@@ -33,6 +44,7 @@ mod thread {
                 inner: raw::Executor::new(signaler as *mut Signaler as *mut ()),
                 not_send: PhantomData,
                 signaler,
+                polling: Cell::new(false),
             }
         }
 
@@ -62,33 +74,279 @@ mod thread {
                 self.signaler.wait()
             }
         }
+
+        /// Get a spawner that spawns tasks in this executor.
+        ///
+        /// Use it to spawn tasks before driving the executor with [`poll_once()`](Self::poll_once),
+        /// [`run_until_stalled()`](Self::run_until_stalled) or [`run_until()`](Self::run_until).
+        pub fn spawner(&'static self) -> Spawner {
+            self.inner.spawner()
+        }
+
+        /// Set the seed of the pseudo-random order tasks are polled in.
+        ///
+        /// See [`raw::Executor::set_shuffle_seed()`].
+        #[cfg(feature = "shuffle-run-queue")]
+        pub fn set_shuffle_seed(&self, seed: u64) {
+            self.inner.set_shuffle_seed(seed)
+        }
+
+        /// Poll the tasks that are currently queued, once each.
+        ///
+        /// Tasks woken while polling are left queued for the next call.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called from a task running in this executor.
+        pub fn poll_once(&'static self) {
+            assert!(!self.polling.replace(true), "executor polled reentrantly");
+            unsafe { self.inner.poll() };
+            self.polling.set(false);
+        }
+
+        /// Poll tasks until none of them is ready to make progress.
+        ///
+        /// Tasks waiting for something outside the executor, like a timer or another thread,
+        /// are left pending. This is useful in tests, to run the tasks and then check their
+        /// effects.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called from a task running in this executor.
+        pub fn run_until_stalled(&'static self) {
+            loop {
+                self.poll_once();
+                if !self.signaler.take() {
+                    break;
+                }
+            }
+        }
+
+        /// Run the executor until `fut` completes, returning its output.
+        ///
+        /// `fut` is polled on the current thread, alongside the tasks spawned in this executor.
+        /// Unlike [`run_until_stalled()`](Self::run_until_stalled), this waits for tasks
+        /// pending on timers or other threads to be woken.
+        ///
+        /// # Panics
+        ///
+        /// Panics if called from a task running in this executor.
+        pub fn run_until<F: Future>(&'static self, fut: F) -> F::Output {
+            let waker = self.signaler.waker();
+            let mut cx = Context::from_waker(&waker);
+            let mut fut = pin!(fut);
+
+            loop {
+                if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                    return output;
+                }
+                self.poll_once();
+                self.signaler.wait()
+            }
+        }
+    }
+
+    /// Multi-threaded std-based executor.
+    ///
+    /// This runs tasks on a pool of worker threads, each running its own executor. It's a
+    /// sharded pool, not a work-stealing one: tasks spawned with the pool's [`SendSpawner`] are
+    /// put in a shared queue, and an idle worker is woken up to take them. Busy workers take
+    /// more tasks from the queue whenever they're done polling theirs.
+    ///
+    /// Once a task has been polled, it stays on its worker thread: only the arguments of a task
+    /// have to be `Send` (see [`SpawnToken`](crate::SpawnToken)), not the future while it runs.
+    /// Tasks are therefore never moved away from a busy worker. For the same reason, tasks spawned
+    /// from a task with [`Spawner::for_current_executor()`] stay on the spawning thread, and
+    /// don't need to be `Send`. Use the pool's [`SendSpawner`] to spread them instead.
+    ///
+    /// With the `integrated-timers` feature, each worker allocates an alarm from the time driver.
+    pub struct ThreadPoolExecutor {
+        /// Executor the pool's tasks are spawned in. It's never polled, workers take its tasks.
+        injector: raw::SyncExecutor,
+        signalers: &'static [Signaler],
+        /// Tasks taken from the injector that no worker has adopted yet.
+        queue: Mutex<VecDeque<TaskRef>>,
+        started: AtomicBool,
+    }
+
+    impl ThreadPoolExecutor {
+        /// Create a new, not started `ThreadPoolExecutor` with `threads` worker threads.
+        ///
+        /// # Panics
+        ///
+        /// Panics if `threads` is zero.
+        pub fn new(threads: usize) -> Self {
+            assert!(threads > 0, "a thread pool needs at least one thread");
+            let signalers: &'static [Signaler] = Box::leak((0..threads).map(|_| Signaler::new()).collect());
+            // Nobody waits on the injector's signaler, spawning a task wakes up an idle worker.
+            let signaler = Box::leak(Box::new(Signaler::forwarding(signalers)));
+            Self {
+                injector: raw::SyncExecutor::new_unpolled(raw::Pender(signaler as *mut Signaler as *mut ())),
+                signalers,
+                queue: Mutex::new(VecDeque::new()),
+                started: AtomicBool::new(false),
+            }
+        }
+
+        /// Start the worker threads.
+        ///
+        /// This returns a [`SendSpawner`] spawning tasks on the pool, like [`spawner()`](Self::spawner).
+        ///
+        /// # Panics
+        ///
+        /// Panics if the pool is started more than once.
+        pub fn start(&'static self) -> SendSpawner {
+            if self.started.swap(true, Ordering::AcqRel) {
+                panic!("ThreadPoolExecutor::start() called multiple times on the same executor.");
+            }
+
+            for (index, signaler) in self.signalers.iter().enumerate() {
+                std::thread::Builder::new()
+                    .name(format!("embassy-worker-{}", index))
+                    .spawn(move || self.run_worker(signaler))
+                    .unwrap();
+            }
+
+            self.spawner()
+        }
+
+        /// Get a spawner that spawns tasks on the pool.
+        ///
+        /// Tasks can be spawned before the pool is started, they're run once it is.
+        pub fn spawner(&'static self) -> SendSpawner {
+            SendSpawner::new(&self.injector)
+        }
+
+        fn run_worker(&'static self, signaler: &'static Signaler) -> ! {
+            // The executor is created here, it must only be polled on the thread it was created on.
+            let executor: &'static raw::Executor =
+                Box::leak(Box::new(raw::Executor::new(signaler as *const Signaler as *mut ())));
+
+            loop {
+                unsafe { executor.poll() };
+
+                // Mark the worker idle before looking for tasks: a task spawned after this either
+                // wakes it up, or is found below.
+                signaler.set_idle(true);
+                match self.take(signaler) {
+                    // safety: tasks in the injector have never been polled.
+                    Some(task) => unsafe { executor.adopt(task) },
+                    None => signaler.wait(),
+                }
+                signaler.set_idle(false);
+            }
+        }
+
+        /// Take a task spawned on the pool, if any.
+        fn take(&self, worker: &Signaler) -> Option<TaskRef> {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.is_empty() {
+                // Keep the order the injector's run queue yields them in, e.g. highest priority first.
+                let queue = RefCell::new(&mut *queue);
+                self.injector.take_queued(|task| queue.borrow_mut().push_back(task));
+            }
+            let task = queue.pop_front();
+            if !queue.is_empty() {
+                // The injector only pends when its run queue was empty, hand the rest to another worker.
+                wake_idle(self.signalers, Some(worker));
+            }
+            task
+        }
+    }
+
+    /// Signal one of the idle `signalers`, other than `except`.
+    fn wake_idle(signalers: &[Signaler], except: Option<&Signaler>) {
+        for signaler in signalers {
+            if except.is_some_and(|except| std::ptr::eq(signaler, except)) {
+                continue;
+            }
+            if signaler.signal_if_idle() {
+                return;
+            }
+        }
     }
 
     struct Signaler {
-        mutex: Mutex<bool>,
+        state: Mutex<SignalerState>,
         condvar: Condvar,
+        /// Signalers of the workers woken up instead of this one.
+        forward: &'static [Signaler],
+    }
+
+    struct SignalerState {
+        signaled: bool,
+        /// The worker waiting on this signaler is looking for tasks spawned on its pool.
+        idle: bool,
     }
 
     impl Signaler {
         fn new() -> Self {
+            Self::forwarding(&[])
+        }
+
+        fn forwarding(forward: &'static [Signaler]) -> Self {
             Self {
-                mutex: Mutex::new(false),
+                state: Mutex::new(SignalerState {
+                    signaled: false,
+                    idle: false,
+                }),
                 condvar: Condvar::new(),
+                forward,
             }
         }
 
         fn wait(&self) {
-            let mut signaled = self.mutex.lock().unwrap();
-            while !*signaled {
-                signaled = self.condvar.wait(signaled).unwrap();
+            let mut state = self.state.lock().unwrap();
+            while !state.signaled {
+                state = self.condvar.wait(state).unwrap();
             }
-            *signaled = false;
+            state.signaled = false;
+        }
+
+        /// Clear the signal, returning whether it was set.
+        fn take(&self) -> bool {
+            let mut state = self.state.lock().unwrap();
+            let was_signaled = state.signaled;
+            state.signaled = false;
+            was_signaled
         }
 
         fn signal(&self) {
-            let mut signaled = self.mutex.lock().unwrap();
-            *signaled = true;
+            if !self.forward.is_empty() {
+                // If no worker is idle, the busy ones take the task once they're done polling.
+                wake_idle(self.forward, None);
+                return;
+            }
+
+            let mut state = self.state.lock().unwrap();
+            state.signaled = true;
+            self.condvar.notify_one();
+        }
+
+        fn set_idle(&self, idle: bool) {
+            self.state.lock().unwrap().idle = idle;
+        }
+
+        /// Signal this signaler if it's idle and not signaled yet. Return whether it was.
+        fn signal_if_idle(&self) -> bool {
+            let mut state = self.state.lock().unwrap();
+            if !state.idle || state.signaled {
+                return false;
+            }
+            state.signaled = true;
             self.condvar.notify_one();
+            true
+        }
+
+        /// Waker signaling this signaler, for futures polled outside of a task.
+        fn waker(&'static self) -> Waker {
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(
+                |p| RawWaker::new(p, &VTABLE),
+                |p| unsafe { &*(p as *const Signaler) }.signal(),
+                |p| unsafe { &*(p as *const Signaler) }.signal(),
+                |_| {},
+            );
+            unsafe { Waker::from_raw(RawWaker::new(self as *const Self as *const (), &VTABLE)) }
         }
     }
 }
@@ -9,6 +9,9 @@
 // This mod MUST go first, so that the others see its macros.
 pub(crate) mod fmt;
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 pub use embassy_executor_macros::task;
 
 macro_rules! check_at_most_one {
@@ -42,6 +45,14 @@ pub mod raw;
 mod spawner;
 pub use spawner::*;
 
+#[cfg(all(feature = "_arch", feature = "executor-multicore"))]
+mod multicore;
+
+#[cfg(feature = "supervisor")]
+pub mod supervisor;
+#[cfg(feature = "task-local")]
+pub mod task_local;
+
 mod config {
     #![allow(unused)]
     include!(concat!(env!("OUT_DIR"), "/config.rs"));
@@ -140,3 +151,30 @@ pub mod _export {
         }
     }
 }
+
+// Used by the `task` macro for `#[task(priority = ...)]`, which can't see the features of this
+// crate: checks the priority at compile time and sets it on the spawn token.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "task-priorities")]
+macro_rules! __task_priority {
+    ($token:expr, $priority:expr) => {{
+        const _: () = ::core::assert!(
+            ($priority as usize) < $crate::raw::PRIORITY_LEVELS,
+            "task priority must be lower than `embassy_executor::raw::PRIORITY_LEVELS`"
+        );
+        $token.with_priority($priority)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "task-priorities"))]
+macro_rules! __task_priority {
+    ($token:expr, $priority:expr) => {{
+        ::core::compile_error!(
+            "`#[task(priority = ...)]` requires the `task-priorities` feature of `embassy-executor`"
+        );
+        $token
+    }};
+}
@@ -0,0 +1,28 @@
+//! Pender contexts of per-core thread executors, see `Executor::new_for_core()`.
+
+/// Maximum number of cores.
+pub(crate) const MAX_CORES: usize = 256;
+
+/// Core contexts are taken from the top of the address space, right below the single-core
+/// thread executor's `usize::MAX`. These values can't be valid pointers or interrupt numbers.
+const CORE_PENDER_BASE: usize = usize::MAX - 1;
+
+/// Get the pender context of the thread executor for core `core_id`.
+pub(crate) fn context(core_id: usize) -> *mut () {
+    assert!(core_id < MAX_CORES, "core id out of range");
+    (CORE_PENDER_BASE - core_id) as *mut ()
+}
+
+/// Get the core id from a pender context, if it belongs to a per-core thread executor.
+pub(crate) fn core_id(context: usize) -> Option<usize> {
+    let core_id = CORE_PENDER_BASE.wrapping_sub(context);
+    (core_id < MAX_CORES).then_some(core_id)
+}
+
+/// Wake up the thread executor of core `core_id` through the user-provided hook.
+pub(crate) fn pend(core_id: usize) {
+    extern "Rust" {
+        fn __core_pender(core_id: usize);
+    }
+    unsafe { __core_pender(core_id) }
+}
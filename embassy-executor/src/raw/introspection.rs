@@ -0,0 +1,209 @@
+//! Task introspection.
+//!
+//! With the `introspection` feature enabled, the executor keeps track of every task that
+//! has been spawned, along with its name and poll statistics. This can be used to list
+//! the tasks from a shell or debug command:
+//!
+//! ```rust,ignore
+//! for task in embassy_executor::raw::introspection::tasks() {
+//!     info!("{}: {:?}, {} polls", task.name.unwrap_or("?"), task.state, task.polls);
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use super::state::{STATE_RUN_QUEUED, STATE_SPAWNED};
+use super::TaskRef;
+
+/// All tasks that have ever been spawned.
+///
+/// Task storage lives forever, so tasks are never removed from the list.
+static TASKS: Mutex<Cell<Option<TaskRef>>> = Mutex::new(Cell::new(None));
+
+#[derive(Clone, Copy)]
+struct Meta {
+    name: Option<&'static str>,
+    next: Option<TaskRef>,
+    registered: bool,
+    running: bool,
+    polls: u32,
+    poll_ticks: u64,
+}
+
+/// Per-task introspection data, stored in the task header.
+pub(crate) struct TaskMeta {
+    meta: Mutex<Cell<Meta>>,
+}
+
+impl TaskMeta {
+    pub(crate) const fn new() -> Self {
+        Self {
+            meta: Mutex::new(Cell::new(Meta {
+                name: None,
+                next: None,
+                registered: false,
+                running: false,
+                polls: 0,
+                poll_ticks: 0,
+            })),
+        }
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut Meta) -> R) -> R {
+        critical_section::with(|cs| {
+            let cell = self.meta.borrow(cs);
+            let mut meta = cell.get();
+            let r = f(&mut meta);
+            cell.set(meta);
+            r
+        })
+    }
+
+    fn get(&self) -> Meta {
+        critical_section::with(|cs| self.meta.borrow(cs).get())
+    }
+}
+
+/// Record a newly spawned task, adding it to the task list the first time.
+///
+/// Heap-allocated tasks aren't added to the list, as they're freed when they finish.
+pub(crate) fn spawned(task: TaskRef, name: Option<&'static str>) {
+    #[cfg(feature = "alloc")]
+    let listed = !task.is_boxed();
+    #[cfg(not(feature = "alloc"))]
+    let listed = true;
+
+    critical_section::with(|cs| {
+        let cell = task.header().meta.meta.borrow(cs);
+        let mut meta = cell.get();
+        meta.name = name;
+        meta.running = false;
+        meta.polls = 0;
+        meta.poll_ticks = 0;
+        if listed && !meta.registered {
+            meta.registered = true;
+            let head = TASKS.borrow(cs);
+            meta.next = head.get();
+            head.set(Some(task));
+        }
+        cell.set(meta);
+    })
+}
+
+/// Mark the task as running, returning the poll start time.
+pub(crate) fn poll_begin(task: TaskRef) -> u64 {
+    task.header().meta.update(|m| m.running = true);
+    embassy_time_driver::now()
+}
+
+/// Mark the task as no longer running and account for the poll, returning its duration.
+pub(crate) fn poll_end(task: TaskRef, start: u64) -> u64 {
+    let elapsed = embassy_time_driver::now().saturating_sub(start);
+    task.header().meta.update(|m| {
+        m.running = false;
+        m.polls = m.polls.wrapping_add(1);
+        m.poll_ticks += elapsed;
+    });
+    elapsed
+}
+
+pub(crate) fn name(task: TaskRef) -> Option<&'static str> {
+    task.header().meta.get().name
+}
+
+/// State of a task.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum TaskState {
+    /// The task is being polled.
+    Running,
+    /// The task has been woken and is waiting in the run queue.
+    Queued,
+    /// The task is spawned and waiting to be woken.
+    Spawned,
+}
+
+/// Information about a task.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct TaskInfo {
+    /// Task ID. This is the address of the task, the same ID reported to `rtos-trace`.
+    pub id: usize,
+    /// Task name, set with `#[task(name = "...")]`, defaulting to the task function's name.
+    pub name: Option<&'static str>,
+    /// Task state.
+    pub state: TaskState,
+    /// Number of times the task has been polled since it was spawned.
+    pub polls: u32,
+    /// Total time spent polling the task since it was spawned, in [`embassy_time_driver::TICK_HZ`] ticks.
+    pub poll_ticks: u64,
+}
+
+/// Iterator over the live tasks, returned by [`tasks()`].
+pub struct Tasks {
+    next: Option<TaskRef>,
+}
+
+impl Iterator for Tasks {
+    type Item = TaskInfo;
+
+    fn next(&mut self) -> Option<TaskInfo> {
+        loop {
+            let task = self.next?;
+            let header = task.header();
+            let meta = header.meta.get();
+            self.next = meta.next;
+
+            let state = header.state.load();
+            if state & STATE_SPAWNED == 0 {
+                continue;
+            }
+            let state = if meta.running {
+                TaskState::Running
+            } else if state & STATE_RUN_QUEUED != 0 {
+                TaskState::Queued
+            } else {
+                TaskState::Spawned
+            };
+
+            return Some(TaskInfo {
+                id: task.as_ptr() as usize,
+                name: meta.name,
+                state,
+                polls: meta.polls,
+                poll_ticks: meta.poll_ticks,
+            });
+        }
+    }
+}
+
+/// Iterate over all live tasks, in all executors.
+///
+/// Tasks spawned while iterating may or may not be returned. Tasks spawned with
+/// [`Spawner::spawn_boxed()`](crate::Spawner::spawn_boxed) are not returned.
+pub fn tasks() -> Tasks {
+    Tasks {
+        next: critical_section::with(|cs| TASKS.borrow(cs).get()),
+    }
+}
+
+/// Send the name of a task to `rtos-trace`.
+#[cfg(feature = "rtos-trace")]
+pub(crate) fn send_info(task: TaskRef) {
+    rtos_trace::trace::task_send_info(
+        task.as_ptr() as u32,
+        rtos_trace::TaskInfo {
+            name: name(task).unwrap_or(""),
+            #[cfg(feature = "task-priorities")]
+            priority: unsafe { task.header().priority.get() } as u32,
+            #[cfg(not(feature = "task-priorities"))]
+            priority: 0,
+            stack_base: 0,
+            stack_size: 0,
+        },
+    );
+}
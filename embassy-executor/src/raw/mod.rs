@@ -10,22 +10,39 @@
 #[cfg_attr(target_has_atomic = "ptr", path = "run_queue_atomics.rs")]
 #[cfg_attr(not(target_has_atomic = "ptr"), path = "run_queue_critical_section.rs")]
 mod run_queue;
+#[cfg(feature = "task-priorities")]
+mod run_queue_priority;
+#[cfg(feature = "shuffle-run-queue")]
+mod run_queue_shuffle;
+
+#[cfg(all(feature = "task-priorities", feature = "shuffle-run-queue"))]
+compile_error!("`task-priorities` and `shuffle-run-queue` can't be enabled at the same time.");
+#[cfg(all(feature = "alloc", feature = "turbowakers"))]
+compile_error!("`alloc` and `turbowakers` can't be enabled at the same time.");
 
 #[cfg_attr(all(cortex_m, target_has_atomic = "8"), path = "state_atomics_arm.rs")]
 #[cfg_attr(all(not(cortex_m), target_has_atomic = "8"), path = "state_atomics.rs")]
 #[cfg_attr(not(target_has_atomic = "8"), path = "state_critical_section.rs")]
 mod state;
 
+#[cfg(feature = "introspection")]
+pub mod introspection;
+#[cfg(feature = "poll-monitor")]
+pub mod monitor;
 #[cfg(feature = "integrated-timers")]
 mod timer_queue;
 pub(crate) mod util;
 #[cfg_attr(feature = "turbowakers", path = "waker_turbo.rs")]
 mod waker;
 
+#[cfg(feature = "alloc")]
+use core::alloc::Layout;
 use core::future::Future;
 use core::marker::PhantomData;
 use core::mem;
 use core::pin::Pin;
+#[cfg(any(feature = "join-handles", feature = "alloc"))]
+use core::ptr;
 use core::ptr::NonNull;
 use core::task::{Context, Poll};
 
@@ -34,23 +51,88 @@ use embassy_time_driver::AlarmHandle;
 #[cfg(feature = "rtos-trace")]
 use rtos_trace::trace;
 
-use self::run_queue::{RunQueue, RunQueueItem};
+#[cfg(not(any(feature = "task-priorities", feature = "shuffle-run-queue")))]
+use self::run_queue::RunQueue;
+use self::run_queue::RunQueueItem;
+#[cfg(feature = "task-priorities")]
+use self::run_queue_priority::PriorityRunQueue as RunQueue;
+#[cfg(feature = "task-priorities")]
+pub use self::run_queue_priority::PRIORITY_LEVELS;
+#[cfg(feature = "shuffle-run-queue")]
+use self::run_queue_shuffle::ShuffleRunQueue as RunQueue;
 use self::state::State;
+#[cfg(feature = "join-handles")]
+use self::util::AtomicWaker;
+#[cfg(feature = "alloc")]
+use self::util::RefCount;
 use self::util::{SyncUnsafeCell, UninitCell};
 pub use self::waker::task_from_waker;
 use super::SpawnToken;
 
+/// Moves the output of a finished task to the given pointer, or drops it if null.
+#[cfg(feature = "join-handles")]
+pub(crate) type OutputFn = unsafe fn(TaskRef, *mut ());
+
 /// Raw task header for use in task pointers.
 pub(crate) struct TaskHeader {
     pub(crate) state: State,
     pub(crate) run_queue_item: RunQueueItem,
     pub(crate) executor: SyncUnsafeCell<Option<&'static SyncExecutor>>,
     poll_fn: SyncUnsafeCell<Option<unsafe fn(TaskRef)>>,
+    #[cfg(feature = "join-handles")]
+    pub(crate) output_fn: SyncUnsafeCell<Option<OutputFn>>,
+    #[cfg(feature = "join-handles")]
+    pub(crate) join_waker: AtomicWaker,
+
+    #[cfg(feature = "task-priorities")]
+    pub(crate) priority: SyncUnsafeCell<u8>,
+    #[cfg(feature = "introspection")]
+    pub(crate) meta: introspection::TaskMeta,
 
     #[cfg(feature = "integrated-timers")]
     pub(crate) expires_at: SyncUnsafeCell<u64>,
     #[cfg(feature = "integrated-timers")]
     pub(crate) timer_queue_item: timer_queue::TimerQueueItem,
+
+    /// Frees the task, set only for heap-allocated tasks.
+    #[cfg(feature = "alloc")]
+    dealloc: SyncUnsafeCell<Option<unsafe fn(TaskRef)>>,
+    /// References keeping a heap-allocated task alive: one while it's spawned or its join handle
+    /// is alive, one while it's in the run queue, one while it's in the timer queue, and one per
+    /// waker clone.
+    #[cfg(feature = "alloc")]
+    refs: RefCount,
+}
+
+impl TaskHeader {
+    const fn new() -> Self {
+        Self {
+            state: State::new(),
+            run_queue_item: RunQueueItem::new(),
+            executor: SyncUnsafeCell::new(None),
+            // Note: this is lazily initialized so that a static `TaskStorage` will go in `.bss`
+            poll_fn: SyncUnsafeCell::new(None),
+            #[cfg(feature = "join-handles")]
+            output_fn: SyncUnsafeCell::new(None),
+            #[cfg(feature = "join-handles")]
+            join_waker: AtomicWaker::new(),
+
+            #[cfg(feature = "task-priorities")]
+            priority: SyncUnsafeCell::new(0),
+            #[cfg(feature = "introspection")]
+            meta: introspection::TaskMeta::new(),
+
+            #[cfg(feature = "integrated-timers")]
+            expires_at: SyncUnsafeCell::new(0),
+            #[cfg(feature = "integrated-timers")]
+            timer_queue_item: timer_queue::TimerQueueItem::new(),
+
+            #[cfg(feature = "alloc")]
+            dealloc: SyncUnsafeCell::new(None),
+            #[cfg(feature = "alloc")]
+            refs: RefCount::new(),
+        }
+    }
 }
 
 /// This is essentially a `&'static TaskStorage<F>` where the type of the future has been erased.
@@ -84,6 +166,43 @@ impl TaskRef {
     pub(crate) fn as_ptr(self) -> *const TaskHeader {
         self.ptr.as_ptr()
     }
+
+    /// Get the name of the task, set with `#[task(name = "...")]`.
+    #[cfg(feature = "introspection")]
+    pub fn name(&self) -> Option<&'static str> {
+        introspection::name(*self)
+    }
+
+    /// Return whether the task was allocated by [`Spawner::spawn_boxed()`](super::Spawner::spawn_boxed).
+    #[cfg(feature = "alloc")]
+    pub(crate) fn is_boxed(self) -> bool {
+        unsafe { self.header().dealloc.get() }.is_some()
+    }
+
+    /// Take a reference keeping the task alive, if it's heap-allocated.
+    #[inline(always)]
+    pub(crate) fn acquire(self) {
+        #[cfg(feature = "alloc")]
+        if self.is_boxed() {
+            self.header().refs.increment();
+        }
+    }
+
+    /// Drop a reference taken with [`acquire()`](Self::acquire), freeing the task if it's
+    /// heap-allocated and this was the last reference.
+    ///
+    /// # Safety
+    ///
+    /// The task must not be used after this call, unless another reference is held.
+    #[inline(always)]
+    pub(crate) unsafe fn drop_ref(self) {
+        #[cfg(feature = "alloc")]
+        if let Some(dealloc) = self.header().dealloc.get() {
+            if self.header().refs.decrement() {
+                dealloc(self);
+            }
+        }
+    }
 }
 
 /// Raw storage in which a task can be spawned.
@@ -105,6 +224,8 @@ impl TaskRef {
 pub struct TaskStorage<F: Future + 'static> {
     raw: TaskHeader,
     future: UninitCell<F>, // Valid if STATE_SPAWNED
+    #[cfg(feature = "join-handles")]
+    output: UninitCell<F::Output>, // Valid if STATE_FINISHED and not STATE_ABORTED, until taken
 }
 
 impl<F: Future + 'static> TaskStorage<F> {
@@ -113,19 +234,10 @@ impl<F: Future + 'static> TaskStorage<F> {
     /// Create a new TaskStorage, in not-spawned state.
     pub const fn new() -> Self {
         Self {
-            raw: TaskHeader {
-                state: State::new(),
-                run_queue_item: RunQueueItem::new(),
-                executor: SyncUnsafeCell::new(None),
-                // Note: this is lazily initialized so that a static `TaskStorage` will go in `.bss`
-                poll_fn: SyncUnsafeCell::new(None),
-
-                #[cfg(feature = "integrated-timers")]
-                expires_at: SyncUnsafeCell::new(0),
-                #[cfg(feature = "integrated-timers")]
-                timer_queue_item: timer_queue::TimerQueueItem::new(),
-            },
+            raw: TaskHeader::new(),
             future: UninitCell::uninit(),
+            #[cfg(feature = "join-handles")]
+            output: UninitCell::uninit(),
         }
     }
 
@@ -140,9 +252,10 @@ impl<F: Future + 'static> TaskStorage<F> {
     /// In this case, the error is delayed: a "poisoned" SpawnToken is returned, which will
     /// cause [`Spawner::spawn()`](super::Spawner::spawn) to return the error.
     ///
-    /// Once the task has finished running, you may spawn it again. It is allowed to spawn it
-    /// on a different executor.
-    pub fn spawn(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized> {
+    /// Once the task has finished running, and its `JoinHandle` has been dropped if it was
+    /// spawned with [`Spawner::spawn_joinable()`](super::Spawner::spawn_joinable), you may spawn it
+    /// again. It is allowed to spawn it on a different executor.
+    pub fn spawn(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized, F::Output> {
         let task = AvailableTask::claim(self);
         match task {
             Some(task) => task.initialize(future),
@@ -153,13 +266,45 @@ impl<F: Future + 'static> TaskStorage<F> {
     unsafe fn poll(p: TaskRef) {
         let this = &*(p.as_ptr() as *const TaskStorage<F>);
 
+        #[cfg(feature = "join-handles")]
+        if this.raw.state.is_aborted() {
+            this.future.drop_in_place();
+            if !this.raw.state.finish(true) {
+                this.raw.state.despawn();
+                // The run queue still holds a reference, the task isn't freed yet.
+                p.drop_ref();
+            }
+            this.raw.join_waker.wake();
+
+            #[cfg(feature = "integrated-timers")]
+            this.raw.expires_at.set(u64::MAX);
+            return;
+        }
+
         let future = Pin::new_unchecked(this.future.as_mut());
         let waker = waker::from_task(p);
         let mut cx = Context::from_waker(&waker);
         match future.poll(&mut cx) {
+            #[cfg(feature = "join-handles")]
+            Poll::Ready(output) => {
+                this.future.drop_in_place();
+                this.output.write_in_place(|| output);
+                if !this.raw.state.finish(false) {
+                    // Nobody is waiting for the output, drop it before freeing the task.
+                    this.output.drop_in_place();
+                    this.raw.state.despawn();
+                    p.drop_ref();
+                }
+                this.raw.join_waker.wake();
+
+                #[cfg(feature = "integrated-timers")]
+                this.raw.expires_at.set(u64::MAX);
+            }
+            #[cfg(not(feature = "join-handles"))]
             Poll::Ready(_) => {
                 this.future.drop_in_place();
                 this.raw.state.despawn();
+                p.drop_ref();
 
                 #[cfg(feature = "integrated-timers")]
                 this.raw.expires_at.set(u64::MAX);
@@ -172,6 +317,50 @@ impl<F: Future + 'static> TaskStorage<F> {
         mem::forget(waker);
     }
 
+    /// Move the output of the finished task to `dst`, or drop it if `dst` is null.
+    #[cfg(feature = "join-handles")]
+    unsafe fn take_output(p: TaskRef, dst: *mut ()) {
+        let this = &*(p.as_ptr() as *const TaskStorage<F>);
+
+        if dst.is_null() {
+            this.output.drop_in_place();
+        } else {
+            ptr::copy_nonoverlapping(this.output.as_mut_ptr(), dst as *mut F::Output, 1);
+        }
+    }
+
+    /// Drop and free a heap-allocated task.
+    #[cfg(feature = "alloc")]
+    unsafe fn dealloc(p: TaskRef) {
+        let this = p.as_ptr() as *mut TaskStorage<F>;
+        ptr::drop_in_place(ptr::addr_of_mut!((*this).raw));
+        alloc::alloc::dealloc(this as *mut u8, Layout::new::<Self>());
+    }
+
+    /// Allocate a task on the heap, initialized to run `future`.
+    ///
+    /// The task is freed once it has finished running and its `JoinHandle` has been dropped, if
+    /// it was spawned with one.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn spawn_boxed(future: F) -> SpawnToken<F, F::Output> {
+        let layout = Layout::new::<Self>();
+        unsafe {
+            // Allocate uninitialized memory and only write the header, the future is then
+            // constructed in place like for static tasks.
+            let this = alloc::alloc::alloc(layout) as *mut Self;
+            if this.is_null() {
+                alloc::alloc::handle_alloc_error(layout);
+            }
+            ptr::addr_of_mut!((*this).raw).write(TaskHeader::new());
+
+            let this: &'static Self = &*this;
+            this.raw.dealloc.set(Some(Self::dealloc));
+            // One reference for the spawned task, one for the run queue it's put in when spawned.
+            this.raw.refs.set(2);
+            unwrap!(AvailableTask::claim(this)).initialize(|| future)
+        }
+    }
+
     #[doc(hidden)]
     #[allow(dead_code)]
     fn _assert_sync(self) {
@@ -194,20 +383,30 @@ impl<F: Future + 'static> AvailableTask<F> {
         task.raw.state.spawn().then(|| Self { task })
     }
 
-    fn initialize_impl<S>(self, future: impl FnOnce() -> F) -> SpawnToken<S> {
+    fn initialize_impl<S>(self, name: Option<&'static str>, future: impl FnOnce() -> F) -> SpawnToken<S, F::Output> {
+        #[cfg(not(feature = "introspection"))]
+        let _ = name;
+
         unsafe {
             self.task.raw.poll_fn.set(Some(TaskStorage::<F>::poll));
+            #[cfg(feature = "join-handles")]
+            self.task.raw.output_fn.set(Some(TaskStorage::<F>::take_output));
+            #[cfg(feature = "task-priorities")]
+            self.task.raw.priority.set(0);
             self.task.future.write_in_place(future);
 
             let task = TaskRef::new(self.task);
 
+            #[cfg(feature = "introspection")]
+            introspection::spawned(task, name);
+
             SpawnToken::new(task)
         }
     }
 
     /// Initialize the [`TaskStorage`] to run the given future.
-    pub fn initialize(self, future: impl FnOnce() -> F) -> SpawnToken<F> {
-        self.initialize_impl::<F>(future)
+    pub fn initialize(self, future: impl FnOnce() -> F) -> SpawnToken<F, F::Output> {
+        self.initialize_impl::<F>(None, future)
     }
 
     /// Initialize the [`TaskStorage`] to run the given future.
@@ -217,7 +416,7 @@ impl<F: Future + 'static> AvailableTask<F> {
     /// `future` must be a closure of the form `move || my_async_fn(args)`, where `my_async_fn`
     /// is an `async fn`, NOT a hand-written `Future`.
     #[doc(hidden)]
-    pub unsafe fn __initialize_async_fn<FutFn>(self, future: impl FnOnce() -> F) -> SpawnToken<FutFn> {
+    pub unsafe fn __initialize_async_fn<FutFn>(self, future: impl FnOnce() -> F) -> SpawnToken<FutFn, F::Output> {
         // When send-spawning a task, we construct the future in this thread, and effectively
         // "send" it to the executor thread by enqueuing it in its queue. Therefore, in theory,
         // send-spawning should require the future `F` to be `Send`.
@@ -243,7 +442,7 @@ impl<F: Future + 'static> AvailableTask<F> {
         //
         // This ONLY holds for `async fn` futures. The other `spawn` methods can be called directly
         // by the user, with arbitrary hand-implemented futures. This is why these return `SpawnToken<F>`.
-        self.initialize_impl::<FutFn>(future)
+        self.initialize_impl::<FutFn>(None, future)
     }
 }
 
@@ -262,9 +461,13 @@ impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
         }
     }
 
-    fn spawn_impl<T>(&'static self, future: impl FnOnce() -> F) -> SpawnToken<T> {
+    fn spawn_impl<T>(
+        &'static self,
+        name: Option<&'static str>,
+        future: impl FnOnce() -> F,
+    ) -> SpawnToken<T, F::Output> {
         match self.pool.iter().find_map(AvailableTask::claim) {
-            Some(task) => task.initialize_impl::<T>(future),
+            Some(task) => task.initialize_impl::<T>(name, future),
             None => SpawnToken::new_failed(),
         }
     }
@@ -276,8 +479,8 @@ impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
     /// This will loop over the pool and spawn the task in the first storage that
     /// is currently free. If none is free, a "poisoned" SpawnToken is returned,
     /// which will cause [`Spawner::spawn()`](super::Spawner::spawn) to return the error.
-    pub fn spawn(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized> {
-        self.spawn_impl::<F>(future)
+    pub fn spawn(&'static self, future: impl FnOnce() -> F) -> SpawnToken<impl Sized, F::Output> {
+        self.spawn_impl::<F>(None, future)
     }
 
     /// Like spawn(), but allows the task to be send-spawned if the args are Send even if
@@ -289,17 +492,21 @@ impl<F: Future + 'static, const N: usize> TaskPool<F, N> {
     /// SAFETY: `future` must be a closure of the form `move || my_async_fn(args)`, where `my_async_fn`
     /// is an `async fn`, NOT a hand-written `Future`.
     #[doc(hidden)]
-    pub unsafe fn _spawn_async_fn<FutFn>(&'static self, future: FutFn) -> SpawnToken<impl Sized>
+    pub unsafe fn _spawn_async_fn<FutFn>(
+        &'static self,
+        name: &'static str,
+        future: FutFn,
+    ) -> SpawnToken<impl Sized, F::Output>
     where
         FutFn: FnOnce() -> F,
     {
         // See the comment in AvailableTask::__initialize_async_fn for explanation.
-        self.spawn_impl::<FutFn>(future)
+        self.spawn_impl::<FutFn>(Some(name), future)
     }
 }
 
 #[derive(Clone, Copy)]
-pub(crate) struct Pender(*mut ());
+pub(crate) struct Pender(pub(crate) *mut ());
 
 unsafe impl Send for Pender {}
 unsafe impl Sync for Pender {}
@@ -316,26 +523,44 @@ impl Pender {
 pub(crate) struct SyncExecutor {
     run_queue: RunQueue,
     pender: Pender,
+    #[cfg(feature = "poll-monitor")]
+    monitor: monitor::ExecutorMonitor,
+    #[cfg(feature = "task-local")]
+    polling: crate::task_local::Polling,
 
     #[cfg(feature = "integrated-timers")]
     pub(crate) timer_queue: timer_queue::TimerQueue,
+    /// `None` for executors that are never polled.
     #[cfg(feature = "integrated-timers")]
-    alarm: AlarmHandle,
+    alarm: Option<AlarmHandle>,
 }
 
 impl SyncExecutor {
     pub(crate) fn new(pender: Pender) -> Self {
+        #[allow(unused_mut)]
+        let mut this = Self::new_unpolled(pender);
         #[cfg(feature = "integrated-timers")]
-        let alarm = unsafe { unwrap!(embassy_time_driver::allocate_alarm()) };
+        {
+            this.alarm = Some(unsafe { unwrap!(embassy_time_driver::allocate_alarm()) });
+        }
+        this
+    }
 
+    /// Create an executor that's never polled: its tasks are moved to other executors with
+    /// [`take_queued()`](Self::take_queued) before their first poll. It allocates no alarm.
+    pub(crate) fn new_unpolled(pender: Pender) -> Self {
         Self {
             run_queue: RunQueue::new(),
             pender,
+            #[cfg(feature = "poll-monitor")]
+            monitor: monitor::ExecutorMonitor::new(),
+            #[cfg(feature = "task-local")]
+            polling: crate::task_local::Polling::new(),
 
             #[cfg(feature = "integrated-timers")]
             timer_queue: timer_queue::TimerQueue::new(),
             #[cfg(feature = "integrated-timers")]
-            alarm,
+            alarm: None,
         }
     }
 
@@ -366,16 +591,34 @@ impl SyncExecutor {
 
         #[cfg(feature = "rtos-trace")]
         trace::task_new(task.as_ptr() as u32);
+        #[cfg(all(feature = "rtos-trace", feature = "introspection"))]
+        introspection::send_info(task);
 
         self.enqueue(task);
     }
 
+    pub(crate) fn take_queued(&self, on_task: impl Fn(TaskRef)) {
+        self.run_queue.dequeue_all(on_task)
+    }
+
+    pub(crate) unsafe fn adopt(&'static self, task: TaskRef) {
+        task.header().executor.set(Some(self));
+        self.enqueue(task);
+    }
+
     /// # Safety
     ///
     /// Same as [`Executor::poll`], plus you must only call this on the thread this executor was created.
     pub(crate) unsafe fn poll(&'static self) {
+        #[cfg(feature = "poll-monitor")]
+        self.monitor.poll_begin();
+        #[cfg(feature = "poll-monitor")]
+        let long_poll_handler = monitor::long_poll_handler();
+
+        #[cfg(feature = "integrated-timers")]
+        let alarm = unwrap!(self.alarm, "executor created without an alarm is polled");
         #[cfg(feature = "integrated-timers")]
-        embassy_time_driver::set_alarm_callback(self.alarm, Self::alarm_callback, self as *const _ as *mut ());
+        embassy_time_driver::set_alarm_callback(alarm, Self::alarm_callback, self as *const _ as *mut ());
 
         #[allow(clippy::never_loop)]
         loop {
@@ -395,21 +638,38 @@ impl SyncExecutor {
                     //   - While task is being polled, it gets woken. It gets placed in the queue.
                     //   - Task poll finishes, returning done=true
                     //   - RUNNING bit is cleared, but the task is already in the queue.
+                    p.drop_ref();
                     return;
                 }
 
                 #[cfg(feature = "rtos-trace")]
                 trace::task_exec_begin(p.as_ptr() as u32);
+                #[cfg(feature = "introspection")]
+                let start = introspection::poll_begin(p);
+
+                #[cfg(feature = "task-local")]
+                self.polling.enter(p);
 
                 // Run the task
                 task.poll_fn.get().unwrap_unchecked()(p);
 
+                #[cfg(feature = "task-local")]
+                self.polling.exit();
+
+                #[cfg(feature = "introspection")]
+                let _ticks = introspection::poll_end(p, start);
+                #[cfg(feature = "poll-monitor")]
+                self.monitor.task_polled(p, _ticks, long_poll_handler);
+
                 #[cfg(feature = "rtos-trace")]
                 trace::task_exec_end();
 
                 // Enqueue or update into timer_queue
                 #[cfg(feature = "integrated-timers")]
                 self.timer_queue.update(p);
+
+                // Drop the reference the run queue held.
+                p.drop_ref();
             });
 
             #[cfg(feature = "integrated-timers")]
@@ -417,7 +677,7 @@ impl SyncExecutor {
                 // If this is already in the past, set_alarm might return false
                 // In that case do another poll loop iteration.
                 let next_expiration = self.timer_queue.next_expiration();
-                if embassy_time_driver::set_alarm(self.alarm, next_expiration) {
+                if embassy_time_driver::set_alarm(alarm, next_expiration) {
                     break;
                 }
             }
@@ -526,6 +786,21 @@ impl Executor {
         self.inner.poll()
     }
 
+    /// Set the seed of the pseudo-random order tasks are polled in.
+    ///
+    /// Polling the same tasks with the same seed gives the same order, so a seed that
+    /// uncovered a bug can be reused to reproduce it.
+    #[cfg(feature = "shuffle-run-queue")]
+    pub fn set_shuffle_seed(&self, seed: u64) {
+        self.inner.run_queue.set_seed(seed)
+    }
+
+    /// Get the CPU usage of this executor.
+    #[cfg(feature = "poll-monitor")]
+    pub fn stats(&self) -> monitor::ExecutorStats {
+        self.inner.monitor.stats()
+    }
+
     /// Get a spawner that spawns tasks in this executor.
     ///
     /// It is OK to call this method multiple times to obtain multiple
@@ -533,6 +808,33 @@ impl Executor {
     pub fn spawner(&'static self) -> super::Spawner {
         super::Spawner::new(self)
     }
+
+    /// Empty the run queue without polling the tasks, calling `on_task` for each of them.
+    ///
+    /// This allows spreading the tasks spawned in this executor over other executors, by moving
+    /// them with [`adopt()`](Self::adopt).
+    ///
+    /// # Safety
+    ///
+    /// Every task passed to `on_task` must then be adopted by an executor. The tasks stay marked
+    /// as queued until then: a task that isn't adopted is never polled again, so it never
+    /// finishes and its storage is never freed.
+    pub unsafe fn take_queued(&self, on_task: impl Fn(TaskRef)) {
+        self.inner.take_queued(on_task)
+    }
+
+    /// Move a task taken from another executor with [`take_queued()`](Self::take_queued) to this
+    /// one, and queue it.
+    ///
+    /// # Safety
+    ///
+    /// `task` must have been taken with `take_queued()` and not adopted yet. If it was spawned
+    /// with a [`SendSpawner`](super::SendSpawner) and never polled, this may be called from any
+    /// thread, like [`spawn()`](Self::spawn): its future only has to be `Send` until it's first
+    /// polled. Otherwise this executor must run on the thread that polled or spawned the task.
+    pub unsafe fn adopt(&'static self, task: TaskRef) {
+        self.inner.adopt(task)
+    }
 }
 
 /// Wake a task by `TaskRef`.
@@ -542,6 +844,7 @@ pub fn wake_task(task: TaskRef) {
     let header = task.header();
     if header.state.run_enqueue() {
         // We have just marked the task as scheduled, so enqueue it.
+        task.acquire();
         unsafe {
             let executor = header.executor.get().unwrap_unchecked();
             executor.enqueue(task);
@@ -556,6 +859,7 @@ pub fn wake_task_no_pend(task: TaskRef) {
     let header = task.header();
     if header.state.run_enqueue() {
         // We have just marked the task as scheduled, so enqueue it.
+        task.acquire();
         unsafe {
             let executor = header.executor.get().unwrap_unchecked();
             executor.run_queue.enqueue(task);
@@ -592,6 +896,13 @@ const fn gcd(a: u64, b: u64) -> u64 {
 
 #[cfg(feature = "rtos-trace")]
 impl rtos_trace::RtosTraceOSCallbacks for Executor {
+    #[cfg(feature = "introspection")]
+    fn task_list() {
+        for task in introspection::tasks() {
+            introspection::send_info(unsafe { TaskRef::from_ptr(task.id as *const TaskHeader) });
+        }
+    }
+    #[cfg(not(feature = "introspection"))]
     fn task_list() {
         // We don't know what tasks exist, so we can't send them.
     }
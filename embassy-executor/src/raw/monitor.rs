@@ -0,0 +1,138 @@
+//! Poll monitoring.
+//!
+//! With the `poll-monitor` feature enabled, the executor reports polls that take longer than a
+//! threshold, which usually means a task is blocking or busy-looping instead of awaiting, and
+//! tracks how busy each executor is.
+//!
+//! The time each task spent being polled is available from [`introspection`](super::introspection).
+//!
+//! ```rust,ignore
+//! fn on_long_poll(poll: &LongPoll) {
+//!     warn!("task {} blocked the executor for {} ticks", poll.name.unwrap_or("?"), poll.ticks);
+//! }
+//!
+//! // Report polls taking more than 10ms.
+//! monitor::set_long_poll_handler(embassy_time_driver::TICK_HZ / 100, on_long_poll);
+//! ```
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use super::{introspection, TaskRef};
+
+/// Long poll threshold and handler.
+pub(crate) type Handler = (u64, fn(&LongPoll));
+
+static LONG_POLL_HANDLER: Mutex<Cell<Option<Handler>>> = Mutex::new(Cell::new(None));
+
+/// Set the function called after a poll that took more than `threshold_ticks`.
+///
+/// The handler is called from the executor that polled the task, right after the poll. It
+/// applies to all executors.
+pub fn set_long_poll_handler(threshold_ticks: u64, handler: fn(&LongPoll)) {
+    critical_section::with(|cs| LONG_POLL_HANDLER.borrow(cs).set(Some((threshold_ticks, handler))))
+}
+
+/// Stop reporting long polls.
+pub fn clear_long_poll_handler() {
+    critical_section::with(|cs| LONG_POLL_HANDLER.borrow(cs).set(None))
+}
+
+/// A poll that took longer than the threshold.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct LongPoll {
+    /// Task ID, see [`TaskInfo::id`](super::introspection::TaskInfo::id).
+    pub id: usize,
+    /// Task name.
+    pub name: Option<&'static str>,
+    /// Duration of the poll, in [`embassy_time_driver::TICK_HZ`] ticks.
+    pub ticks: u64,
+}
+
+/// Executor CPU usage, returned by [`Executor::stats()`](super::Executor::stats).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExecutorStats {
+    /// Total time spent polling tasks, in [`embassy_time_driver::TICK_HZ`] ticks.
+    pub busy_ticks: u64,
+    /// Time since the executor was first polled, in [`embassy_time_driver::TICK_HZ`] ticks.
+    pub elapsed_ticks: u64,
+}
+
+impl ExecutorStats {
+    /// Return the usage between an `earlier` snapshot and this one.
+    pub fn since(&self, earlier: &ExecutorStats) -> ExecutorStats {
+        ExecutorStats {
+            busy_ticks: self.busy_ticks.saturating_sub(earlier.busy_ticks),
+            elapsed_ticks: self.elapsed_ticks.saturating_sub(earlier.elapsed_ticks),
+        }
+    }
+
+    /// Fraction of the time the executor was idle, between 0.0 and 1.0.
+    pub fn idle_ratio(&self) -> f32 {
+        if self.elapsed_ticks == 0 {
+            return 1.0;
+        }
+        let busy = self.busy_ticks.min(self.elapsed_ticks);
+        1.0 - busy as f32 / self.elapsed_ticks as f32
+    }
+}
+
+/// Get the long poll handler, once per executor `poll()`.
+pub(crate) fn long_poll_handler() -> Option<Handler> {
+    critical_section::with(|cs| LONG_POLL_HANDLER.borrow(cs).get())
+}
+
+/// Per-executor monitoring state.
+pub(crate) struct ExecutorMonitor {
+    started_at: Cell<Option<u64>>,
+    busy_ticks: Cell<u64>,
+}
+
+// Safety: the fields are only accessed by the context polling the executor: from `poll()`, and
+// from `stats()` through `Executor` and `Spawner`, which are neither `Send` nor `Sync`.
+unsafe impl Sync for ExecutorMonitor {}
+
+impl ExecutorMonitor {
+    pub(crate) const fn new() -> Self {
+        Self {
+            started_at: Cell::new(None),
+            busy_ticks: Cell::new(0),
+        }
+    }
+
+    /// Start counting elapsed time, on the first poll.
+    pub(crate) fn poll_begin(&self) {
+        if self.started_at.get().is_none() {
+            self.started_at.set(Some(embassy_time_driver::now()));
+        }
+    }
+
+    /// Account for a task poll that took `ticks`, reporting it to `handler` if it took too long.
+    pub(crate) fn task_polled(&self, task: TaskRef, ticks: u64, handler: Option<Handler>) {
+        self.busy_ticks.set(self.busy_ticks.get() + ticks);
+
+        if let Some((threshold, handler)) = handler {
+            if ticks > threshold {
+                handler(&LongPoll {
+                    id: task.as_ptr() as usize,
+                    name: introspection::name(task),
+                    ticks,
+                });
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> ExecutorStats {
+        ExecutorStats {
+            busy_ticks: self.busy_ticks.get(),
+            elapsed_ticks: self
+                .started_at
+                .get()
+                .map_or(0, |started_at| embassy_time_driver::now().saturating_sub(started_at)),
+        }
+    }
+}
@@ -67,21 +67,72 @@ impl RunQueue {
     /// Empty the queue, then call `on_task` for each task that was in the queue.
     /// NOTE: It is OK for `on_task` to enqueue more tasks. In this case they're left in the queue
     /// and will be processed by the *next* call to `dequeue_all`, *not* the current one.
+    #[cfg(not(any(feature = "task-priorities", feature = "shuffle-run-queue")))]
     pub(crate) fn dequeue_all(&self, on_task: impl Fn(TaskRef)) {
-        // Atomically empty the queue.
-        let ptr = self.head.swap(ptr::null_mut(), Ordering::AcqRel);
-
-        // safety: the pointer is either null or valid
-        let mut next = unsafe { NonNull::new(ptr).map(|ptr| TaskRef::from_ptr(ptr.as_ptr())) };
+        let mut next = self.take_all();
 
         // Iterate the linked list of tasks that were previously in the queue.
         while let Some(task) = next {
             // If the task re-enqueues itself, the `next` pointer will get overwritten.
             // Therefore, first read the next pointer, and only then process the task.
-            // safety: there are no concurrent accesses to `next`
-            next = unsafe { task.header().run_queue_item.next.get() };
+            next = Self::next(task);
 
             on_task(task);
         }
     }
+
+    /// Empty the queue, returning the first task of the batch that was in it.
+    pub(crate) fn take_all(&self) -> Option<TaskRef> {
+        // Atomically empty the queue.
+        let ptr = self.head.swap(ptr::null_mut(), Ordering::AcqRel);
+
+        // safety: the pointer is either null or valid
+        unsafe { NonNull::new(ptr).map(|ptr| TaskRef::from_ptr(ptr.as_ptr())) }
+    }
+
+    /// Get the task following `task` in a batch returned by `take_all`.
+    pub(crate) fn next(task: TaskRef) -> Option<TaskRef> {
+        // safety: there are no concurrent accesses to `next`
+        unsafe { task.header().run_queue_item.next.get() }
+    }
+
+    /// Set the task following `task` in a batch returned by `take_all`.
+    ///
+    /// # Safety
+    ///
+    /// `task` must be part of a batch taken from this queue, and not processed yet.
+    #[cfg(feature = "shuffle-run-queue")]
+    pub(crate) unsafe fn set_next(task: TaskRef, next: Option<TaskRef>) {
+        task.header().run_queue_item.next.set(next)
+    }
+
+    /// Return whether the queue is empty.
+    #[cfg(feature = "task-priorities")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    /// Put back the unprocessed rest of a batch returned by `take_all`, so that it's dequeued
+    /// before the tasks enqueued since.
+    ///
+    /// # Safety
+    ///
+    /// `batch` must be the unprocessed rest of a batch taken from this queue.
+    #[cfg(feature = "task-priorities")]
+    pub(crate) unsafe fn requeue(&self, batch: TaskRef) {
+        let mut tail = batch;
+        while let Some(next) = Self::next(tail) {
+            tail = next;
+        }
+
+        self.head
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |prev| {
+                // safety: the pointer is either null or valid
+                let prev = NonNull::new(prev).map(|ptr| TaskRef::from_ptr(ptr.as_ptr()));
+                // safety: there are no concurrent accesses to `next`
+                tail.header().run_queue_item.next.set(prev);
+                Some(batch.as_ptr() as *mut _)
+            })
+            .ok();
+    }
 }
@@ -56,20 +56,67 @@ impl RunQueue {
     /// Empty the queue, then call `on_task` for each task that was in the queue.
     /// NOTE: It is OK for `on_task` to enqueue more tasks. In this case they're left in the queue
     /// and will be processed by the *next* call to `dequeue_all`, *not* the current one.
+    #[cfg(not(any(feature = "task-priorities", feature = "shuffle-run-queue")))]
     pub(crate) fn dequeue_all(&self, on_task: impl Fn(TaskRef)) {
-        // Atomically empty the queue.
-        let mut next = critical_section::with(|cs| self.head.borrow(cs).take());
+        let mut next = self.take_all();
 
         // Iterate the linked list of tasks that were previously in the queue.
         while let Some(task) = next {
             // If the task re-enqueues itself, the `next` pointer will get overwritten.
             // Therefore, first read the next pointer, and only then process the task.
-
-            // safety: we know if the task is enqueued, no one else will touch the `next` pointer.
-            let cs = unsafe { CriticalSection::new() };
-            next = task.header().run_queue_item.next.borrow(cs).get();
+            next = Self::next(task);
 
             on_task(task);
         }
     }
+
+    /// Empty the queue, returning the first task of the batch that was in it.
+    pub(crate) fn take_all(&self) -> Option<TaskRef> {
+        // Atomically empty the queue.
+        critical_section::with(|cs| self.head.borrow(cs).take())
+    }
+
+    /// Get the task following `task` in a batch returned by `take_all`.
+    pub(crate) fn next(task: TaskRef) -> Option<TaskRef> {
+        // safety: we know if the task is enqueued, no one else will touch the `next` pointer.
+        let cs = unsafe { CriticalSection::new() };
+        task.header().run_queue_item.next.borrow(cs).get()
+    }
+
+    /// Set the task following `task` in a batch returned by `take_all`.
+    ///
+    /// # Safety
+    ///
+    /// `task` must be part of a batch taken from this queue, and not processed yet.
+    #[cfg(feature = "shuffle-run-queue")]
+    pub(crate) unsafe fn set_next(task: TaskRef, next: Option<TaskRef>) {
+        // safety: we know if the task is enqueued, no one else will touch the `next` pointer.
+        let cs = CriticalSection::new();
+        task.header().run_queue_item.next.borrow(cs).set(next)
+    }
+
+    /// Return whether the queue is empty.
+    #[cfg(feature = "task-priorities")]
+    pub(crate) fn is_empty(&self) -> bool {
+        critical_section::with(|cs| self.head.borrow(cs).get().is_none())
+    }
+
+    /// Put back the unprocessed rest of a batch returned by `take_all`, so that it's dequeued
+    /// before the tasks enqueued since.
+    ///
+    /// # Safety
+    ///
+    /// `batch` must be the unprocessed rest of a batch taken from this queue.
+    #[cfg(feature = "task-priorities")]
+    pub(crate) unsafe fn requeue(&self, batch: TaskRef) {
+        let mut tail = batch;
+        while let Some(next) = Self::next(tail) {
+            tail = next;
+        }
+
+        critical_section::with(|cs| {
+            let prev = self.head.borrow(cs).replace(Some(batch));
+            tail.header().run_queue_item.next.borrow(cs).set(prev);
+        })
+    }
 }
@@ -0,0 +1,87 @@
+use super::run_queue::RunQueue;
+use super::TaskRef;
+
+/// Number of task priority levels.
+///
+/// Priorities go from 0, the lowest and the default, to `PRIORITY_LEVELS - 1`.
+pub const PRIORITY_LEVELS: usize = 8;
+
+/// Task queue with one [`RunQueue`] per priority level.
+///
+/// Batches are dequeued from the highest priority level first. If a task gets enqueued at a
+/// level whose batch was already taken while lower priority tasks are still waiting to be
+/// processed, those are put back in their queues and `dequeue_all` returns early. Enqueuing
+/// the task pended the executor, so the next `dequeue_all` processes it first.
+///
+/// Within a level, batches are processed exactly as in a plain [`RunQueue`].
+pub(crate) struct PriorityRunQueue {
+    levels: [RunQueue; PRIORITY_LEVELS],
+}
+
+impl PriorityRunQueue {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: RunQueue = RunQueue::new();
+
+        Self {
+            levels: [EMPTY; PRIORITY_LEVELS],
+        }
+    }
+
+    /// Enqueues an item at its priority level. Returns true if that level was empty.
+    ///
+    /// # Safety
+    ///
+    /// `item` must NOT be already enqueued in any queue.
+    #[inline(always)]
+    pub(crate) unsafe fn enqueue(&self, task: TaskRef) -> bool {
+        let level = task.header().priority.get() as usize;
+        self.levels[level].enqueue(task)
+    }
+
+    /// Empty the queues, then call `on_task` for each task that was in them, highest priority first.
+    /// NOTE: It is OK for `on_task` to enqueue more tasks. Like with [`RunQueue`], they're left in
+    /// the queue and will be processed by the *next* call to `dequeue_all`, *not* the current one.
+    pub(crate) fn dequeue_all(&self, on_task: impl Fn(TaskRef)) {
+        let mut batches: [Option<TaskRef>; PRIORITY_LEVELS] = [None; PRIORITY_LEVELS];
+        let mut taken = [false; PRIORITY_LEVELS];
+
+        loop {
+            let mut current = None;
+            for level in (0..PRIORITY_LEVELS).rev() {
+                if batches[level].is_some() {
+                    current = Some(level);
+                    break;
+                }
+                if self.levels[level].is_empty() {
+                    continue;
+                }
+                if taken[level] {
+                    // A task was enqueued at this level during this call. Don't process any
+                    // lower priority task before the next call has processed it.
+                    for (queue, batch) in self.levels.iter().zip(batches).take(level) {
+                        if let Some(batch) = batch {
+                            unsafe { queue.requeue(batch) };
+                        }
+                    }
+                    return;
+                }
+
+                batches[level] = self.levels[level].take_all();
+                taken[level] = true;
+                current = Some(level);
+                break;
+            }
+
+            let Some(level) = current else {
+                return;
+            };
+            let task = unwrap!(batches[level]);
+            // If the task re-enqueues itself, the `next` pointer will get overwritten.
+            // Therefore, first read the next pointer, and only then process the task.
+            batches[level] = RunQueue::next(task);
+
+            on_task(task);
+        }
+    }
+}
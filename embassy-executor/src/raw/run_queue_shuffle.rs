@@ -0,0 +1,95 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use super::run_queue::RunQueue;
+use super::TaskRef;
+
+/// Seed used until [`Executor::set_shuffle_seed()`](super::Executor::set_shuffle_seed) is called.
+const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Task queue processing each batch in a pseudo-random order.
+///
+/// This is a testing aid: the order tasks run in is an implementation detail, and code that
+/// relies on it has a race condition. Shuffling with a seeded generator makes such races show
+/// up, while still letting a failing order be reproduced by reusing the seed.
+///
+/// Apart from the order within a batch, batches are processed exactly as in a plain [`RunQueue`].
+pub(crate) struct ShuffleRunQueue {
+    queue: RunQueue,
+    rng: Mutex<Cell<u64>>,
+}
+
+impl ShuffleRunQueue {
+    pub const fn new() -> Self {
+        Self {
+            queue: RunQueue::new(),
+            rng: Mutex::new(Cell::new(DEFAULT_SEED)),
+        }
+    }
+
+    /// Restart the pseudo-random sequence from `seed`.
+    pub(crate) fn set_seed(&self, seed: u64) {
+        // xorshift gets stuck at zero.
+        let seed = if seed == 0 { DEFAULT_SEED } else { seed };
+        critical_section::with(|cs| self.rng.borrow(cs).set(seed))
+    }
+
+    fn random(&self) -> u64 {
+        critical_section::with(|cs| {
+            let rng = self.rng.borrow(cs);
+            // xorshift64*
+            let mut x = rng.get();
+            x ^= x >> 12;
+            x ^= x << 25;
+            x ^= x >> 27;
+            rng.set(x);
+            x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        })
+    }
+
+    /// Enqueues an item. Returns true if the queue was empty.
+    ///
+    /// # Safety
+    ///
+    /// `item` must NOT be already enqueued in any queue.
+    #[inline(always)]
+    pub(crate) unsafe fn enqueue(&self, task: TaskRef) -> bool {
+        self.queue.enqueue(task)
+    }
+
+    /// Empty the queue, then call `on_task` for each task that was in the queue, in a random order.
+    /// NOTE: It is OK for `on_task` to enqueue more tasks. Like with [`RunQueue`], they're left in
+    /// the queue and will be processed by the *next* call to `dequeue_all`, *not* the current one.
+    pub(crate) fn dequeue_all(&self, on_task: impl Fn(TaskRef)) {
+        let mut batch = self.queue.take_all();
+        let mut len = 0;
+        let mut next = batch;
+        while let Some(task) = next {
+            next = RunQueue::next(task);
+            len += 1;
+        }
+
+        while let Some(head) = batch {
+            // Unlink a random task from the batch before processing it. The tasks left in the
+            // batch are still queued, so they can't be enqueued again and their `next` pointers
+            // stay valid.
+            let index = (self.random() % len) as usize;
+            let task = if index == 0 {
+                batch = RunQueue::next(head);
+                head
+            } else {
+                let mut prev = head;
+                for _ in 1..index {
+                    prev = unwrap!(RunQueue::next(prev));
+                }
+                let task = unwrap!(RunQueue::next(prev));
+                unsafe { RunQueue::set_next(prev, RunQueue::next(task)) };
+                task
+            };
+            len -= 1;
+
+            on_task(task);
+        }
+    }
+}
@@ -7,6 +7,15 @@ pub(crate) const STATE_RUN_QUEUED: u32 = 1 << 1;
 /// Task is in the executor timer queue
 #[cfg(feature = "integrated-timers")]
 pub(crate) const STATE_TIMER_QUEUED: u32 = 1 << 2;
+/// A `JoinHandle` to the task exists
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_JOIN_HANDLE: u32 = 1 << 3;
+/// Task has finished, its output is kept for the `JoinHandle`
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_FINISHED: u32 = 1 << 4;
+/// Task has been asked to abort, or was aborted if finished
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_ABORTED: u32 = 1 << 5;
 
 pub(crate) struct State {
     state: AtomicU32,
@@ -27,12 +36,107 @@ impl State {
             .is_ok()
     }
 
+    /// Mark the task as having a join handle. Must be called before the task is first polled.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn add_join_handle(&self) {
+        self.state.fetch_or(STATE_JOIN_HANDLE, Ordering::AcqRel);
+    }
+
     /// Unmark the task as spawned.
+    #[cfg(not(feature = "join-handles"))]
     #[inline(always)]
     pub fn despawn(&self) {
         self.state.fetch_and(!STATE_SPAWNED, Ordering::AcqRel);
     }
 
+    /// Unmark the task as spawned (and aborted).
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn despawn(&self) {
+        self.state.fetch_and(!(STATE_SPAWNED | STATE_ABORTED), Ordering::AcqRel);
+    }
+
+    /// If the task has a join handle, unmark it as spawned, mark it as finished and return true.
+    /// Otherwise leave the state untouched and return false.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finish(&self, aborted: bool) -> bool {
+        let aborted = if aborted { STATE_ABORTED } else { 0 };
+        self.state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                if state & STATE_JOIN_HANDLE == 0 {
+                    None
+                } else {
+                    Some((state & !(STATE_SPAWNED | STATE_ABORTED)) | STATE_FINISHED | aborted)
+                }
+            })
+            .is_ok()
+    }
+
+    /// If the task is finished, return whether it was aborted.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finished(&self) -> Option<bool> {
+        let state = self.state.load(Ordering::Acquire);
+        (state & STATE_FINISHED != 0).then_some(state & STATE_ABORTED != 0)
+    }
+
+    /// Mark the task as aborted if it's spawned and isn't already aborted. Return true on success.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn abort(&self) -> bool {
+        self.state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                if (state & STATE_ABORTED != 0) || (state & STATE_SPAWNED == 0) {
+                    None
+                } else {
+                    Some(state | STATE_ABORTED)
+                }
+            })
+            .is_ok()
+    }
+
+    /// Return whether the task has been asked to abort.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn is_aborted(&self) -> bool {
+        self.state.load(Ordering::Acquire) & STATE_ABORTED != 0
+    }
+
+    /// Unmark the task as having a join handle if it isn't finished yet and return `None`.
+    /// If it is finished, leave the state untouched and return whether it was aborted, the
+    /// caller must then dispose of the output and call `release`.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn drop_join_handle(&self) -> Option<bool> {
+        match self.state.fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+            if state & STATE_FINISHED != 0 {
+                None
+            } else {
+                Some(state & !STATE_JOIN_HANDLE)
+            }
+        }) {
+            Ok(_) => None,
+            Err(state) => Some(state & STATE_ABORTED != 0),
+        }
+    }
+
+    /// Unmark the finished task as having a join handle, freeing it.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn release(&self) {
+        self.state
+            .fetch_and(!(STATE_JOIN_HANDLE | STATE_FINISHED | STATE_ABORTED), Ordering::AcqRel);
+    }
+
+    /// Return the raw state bits.
+    #[cfg(feature = "introspection")]
+    #[inline(always)]
+    pub fn load(&self) -> u32 {
+        self.state.load(Ordering::Acquire)
+    }
+
     /// Mark the task as run-queued if it's spawned and isn't already run-queued. Return true on success.
     #[inline(always)]
     pub fn run_enqueue(&self) -> bool {
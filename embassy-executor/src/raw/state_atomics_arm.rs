@@ -1,9 +1,15 @@
 use core::arch::asm;
-use core::sync::atomic::{compiler_fence, AtomicBool, AtomicU32, Ordering};
+use core::sync::atomic::{compiler_fence, AtomicBool, AtomicU32, AtomicU8, Ordering};
 
 // Must be kept in sync with the layout of `State`!
 pub(crate) const STATE_SPAWNED: u32 = 1 << 0;
 pub(crate) const STATE_RUN_QUEUED: u32 = 1 << 8;
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_JOIN_HANDLE: u32 = 1 << 24;
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_FINISHED: u32 = 1 << 25;
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_ABORTED: u32 = 1 << 26;
 
 #[repr(C, align(4))]
 pub(crate) struct State {
@@ -13,7 +19,9 @@ pub(crate) struct State {
     run_queued: AtomicBool,
     /// Task is in the executor timer queue
     timer_queued: AtomicBool,
-    pad: AtomicBool,
+    /// `JoinHandle` flags, see `STATE_JOIN_HANDLE`, `STATE_FINISHED` and `STATE_ABORTED`
+    #[cfg_attr(not(feature = "join-handles"), allow(dead_code))]
+    join: AtomicU8,
 }
 
 impl State {
@@ -22,7 +30,7 @@ impl State {
             spawned: AtomicBool::new(false),
             run_queued: AtomicBool::new(false),
             timer_queued: AtomicBool::new(false),
-            pad: AtomicBool::new(false),
+            join: AtomicU8::new(0),
         }
     }
 
@@ -47,13 +55,126 @@ impl State {
         r
     }
 
+    /// Mark the task as having a join handle. Must be called before the task is first polled.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn add_join_handle(&self) {
+        compiler_fence(Ordering::Release);
+        self.as_u32().fetch_or(STATE_JOIN_HANDLE, Ordering::Relaxed);
+    }
+
     /// Unmark the task as spawned.
+    #[cfg(not(feature = "join-handles"))]
     #[inline(always)]
     pub fn despawn(&self) {
         compiler_fence(Ordering::Release);
         self.spawned.store(false, Ordering::Relaxed);
     }
 
+    /// Unmark the task as spawned (and aborted).
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn despawn(&self) {
+        compiler_fence(Ordering::Release);
+        // A concurrent `abort` may set the aborted flag, so clear both flags at once.
+        self.as_u32()
+            .fetch_and(!(STATE_SPAWNED | STATE_ABORTED), Ordering::Relaxed);
+    }
+
+    /// If the task has a join handle, unmark it as spawned, mark it as finished and return true.
+    /// Otherwise leave the state untouched and return false.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finish(&self, aborted: bool) -> bool {
+        let aborted = if aborted { STATE_ABORTED } else { 0 };
+        compiler_fence(Ordering::Release);
+        let r = self
+            .as_u32()
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                if state & STATE_JOIN_HANDLE == 0 {
+                    None
+                } else {
+                    Some((state & !(STATE_SPAWNED | STATE_ABORTED)) | STATE_FINISHED | aborted)
+                }
+            })
+            .is_ok();
+        compiler_fence(Ordering::Acquire);
+        r
+    }
+
+    /// If the task is finished, return whether it was aborted.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finished(&self) -> Option<bool> {
+        let join = self.join.load(Ordering::Relaxed) as u32;
+        compiler_fence(Ordering::Acquire);
+        (join & (STATE_FINISHED >> 24) != 0).then_some(join & (STATE_ABORTED >> 24) != 0)
+    }
+
+    /// Mark the task as aborted if it's spawned and isn't already aborted. Return true on success.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn abort(&self) -> bool {
+        compiler_fence(Ordering::Release);
+        let r = self
+            .as_u32()
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                if (state & STATE_ABORTED != 0) || (state & STATE_SPAWNED == 0) {
+                    None
+                } else {
+                    Some(state | STATE_ABORTED)
+                }
+            })
+            .is_ok();
+        compiler_fence(Ordering::Acquire);
+        r
+    }
+
+    /// Return whether the task has been asked to abort.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn is_aborted(&self) -> bool {
+        self.join.load(Ordering::Relaxed) & (STATE_ABORTED >> 24) as u8 != 0
+    }
+
+    /// Unmark the task as having a join handle if it isn't finished yet and return `None`.
+    /// If it is finished, leave the state untouched and return whether it was aborted, the
+    /// caller must then dispose of the output and call `release`.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn drop_join_handle(&self) -> Option<bool> {
+        compiler_fence(Ordering::Release);
+        let r = match self
+            .as_u32()
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                if state & STATE_FINISHED != 0 {
+                    None
+                } else {
+                    Some(state & !STATE_JOIN_HANDLE)
+                }
+            }) {
+            Ok(_) => None,
+            Err(state) => Some(state & STATE_ABORTED != 0),
+        };
+        compiler_fence(Ordering::Acquire);
+        r
+    }
+
+    /// Unmark the finished task as having a join handle, freeing it.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn release(&self) {
+        compiler_fence(Ordering::Release);
+        self.join.store(0, Ordering::Relaxed);
+    }
+
+    /// Return the raw state bits.
+    #[cfg(feature = "introspection")]
+    #[inline(always)]
+    pub fn load(&self) -> u32 {
+        self.as_u32().load(Ordering::Relaxed)
+    }
+
     /// Mark the task as run-queued if it's spawned and isn't already run-queued. Return true on success.
     #[inline(always)]
     pub fn run_enqueue(&self) -> bool {
@@ -9,6 +9,15 @@ pub(crate) const STATE_RUN_QUEUED: u32 = 1 << 1;
 /// Task is in the executor timer queue
 #[cfg(feature = "integrated-timers")]
 pub(crate) const STATE_TIMER_QUEUED: u32 = 1 << 2;
+/// A `JoinHandle` to the task exists
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_JOIN_HANDLE: u32 = 1 << 3;
+/// Task has finished, its output is kept for the `JoinHandle`
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_FINISHED: u32 = 1 << 4;
+/// Task has been asked to abort, or was aborted if finished
+#[cfg(feature = "join-handles")]
+pub(crate) const STATE_ABORTED: u32 = 1 << 5;
 
 pub(crate) struct State {
     state: Mutex<Cell<u32>>,
@@ -44,12 +53,104 @@ impl State {
         })
     }
 
+    /// Mark the task as having a join handle. Must be called before the task is first polled.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn add_join_handle(&self) {
+        self.update(|s| *s |= STATE_JOIN_HANDLE);
+    }
+
     /// Unmark the task as spawned.
+    #[cfg(not(feature = "join-handles"))]
     #[inline(always)]
     pub fn despawn(&self) {
         self.update(|s| *s &= !STATE_SPAWNED);
     }
 
+    /// Unmark the task as spawned (and aborted).
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn despawn(&self) {
+        self.update(|s| *s &= !(STATE_SPAWNED | STATE_ABORTED));
+    }
+
+    /// If the task has a join handle, unmark it as spawned, mark it as finished and return true.
+    /// Otherwise leave the state untouched and return false.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finish(&self, aborted: bool) -> bool {
+        self.update(|s| {
+            if *s & STATE_JOIN_HANDLE == 0 {
+                false
+            } else {
+                *s &= !(STATE_SPAWNED | STATE_ABORTED);
+                *s |= STATE_FINISHED;
+                if aborted {
+                    *s |= STATE_ABORTED;
+                }
+                true
+            }
+        })
+    }
+
+    /// If the task is finished, return whether it was aborted.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn finished(&self) -> Option<bool> {
+        self.update(|s| (*s & STATE_FINISHED != 0).then_some(*s & STATE_ABORTED != 0))
+    }
+
+    /// Mark the task as aborted if it's spawned and isn't already aborted. Return true on success.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn abort(&self) -> bool {
+        self.update(|s| {
+            if (*s & STATE_ABORTED != 0) || (*s & STATE_SPAWNED == 0) {
+                false
+            } else {
+                *s |= STATE_ABORTED;
+                true
+            }
+        })
+    }
+
+    /// Return whether the task has been asked to abort.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn is_aborted(&self) -> bool {
+        self.update(|s| *s & STATE_ABORTED != 0)
+    }
+
+    /// Unmark the task as having a join handle if it isn't finished yet and return `None`.
+    /// If it is finished, leave the state untouched and return whether it was aborted, the
+    /// caller must then dispose of the output and call `release`.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn drop_join_handle(&self) -> Option<bool> {
+        self.update(|s| {
+            if *s & STATE_FINISHED != 0 {
+                Some(*s & STATE_ABORTED != 0)
+            } else {
+                *s &= !STATE_JOIN_HANDLE;
+                None
+            }
+        })
+    }
+
+    /// Unmark the finished task as having a join handle, freeing it.
+    #[cfg(feature = "join-handles")]
+    #[inline(always)]
+    pub fn release(&self) {
+        self.update(|s| *s &= !(STATE_JOIN_HANDLE | STATE_FINISHED | STATE_ABORTED));
+    }
+
+    /// Return the raw state bits.
+    #[cfg(feature = "introspection")]
+    #[inline(always)]
+    pub fn load(&self) -> u32 {
+        self.update(|s| *s)
+    }
+
     /// Mark the task as run-queued if it's spawned and isn't already run-queued. Return true on success.
     #[inline(always)]
     pub fn run_enqueue(&self) -> bool {
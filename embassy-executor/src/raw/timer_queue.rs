@@ -30,6 +30,7 @@ impl TimerQueue {
         let task = p.header();
         if task.expires_at.get() != u64::MAX {
             if task.state.timer_enqueue() {
+                p.acquire();
                 task.timer_queue_item.next.set(self.head.get());
                 self.head.set(Some(p));
             }
@@ -70,6 +71,7 @@ impl TimerQueue {
                 // Remove it
                 prev.set(task.timer_queue_item.next.get());
                 task.state.timer_dequeue();
+                p.drop_ref();
             }
         }
     }
@@ -1,6 +1,13 @@
+#[cfg(any(feature = "join-handles", all(feature = "alloc", not(target_has_atomic = "ptr"))))]
+use core::cell::Cell;
 use core::cell::UnsafeCell;
 use core::mem::MaybeUninit;
 use core::ptr;
+#[cfg(feature = "join-handles")]
+use core::task::Waker;
+
+#[cfg(any(feature = "join-handles", all(feature = "alloc", not(target_has_atomic = "ptr"))))]
+use critical_section::Mutex;
 
 pub(crate) struct UninitCell<T>(MaybeUninit<UnsafeCell<T>>);
 impl<T> UninitCell<T> {
@@ -55,3 +62,104 @@ impl<T> SyncUnsafeCell<T> {
         *self.value.get()
     }
 }
+
+/// Waker slot that can be registered and woken from any context.
+#[cfg(feature = "join-handles")]
+pub(crate) struct AtomicWaker {
+    waker: Mutex<Cell<Option<Waker>>>,
+}
+
+#[cfg(feature = "join-handles")]
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            waker: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Register a waker, replacing the previous one.
+    pub fn register(&self, w: &Waker) {
+        critical_section::with(|cs| {
+            let cell = self.waker.borrow(cs);
+            let waker = match cell.take() {
+                Some(waker) if waker.will_wake(w) => waker,
+                _ => w.clone(),
+            };
+            cell.set(Some(waker));
+        })
+    }
+
+    /// Wake the registered waker, if any.
+    pub fn wake(&self) {
+        if let Some(waker) = critical_section::with(|cs| self.waker.borrow(cs).take()) {
+            waker.wake();
+        }
+    }
+}
+
+/// Reference count of a heap-allocated task.
+#[cfg(feature = "alloc")]
+pub(crate) struct RefCount {
+    #[cfg(target_has_atomic = "ptr")]
+    count: core::sync::atomic::AtomicUsize,
+    #[cfg(not(target_has_atomic = "ptr"))]
+    count: Mutex<Cell<usize>>,
+}
+
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+impl RefCount {
+    pub const fn new() -> Self {
+        Self {
+            count: core::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set(&self, count: usize) {
+        self.count.store(count, core::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn increment(&self) {
+        self.count.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Decrement the count, returning true if it dropped to zero.
+    pub fn decrement(&self) -> bool {
+        use core::sync::atomic::{fence, Ordering};
+
+        if self.count.fetch_sub(1, Ordering::Release) != 1 {
+            return false;
+        }
+        // Make sure all uses of the task happen before it's freed.
+        fence(Ordering::Acquire);
+        true
+    }
+}
+
+#[cfg(all(feature = "alloc", not(target_has_atomic = "ptr")))]
+impl RefCount {
+    pub const fn new() -> Self {
+        Self {
+            count: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    pub fn set(&self, count: usize) {
+        critical_section::with(|cs| self.count.borrow(cs).set(count))
+    }
+
+    pub fn increment(&self) {
+        critical_section::with(|cs| {
+            let count = self.count.borrow(cs);
+            count.set(count.get() + 1)
+        })
+    }
+
+    /// Decrement the count, returning true if it dropped to zero.
+    pub fn decrement(&self) -> bool {
+        critical_section::with(|cs| {
+            let count = self.count.borrow(cs);
+            count.set(count.get() - 1);
+            count.get() == 0
+        })
+    }
+}
@@ -5,6 +5,10 @@ use super::{wake_task, TaskHeader, TaskRef};
 
 static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
 
+/// Vtable for heap-allocated tasks, where each waker clone keeps the task alive.
+#[cfg(feature = "alloc")]
+static COUNTED_VTABLE: RawWakerVTable = RawWakerVTable::new(counted_clone, counted_wake, wake, counted_drop);
+
 unsafe fn clone(p: *const ()) -> RawWaker {
     RawWaker::new(p, &VTABLE)
 }
@@ -17,7 +21,32 @@ unsafe fn drop(_: *const ()) {
     // nop
 }
 
+#[cfg(feature = "alloc")]
+unsafe fn counted_clone(p: *const ()) -> RawWaker {
+    TaskRef::from_ptr(p as *const TaskHeader).acquire();
+    RawWaker::new(p, &COUNTED_VTABLE)
+}
+
+#[cfg(feature = "alloc")]
+unsafe fn counted_wake(p: *const ()) {
+    let task = TaskRef::from_ptr(p as *const TaskHeader);
+    wake_task(task);
+    task.drop_ref();
+}
+
+#[cfg(feature = "alloc")]
+unsafe fn counted_drop(p: *const ()) {
+    TaskRef::from_ptr(p as *const TaskHeader).drop_ref();
+}
+
+/// Create a waker for a task being polled.
+///
+/// The waker doesn't hold a reference on the task, it must be forgotten instead of dropped.
 pub(crate) unsafe fn from_task(p: TaskRef) -> Waker {
+    #[cfg(feature = "alloc")]
+    if p.is_boxed() {
+        return Waker::from_raw(RawWaker::new(p.as_ptr() as _, &COUNTED_VTABLE));
+    }
     Waker::from_raw(RawWaker::new(p.as_ptr() as _, &VTABLE))
 }
 
@@ -29,6 +58,9 @@ pub(crate) unsafe fn from_task(p: TaskRef) -> Waker {
 ///
 /// You can use the returned task pointer to wake the task with [`wake_task`](super::wake_task).
 ///
+/// For tasks spawned with `Spawner::spawn_boxed()` (`alloc` feature), the task
+/// pointer is only valid as long as `waker` or a clone of it is alive.
+///
 /// # Panics
 ///
 /// Panics if the waker is not created by the Embassy executor.
@@ -38,7 +70,11 @@ pub fn task_from_waker(waker: &Waker) -> TaskRef {
     // indeed the case in the current implementation.
     // TODO use waker_getters when stable. https://github.com/rust-lang/rust/issues/96992
     let hack: &WakerHack = unsafe { mem::transmute(waker) };
-    if hack.vtable != &VTABLE {
+    #[cfg(feature = "alloc")]
+    let known = hack.vtable == &VTABLE || hack.vtable == &COUNTED_VTABLE;
+    #[cfg(not(feature = "alloc"))]
+    let known = hack.vtable == &VTABLE;
+    if !known {
         panic!("Found waker not created by the Embassy executor. `embassy_time::Timer` only works with the Embassy executor.")
     }
 
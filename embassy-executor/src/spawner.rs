@@ -1,7 +1,15 @@
 use core::future::poll_fn;
+#[cfg(any(feature = "join-handles", feature = "alloc"))]
+use core::future::Future;
 use core::marker::PhantomData;
 use core::mem;
+#[cfg(feature = "join-handles")]
+use core::mem::MaybeUninit;
+#[cfg(feature = "join-handles")]
+use core::pin::Pin;
 use core::task::Poll;
+#[cfg(feature = "join-handles")]
+use core::task::{ready, Context};
 
 use super::raw;
 
@@ -15,21 +23,26 @@ use super::raw;
 /// in other threads or not. If `S: Send`, it can, which allows spawning it into a [`SendSpawner`].
 /// If not, it can't, so it can only be spawned into the current thread's executor, with [`Spawner`].
 ///
+/// The generic parameter `T` is the return type of the task. With the `join-handles` feature, you
+/// can get it by awaiting the `JoinHandle` returned by `Spawner::spawn_joinable()`.
+///
 /// # Panics
 ///
 /// Dropping a SpawnToken instance panics. You may not "abort" spawning a task in this way.
 /// Once you've invoked a task function and obtained a SpawnToken, you *must* spawn it.
 #[must_use = "Calling a task function does nothing on its own. You must spawn the returned SpawnToken, typically with Spawner::spawn()"]
-pub struct SpawnToken<S> {
+pub struct SpawnToken<S, T = ()> {
     raw_task: Option<raw::TaskRef>,
     phantom: PhantomData<*mut S>,
+    output: PhantomData<fn() -> T>,
 }
 
-impl<S> SpawnToken<S> {
+impl<S, T> SpawnToken<S, T> {
     pub(crate) unsafe fn new(raw_task: raw::TaskRef) -> Self {
         Self {
             raw_task: Some(raw_task),
             phantom: PhantomData,
+            output: PhantomData,
         }
     }
 
@@ -38,11 +51,53 @@ impl<S> SpawnToken<S> {
         Self {
             raw_task: None,
             phantom: PhantomData,
+            output: PhantomData,
+        }
+    }
+
+    /// Forget the output type of a task that never returns, so that it can be spawned with
+    /// [`Spawner::spawn()`]. Used by the `task` macro for tasks returning `!`.
+    ///
+    /// # Safety
+    ///
+    /// The task must never finish.
+    #[doc(hidden)]
+    pub unsafe fn __never_returns(self) -> SpawnToken<S> {
+        let raw_task = self.raw_task;
+        mem::forget(self);
+        SpawnToken {
+            raw_task,
+            phantom: PhantomData,
+            output: PhantomData,
+        }
+    }
+
+    /// Set the priority of the task.
+    ///
+    /// Ready tasks are always polled before ready tasks of a lower priority in the same
+    /// executor. Priorities go from 0, the lowest and the default, to
+    /// [`PRIORITY_LEVELS`](raw::PRIORITY_LEVELS)` - 1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `priority` is not lower than [`PRIORITY_LEVELS`](raw::PRIORITY_LEVELS). The task
+    /// is then never spawned, and its storage stays claimed.
+    #[cfg(feature = "task-priorities")]
+    pub fn with_priority(self, priority: u8) -> Self {
+        if priority as usize >= raw::PRIORITY_LEVELS {
+            // Dropping the token would panic again while unwinding.
+            mem::forget(self);
+            panic!("task priority out of range");
+        }
+        if let Some(task) = self.raw_task {
+            // safety: the task is not spawned in an executor yet, so nothing reads the priority.
+            unsafe { task.header().priority.set(priority) };
         }
+        self
     }
 }
 
-impl<S> Drop for SpawnToken<S> {
+impl<S, T> Drop for SpawnToken<S, T> {
     fn drop(&mut self) {
         // TODO deallocate the task instead.
         panic!("SpawnToken instances may not be dropped. You must pass them to Spawner::spawn()")
@@ -61,6 +116,129 @@ pub enum SpawnError {
     Busy,
 }
 
+/// Handle to a spawned task, returned by [`Spawner::spawn_joinable()`].
+///
+/// Awaiting a `JoinHandle` waits for the task to finish and returns the value it returned.
+///
+/// Dropping a `JoinHandle` detaches the task: it keeps running, and its return value is dropped
+/// when it finishes. The task's storage is only freed once the task has finished *and* its
+/// `JoinHandle` has been dropped, so a task whose handle is kept around can't be spawned again
+/// in the meantime.
+#[cfg(feature = "join-handles")]
+pub struct JoinHandle<T> {
+    raw: RawJoinHandle,
+    phantom: PhantomData<T>,
+}
+
+#[cfg(feature = "join-handles")]
+impl<T> JoinHandle<T> {
+    fn new(raw_task: raw::TaskRef) -> Self {
+        Self {
+            raw: RawJoinHandle { raw_task, done: false },
+            phantom: PhantomData,
+        }
+    }
+
+    /// Abort the task.
+    ///
+    /// The task's future is dropped the next time the executor would poll it, even if it
+    /// isn't woken otherwise. Awaiting the handle then returns [`JoinError::Aborted`].
+    ///
+    /// This does nothing if the task has already finished.
+    pub fn abort(&self) {
+        self.raw.abort()
+    }
+
+    /// Return whether the task has finished, either by returning or by being aborted.
+    pub fn is_finished(&self) -> bool {
+        self.raw.done || self.raw.raw_task.header().state.finished().is_some()
+    }
+
+    /// Erase the output type, for handles that are only used to wait for the task to finish.
+    #[cfg(feature = "supervisor")]
+    pub(crate) fn into_raw(self) -> RawJoinHandle {
+        self.raw
+    }
+}
+
+#[cfg(feature = "join-handles")]
+impl<T> Unpin for JoinHandle<T> {}
+
+#[cfg(feature = "join-handles")]
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        assert!(!self.raw.done, "JoinHandle polled after completion");
+
+        let aborted = ready!(self.raw.poll_finished(cx));
+        self.raw.done = true;
+        if aborted {
+            return Poll::Ready(Err(JoinError::Aborted));
+        }
+
+        let task = self.raw.raw_task;
+        let mut output = MaybeUninit::<T>::uninit();
+        unsafe {
+            task.header().output_fn.get().unwrap_unchecked()(task, output.as_mut_ptr() as *mut ());
+            Poll::Ready(Ok(output.assume_init()))
+        }
+    }
+}
+
+/// Type-erased part of a [`JoinHandle`], which can wait for the task but not get its output.
+#[cfg(feature = "join-handles")]
+pub(crate) struct RawJoinHandle {
+    raw_task: raw::TaskRef,
+    /// The output has been taken, or there was none.
+    done: bool,
+}
+
+#[cfg(feature = "join-handles")]
+impl RawJoinHandle {
+    pub(crate) fn abort(&self) {
+        if self.raw_task.header().state.abort() {
+            raw::wake_task(self.raw_task);
+        }
+    }
+
+    /// Wait for the task to finish, returning whether it was aborted.
+    ///
+    /// The output is left in place, it is dropped with the handle unless `done` is set.
+    pub(crate) fn poll_finished(&self, cx: &mut Context<'_>) -> Poll<bool> {
+        let header = self.raw_task.header();
+        header.join_waker.register(cx.waker());
+        match header.state.finished() {
+            None => Poll::Pending,
+            Some(aborted) => Poll::Ready(aborted),
+        }
+    }
+}
+
+#[cfg(feature = "join-handles")]
+impl Drop for RawJoinHandle {
+    fn drop(&mut self) {
+        let header = self.raw_task.header();
+        if let Some(aborted) = header.state.drop_join_handle() {
+            // The task has finished, we're the last one referencing it.
+            if !aborted && !self.done {
+                unsafe { header.output_fn.get().unwrap_unchecked()(self.raw_task, core::ptr::null_mut()) };
+            }
+            header.state.release();
+            unsafe { self.raw_task.drop_ref() };
+        }
+    }
+}
+
+/// Error returned when awaiting a [`JoinHandle`].
+#[cfg(feature = "join-handles")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum JoinError {
+    /// The task was aborted with [`JoinHandle::abort()`] before it finished.
+    Aborted,
+}
+
 /// Handle to spawn tasks into an executor.
 ///
 /// This Spawner can spawn any task (Send and non-Send ones), but it can
@@ -103,6 +281,11 @@ impl Spawner {
     ///
     /// You obtain the `token` by calling a task function (i.e. one marked with `#[embassy_executor::task]`).
     pub fn spawn<S>(&self, token: SpawnToken<S>) -> Result<(), SpawnError> {
+        self.spawn_detached(token)
+    }
+
+    /// Spawn a task, dropping its return value when it finishes.
+    fn spawn_detached<S, T>(&self, token: SpawnToken<S, T>) -> Result<(), SpawnError> {
         let task = token.raw_task;
         mem::forget(token);
 
@@ -115,6 +298,25 @@ impl Spawner {
         }
     }
 
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it.
+    ///
+    /// The handle can be awaited to get the task's return value, or used to abort it. It can
+    /// also be dropped, which lets the task run detached.
+    #[cfg(feature = "join-handles")]
+    pub fn spawn_joinable<S, T>(&self, token: SpawnToken<S, T>) -> Result<JoinHandle<T>, SpawnError> {
+        let task = token.raw_task;
+        mem::forget(token);
+
+        match task {
+            Some(task) => {
+                task.header().state.add_join_handle();
+                unsafe { self.executor.spawn(task) };
+                Ok(JoinHandle::new(task))
+            }
+            None => Err(SpawnError::Busy),
+        }
+    }
+
     // Used by the `embassy_executor_macros::main!` macro to throw an error when spawn
     // fails. This is here to allow conditional use of `defmt::unwrap!`
     // without introducing a `defmt` feature in the `embassy_executor_macros` package,
@@ -128,6 +330,41 @@ impl Spawner {
         unwrap!(self.spawn(token));
     }
 
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it, panicking on failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spawning fails.
+    #[cfg(feature = "join-handles")]
+    pub fn must_spawn_joinable<S, T>(&self, token: SpawnToken<S, T>) -> JoinHandle<T> {
+        unwrap!(self.spawn_joinable(token))
+    }
+
+    /// Spawn a future as a task allocated on the heap.
+    ///
+    /// Unlike tasks declared with `#[task]`, this doesn't need a static pool sized at compile
+    /// time, and any future can be spawned, including generic futures and `async` blocks.
+    /// The task is freed once it has finished running.
+    #[cfg(feature = "alloc")]
+    pub fn spawn_boxed<F: Future + 'static>(&self, future: F) {
+        unwrap!(self.spawn_detached(raw::TaskStorage::spawn_boxed(future)))
+    }
+
+    /// Spawn a future as a task allocated on the heap, returning a [`JoinHandle`] to it.
+    ///
+    /// The task is freed once it has finished running and its [`JoinHandle`] has been dropped.
+    /// See [`Spawner::spawn_boxed()`].
+    #[cfg(all(feature = "alloc", feature = "join-handles"))]
+    pub fn spawn_boxed_joinable<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        self.must_spawn_joinable(raw::TaskStorage::spawn_boxed(future))
+    }
+
+    /// Get the CPU usage of the executor this Spawner spawns into.
+    #[cfg(feature = "poll-monitor")]
+    pub fn executor_stats(&self) -> raw::monitor::ExecutorStats {
+        self.executor.stats()
+    }
+
     /// Convert this Spawner to a SendSpawner. This allows you to send the
     /// spawner to other threads, but the spawner loses the ability to spawn
     /// non-Send tasks.
@@ -174,6 +411,11 @@ impl SendSpawner {
     ///
     /// You obtain the `token` by calling a task function (i.e. one marked with `#[embassy_executor::task]`).
     pub fn spawn<S: Send>(&self, token: SpawnToken<S>) -> Result<(), SpawnError> {
+        self.spawn_detached(token)
+    }
+
+    /// Spawn a task, dropping its return value when it finishes.
+    fn spawn_detached<S: Send, T>(&self, token: SpawnToken<S, T>) -> Result<(), SpawnError> {
         let header = token.raw_task;
         mem::forget(token);
 
@@ -186,6 +428,25 @@ impl SendSpawner {
         }
     }
 
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it.
+    ///
+    /// The return value is sent back from the executor thread, so it must be `Send`.
+    /// See [`Spawner::spawn_joinable()`].
+    #[cfg(feature = "join-handles")]
+    pub fn spawn_joinable<S: Send, T: Send>(&self, token: SpawnToken<S, T>) -> Result<JoinHandle<T>, SpawnError> {
+        let header = token.raw_task;
+        mem::forget(token);
+
+        match header {
+            Some(header) => {
+                header.header().state.add_join_handle();
+                unsafe { self.executor.spawn(header) };
+                Ok(JoinHandle::new(header))
+            }
+            None => Err(SpawnError::Busy),
+        }
+    }
+
     /// Spawn a task into an executor, panicking on failure.
     ///
     /// # Panics
@@ -194,4 +455,34 @@ impl SendSpawner {
     pub fn must_spawn<S: Send>(&self, token: SpawnToken<S>) {
         unwrap!(self.spawn(token));
     }
+
+    /// Spawn a task into an executor, returning a [`JoinHandle`] to it, panicking on failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the spawning fails.
+    #[cfg(feature = "join-handles")]
+    pub fn must_spawn_joinable<S: Send, T: Send>(&self, token: SpawnToken<S, T>) -> JoinHandle<T> {
+        unwrap!(self.spawn_joinable(token))
+    }
+
+    /// Spawn a `Send` future as a task allocated on the heap.
+    ///
+    /// See [`Spawner::spawn_boxed()`].
+    #[cfg(feature = "alloc")]
+    pub fn spawn_boxed<F: Future + Send + 'static>(&self, future: F) {
+        unwrap!(self.spawn_detached(raw::TaskStorage::spawn_boxed(future)))
+    }
+
+    /// Spawn a `Send` future as a task allocated on the heap, returning a [`JoinHandle`] to it.
+    ///
+    /// The return value is sent back from the executor thread, so it must be `Send`.
+    /// See [`Spawner::spawn_boxed_joinable()`].
+    #[cfg(all(feature = "alloc", feature = "join-handles"))]
+    pub fn spawn_boxed_joinable<F: Future + Send + 'static>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F::Output: Send,
+    {
+        self.must_spawn_joinable(raw::TaskStorage::spawn_boxed(future))
+    }
 }
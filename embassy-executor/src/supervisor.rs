@@ -0,0 +1,306 @@
+//! Task supervision.
+//!
+//! A [`Supervisor`] spawns tasks, watches for them to exit, and restarts them according
+//! to its [`Config`]. This lets firmware recover from a task giving up, for example after an
+//! unrecoverable driver error, without rebooting.
+//!
+//! ```rust,ignore
+//! #[embassy_executor::task]
+//! async fn net_task(runner: &'static Runner) -> Result<(), Error> {
+//!     // ...
+//! }
+//!
+//! #[embassy_executor::task]
+//! async fn supervisor_task(spawner: Spawner, runner: &'static Runner) {
+//!     let mut net = || net_task(runner);
+//!
+//!     let mut supervisor = Supervisor::<1>::new(spawner, Config::default());
+//!     supervisor.add(&mut net);
+//!     supervisor.run().await;
+//! }
+//! ```
+
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::Poll;
+
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::spawner::RawJoinHandle;
+use crate::{JoinHandle, SpawnError, SpawnToken, Spawner};
+
+/// Which children are restarted when one of them exits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Strategy {
+    /// Only the child that exited is restarted.
+    OneForOne,
+    /// All the other children are aborted, then all children are restarted.
+    OneForAll,
+}
+
+/// Supervisor configuration.
+#[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
+pub struct Config {
+    /// Restart strategy.
+    pub strategy: Strategy,
+    /// Maximum number of restarts of a child within `window`. A child exiting more often
+    /// than that is considered failed and is not restarted anymore.
+    pub max_restarts: u32,
+    /// Time window for `max_restarts`.
+    pub window: Duration,
+    /// Delay before the first restart in a window. It doubles on every subsequent restart.
+    pub backoff_min: Duration,
+    /// Maximum delay before a restart.
+    pub backoff_max: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::OneForOne,
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+            backoff_min: Duration::from_millis(100),
+            backoff_max: Duration::from_secs(10),
+        }
+    }
+}
+
+/// State of a supervised child.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChildState {
+    /// The supervisor hasn't started the child yet.
+    Stopped,
+    /// The child task is running.
+    Running,
+    /// The child exited and is waiting for its backoff delay before being restarted.
+    Restarting,
+    /// The child exited more than `max_restarts` times within the window, and won't be restarted.
+    Failed,
+}
+
+/// Status of a supervised child, returned by [`Supervisor::status()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChildStatus {
+    /// Current state.
+    pub state: ChildState,
+    /// Number of times the child has been restarted.
+    pub restarts: u32,
+}
+
+struct Child<'a> {
+    // Type-erased `&'a mut F`, spawned with `spawn`.
+    factory: *mut (),
+    spawn: unsafe fn(*mut (), &Spawner) -> Result<RawJoinHandle, SpawnError>,
+    handle: Option<RawJoinHandle>,
+    state: ChildState,
+    restarts: u32,
+    window_start: Instant,
+    window_restarts: u32,
+    restart_at: Instant,
+    _factory: PhantomData<&'a mut ()>,
+}
+
+unsafe fn spawn_child<F, S, T>(factory: *mut (), spawner: &Spawner) -> Result<RawJoinHandle, SpawnError>
+where
+    F: FnMut() -> SpawnToken<S, T>,
+{
+    let factory = &mut *(factory as *mut F);
+    spawner.spawn_joinable(factory()).map(JoinHandle::into_raw)
+}
+
+/// Supervisor for up to `N` tasks.
+pub struct Supervisor<'a, const N: usize> {
+    spawner: Spawner,
+    config: Config,
+    children: RefCell<[Option<Child<'a>>; N]>,
+}
+
+impl<'a, const N: usize> Supervisor<'a, N> {
+    /// Create a supervisor spawning its children with `spawner`.
+    pub fn new(spawner: Spawner, config: Config) -> Self {
+        Self {
+            spawner,
+            config,
+            children: RefCell::new([(); N].map(|_| None)),
+        }
+    }
+
+    /// Add a child, returning its index.
+    ///
+    /// `factory` is called to create the task every time the child is (re)started, typically a
+    /// closure calling a task function, like `|| my_task(args)`. The child is started by
+    /// [`run()`](Self::run).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the supervisor already has `N` children.
+    pub fn add<F, S, T>(&mut self, factory: &'a mut F) -> usize
+    where
+        F: FnMut() -> SpawnToken<S, T>,
+    {
+        let children = self.children.get_mut();
+        let index = unwrap!(children.iter().position(Option::is_none), "supervisor is full");
+        children[index] = Some(Child {
+            factory: factory as *mut F as *mut (),
+            spawn: spawn_child::<F, S, T>,
+            handle: None,
+            state: ChildState::Stopped,
+            restarts: 0,
+            window_start: Instant::from_ticks(0),
+            window_restarts: 0,
+            restart_at: Instant::from_ticks(0),
+            _factory: PhantomData,
+        });
+        index
+    }
+
+    /// Get the status of the child at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no child at `index`.
+    pub fn status(&self, index: usize) -> ChildStatus {
+        let children = self.children.borrow();
+        let child = unwrap!(children[index].as_ref(), "no such child");
+        ChildStatus {
+            state: child.state,
+            restarts: child.restarts,
+        }
+    }
+
+    /// Start the children and supervise them.
+    ///
+    /// This returns once all children have failed.
+    pub async fn run(&self) {
+        loop {
+            let now = Instant::now();
+            let mut next_restart = None;
+            let mut alive = false;
+            for child in self.children.borrow_mut().iter_mut().flatten() {
+                let due = child.state == ChildState::Restarting && child.restart_at <= now;
+                if child.state == ChildState::Stopped || due {
+                    self.start(child, now);
+                }
+                match child.state {
+                    ChildState::Restarting => {
+                        let at = next_restart.map_or(child.restart_at, |at: Instant| at.min(child.restart_at));
+                        next_restart = Some(at);
+                        alive = true;
+                    }
+                    ChildState::Running => alive = true,
+                    _ => {}
+                }
+            }
+            if !alive {
+                return;
+            }
+
+            // Wait for a child to exit, or for the next restart.
+            let mut timer = next_restart.map(Timer::at);
+            let exited = poll_fn(|cx| {
+                if let Some(timer) = &mut timer {
+                    if Pin::new(timer).poll(cx).is_ready() {
+                        return Poll::Ready(None);
+                    }
+                }
+                for (index, child) in self.children.borrow().iter().enumerate() {
+                    if let Some(handle) = child.as_ref().and_then(|c| c.handle.as_ref()) {
+                        if handle.poll_finished(cx).is_ready() {
+                            return Poll::Ready(Some(index));
+                        }
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+
+            if let Some(index) = exited {
+                self.exited(index).await;
+            }
+        }
+    }
+
+    fn start(&self, child: &mut Child<'a>, now: Instant) {
+        match unsafe { (child.spawn)(child.factory, &self.spawner) } {
+            Ok(handle) => {
+                child.handle = Some(handle);
+                child.state = ChildState::Running;
+            }
+            Err(_) => {
+                warn!("supervisor: failed to spawn child");
+                self.schedule_restart(child, now);
+            }
+        }
+    }
+
+    async fn exited(&self, index: usize) {
+        let now = Instant::now();
+
+        if self.config.strategy == Strategy::OneForAll {
+            // Abort the other children, and wait for them to finish.
+            for child in self.children.borrow().iter().flatten() {
+                if let Some(handle) = &child.handle {
+                    handle.abort();
+                }
+            }
+            poll_fn(|cx| {
+                for child in self.children.borrow().iter().flatten() {
+                    if let Some(handle) = &child.handle {
+                        if handle.poll_finished(cx).is_pending() {
+                            return Poll::Pending;
+                        }
+                    }
+                }
+                Poll::Ready(())
+            })
+            .await;
+        }
+
+        let mut children = self.children.borrow_mut();
+        let child = unwrap!(children[index].as_mut());
+        child.handle = None;
+        self.schedule_restart(child, now);
+        let restart_at = match child.state {
+            ChildState::Restarting => child.restart_at,
+            _ => now,
+        };
+
+        if self.config.strategy == Strategy::OneForAll {
+            // Restart the other children along with the one that exited.
+            for child in children.iter_mut().flatten() {
+                if child.handle.take().is_some() {
+                    child.state = ChildState::Restarting;
+                    child.restart_at = restart_at;
+                }
+            }
+        }
+    }
+
+    fn schedule_restart(&self, child: &mut Child<'a>, now: Instant) {
+        if now.saturating_duration_since(child.window_start) > self.config.window {
+            child.window_start = now;
+            child.window_restarts = 0;
+        }
+        child.window_restarts += 1;
+        if child.window_restarts > self.config.max_restarts {
+            warn!("supervisor: child failed too often, giving up");
+            child.state = ChildState::Failed;
+            return;
+        }
+
+        let mut backoff = self.config.backoff_min;
+        for _ in 1..child.window_restarts {
+            backoff = (backoff * 2).min(self.config.backoff_max);
+        }
+        child.restarts += 1;
+        child.restart_at = now + backoff;
+        child.state = ChildState::Restarting;
+    }
+}
@@ -0,0 +1,327 @@
+//! Task-local storage.
+//!
+//! A task-local value is set for the duration of a future with [`LocalKey::scope()`], and can
+//! be read by any code running in that future, without passing it around explicitly. Values are
+//! scoped to the task being polled, so tasks in other executors, including interrupt executors
+//! preempting the current one, don't see them.
+//!
+//! Without `arch-std`, the executor must know which core is polling a task, so that a core never
+//! sees the values of another. Provide a hook returning the id of the core it's called from, even
+//! on single-core chips:
+//!
+//! ```rust,ignore
+//! #[export_name = "__core_id"]
+//! fn core_id() -> usize {
+//!     // Read the core id, for example from the RP2040's SIO `CPUID` register. Return 0 on
+//!     // single-core chips.
+//! }
+//! ```
+//!
+//! ```rust,ignore
+//! embassy_executor::task_local! {
+//!     static CONNECTION_ID: u32;
+//! }
+//!
+//! #[embassy_executor::task(pool_size = 4)]
+//! async fn connection_task(id: u32, socket: TcpSocket<'static>) {
+//!     CONNECTION_ID.scope(id, handle_connection(socket)).await
+//! }
+//!
+//! fn log_error(err: Error) {
+//!     warn!("connection {}: {:?}", CONNECTION_ID.get(), err);
+//! }
+//! ```
+
+use core::cell::Cell;
+use core::future::Future;
+use core::pin::Pin;
+#[cfg(not(feature = "arch-std"))]
+use core::ptr;
+use core::ptr::NonNull;
+use core::task::{Context, Poll};
+
+use critical_section::Mutex;
+
+use crate::raw::TaskRef;
+
+/// Declare task-local keys, of type [`LocalKey`].
+///
+/// ```rust,ignore
+/// embassy_executor::task_local! {
+///     pub static REQUEST_ID: u32;
+///     static USER: &'static str;
+/// }
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty; $($rest:tt)*) => {
+        $(#[$attr])* $vis static $name: $crate::task_local::LocalKey<$t> = $crate::task_local::LocalKey::new();
+        $crate::task_local!($($rest)*);
+    };
+}
+
+#[cfg(feature = "arch-std")]
+std::thread_local! {
+    static CURRENT: Cell<Option<TaskRef>> = const { Cell::new(None) };
+}
+
+/// Executors currently polling a task, most recent first.
+#[cfg(not(feature = "arch-std"))]
+static POLLING: Mutex<Cell<Option<&'static Polling>>> = Mutex::new(Cell::new(None));
+
+/// The task an executor is polling, kept in each executor.
+///
+/// Executors nest, for example when an interrupt executor preempts a thread executor, and
+/// several cores may poll tasks at the same time. Without `arch-std`, executors polling a task
+/// are linked into a list along with their core, the current task is the one of the most recent
+/// executor of the current core. With `arch-std`, each thread keeps its current task.
+pub(crate) struct Polling {
+    task: Cell<Option<TaskRef>>,
+    #[cfg(not(feature = "arch-std"))]
+    core: Cell<usize>,
+    #[cfg(not(feature = "arch-std"))]
+    next: Cell<Option<&'static Polling>>,
+}
+
+// Safety: without `arch-std`, the fields are only accessed in critical sections. With it, they're
+// only accessed by the thread polling the executor.
+unsafe impl Sync for Polling {}
+
+impl Polling {
+    pub(crate) const fn new() -> Self {
+        Self {
+            task: Cell::new(None),
+            #[cfg(not(feature = "arch-std"))]
+            core: Cell::new(0),
+            #[cfg(not(feature = "arch-std"))]
+            next: Cell::new(None),
+        }
+    }
+
+    /// Mark `task` as being polled by this executor.
+    ///
+    /// Must be followed by [`exit()`](Self::exit) once the poll is done.
+    pub(crate) fn enter(&'static self, task: TaskRef) {
+        #[cfg(feature = "arch-std")]
+        self.task.set(CURRENT.with(|current| current.replace(Some(task))));
+        #[cfg(not(feature = "arch-std"))]
+        critical_section::with(|cs| {
+            let head = POLLING.borrow(cs);
+            self.task.set(Some(task));
+            self.core.set(current_core());
+            self.next.set(head.get());
+            head.set(Some(self));
+        })
+    }
+
+    /// Unmark the task marked with [`enter()`](Self::enter).
+    pub(crate) fn exit(&'static self) {
+        #[cfg(feature = "arch-std")]
+        CURRENT.with(|current| current.set(self.task.take()));
+        #[cfg(not(feature = "arch-std"))]
+        critical_section::with(|cs| {
+            // Executors of other cores may have been linked since.
+            let head = POLLING.borrow(cs);
+            let is_self = |polling: Option<&Polling>| polling.is_some_and(|polling| ptr::eq(polling, self));
+            self.task.set(None);
+            if is_self(head.get()) {
+                head.set(self.next.get());
+                return;
+            }
+            let mut next = head.get();
+            while let Some(prev) = next {
+                if is_self(prev.next.get()) {
+                    prev.next.set(self.next.get());
+                    return;
+                }
+                next = prev.next.get();
+            }
+        })
+    }
+}
+
+/// Get the id of the core this is running on through the user-provided hook.
+#[cfg(not(feature = "arch-std"))]
+fn current_core() -> usize {
+    extern "Rust" {
+        fn __core_id() -> usize;
+    }
+    unsafe { __core_id() }
+}
+
+fn current() -> Option<TaskRef> {
+    #[cfg(feature = "arch-std")]
+    return CURRENT.with(|current| current.get());
+    #[cfg(not(feature = "arch-std"))]
+    return critical_section::with(|cs| {
+        let core = current_core();
+        let mut next = POLLING.borrow(cs).get();
+        while let Some(polling) = next {
+            if polling.core.get() == core {
+                return polling.task.get();
+            }
+            next = polling.next.get();
+        }
+        None
+    });
+}
+
+/// Error returned by [`LocalKey::try_with()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessError {
+    /// The value isn't set in the current task, or no task is being polled.
+    NotSet,
+}
+
+/// A value set while a [`LocalKey::scope()`] future is being polled.
+struct Entry<T> {
+    task: TaskRef,
+    value: NonNull<T>,
+    next: Cell<Option<NonNull<Entry<T>>>>,
+}
+
+/// Key for a task-local value, declared with [`task_local!`](crate::task_local!).
+pub struct LocalKey<T: 'static> {
+    /// Values of the scopes being polled, innermost first.
+    entries: Mutex<Cell<Option<NonNull<Entry<T>>>>>,
+}
+
+// Safety: a value is only accessed by the task polling the scope it was set in, which may run in
+// another thread than the one that set it when spawned with a `SendSpawner`.
+unsafe impl<T: Send + Sync + 'static> Sync for LocalKey<T> {}
+
+impl<T: 'static> LocalKey<T> {
+    #[doc(hidden)]
+    pub const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Run `future` with the task-local value set to `value`.
+    ///
+    /// Scopes can be nested, the innermost value is seen.
+    pub fn scope<F: Future>(&'static self, value: T, future: F) -> TaskLocalFuture<T, F> {
+        TaskLocalFuture {
+            key: self,
+            value,
+            future,
+        }
+    }
+
+    /// Call `f` with a reference to the task-local value, or return an error if it isn't set.
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        let task = current().ok_or(AccessError::NotSet)?;
+        let value = critical_section::with(|cs| {
+            let mut next = self.entries.borrow(cs).get();
+            while let Some(entry) = next {
+                let entry = unsafe { entry.as_ref() };
+                if entry.task.as_ptr() == task.as_ptr() {
+                    return Some(entry.value);
+                }
+                next = entry.next.get();
+            }
+            None
+        });
+
+        // safety: the entry belongs to a scope of the current task, so the value stays valid
+        // until this returns.
+        value
+            .map(|value| f(unsafe { value.as_ref() }))
+            .ok_or(AccessError::NotSet)
+    }
+
+    /// Call `f` with a reference to the task-local value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value isn't set in the current task.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        unwrap!(self.try_with(f), "task-local value not set")
+    }
+
+    /// Get a copy of the task-local value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value isn't set in the current task.
+    pub fn get(&'static self) -> T
+    where
+        T: Copy,
+    {
+        self.with(|value| *value)
+    }
+
+    fn push(&self, entry: &Entry<T>) {
+        critical_section::with(|cs| {
+            let head = self.entries.borrow(cs);
+            entry.next.set(head.get());
+            head.set(Some(NonNull::from(entry)));
+        })
+    }
+
+    fn remove(&self, entry: &Entry<T>) {
+        critical_section::with(|cs| {
+            // Entries of other tasks may have been pushed since, when executors nest or run in
+            // other threads.
+            let head = self.entries.borrow(cs);
+            let target = NonNull::from(entry);
+            if head.get() == Some(target) {
+                head.set(entry.next.get());
+                return;
+            }
+            let mut next = head.get();
+            while let Some(prev) = next {
+                let prev = unsafe { prev.as_ref() };
+                if prev.next.get() == Some(target) {
+                    prev.next.set(entry.next.get());
+                    return;
+                }
+                next = prev.next.get();
+            }
+        })
+    }
+}
+
+/// Future returned by [`LocalKey::scope()`].
+pub struct TaskLocalFuture<T: 'static, F> {
+    key: &'static LocalKey<T>,
+    value: T,
+    future: F,
+}
+
+impl<T: 'static, F: Future> Future for TaskLocalFuture<T, F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let task = unwrap!(current(), "task-local scopes must be polled by an Embassy executor");
+
+        // safety: `future` is never moved out of `self`, `value` is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let key = this.key;
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let entry = Entry {
+            task,
+            value: NonNull::from(&this.value),
+            next: Cell::new(None),
+        };
+        key.push(&entry);
+
+        // Remove the entry even if the poll panics.
+        struct Guard<'a, T: 'static> {
+            key: &'static LocalKey<T>,
+            entry: &'a Entry<T>,
+        }
+        impl<T: 'static> Drop for Guard<'_, T> {
+            fn drop(&mut self) {
+                self.key.remove(self.entry)
+            }
+        }
+        let _guard = Guard { key, entry: &entry };
+
+        future.poll(cx)
+    }
+}
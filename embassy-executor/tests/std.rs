@@ -0,0 +1,419 @@
+#![cfg(all(feature = "arch-std", feature = "executor-thread"))]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::boxed::Box;
+use std::cell::Cell;
+use std::future::{pending, poll_fn};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+use embassy_executor::{task, Executor, Spawner, ThreadPoolExecutor};
+
+#[derive(Clone)]
+struct Trace {
+    trace: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Trace {
+    fn new() -> Self {
+        Self {
+            trace: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    fn push(&self, value: &'static str) {
+        self.trace.lock().unwrap().push(value)
+    }
+
+    fn get(&self) -> Vec<&'static str> {
+        self.trace.lock().unwrap().clone()
+    }
+}
+
+fn setup() -> &'static Executor {
+    Box::leak(Box::new(Executor::new()))
+}
+
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+#[test]
+fn std_poll_once() {
+    #[task]
+    async fn task1(trace: Trace) {
+        let mut yielded = false;
+        poll_fn(|cx| {
+            trace.push("poll task1");
+            if yielded {
+                return Poll::Ready(());
+            }
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    let executor = setup();
+    let trace = Trace::new();
+    executor.spawner().spawn(task1(trace.clone())).unwrap();
+
+    executor.poll_once();
+    assert_eq!(trace.get(), &["poll task1"]);
+    executor.poll_once();
+    assert_eq!(trace.get(), &["poll task1", "poll task1"]);
+}
+
+#[test]
+fn std_run_until_stalled() {
+    #[task]
+    async fn waiter(trace: Trace, flag: Arc<Mutex<bool>>) {
+        poll_fn(|cx| {
+            if *flag.lock().unwrap() {
+                return Poll::Ready(());
+            }
+            // Poll again until the flag is set.
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await;
+        trace.push("waiter done");
+    }
+
+    #[task]
+    async fn setter(trace: Trace, flag: Arc<Mutex<bool>>) {
+        *flag.lock().unwrap() = true;
+        trace.push("setter done");
+    }
+
+    #[task]
+    async fn stuck() {
+        pending::<()>().await
+    }
+
+    let executor = setup();
+    let trace = Trace::new();
+    let flag = Arc::new(Mutex::new(false));
+    executor.spawner().spawn(stuck()).unwrap();
+    executor.spawner().spawn(waiter(trace.clone(), flag.clone())).unwrap();
+    executor.spawner().spawn(setter(trace.clone(), flag)).unwrap();
+
+    // Returns even though `stuck` never finishes.
+    executor.run_until_stalled();
+    assert_eq!(trace.get(), &["setter done", "waiter done"]);
+}
+
+#[test]
+fn std_run_until() {
+    static RESULT: AtomicUsize = AtomicUsize::new(0);
+    static WAITER: Mutex<Option<Waker>> = Mutex::new(None);
+
+    #[task]
+    async fn task1() {
+        // Wait for a wake coming from another thread.
+        let mut thread = None;
+        poll_fn(|cx| match &thread {
+            None => {
+                let waker = cx.waker().clone();
+                thread = Some(std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(10));
+                    waker.wake();
+                }));
+                Poll::Pending
+            }
+            Some(_) => Poll::Ready(()),
+        })
+        .await;
+        RESULT.store(42, Ordering::SeqCst);
+        if let Some(waker) = WAITER.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    let executor = setup();
+    executor.spawner().spawn(task1()).unwrap();
+    let result = executor.run_until(poll_fn(|cx| {
+        *WAITER.lock().unwrap() = Some(cx.waker().clone());
+        match RESULT.load(Ordering::SeqCst) {
+            0 => Poll::Pending,
+            n => Poll::Ready(n),
+        }
+    }));
+    assert_eq!(result, 42);
+}
+
+#[test]
+fn std_thread_pool_spreads_tasks() {
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+    #[task(pool_size = 4)]
+    async fn task1(sender: mpsc::Sender<ThreadId>) {
+        // Block the worker until all tasks have started, so each of them needs its own worker.
+        STARTED.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        while STARTED.load(Ordering::SeqCst) < 4 && start.elapsed() < Duration::from_secs(5) {
+            std::thread::yield_now();
+        }
+        sender.send(std::thread::current().id()).unwrap();
+    }
+
+    let pool = Box::leak(Box::new(ThreadPoolExecutor::new(4)));
+    let (sender, receiver) = mpsc::channel();
+    let spawner = pool.start();
+    for _ in 0..4 {
+        spawner.spawn(task1(sender.clone())).unwrap();
+    }
+
+    let mut threads: Vec<_> = (0..4)
+        .map(|_| receiver.recv_timeout(Duration::from_secs(10)).unwrap())
+        .collect();
+    threads.sort_by_key(|id| format!("{:?}", id));
+    threads.dedup();
+    assert_eq!(threads.len(), 4);
+    assert!(threads.iter().all(|id| *id != std::thread::current().id()));
+}
+
+#[test]
+fn std_thread_pool_busy_worker_takes_tasks() {
+    static RELEASE: AtomicUsize = AtomicUsize::new(0);
+
+    #[task]
+    async fn blocking(sender: mpsc::Sender<&'static str>) {
+        sender.send("blocking").unwrap();
+        let start = Instant::now();
+        while RELEASE.load(Ordering::SeqCst) == 0 && start.elapsed() < Duration::from_secs(5) {
+            std::thread::yield_now();
+        }
+    }
+
+    #[task]
+    async fn queued(sender: mpsc::Sender<&'static str>) {
+        sender.send("queued").unwrap();
+    }
+
+    let pool = Box::leak(Box::new(ThreadPoolExecutor::new(1)));
+    let (sender, receiver) = mpsc::channel();
+    let spawner = pool.start();
+    spawner.spawn(blocking(sender.clone())).unwrap();
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok("blocking"));
+
+    // The only worker is busy, the task waits in the pool's queue.
+    spawner.spawn(queued(sender)).unwrap();
+    assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+    RELEASE.store(1, Ordering::SeqCst);
+    assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok("queued"));
+}
+
+#[test]
+fn std_thread_pool_pins_local_tasks() {
+    #[task]
+    async fn local(counter: Rc<Cell<u32>>, sender: mpsc::Sender<ThreadId>) {
+        for _ in 0..3 {
+            counter.set(counter.get() + 1);
+            sender.send(std::thread::current().id()).unwrap();
+            yield_now().await;
+        }
+    }
+
+    #[task]
+    async fn parent(sender: mpsc::Sender<ThreadId>) {
+        sender.send(std::thread::current().id()).unwrap();
+        let counter = Rc::new(Cell::new(0));
+        let spawner = Spawner::for_current_executor().await;
+        spawner.spawn(local(counter, sender)).unwrap();
+    }
+
+    let pool = Box::leak(Box::new(ThreadPoolExecutor::new(2)));
+    let (sender, receiver) = mpsc::channel();
+    pool.spawner().spawn(parent(sender)).unwrap();
+    pool.start();
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| receiver.recv_timeout(Duration::from_secs(10)).unwrap())
+        .collect();
+    assert!(threads.iter().all(|id| *id == threads[0]));
+}
+
+#[cfg(feature = "task-priorities")]
+#[test]
+fn std_task_priorities() {
+    #[task]
+    async fn low(trace: Trace) {
+        trace.push("low")
+    }
+
+    #[task(priority = 2)]
+    async fn high(trace: Trace) {
+        trace.push("high")
+    }
+
+    #[task]
+    async fn mid(trace: Trace) {
+        trace.push("mid")
+    }
+
+    let executor = setup();
+    let trace = Trace::new();
+    let spawner = executor.spawner();
+    spawner.spawn(low(trace.clone())).unwrap();
+    spawner.spawn(high(trace.clone())).unwrap();
+    spawner.spawn(mid(trace.clone()).with_priority(1)).unwrap();
+
+    executor.run_until_stalled();
+    assert_eq!(trace.get(), &["high", "mid", "low"]);
+}
+
+#[cfg(feature = "task-priorities")]
+#[test]
+fn std_task_priorities_wake() {
+    #[task(priority = 1)]
+    async fn high(trace: Trace, waker: Arc<Mutex<Option<Waker>>>) {
+        let mut polled = false;
+        poll_fn(|cx| {
+            trace.push("high");
+            if polled {
+                return Poll::Ready(());
+            }
+            polled = true;
+            *waker.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[task(pool_size = 2)]
+    async fn low(trace: Trace, name: &'static str, waker: Arc<Mutex<Option<Waker>>>) {
+        trace.push(name);
+        if let Some(waker) = waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+
+    let executor = setup();
+    let trace = Trace::new();
+    let spawner = executor.spawner();
+    let waker = Arc::new(Mutex::new(None));
+    spawner.spawn(high(trace.clone(), waker.clone())).unwrap();
+    executor.run_until_stalled();
+
+    spawner.spawn(low(trace.clone(), "low 1", waker.clone())).unwrap();
+    spawner.spawn(low(trace.clone(), "low 2", waker.clone())).unwrap();
+    executor.run_until_stalled();
+
+    // Woken by the first low priority task, `high` runs before the second one.
+    assert_eq!(trace.get(), &["high", "low 2", "high", "low 1"]);
+}
+
+#[cfg(feature = "task-priorities")]
+#[test]
+#[should_panic(expected = "task priority out of range")]
+fn std_task_priority_out_of_range() {
+    #[task]
+    async fn task1() {}
+
+    let executor = setup();
+    let priority = embassy_executor::raw::PRIORITY_LEVELS as u8;
+    executor.spawner().spawn(task1().with_priority(priority)).unwrap();
+}
+
+#[cfg(feature = "shuffle-run-queue")]
+#[test]
+fn std_shuffle_run_queue() {
+    #[task(pool_size = 8)]
+    async fn task1(trace: Trace, name: &'static str) {
+        trace.push(name)
+    }
+
+    fn run(seed: u64) -> Vec<&'static str> {
+        let executor = setup();
+        executor.set_shuffle_seed(seed);
+        let trace = Trace::new();
+        for name in ["a", "b", "c", "d"] {
+            executor.spawner().spawn(task1(trace.clone(), name)).unwrap();
+        }
+        executor.run_until_stalled();
+        trace.get()
+    }
+
+    // The same seed gives the same order.
+    assert_eq!(run(1), run(1));
+
+    // Different seeds give different orders.
+    let orders: Vec<_> = (1..8).map(run).collect();
+    assert!(orders.iter().any(|order| order != &orders[0]));
+    for order in &orders {
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, &["a", "b", "c", "d"]);
+    }
+}
+
+#[cfg(feature = "task-local")]
+#[test]
+fn std_task_local() {
+    use embassy_executor::task_local;
+    use embassy_executor::task_local::AccessError;
+
+    task_local! {
+        static REQUEST_ID: u32;
+    }
+
+    fn log(trace: &Trace) {
+        match REQUEST_ID.try_with(|id| *id) {
+            Ok(1) => trace.push("request 1"),
+            Ok(2) => trace.push("request 2"),
+            Ok(_) => unreachable!(),
+            Err(AccessError::NotSet) => trace.push("no request"),
+        }
+    }
+
+    #[task(pool_size = 2)]
+    async fn task1(trace: Trace, id: u32) {
+        REQUEST_ID
+            .scope(id, async {
+                log(&trace);
+                // The value is kept across polls, and not seen by the other task.
+                yield_now().await;
+                log(&trace);
+                REQUEST_ID.scope(2, async { log(&trace) }).await;
+                log(&trace);
+            })
+            .await;
+        log(&trace);
+    }
+
+    let executor = setup();
+    let trace = Trace::new();
+    executor.spawner().spawn(task1(trace.clone(), 1)).unwrap();
+    executor.poll_once();
+    assert_eq!(trace.get(), &["request 1"]);
+
+    let other = Trace::new();
+    executor.spawner().spawn(task1(other.clone(), 2)).unwrap();
+    executor.run_until_stalled();
+    assert_eq!(
+        trace.get(),
+        &["request 1", "request 1", "request 2", "request 1", "no request"]
+    );
+    assert_eq!(
+        other.get(),
+        &["request 2", "request 2", "request 2", "request 2", "no request"]
+    );
+
+    // Not set outside of tasks.
+    assert_eq!(REQUEST_ID.try_with(|_| ()), Err(AccessError::NotSet));
+}
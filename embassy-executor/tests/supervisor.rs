@@ -0,0 +1,179 @@
+#![cfg(all(feature = "supervisor", feature = "integrated-timers"))]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::boxed::Box;
+use std::future::pending;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use embassy_executor::raw::Executor;
+use embassy_executor::supervisor::{ChildState, ChildStatus, Config, Strategy, Supervisor};
+use embassy_executor::{task, Spawner};
+use embassy_time::Duration;
+use embassy_time_driver::{AlarmHandle, Driver};
+
+static PENDED: AtomicBool = AtomicBool::new(false);
+
+#[export_name = "__pender"]
+fn __pender(_context: *mut ()) {
+    PENDED.store(true, Ordering::Relaxed);
+}
+
+struct MockDriver(AtomicU64);
+
+impl Driver for MockDriver {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+    unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+        Some(AlarmHandle::new(0))
+    }
+    fn set_alarm_callback(&self, _alarm: AlarmHandle, _callback: fn(*mut ()), _ctx: *mut ()) {}
+    fn set_alarm(&self, _alarm: AlarmHandle, timestamp: u64) -> bool {
+        timestamp > self.now()
+    }
+}
+
+embassy_time_driver::time_driver_impl!(static DRIVER: MockDriver = MockDriver(AtomicU64::new(0)));
+
+// Tests share the clock, so they must not run concurrently.
+static CLOCK: Mutex<()> = Mutex::new(());
+
+fn set_time(secs: u64) {
+    DRIVER.0.store(secs * embassy_time_driver::TICK_HZ, Ordering::Relaxed);
+}
+
+#[derive(Clone)]
+struct Trace {
+    trace: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Trace {
+    fn new() -> Self {
+        Self {
+            trace: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    fn push(&self, value: &'static str) {
+        self.trace.lock().unwrap().push(value)
+    }
+
+    fn get(&self) -> Vec<&'static str> {
+        self.trace.lock().unwrap().clone()
+    }
+}
+
+fn setup() -> &'static Executor {
+    &*Box::leak(Box::new(Executor::new(std::ptr::null_mut())))
+}
+
+/// Poll the executor until it has nothing left to do at the current time.
+///
+/// The mock driver never fires alarms, so this always polls at least once.
+fn run(executor: &'static Executor) {
+    PENDED.store(true, Ordering::Relaxed);
+    while PENDED.swap(false, Ordering::Relaxed) {
+        unsafe { executor.poll() };
+    }
+}
+
+fn config(strategy: Strategy) -> Config {
+    let mut config = Config::default();
+    config.strategy = strategy;
+    config.max_restarts = 2;
+    config.window = Duration::from_secs(10);
+    config.backoff_min = Duration::from_secs(1);
+    config.backoff_max = Duration::from_secs(4);
+    config
+}
+
+#[test]
+fn supervisor_one_for_one() {
+    #[task]
+    async fn flaky(trace: Trace) {
+        trace.push("flaky");
+    }
+
+    #[task]
+    async fn supervisor(spawner: Spawner, trace: Trace) {
+        let mut child = || flaky(trace.clone());
+        let mut supervisor = Supervisor::<1>::new(spawner, config(Strategy::OneForOne));
+        let index = supervisor.add(&mut child);
+        supervisor.run().await;
+
+        assert_eq!(
+            supervisor.status(index),
+            ChildStatus {
+                state: ChildState::Failed,
+                restarts: 2
+            }
+        );
+        trace.push("gave up");
+    }
+
+    let _clock = CLOCK.lock().unwrap();
+    set_time(0);
+    let executor = setup();
+    let trace = Trace::new();
+    executor
+        .spawner()
+        .spawn(supervisor(executor.spawner(), trace.clone()))
+        .unwrap();
+
+    run(executor);
+    assert_eq!(trace.get(), &["flaky"]);
+
+    // Restarts back off, 1s then 2s.
+    set_time(1);
+    run(executor);
+    assert_eq!(trace.get(), &["flaky", "flaky"]);
+
+    set_time(2);
+    run(executor);
+    assert_eq!(trace.get(), &["flaky", "flaky"]);
+
+    set_time(3);
+    run(executor);
+    assert_eq!(trace.get(), &["flaky", "flaky", "flaky", "gave up"]);
+}
+
+#[test]
+fn supervisor_one_for_all() {
+    #[task]
+    async fn flaky(trace: Trace) {
+        trace.push("flaky");
+    }
+
+    #[task]
+    async fn steady(trace: Trace) {
+        trace.push("steady");
+        pending::<()>().await
+    }
+
+    #[task]
+    async fn supervisor(spawner: Spawner, trace: Trace) {
+        let mut flaky_child = || flaky(trace.clone());
+        let mut steady_child = || steady(trace.clone());
+        let mut supervisor = Supervisor::<2>::new(spawner, config(Strategy::OneForAll));
+        supervisor.add(&mut flaky_child);
+        supervisor.add(&mut steady_child);
+        supervisor.run().await;
+    }
+
+    let _clock = CLOCK.lock().unwrap();
+    set_time(0);
+    let executor = setup();
+    let trace = Trace::new();
+    executor
+        .spawner()
+        .spawn(supervisor(executor.spawner(), trace.clone()))
+        .unwrap();
+
+    run(executor);
+    assert_eq!(trace.get(), &["steady", "flaky"]);
+
+    // Both children are restarted together.
+    set_time(1);
+    run(executor);
+    assert_eq!(trace.get(), &["steady", "flaky", "steady", "flaky"]);
+}
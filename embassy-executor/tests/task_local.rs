@@ -0,0 +1,153 @@
+#![cfg(all(feature = "task-local", not(feature = "arch-std")))]
+#![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
+
+use std::boxed::Box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+
+use embassy_executor::raw::Executor;
+use embassy_executor::task;
+use embassy_executor::task_local::AccessError;
+
+#[export_name = "__pender"]
+fn __pender(_context: *mut ()) {}
+
+// Each thread is a core, so tests running concurrently don't see each other's executors.
+#[export_name = "__core_id"]
+fn __core_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    std::thread_local! {
+        static ID: usize = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    ID.with(|id| *id)
+}
+
+embassy_executor::task_local! {
+    static REQUEST_ID: u32;
+}
+
+#[derive(Clone)]
+struct Trace {
+    trace: Arc<Mutex<Vec<Result<u32, AccessError>>>>,
+}
+
+impl Trace {
+    fn new() -> Self {
+        Self {
+            trace: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn log(&self) {
+        self.trace.lock().unwrap().push(REQUEST_ID.try_with(|id| *id))
+    }
+
+    fn get(&self) -> Vec<Result<u32, AccessError>> {
+        self.trace.lock().unwrap().clone()
+    }
+}
+
+fn executor() -> &'static Executor {
+    Box::leak(Box::new(Executor::new(core::ptr::null_mut())))
+}
+
+#[test]
+fn task_local_nested_executors() {
+    #[task]
+    async fn inner(trace: Trace) {
+        trace.log();
+        REQUEST_ID.scope(2, async { trace.log() }).await;
+    }
+
+    #[task]
+    async fn outer(trace: Trace, nested: &'static Executor) {
+        REQUEST_ID
+            .scope(1, async {
+                trace.log();
+                // Like an interrupt executor preempting this one.
+                unsafe { nested.poll() };
+                trace.log();
+            })
+            .await;
+        trace.log();
+    }
+
+    let trace = Trace::new();
+    let nested = executor();
+    nested.spawner().spawn(inner(trace.clone())).unwrap();
+
+    let executor = executor();
+    executor.spawner().spawn(outer(trace.clone(), nested)).unwrap();
+    unsafe { executor.poll() };
+
+    assert_eq!(
+        trace.get(),
+        &[
+            Ok(1),                    // outer
+            Err(AccessError::NotSet), // inner doesn't see outer's value
+            Ok(2),                    //
+            Ok(1),                    // back in outer
+            Err(AccessError::NotSet), // outside of the scope
+        ]
+    );
+
+    // Not set outside of tasks.
+    assert_eq!(REQUEST_ID.try_with(|_| ()), Err(AccessError::NotSet));
+}
+
+#[test]
+fn task_local_two_cores() {
+    #[task]
+    async fn setter(trace: Trace, barrier: Arc<Barrier>) {
+        REQUEST_ID
+            .scope(1, async {
+                trace.log();
+                // Hold the scope while the other core reads.
+                barrier.wait();
+                barrier.wait();
+            })
+            .await;
+    }
+
+    #[task]
+    async fn reader(trace: Trace, barrier: Arc<Barrier>) {
+        // Let the other core start polling while this one is polling too.
+        barrier.wait();
+        barrier.wait();
+        trace.log();
+        REQUEST_ID.scope(2, async { trace.log() }).await;
+        barrier.wait();
+    }
+
+    let trace = Trace::new();
+    let barrier = Arc::new(Barrier::new(2));
+    let core1 = thread::spawn({
+        let (trace, barrier) = (trace.clone(), barrier.clone());
+        move || {
+            let executor = executor();
+            executor.spawner().spawn(reader(trace, barrier)).unwrap();
+            unsafe { executor.poll() };
+        }
+    });
+    let core2 = thread::spawn({
+        let trace = trace.clone();
+        move || {
+            barrier.wait();
+            let executor = executor();
+            executor.spawner().spawn(setter(trace, barrier)).unwrap();
+            unsafe { executor.poll() };
+        }
+    });
+    core1.join().unwrap();
+    core2.join().unwrap();
+
+    assert_eq!(
+        trace.get(),
+        &[
+            Ok(1),                    // setter
+            Err(AccessError::NotSet), // reader doesn't see the value of the other core
+            Ok(2),                    //
+        ]
+    );
+}
@@ -1,7 +1,7 @@
 #![cfg_attr(feature = "nightly", feature(impl_trait_in_assoc_type))]
 
 use std::boxed::Box;
-use std::future::poll_fn;
+use std::future::{pending, poll_fn};
 use std::sync::{Arc, Mutex};
 use std::task::Poll;
 
@@ -149,3 +149,383 @@ fn executor_task_cfg_args() {
         let (_, _, _) = (a, b, c);
     }
 }
+
+#[test]
+fn executor_task_never_returns() {
+    #[task]
+    async fn task1() -> ! {
+        pending().await
+    }
+
+    let (executor, _) = setup();
+    executor.spawner().spawn(task1()).unwrap();
+}
+
+#[cfg(feature = "join-handles")]
+mod join {
+    use std::future::pending;
+
+    use embassy_executor::{task, JoinError, JoinHandle};
+
+    use super::{setup, Trace};
+
+    #[test]
+    fn executor_task_join() {
+        #[task]
+        async fn task1(trace: Trace) -> u32 {
+            trace.push("poll task1");
+            42
+        }
+
+        #[task]
+        async fn task2(trace: Trace, handle: JoinHandle<u32>) {
+            trace.push("poll task2");
+            assert_eq!(handle.await, Ok(42));
+            trace.push("task2 joined");
+        }
+
+        let (executor, trace) = setup();
+        let handle = executor.spawner().spawn_joinable(task1(trace.clone())).unwrap();
+        executor.spawner().spawn(task2(trace.clone(), handle)).unwrap();
+
+        unsafe { executor.poll() };
+        unsafe { executor.poll() };
+
+        assert_eq!(
+            trace.get(),
+            &[
+                "pend",         // spawning a task pends the executor
+                "poll task2",   // task2 waits for task1
+                "poll task1",   // task1 finishes
+                "pend",         // finishing wakes task2
+                "task2 joined", //
+            ]
+        )
+    }
+
+    #[test]
+    fn executor_task_abort() {
+        #[task]
+        async fn task1(trace: Trace) {
+            trace.push("poll task1");
+            pending::<()>().await
+        }
+
+        #[task]
+        async fn task2(trace: Trace, handle: JoinHandle<()>) {
+            trace.push("poll task2");
+            assert_eq!(handle.await, Err(JoinError::Aborted));
+            trace.push("task2 joined");
+        }
+
+        let (executor, trace) = setup();
+        let spawner = executor.spawner();
+        let handle = spawner.spawn_joinable(task1(trace.clone())).unwrap();
+
+        unsafe { executor.poll() };
+        assert!(!handle.is_finished());
+
+        handle.abort();
+        unsafe { executor.poll() };
+        assert!(handle.is_finished());
+
+        // The storage is kept until the handle is gone.
+        assert!(spawner.spawn_joinable(task1(trace.clone())).is_err());
+        spawner.spawn(task2(trace.clone(), handle)).unwrap();
+        unsafe { executor.poll() };
+        spawner.spawn_joinable(task1(trace.clone())).unwrap();
+        unsafe { executor.poll() };
+
+        assert_eq!(
+            trace.get(),
+            &[
+                "pend",         // spawning a task pends the executor
+                "poll task1",   //
+                "pend",         // abort wakes the task, which is dropped without polling
+                "pend",         // spawn task2
+                "poll task2",   //
+                "task2 joined", // handle dropped, task1 can be spawned again
+                "pend",         //
+                "poll task1",   //
+            ]
+        )
+    }
+
+    #[test]
+    fn executor_task_detach() {
+        #[task]
+        async fn task1(trace: Trace) -> u32 {
+            trace.push("poll task1");
+            42
+        }
+
+        let (executor, trace) = setup();
+        let spawner = executor.spawner();
+        drop(spawner.spawn_joinable(task1(trace.clone())).unwrap());
+        unsafe { executor.poll() };
+
+        // Output is dropped, the storage is free to spawn again.
+        let handle = spawner.spawn_joinable(task1(trace.clone())).unwrap();
+        unsafe { executor.poll() };
+        assert!(handle.is_finished());
+
+        assert_eq!(
+            trace.get(),
+            &[
+                "pend",       //
+                "poll task1", //
+                "pend",       //
+                "poll task1", //
+            ]
+        )
+    }
+}
+
+#[cfg(feature = "introspection")]
+mod introspection {
+    use std::future::poll_fn;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::task::Poll;
+
+    use embassy_executor::raw::introspection::{tasks, TaskInfo, TaskState};
+    use embassy_executor::raw::task_from_waker;
+    use embassy_executor::task;
+    use embassy_time_driver::{AlarmHandle, Driver};
+
+    use super::{setup, Trace};
+
+    // Every call to `now()` advances time by one tick.
+    struct MockDriver(AtomicU64);
+
+    impl Driver for MockDriver {
+        fn now(&self) -> u64 {
+            self.0.fetch_add(1, Ordering::Relaxed)
+        }
+        unsafe fn allocate_alarm(&self) -> Option<AlarmHandle> {
+            None
+        }
+        fn set_alarm_callback(&self, _alarm: AlarmHandle, _callback: fn(*mut ()), _ctx: *mut ()) {}
+        fn set_alarm(&self, _alarm: AlarmHandle, _timestamp: u64) -> bool {
+            false
+        }
+    }
+
+    embassy_time_driver::time_driver_impl!(static DRIVER: MockDriver = MockDriver(AtomicU64::new(0)));
+
+    fn find(name: &str) -> Option<TaskInfo> {
+        tasks().find(|t| t.name == Some(name))
+    }
+
+    #[test]
+    fn executor_task_introspection() {
+        #[task(name = "introspected")]
+        async fn task1(trace: Trace) {
+            poll_fn(|cx| {
+                trace.push("poll task1");
+                assert_eq!(task_from_waker(cx.waker()).name(), Some("introspected"));
+                assert_eq!(find("introspected").unwrap().state, TaskState::Running);
+                Poll::<()>::Pending
+            })
+            .await
+        }
+
+        #[task]
+        async fn introspected_default() {}
+
+        let (executor, trace) = setup();
+        executor.spawner().spawn(task1(trace.clone())).unwrap();
+
+        let info = find("introspected").unwrap();
+        assert_eq!(info.state, TaskState::Queued);
+        assert_eq!(info.polls, 0);
+
+        unsafe { executor.poll() };
+
+        let info = find("introspected").unwrap();
+        assert_eq!(info.state, TaskState::Spawned);
+        assert_eq!(info.polls, 1);
+        assert!(info.poll_ticks >= 1);
+
+        // Tasks are named after their function by default, and finished tasks aren't listed.
+        executor.spawner().spawn(introspected_default()).unwrap();
+        assert!(find("introspected_default").is_some());
+        unsafe { executor.poll() };
+        assert!(find("introspected_default").is_none());
+
+        assert_eq!(trace.get(), &["pend", "poll task1", "pend"]);
+    }
+
+    #[cfg(feature = "poll-monitor")]
+    #[test]
+    fn executor_long_poll() {
+        use std::sync::Mutex;
+
+        use embassy_executor::raw::monitor::{set_long_poll_handler, LongPoll};
+
+        static LONG_POLLS: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        fn on_long_poll(poll: &LongPoll) {
+            LONG_POLLS.lock().unwrap().push(poll.name.unwrap());
+        }
+
+        #[task]
+        async fn busy_task() {
+            // Every call advances time by one tick.
+            for _ in 0..100 {
+                embassy_time_driver::now();
+            }
+        }
+
+        set_long_poll_handler(50, on_long_poll);
+
+        let (executor, _) = setup();
+        executor.spawner().spawn(busy_task()).unwrap();
+        unsafe { executor.poll() };
+
+        assert!(LONG_POLLS.lock().unwrap().contains(&"busy_task"));
+
+        let stats = executor.stats();
+        assert!(stats.busy_ticks > 100);
+        assert!(stats.elapsed_ticks >= stats.busy_ticks);
+        assert!(stats.idle_ratio() < 1.0);
+
+        // Nothing was polled since.
+        let later = executor.spawner().executor_stats();
+        assert_eq!(later.since(&stats).busy_ticks, 0);
+    }
+}
+
+#[cfg(feature = "alloc")]
+mod boxed {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::future::poll_fn;
+    use std::sync::{Arc, Mutex};
+    use std::task::{Poll, Waker};
+
+    use super::setup;
+
+    // Count the live allocations made by each thread, to check tasks get freed.
+    struct CountingAlloc;
+
+    thread_local! {
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = LIVE.try_with(|live| live.set(live.get() + 1));
+            System.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE.try_with(|live| live.set(live.get() - 1));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOC: CountingAlloc = CountingAlloc;
+
+    fn live() -> isize {
+        LIVE.with(|live| live.get())
+    }
+
+    #[test]
+    fn executor_boxed_task() {
+        let (executor, trace) = setup();
+        let spawner = executor.spawner();
+        // Allocate the trace's buffer before counting.
+        trace.push("start");
+        let before = live();
+
+        let t = trace.clone();
+        spawner.spawn_boxed(async move {
+            t.push("poll task1");
+            poll_fn(|cx| {
+                cx.waker().wake_by_ref();
+                Poll::Ready(())
+            })
+            .await;
+            t.push("task1 done");
+        });
+        assert_eq!(live(), before + 1);
+
+        unsafe { executor.poll() };
+
+        assert_eq!(
+            trace.get(),
+            &[
+                "start",      //
+                "pend",       // spawning a task pends the executor
+                "poll task1", //
+                "pend",       // the task wakes itself before finishing
+                "task1 done", //
+            ]
+        );
+        // The finished task is still in the run queue, it's freed once dequeued.
+        assert_eq!(live(), before + 1);
+        unsafe { executor.poll() };
+        assert_eq!(live(), before);
+    }
+
+    #[cfg(feature = "join-handles")]
+    #[test]
+    fn executor_boxed_task_joinable() {
+        let (executor, trace) = setup();
+        let spawner = executor.spawner();
+        let before = live();
+
+        let t = trace.clone();
+        let handle = spawner.spawn_boxed_joinable(async move {
+            t.push("poll task1");
+            42
+        });
+        let t = trace.clone();
+        spawner.spawn_boxed(async move {
+            t.push("poll task2");
+            assert_eq!(handle.await, Ok(42));
+            t.push("task2 joined");
+        });
+
+        unsafe { executor.poll() };
+        unsafe { executor.poll() };
+
+        assert_eq!(
+            trace.get(),
+            &[
+                "pend",         // spawning a task pends the executor
+                "poll task2",   // task2 waits for task1
+                "poll task1",   // task1 finishes
+                "pend",         // finishing wakes task2
+                "task2 joined", //
+            ]
+        );
+        // Both tasks were freed, the only allocation left is the trace's buffer.
+        assert_eq!(live(), before + 1);
+    }
+
+    #[test]
+    fn executor_boxed_task_waker_outlives_task() {
+        let (executor, trace) = setup();
+        let spawner = executor.spawner();
+        let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        // Allocate the trace's buffer before counting.
+        trace.push("start");
+        let before = live();
+
+        let w = waker.clone();
+        spawner.spawn_boxed(poll_fn(move |cx| {
+            *w.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Ready(())
+        }));
+        unsafe { executor.poll() };
+
+        // The waker keeps the finished task alive, waking it does nothing.
+        assert_eq!(live(), before + 1);
+        waker.lock().unwrap().as_ref().unwrap().wake_by_ref();
+        unsafe { executor.poll() };
+
+        waker.lock().unwrap().take();
+        assert_eq!(live(), before);
+    }
+}
@@ -14,14 +14,21 @@ mod crc32;
 mod crc8;
 mod mdio;
 mod phy;
+mod ptp;
 mod regs;
+mod switch;
+
+use core::cell::RefCell;
 
 use ch::driver::LinkState;
 pub use crc32::ETH_FCS;
 use crc8::crc8;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_net_driver_channel as ch;
-use embassy_time::Timer;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
 use embedded_hal_1::digital::OutputPin;
 use embedded_hal_async::digital::Wait;
 use embedded_hal_async::spi::{Error, Operation, SpiDevice};
@@ -29,14 +36,74 @@ use heapless::Vec;
 pub use mdio::MdioBus;
 pub use phy::Phy10BaseT1x;
 use phy::{RegsC22, RegsC45};
+use ptp::TimestampRing;
+pub use ptp::{ptp_event, EventTimestamp, PtpEvent, Timestamp};
 use regs::{Config0, Config2, SpiRegisters as sr, Status0, Status1};
+pub use switch::{ForwardingTable, Port, FORWARDING_AGEING_TIME, FORWARDING_TABLE_SIZE};
 
 use crate::fmt::Bytes;
-use crate::regs::{LedCntrl, LedFunc, LedPol, LedPolarity, SpiHeader};
+use crate::regs::{LedCntrl, LedFunc, LedPol, LedPolarity, SpiHeader, TsCfg, TxFrameHeader};
 
 /// ADIN1110 intern PHY ID
 pub const PHYID: u32 = 0x0283_BC91;
 
+/// ADIN2111 intern PHY ID
+pub const PHYID_ADIN2111: u32 = 0x0283_BCA1;
+
+/// Chip variant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Chip {
+    /// ADIN1110, one port.
+    Adin1110,
+    /// ADIN2111, two ports with an integrated switch.
+    Adin2111,
+}
+
+impl Chip {
+    /// Value of the `PHYID` register.
+    #[must_use]
+    pub fn phy_id(self) -> u32 {
+        match self {
+            Chip::Adin1110 => PHYID,
+            Chip::Adin2111 => PHYID_ADIN2111,
+        }
+    }
+
+    /// Ports of the chip.
+    #[must_use]
+    pub fn ports(self) -> &'static [Port] {
+        match self {
+            Chip::Adin1110 => &[Port::P1],
+            Chip::Adin2111 => &[Port::P1, Port::P2],
+        }
+    }
+}
+
+/// Slot the chip captures the timestamp of a sent frame in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimestampSlot {
+    /// `TTSCA` registers
+    A,
+    /// `TTSCB` registers
+    B,
+    /// `TTSCC` registers
+    C,
+}
+
+impl TimestampSlot {
+    const ALL: [TimestampSlot; 3] = [TimestampSlot::A, TimestampSlot::B, TimestampSlot::C];
+
+    fn index(self) -> usize {
+        match self {
+            TimestampSlot::A => 0,
+            TimestampSlot::B => 1,
+            TimestampSlot::C => 2,
+        }
+    }
+}
+
 /// Error values ADIN1110
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -89,8 +156,11 @@ const FRAME_HEADER_LEN: usize = 2;
 /// Space for last bytes to create multipule 4 bytes on the end of a FIFO read/write.
 const SPI_SPACE_MULTIPULE: usize = 3;
 
-/// P1 = 0x00, P2 = 0x01
-const PORT_ID_BYTE: u8 = 0x00;
+/// Receive frame timestamp length, with 64-bit timestamps.
+const TIMESTAMP_LEN: usize = 8;
+
+/// Nominal `TS_ADDEND` value, for the 1588 timer to count at the rate of the system clock.
+const TS_ADDEND_NOMINAL: u32 = 0x8555_5555;
 
 /// Type alias for the embassy-net driver for ADIN1110
 pub type Device<'d> = embassy_net_driver_channel::Device<'d, MTU>;
@@ -98,6 +168,7 @@ pub type Device<'d> = embassy_net_driver_channel::Device<'d, MTU>;
 /// Internal state for the embassy-net integration.
 pub struct State<const N_RX: usize, const N_TX: usize> {
     ch_state: ch::State<MTU, N_RX, N_TX>,
+    shared: Shared,
 }
 impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
     /// Create a new `State`.
@@ -105,12 +176,14 @@ impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
     pub const fn new() -> Self {
         Self {
             ch_state: ch::State::new(),
+            shared: Shared::new(),
         }
     }
 }
 
 /// ADIN1110 embassy-net driver
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ADIN1110<SPI> {
     /// SPI bus
     spi: SPI,
@@ -119,6 +192,12 @@ pub struct ADIN1110<SPI> {
     spi_crc: bool,
     /// Append FCS by the application of transmit packet, false = FCS is appended by the MAC, true = FCS appended by the application.
     append_fcs_on_tx: bool,
+    /// Chip variant.
+    chip: Chip,
+    /// Received frames are prefixed with a 64-bit timestamp, `CONFIG0` `FTSE` and `FTSS` bits.
+    timestamps: bool,
+    /// Forward frames between the ports of an ADIN2111 in hardware.
+    forward_between_ports: bool,
 }
 
 impl<SPI: SpiDevice> ADIN1110<SPI> {
@@ -128,6 +207,9 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
             spi,
             spi_crc,
             append_fcs_on_tx,
+            chip: Chip::Adin1110,
+            timestamps: false,
+            forward_between_ports: false,
         }
     }
 
@@ -215,19 +297,34 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
 
     /// Read out fifo ethernet packet memory received via the wire.
     pub async fn read_fifo(&mut self, frame: &mut [u8]) -> AEResult<usize, SPI::Error> {
+        self.read_fifo_port(frame, Port::P1).await.map(|(n, _)| n)
+    }
+
+    /// Read out a frame received on `port`, with its receive timestamp when frame timestamps are enabled.
+    pub async fn read_fifo_port(
+        &mut self,
+        frame: &mut [u8],
+        port: Port,
+    ) -> AEResult<(usize, Option<Timestamp>), SPI::Error> {
         const HEAD_LEN: usize = SPI_HEADER_LEN + SPI_HEADER_CRC_LEN + SPI_HEADER_TA_LEN;
         const TAIL_LEN: usize = FCS_LEN + SPI_SPACE_MULTIPULE;
 
         let mut tx_buf = Vec::<u8, HEAD_LEN>::new();
 
-        // Size of the frame, also includes the `frame header` and `FCS`.
-        let fifo_frame_size = self.read_reg(sr::RX_FSIZE).await? as usize;
+        let (fsize_reg, rx_reg) = match port {
+            Port::P1 => (sr::RX_FSIZE, sr::RX),
+            Port::P2 => (sr::RX_P2_FSIZE, sr::RX_P2),
+        };
+        let ts_len = if self.timestamps { TIMESTAMP_LEN } else { 0 };
+
+        // Size of the frame, also includes the `frame header`, the optional timestamp and `FCS`.
+        let fifo_frame_size = self.read_reg(fsize_reg).await? as usize;
 
-        if fifo_frame_size < ETH_MIN_LEN + FRAME_HEADER_LEN {
+        if fifo_frame_size < ETH_MIN_LEN + FRAME_HEADER_LEN + ts_len {
             return Err(AdinError::PACKET_TOO_SMALL);
         }
 
-        let packet_size = fifo_frame_size - FRAME_HEADER_LEN - FCS_LEN;
+        let packet_size = fifo_frame_size - FRAME_HEADER_LEN - ts_len - FCS_LEN;
 
         if packet_size > frame.len() {
             trace!("MAX: {} WANT: {}", frame.len(), packet_size);
@@ -236,7 +333,7 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
 
         let mut spi_hdr = SpiHeader(0);
         spi_hdr.set_control(true);
-        spi_hdr.set_addr(sr::RX);
+        spi_hdr.set_addr(rx_reg);
         let _ = tx_buf.extend_from_slice(spi_hdr.0.to_be_bytes().as_slice());
 
         if self.spi_crc {
@@ -247,7 +344,8 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
         // Turn around byte, TODO: Unknown that this is.
         let _ = tx_buf.push(TURN_AROUND_BYTE);
 
-        let mut frame_header = [0, 0];
+        // Frame header, followed by the timestamp when enabled.
+        let mut frame_header = [0; FRAME_HEADER_LEN + TIMESTAMP_LEN];
         let mut fcs_and_extra = [0; TAIL_LEN];
 
         // Packet read of write to the MAC packet buffer must be a multipul of 4!
@@ -255,7 +353,7 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
 
         let mut spi_op = [
             Operation::Write(&tx_buf),
-            Operation::Read(&mut frame_header),
+            Operation::Read(&mut frame_header[..FRAME_HEADER_LEN + ts_len]),
             Operation::Read(&mut frame[0..packet_size]),
             Operation::Read(&mut fcs_and_extra[0..tail_size]),
         ];
@@ -268,7 +366,10 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
         let fcs_calc = ETH_FCS::new(&frame[0..packet_size]);
 
         if fcs_calc.hton_bytes() == fcs_and_extra[0..4] {
-            Ok(packet_size)
+            let timestamp = self
+                .timestamps
+                .then(|| Timestamp::from_be_bytes(frame_header[FRAME_HEADER_LEN..].try_into().unwrap()));
+            Ok((packet_size, timestamp))
         } else {
             Err(AdinError::FCS)
         }
@@ -276,6 +377,19 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
 
     /// Write to fifo ethernet packet memory send over the wire.
     pub async fn write_fifo(&mut self, frame: &[u8]) -> AEResult<(), SPI::Error> {
+        self.write_fifo_port(frame, Port::P1, None).await
+    }
+
+    /// Write a frame to send on `port`, optionally capturing its egress timestamp in `capture`.
+    ///
+    /// The timestamp can be read with [`read_tx_timestamp`](Self::read_tx_timestamp) once the
+    /// matching `TTSCAx` bit is set in `STATUS0`.
+    pub async fn write_fifo_port(
+        &mut self,
+        frame: &[u8],
+        port: Port,
+        capture: Option<TimestampSlot>,
+    ) -> AEResult<(), SPI::Error> {
         const HEAD_LEN: usize = SPI_HEADER_LEN + SPI_HEADER_CRC_LEN + FRAME_HEADER_LEN;
         const TAIL_LEN: usize = ETH_MIN_LEN - FCS_LEN + FCS_LEN + SPI_SPACE_MULTIPULE;
 
@@ -308,8 +422,12 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
         }
 
         // Add port number, ADIN1110 its fixed to zero/P1, but for ADIN2111 has two ports.
+        let mut frame_header = TxFrameHeader(0);
+        frame_header.set_port(port == Port::P2);
+        #[allow(clippy::cast_possible_truncation)]
+        frame_header.set_egress_capture(capture.map_or(0, |slot| slot.index() as u16 + 1));
         head_data
-            .extend_from_slice(u16::from(PORT_ID_BYTE).to_be_bytes().as_slice())
+            .extend_from_slice(frame_header.0.to_be_bytes().as_slice())
             .map_err(|_e| AdinError::PACKET_TOO_BIG)?;
 
         // ADIN1110 MAC and PHY don´t accept ethernet packet smaller than 64 bytes.
@@ -369,16 +487,25 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
         let mac_high_part = u16::from_be_bytes(mac[0..2].try_into().unwrap());
         let mac_low_part = u32::from_be_bytes(mac[2..6].try_into().unwrap());
 
+        // Frames received on port 2 of an ADIN2111 are only filtered with `APPLY2PORT2` set.
+        let apply2port = if self.chip == Chip::Adin2111 {
+            (1 << 30) | (1 << 31)
+        } else {
+            1 << 30
+        };
+
         // program our mac address in the mac address filter
-        self.write_reg(sr::ADDR_FILT_UPR0, (1 << 16) | (1 << 30) | u32::from(mac_high_part))
+        self.write_reg(sr::ADDR_FILT_UPR0, (1 << 16) | apply2port | u32::from(mac_high_part))
             .await?;
         self.write_reg(sr::ADDR_FILT_LWR0, mac_low_part).await?;
 
         self.write_reg(sr::ADDR_MSK_UPR0, u32::from(mac_high_part)).await?;
         self.write_reg(sr::ADDR_MSK_LWR0, mac_low_part).await?;
 
-        // Also program broadcast address in the mac address filter
-        self.write_reg(sr::ADDR_FILT_UPR1, (1 << 16) | (1 << 30) | 0xFFFF)
+        // Also program broadcast address in the mac address filter, flooding it to the other port
+        // when forwarding between ports.
+        let to_other_port = if self.forward_between_ports { 1 << 17 } else { 0 };
+        self.write_reg(sr::ADDR_FILT_UPR1, (1 << 16) | to_other_port | apply2port | 0xFFFF)
             .await?;
         self.write_reg(sr::ADDR_FILT_LWR1, 0xFFFF_FFFF).await?;
         self.write_reg(sr::ADDR_MSK_UPR1, 0xFFFF).await?;
@@ -386,6 +513,73 @@ impl<SPI: SpiDevice> ADIN1110<SPI> {
 
         Ok(())
     }
+
+    /// Read the egress timestamp captured in `slot`.
+    pub async fn read_tx_timestamp(&mut self, slot: TimestampSlot) -> AEResult<Timestamp, SPI::Error> {
+        let (hi, lo) = match slot {
+            TimestampSlot::A => (sr::TTSCAH, sr::TTSCAL),
+            TimestampSlot::B => (sr::TTSCBH, sr::TTSCBL),
+            TimestampSlot::C => (sr::TTSCCH, sr::TTSCCL),
+        };
+        Ok(Timestamp {
+            seconds: self.read_reg(hi).await?,
+            nanoseconds: self.read_reg(lo).await?,
+        })
+    }
+
+    /// Start the 1588 timer used to timestamp frames, at its nominal rate.
+    pub async fn enable_timer(&mut self) -> AEResult<(), SPI::Error> {
+        self.write_reg(sr::TS_ADDEND, TS_ADDEND_NOMINAL).await?;
+        let mut ts_cfg = TsCfg(0);
+        ts_cfg.set_ts_en(true);
+        self.write_reg(sr::TS_CFG, ts_cfg.0).await
+    }
+
+    /// Read the 1588 timer.
+    pub async fn time(&mut self) -> AEResult<Timestamp, SPI::Error> {
+        loop {
+            let seconds = self.read_reg(sr::TS_SEC_CNT).await?;
+            let nanoseconds = self.read_reg(sr::TS_NS_CNT).await?;
+            // Retry if the seconds rolled over in between.
+            if self.read_reg(sr::TS_SEC_CNT).await? == seconds {
+                return Ok(Timestamp { seconds, nanoseconds });
+            }
+        }
+    }
+
+    /// Set the 1588 timer.
+    pub async fn set_time(&mut self, time: Timestamp) -> AEResult<(), SPI::Error> {
+        self.write_reg(sr::TS_SEC_CNT, time.seconds).await?;
+        self.write_reg(sr::TS_NS_CNT, time.nanoseconds).await
+    }
+
+    /// Adjust the rate of the 1588 timer by `ppb` parts per billion relative to its nominal rate.
+    pub async fn adjust_frequency(&mut self, ppb: i32) -> AEResult<(), SPI::Error> {
+        let nominal = i64::from(TS_ADDEND_NOMINAL);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let addend = (nominal + nominal * i64::from(ppb) / 1_000_000_000) as u32;
+        self.write_reg(sr::TS_ADDEND, addend).await
+    }
+
+    /// Handles a timer request from [`Control`], returning the time after it.
+    async fn request(&mut self, request: Request) -> AEResult<Timestamp, SPI::Error> {
+        match request {
+            Request::Time => self.time().await,
+            Request::SetTime(time) => {
+                self.set_time(time).await?;
+                Ok(time)
+            }
+            Request::AdjustTime(delta) => {
+                let time = Timestamp::from_nanos(self.time().await?.as_nanos() + delta);
+                self.set_time(time).await?;
+                Ok(time)
+            }
+            Request::AdjustFrequency(ppb) => {
+                self.adjust_frequency(ppb).await?;
+                self.time().await
+            }
+        }
+    }
 }
 
 impl<SPI: SpiDevice> mdio::MdioBus for ADIN1110<SPI> {
@@ -436,6 +630,172 @@ impl<SPI: SpiDevice> mdio::MdioBus for ADIN1110<SPI> {
     }
 }
 
+/// Request from [`Control`] to the runner, which owns the SPI bus.
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    Time,
+    SetTime(Timestamp),
+    AdjustTime(i64),
+    AdjustFrequency(i32),
+}
+
+/// State shared between the runner and [`Control`].
+struct Shared {
+    inner: Mutex<NoopRawMutex, RefCell<SharedInner>>,
+    request: Signal<NoopRawMutex, Request>,
+    response: Signal<NoopRawMutex, Timestamp>,
+    tx_timestamp: Signal<NoopRawMutex, ()>,
+    control: embassy_sync::mutex::Mutex<NoopRawMutex, ()>,
+}
+
+struct SharedInner {
+    links: [LinkState; 2],
+    forwarding: ForwardingTable,
+    rx_timestamps: TimestampRing,
+    tx_timestamps: TimestampRing,
+}
+
+impl Shared {
+    const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(SharedInner {
+                links: [LinkState::Down; 2],
+                forwarding: ForwardingTable::new(),
+                rx_timestamps: TimestampRing::new(),
+                tx_timestamps: TimestampRing::new(),
+            })),
+            request: Signal::new(),
+            response: Signal::new(),
+            tx_timestamp: Signal::new(),
+            control: embassy_sync::mutex::Mutex::new(()),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut SharedInner) -> R) -> R {
+        self.inner.lock(|inner| f(&mut inner.borrow_mut()))
+    }
+}
+
+/// Configuration for [`new_with_config`].
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Chip variant. Defaults to [`Chip::Adin1110`].
+    pub chip: Chip,
+    /// Enable CRC on SPI transfers.
+    /// This must match with the hardware pin `SPI_CFG0` were low = CRC enable, high = CRC disabled.
+    pub spi_crc: bool,
+    /// Append the FCS to sent frames in software instead of by the MAC.
+    pub append_fcs_on_tx: bool,
+    /// Timestamp PTP event messages, see [`Control::rx_timestamp`] and [`Control::tx_timestamp`].
+    pub timestamps: bool,
+    /// Forward frames between the two ports of an ADIN2111 in hardware, like a switch.
+    /// Defaults to `true`.
+    pub forward_between_ports: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            chip: Chip::Adin1110,
+            spi_crc: false,
+            append_fcs_on_tx: false,
+            timestamps: false,
+            forward_between_ports: true,
+        }
+    }
+}
+
+/// Control handle for the ADIN1110 or ADIN2111, to get the state of its ports, manage the
+/// forwarding table and use hardware timestamps.
+///
+/// Requests needing the SPI bus are served by the [`Runner`], which must be running.
+pub struct Control<'d> {
+    shared: &'d Shared,
+}
+
+impl<'d> Control<'d> {
+    /// Get the link state of `port`.
+    #[must_use]
+    pub fn link_state(&self, port: Port) -> LinkState {
+        self.shared.with(|s| s.links[port.index()])
+    }
+
+    /// Get the port frames to `mac` are sent on, or `None` if they are sent on both ports.
+    #[must_use]
+    pub fn forwarding_port(&self, mac: [u8; 6]) -> Option<Port> {
+        self.shared.with(|s| s.forwarding.lookup(mac, Instant::now()))
+    }
+
+    /// Add a static entry to the forwarding table, sending frames to `mac` only on `port`.
+    ///
+    /// Returns `false` if the table is full of static entries.
+    #[must_use]
+    pub fn add_forwarding_entry(&self, mac: [u8; 6], port: Port) -> bool {
+        self.shared.with(|s| s.forwarding.insert_static(mac, port))
+    }
+
+    /// Remove the forwarding table entry for `mac`, learned or static.
+    pub fn remove_forwarding_entry(&self, mac: [u8; 6]) {
+        self.shared.with(|s| s.forwarding.remove(mac));
+    }
+
+    /// Remove all forwarding table entries, learned and static.
+    pub fn clear_forwarding_table(&self) {
+        self.shared.with(|s| s.forwarding.clear());
+    }
+
+    /// Take the receive timestamp of a PTP event message, if it was received recently.
+    ///
+    /// Requires [`Config::timestamps`].
+    #[must_use]
+    pub fn rx_timestamp(&self, event: PtpEvent) -> Option<EventTimestamp> {
+        self.shared.with(|s| s.rx_timestamps.take(event))
+    }
+
+    /// Wait for the transmit timestamp of a PTP event message.
+    ///
+    /// When the message is sent on both ports of an ADIN2111, call this once for each port.
+    /// Requires [`Config::timestamps`]. Use a timeout, as the timestamp is dropped if all
+    /// capture slots are busy.
+    pub async fn tx_timestamp(&self, event: PtpEvent) -> EventTimestamp {
+        loop {
+            if let Some(ts) = self.shared.with(|s| s.tx_timestamps.take(event)) {
+                return ts;
+            }
+            self.shared.tx_timestamp.wait().await;
+        }
+    }
+
+    /// Read the 1588 timer frames are timestamped with.
+    pub async fn time(&self) -> Timestamp {
+        self.request(Request::Time).await
+    }
+
+    /// Set the 1588 timer.
+    pub async fn set_time(&self, time: Timestamp) {
+        self.request(Request::SetTime(time)).await;
+    }
+
+    /// Step the 1588 timer by `delta` nanoseconds.
+    pub async fn adjust_time(&self, delta: i64) {
+        self.request(Request::AdjustTime(delta)).await;
+    }
+
+    /// Adjust the rate of the 1588 timer by `ppb` parts per billion relative to its nominal rate.
+    pub async fn adjust_frequency(&self, ppb: i32) {
+        self.request(Request::AdjustFrequency(ppb)).await;
+    }
+
+    async fn request(&self, request: Request) -> Timestamp {
+        let _guard = self.shared.control.lock().await;
+        self.shared.response.reset();
+        self.shared.request.signal(request);
+        self.shared.response.wait().await
+    }
+}
+
 /// Background runner for the ADIN1110.
 ///
 /// You must call `.run()` in a background task for the ADIN1110 to operate.
@@ -444,169 +804,335 @@ pub struct Runner<'d, SPI, INT, RST> {
     ch: ch::Runner<'d, MTU>,
     int: INT,
     is_link_up: bool,
+    shared: &'d Shared,
+    /// PTP event messages waiting for their egress timestamp, by capture slot.
+    tx_captures: [Option<(PtpEvent, Port)>; 3],
     _reset: RST,
 }
 
 impl<'d, SPI: SpiDevice, INT: Wait, RST: OutputPin> Runner<'d, SPI, INT, RST> {
     /// Run the driver.
-    #[allow(clippy::too_many_lines)]
     pub async fn run(mut self) -> ! {
+        let shared = self.shared;
+        let (state_chan, mut rx_chan, mut tx_chan) = self.ch.split();
+
         loop {
-            let (state_chan, mut rx_chan, mut tx_chan) = self.ch.split();
-
-            loop {
-                debug!("Waiting for interrupts");
-                match select(self.int.wait_for_low(), tx_chan.tx_buf()).await {
-                    Either::First(_) => {
-                        let mut status1_clr = Status1(0);
-                        let mut status1 = Status1(self.mac.read_reg(sr::STATUS1).await.unwrap());
-
-                        while status1.p1_rx_rdy() {
-                            debug!("alloc RX packet buffer");
-                            match select(rx_chan.rx_buf(), tx_chan.tx_buf()).await {
-                                // Handle frames that needs to transmit from the wire.
-                                // Note: rx_chan.rx_buf() channel don´t accept new request
-                                //       when the tx_chan is full. So these will be handled
-                                //       automaticly.
-                                Either::First(frame) => match self.mac.read_fifo(frame).await {
-                                    Ok(n) => {
-                                        rx_chan.rx_done(n);
-                                    }
-                                    Err(e) => match e {
-                                        AdinError::PACKET_TOO_BIG => {
-                                            error!("RX Packet too big, DROP");
-                                            self.mac.write_reg(sr::FIFO_CLR, 1).await.unwrap();
-                                        }
-                                        AdinError::PACKET_TOO_SMALL => {
-                                            error!("RX Packet too small, DROP");
-                                            self.mac.write_reg(sr::FIFO_CLR, 1).await.unwrap();
-                                        }
-                                        AdinError::Spi(e) => {
-                                            error!("RX Spi error {}", e.kind());
-                                        }
-                                        e => {
-                                            error!("RX Error {:?}", e);
-                                        }
-                                    },
-                                },
-                                Either::Second(frame) => {
-                                    // Handle frames that needs to transmit to the wire.
-                                    self.mac.write_fifo(frame).await.unwrap();
-                                    tx_chan.tx_done();
-                                }
-                            }
-                            status1 = Status1(self.mac.read_reg(sr::STATUS1).await.unwrap());
-                        }
-
-                        let status0 = Status0(self.mac.read_reg(sr::STATUS0).await.unwrap());
-                        if status1.0 & !0x1b != 0 {
-                            error!("SPE CHIP STATUS 0:{:08x} 1:{:08x}", status0.0, status1.0);
-                        }
-
-                        if status1.tx_rdy() {
-                            status1_clr.set_tx_rdy(true);
-                            trace!("TX_DONE");
-                        }
-
-                        if status1.link_change() {
-                            let link = status1.p1_link_status();
-                            self.is_link_up = link;
-
-                            if link {
-                                let link_status = self
-                                    .mac
-                                    .read_cl45(MDIO_PHY_ADDR, RegsC45::DA7::AN_STATUS_EXTRA.into())
-                                    .await
-                                    .unwrap();
-
-                                let volt = if link_status & (0b11 << 5) == (0b11 << 5) {
-                                    "2.4"
-                                } else {
-                                    "1.0"
-                                };
-
-                                let mse = self
-                                    .mac
-                                    .read_cl45(MDIO_PHY_ADDR, RegsC45::DA1::MSE_VAL.into())
-                                    .await
-                                    .unwrap();
-
-                                info!("LINK Changed: Link Up, Volt: {} V p-p, MSE: {:0004}", volt, mse);
-                            } else {
-                                info!("LINK Changed: Link Down");
-                            }
-
-                            state_chan.set_link_state(if link { LinkState::Up } else { LinkState::Down });
-                            status1_clr.set_link_change(true);
-                        }
-
-                        if status1.tx_ecc_err() {
-                            error!("SPI TX_ECC_ERR error, CLEAR TX FIFO");
-                            self.mac.write_reg(sr::FIFO_CLR, 2).await.unwrap();
-                            status1_clr.set_tx_ecc_err(true);
-                        }
-
-                        if status1.rx_ecc_err() {
-                            error!("SPI RX_ECC_ERR error");
-                            status1_clr.set_rx_ecc_err(true);
-                        }
-
-                        if status1.spi_err() {
-                            error!("SPI SPI_ERR CRC error");
-                            status1_clr.set_spi_err(true);
-                        }
-
-                        if status0.phyint() {
-                            let crsm_irq_st = self
-                                .mac
-                                .read_cl45(MDIO_PHY_ADDR, RegsC45::DA1E::CRSM_IRQ_STATUS.into())
-                                .await
-                                .unwrap();
-
-                            let phy_irq_st = self
-                                .mac
-                                .read_cl45(MDIO_PHY_ADDR, RegsC45::DA1F::PHY_SYBSYS_IRQ_STATUS.into())
-                                .await
-                                .unwrap();
-
-                            warn!(
-                                "SPE CHIP PHY CRSM_IRQ_STATUS {:04x} PHY_SUBSYS_IRQ_STATUS {:04x}",
-                                crsm_irq_st, phy_irq_st
-                            );
-                        }
-
-                        if status0.txfcse() {
-                            error!("Ethernet Frame FCS and calc FCS don't match!");
-                        }
-
-                        // Clear status0
-                        self.mac.write_reg(sr::STATUS0, 0xFFF).await.unwrap();
-                        self.mac.write_reg(sr::STATUS1, status1_clr.0).await.unwrap();
+            debug!("Waiting for interrupts");
+            match select3(self.int.wait_for_low(), tx_chan.tx_buf(), shared.request.wait()).await {
+                Either3::First(_) => {
+                    let mut status1_clr = Status1(0);
+                    let status1 =
+                        receive_frames(&mut self.mac, shared, &mut self.tx_captures, &mut rx_chan, &mut tx_chan).await;
+
+                    let status0 = Status0(self.mac.read_reg(sr::STATUS0).await.unwrap());
+                    if status1.0 & !0x0002_001b != 0 {
+                        error!("SPE CHIP STATUS 0:{:08x} 1:{:08x}", status0.0, status1.0);
                     }
-                    Either::Second(packet) => {
-                        // Handle frames that needs to transmit to the wire.
-                        self.mac.write_fifo(packet).await.unwrap();
-                        tx_chan.tx_done();
+
+                    if status1.tx_rdy() {
+                        status1_clr.set_tx_rdy(true);
+                        trace!("TX_DONE");
+                    }
+
+                    take_tx_timestamps(&mut self.mac, shared, &mut self.tx_captures, &status0).await;
+
+                    // The PHY of port 2 only reports link changes with a PHY interrupt.
+                    if status1.link_change() || (self.mac.chip == Chip::Adin2111 && status0.phyint()) {
+                        let any_up = update_links(&mut self.mac, shared, &status1).await;
+                        self.is_link_up = any_up;
+
+                        state_chan.set_link_state(if any_up { LinkState::Up } else { LinkState::Down });
+                        status1_clr.set_link_change(true);
+                    }
+
+                    if status1.tx_ecc_err() {
+                        error!("SPI TX_ECC_ERR error, CLEAR TX FIFO");
+                        self.mac.write_reg(sr::FIFO_CLR, 2).await.unwrap();
+                        status1_clr.set_tx_ecc_err(true);
+                    }
+
+                    if status1.rx_ecc_err() {
+                        error!("SPI RX_ECC_ERR error");
+                        status1_clr.set_rx_ecc_err(true);
+                    }
+
+                    if status1.spi_err() {
+                        error!("SPI SPI_ERR CRC error");
+                        status1_clr.set_spi_err(true);
+                    }
+
+                    if status0.phyint() {
+                        let crsm_irq_st = self
+                            .mac
+                            .read_cl45(MDIO_PHY_ADDR, RegsC45::DA1E::CRSM_IRQ_STATUS.into())
+                            .await
+                            .unwrap();
+
+                        let phy_irq_st = self
+                            .mac
+                            .read_cl45(MDIO_PHY_ADDR, RegsC45::DA1F::PHY_SYBSYS_IRQ_STATUS.into())
+                            .await
+                            .unwrap();
+
+                        warn!(
+                            "SPE CHIP PHY CRSM_IRQ_STATUS {:04x} PHY_SUBSYS_IRQ_STATUS {:04x}",
+                            crsm_irq_st, phy_irq_st
+                        );
+                    }
+
+                    if status0.txfcse() {
+                        error!("Ethernet Frame FCS and calc FCS don't match!");
+                    }
+
+                    // Clear status0
+                    self.mac.write_reg(sr::STATUS0, 0xFFF).await.unwrap();
+                    self.mac.write_reg(sr::STATUS1, status1_clr.0).await.unwrap();
+                }
+                Either3::Second(packet) => {
+                    // Handle frames that needs to transmit to the wire.
+                    transmit(&mut self.mac, shared, &mut self.tx_captures, packet).await;
+                    tx_chan.tx_done();
+                }
+                Either3::Third(request) => {
+                    let time = self.mac.request(request).await.unwrap();
+                    shared.response.signal(time);
+                }
+            }
+        }
+    }
+}
+
+/// Reads frames from the RX FIFOs until they are empty, transmitting frames from the host in between.
+///
+/// Returns the last read `STATUS1`.
+async fn receive_frames<SPI: SpiDevice>(
+    mac: &mut ADIN1110<SPI>,
+    shared: &Shared,
+    tx_captures: &mut [Option<(PtpEvent, Port)>; 3],
+    rx_chan: &mut ch::RxRunner<'_, MTU>,
+    tx_chan: &mut ch::TxRunner<'_, MTU>,
+) -> Status1 {
+    loop {
+        let status1 = Status1(mac.read_reg(sr::STATUS1).await.unwrap());
+        let port = if status1.p1_rx_rdy() {
+            Port::P1
+        } else if mac.chip == Chip::Adin2111 && status1.p2_rx_rdy() {
+            Port::P2
+        } else {
+            return status1;
+        };
+
+        debug!("alloc RX packet buffer");
+        match select(rx_chan.rx_buf(), tx_chan.tx_buf()).await {
+            // Handle frames that needs to transmit from the wire.
+            // Note: rx_chan.rx_buf() channel don´t accept new request
+            //       when the tx_chan is full. So these will be handled
+            //       automaticly.
+            Either::First(frame) => match mac.read_fifo_port(frame, port).await {
+                Ok((n, timestamp)) => {
+                    received(shared, &frame[..n], port, timestamp);
+                    match timestamp {
+                        Some(ts) => rx_chan.rx_done_with_timestamp(
+                            n,
+                            ch::driver::Timestamp {
+                                seconds: ts.seconds.into(),
+                                nanoseconds: ts.nanoseconds,
+                            },
+                        ),
+                        None => rx_chan.rx_done(n),
                     }
                 }
+                Err(e @ (AdinError::PACKET_TOO_BIG | AdinError::PACKET_TOO_SMALL)) => {
+                    let size = if matches!(e, AdinError::PACKET_TOO_BIG) {
+                        "big"
+                    } else {
+                        "small"
+                    };
+                    error!("RX Packet too {}, DROP", size);
+                    mac.write_reg(sr::FIFO_CLR, 1).await.unwrap();
+                }
+                Err(AdinError::Spi(e)) => {
+                    error!("RX Spi error {}", e.kind());
+                }
+                Err(e) => {
+                    error!("RX Error {:?}", e);
+                }
+            },
+            Either::Second(frame) => {
+                // Handle frames that needs to transmit to the wire.
+                transmit(mac, shared, tx_captures, frame).await;
+                tx_chan.tx_done();
             }
         }
     }
 }
 
+/// Reads the egress timestamps captured according to `status0`, and hands them to [`Control`].
+async fn take_tx_timestamps<SPI: SpiDevice>(
+    mac: &mut ADIN1110<SPI>,
+    shared: &Shared,
+    tx_captures: &mut [Option<(PtpEvent, Port)>; 3],
+    status0: &Status0,
+) {
+    let captured = [status0.ttscaa(), status0.ttscab(), status0.ttscac()];
+    for (slot, captured) in TimestampSlot::ALL.into_iter().zip(captured) {
+        if !captured {
+            continue;
+        }
+        let timestamp = mac.read_tx_timestamp(slot).await.unwrap();
+        if let Some((event, port)) = tx_captures[slot.index()].take() {
+            trace!("TX timestamp {:?} on {:?}: {:?}", event, port, timestamp);
+            shared.with(|s| {
+                s.tx_timestamps.push(EventTimestamp { event, port, timestamp });
+            });
+            shared.tx_timestamp.signal(());
+        }
+    }
+}
+
+/// Updates the link state of each port, returning whether any port is up.
+async fn update_links<SPI: SpiDevice>(mac: &mut ADIN1110<SPI>, shared: &Shared, status1: &Status1) -> bool {
+    let mut any_up = false;
+    for &port in mac.chip.ports() {
+        let link = match port {
+            Port::P1 => status1.p1_link_status(),
+            Port::P2 => port_link_up(mac, port).await,
+        };
+        any_up |= link;
+
+        let was_up = shared.with(|s| s.links[port.index()] == LinkState::Up);
+        if link == was_up {
+            continue;
+        }
+
+        if link {
+            let link_status = mac
+                .read_cl45(port.phy_addr(), RegsC45::DA7::AN_STATUS_EXTRA.into())
+                .await
+                .unwrap();
+
+            let volt = if link_status & (0b11 << 5) == (0b11 << 5) {
+                "2.4"
+            } else {
+                "1.0"
+            };
+
+            let mse = mac
+                .read_cl45(port.phy_addr(), RegsC45::DA1::MSE_VAL.into())
+                .await
+                .unwrap();
+
+            info!(
+                "LINK Changed: {:?} Link Up, Volt: {} V p-p, MSE: {:0004}",
+                port, volt, mse
+            );
+        } else {
+            info!("LINK Changed: {:?} Link Down", port);
+        }
+
+        shared.with(|s| {
+            s.links[port.index()] = if link { LinkState::Up } else { LinkState::Down };
+            if !link {
+                s.forwarding.flush(port);
+            }
+        });
+    }
+    any_up
+}
+
+/// Learns the port of the sender of a received frame, and keeps its timestamp if it's a PTP event message.
+fn received(shared: &Shared, frame: &[u8], port: Port, timestamp: Option<Timestamp>) {
+    let event = timestamp.and_then(|timestamp| Some((ptp_event(frame)?, timestamp)));
+    shared.with(|s| {
+        if let Ok(src) = frame[6..12].try_into() {
+            s.forwarding.learn(src, port, Instant::now());
+        }
+        if let Some((event, timestamp)) = event {
+            trace!("RX timestamp {:?} on {:?}: {:?}", event, port, timestamp);
+            s.rx_timestamps.push(EventTimestamp { event, port, timestamp });
+        }
+    });
+}
+
+/// Sends a frame from the host, only on the port of its destination if known.
+async fn transmit<SPI: SpiDevice>(
+    mac: &mut ADIN1110<SPI>,
+    shared: &Shared,
+    tx_captures: &mut [Option<(PtpEvent, Port)>; 3],
+    frame: &[u8],
+) {
+    let ports = match mac.chip {
+        Chip::Adin1110 => &[Port::P1][..],
+        Chip::Adin2111 => {
+            let dst = frame[0..6].try_into().unwrap();
+            match shared.with(|s| s.forwarding.lookup(dst, Instant::now())) {
+                Some(Port::P1) => &[Port::P1][..],
+                Some(Port::P2) => &[Port::P2],
+                None => mac.chip.ports(),
+            }
+        }
+    };
+    let event = if mac.timestamps { ptp_event(frame) } else { None };
+
+    for &port in ports {
+        let capture = event.and_then(|event| {
+            let Some(slot) = TimestampSlot::ALL
+                .into_iter()
+                .find(|s| tx_captures[s.index()].is_none())
+            else {
+                warn!("No free timestamp slot for {:?}", event);
+                return None;
+            };
+            tx_captures[slot.index()] = Some((event, port));
+            Some(slot)
+        });
+        mac.write_fifo_port(frame, port, capture).await.unwrap();
+    }
+}
+
+/// Reads the link status of the PHY of `port`.
+async fn port_link_up<SPI: SpiDevice>(mac: &mut ADIN1110<SPI>, port: Port) -> bool {
+    // The link status bit is latched low, read it twice to get the current state.
+    let _ = mac.read_cl22(port.phy_addr(), RegsC22::STATUS as u8).await.unwrap();
+    let status = mac.read_cl22(port.phy_addr(), RegsC22::STATUS as u8).await.unwrap();
+    status & (1 << 2) != 0
+}
+
 /// Obtain a driver for using the ADIN1110 with [`embassy-net`](crates.io/crates/embassy-net).
 pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait, RST: OutputPin>(
     mac_addr: [u8; 6],
     state: &'_ mut State<N_RX, N_TX>,
     spi_dev: SPI,
     int: INT,
-    mut reset: RST,
+    reset: RST,
     spi_crc: bool,
     append_fcs_on_tx: bool,
 ) -> (Device<'_>, Runner<'_, SPI, INT, RST>) {
+    let config = Config {
+        spi_crc,
+        append_fcs_on_tx,
+        ..Config::default()
+    };
+    let (device, runner, _control) = new_with_config(mac_addr, state, spi_dev, int, reset, config).await;
+    (device, runner)
+}
+
+/// Obtain a driver for using the ADIN1110 or ADIN2111 with [`embassy-net`](crates.io/crates/embassy-net),
+/// and a [`Control`] handle.
+///
+/// With an ADIN2111, the link is reported up to `embassy-net` when either port is up.
+#[allow(clippy::too_many_lines)]
+pub async fn new_with_config<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait, RST: OutputPin>(
+    mac_addr: [u8; 6],
+    state: &'_ mut State<N_RX, N_TX>,
+    spi_dev: SPI,
+    int: INT,
+    mut reset: RST,
+    config: Config,
+) -> (Device<'_>, Runner<'_, SPI, INT, RST>, Control<'_>) {
     use crate::regs::{IMask0, IMask1};
 
-    info!("INIT ADIN1110");
+    info!("INIT {:?}", config.chip);
 
     // Reset sequence
     reset.set_low().unwrap();
@@ -620,11 +1146,14 @@ pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait
     Timer::after_millis(50).await;
 
     // Create device
-    let mut mac = ADIN1110::new(spi_dev, spi_crc, append_fcs_on_tx);
+    let mut mac = ADIN1110::new(spi_dev, config.spi_crc, config.append_fcs_on_tx);
+    mac.chip = config.chip;
+    mac.timestamps = config.timestamps;
+    mac.forward_between_ports = config.chip == Chip::Adin2111 && config.forward_between_ports;
 
     // Check PHYID
     let id = mac.read_reg(sr::PHYID).await.unwrap();
-    assert_eq!(id, PHYID);
+    assert_eq!(id, config.chip.phy_id());
 
     debug!("SPE: CHIP MAC/ID: {:08x}", id);
 
@@ -635,27 +1164,44 @@ pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait
         debug!("SPE: CHIP: PHY ID: {:08x}", phy_id);
     }
 
-    let mi_control = mac.read_cl22(MDIO_PHY_ADDR, RegsC22::CONTROL as u8).await.unwrap();
-    debug!("SPE CHIP PHY MI_CONTROL {:04x}", mi_control);
-    if mi_control & 0x0800 != 0 {
-        let val = mi_control & !0x0800;
-        debug!("SPE CHIP PHY MI_CONTROL Disable PowerDown");
-        mac.write_cl22(MDIO_PHY_ADDR, RegsC22::CONTROL as u8, val)
-            .await
-            .unwrap();
+    for &port in config.chip.ports() {
+        let mi_control = mac.read_cl22(port.phy_addr(), RegsC22::CONTROL as u8).await.unwrap();
+        debug!("SPE CHIP PHY {:?} MI_CONTROL {:04x}", port, mi_control);
+        if mi_control & 0x0800 != 0 {
+            let val = mi_control & !0x0800;
+            debug!("SPE CHIP PHY {:?} MI_CONTROL Disable PowerDown", port);
+            mac.write_cl22(port.phy_addr(), RegsC22::CONTROL as u8, val)
+                .await
+                .unwrap();
+        }
     }
 
     // Config0
     let mut config0 = Config0(0x0000_0006);
     config0.set_txfcsve(mac.append_fcs_on_tx);
+    if config.timestamps {
+        // Prefix received frames with a 64-bit timestamp.
+        config0.set_ftse(true);
+        config0.set_ftss(true);
+    }
     mac.write_reg(sr::CONFIG0, config0.0).await.unwrap();
 
     // Config2
     let mut config2 = Config2(0x0000_0800);
     // crc_append must be disable if tx_fcs_validation_enable is true!
     config2.set_crc_append(!mac.append_fcs_on_tx);
+    if config.chip == Chip::Adin2111 {
+        // Frames not for us are forwarded to the other port, or dropped.
+        config2.set_port_cut_thru_en(mac.forward_between_ports);
+        config2.set_p1_fwd_unk2p2(mac.forward_between_ports);
+        config2.set_p2_fwd_unk2p1(mac.forward_between_ports);
+    }
     mac.write_reg(sr::CONFIG2, config2.0).await.unwrap();
 
+    if config.timestamps {
+        mac.enable_timer().await.unwrap();
+    }
+
     // Pin Mux Config 1
     let led_val = (0b11 << 6) | (0b11 << 4); // | (0b00 << 1);
     mac.write_cl45(MDIO_PHY_ADDR, RegsC45::DA1E::DIGIO_PINMUX.into(), led_val)
@@ -694,6 +1240,11 @@ pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait
     imask0_val.set_txboem(false);
     imask0_val.set_rxboem(false);
     imask0_val.set_txpem(false);
+    if config.timestamps {
+        imask0_val.set_ttscaam(false);
+        imask0_val.set_ttscabm(false);
+        imask0_val.set_ttscacm(false);
+    }
 
     mac.write_reg(sr::IMASK0, imask0_val.0).await.unwrap();
 
@@ -706,13 +1257,23 @@ pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait
     imask1_val.set_spi_err_mask(false);
     imask1_val.set_tx_ecc_err_mask(false);
     imask1_val.set_rx_ecc_err_mask(false);
+    if config.chip == Chip::Adin2111 {
+        imask1_val.set_p2_rx_rdy_mask(false);
+    }
 
     mac.write_reg(sr::IMASK1, imask1_val.0).await.unwrap();
 
     // Program mac address but also sets mac filters.
     mac.set_mac_addr(&mac_addr).await.unwrap();
 
-    let (runner, device) = ch::new(&mut state.ch_state, ch::driver::HardwareAddress::Ethernet(mac_addr));
+    let shared = &state.shared;
+    let (runner, mut device) = ch::new(&mut state.ch_state, ch::driver::HardwareAddress::Ethernet(mac_addr));
+    if config.timestamps {
+        // Transmit timestamps are only captured for PTP event messages, see `Control::tx_timestamp`.
+        let mut timestamps = ch::driver::TimestampCapabilities::default();
+        timestamps.rx = true;
+        device.set_timestamp_capabilities(timestamps);
+    }
     (
         device,
         Runner {
@@ -720,8 +1281,11 @@ pub async fn new<const N_RX: usize, const N_TX: usize, SPI: SpiDevice, INT: Wait
             mac,
             int,
             is_link_up: false,
+            shared,
+            tx_captures: [None; 3],
             _reset: reset,
         },
+        Control { shared },
     )
 }
 
@@ -1309,6 +1873,79 @@ mod tests {
         }
     }
 
+    #[futures_test::test]
+    async fn read_packet_from_p2_fifo_with_timestamp() {
+        let mut expectations = vec![];
+
+        // Read RX_P2_FSIZE reg, frame header + timestamp + packet + FCS
+        expectations.push(SpiTransaction::write_vec(vec![128, 192, TURN_AROUND_BYTE]));
+        expectations.push(SpiTransaction::read_vec(vec![0, 0, 0, 74]));
+        expectations.push(SpiTransaction::flush());
+
+        let packet = [0xAA_u8; 60];
+        let mut frame = [0; MTU];
+
+        // Read RX_P2 reg
+        expectations.push(SpiTransaction::write_vec(vec![128, 193, TURN_AROUND_BYTE]));
+        // Frame Header and timestamp, 5 s 256 ns
+        expectations.push(SpiTransaction::read_vec(vec![0, 0, 0, 0, 0, 5, 0, 0, 1, 0]));
+        expectations.push(SpiTransaction::read_vec(packet.to_vec()));
+        let mut tail = ETH_FCS::new(&packet).hton_bytes().to_vec();
+        tail.resize(tail.len() + 2, DONT_CARE_BYTE);
+        expectations.push(SpiTransaction::read_vec(tail));
+        expectations.push(SpiTransaction::flush());
+
+        let mut th = TestHarnass::new(&expectations, false, false);
+        th.spe.chip = Chip::Adin2111;
+        th.spe.timestamps = true;
+
+        let (n, timestamp) = th.spe.read_fifo_port(&mut frame, Port::P2).await.expect("Error!");
+        assert_eq!(n, packet.len());
+        assert_eq!(
+            timestamp,
+            Some(Timestamp {
+                seconds: 5,
+                nanoseconds: 256
+            })
+        );
+
+        th.done();
+    }
+
+    #[futures_test::test]
+    async fn write_packet_to_p2_fifo_with_timestamp_capture() {
+        let mut expectations = vec![];
+
+        // Write TX_SIZE reg
+        expectations.push(SpiTransaction::write_vec(vec![160, 48, 136, 0, 0, 0, 66, 201]));
+        expectations.push(SpiTransaction::flush());
+
+        // Write TX reg, with the frame header for P2 and capture in TTSCB.
+        expectations.push(SpiTransaction::write_vec(vec![160, 49, 143, 0x80, 0x01]));
+        let packet = [0xFF_u8; 60];
+        expectations.push(SpiTransaction::write_vec(packet.to_vec()));
+        expectations.push(SpiTransaction::write_vec(vec![
+            77,
+            241,
+            140,
+            244,
+            DONT_CARE_BYTE,
+            DONT_CARE_BYTE,
+        ]));
+        expectations.push(SpiTransaction::flush());
+
+        let mut th = TestHarnass::new(&expectations, true, true);
+        th.spe.chip = Chip::Adin2111;
+
+        assert!(th
+            .spe
+            .write_fifo_port(&packet, Port::P2, Some(TimestampSlot::B))
+            .await
+            .is_ok());
+
+        th.done();
+    }
+
     #[futures_test::test]
     async fn spi_crc_error() {
         // Configure expectations
@@ -0,0 +1,282 @@
+//! IEEE 1588 frame timestamps.
+
+use crate::switch::Port;
+
+/// Time of the chip 1588 timer, as captured when a frame is sent or received.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    /// Seconds
+    pub seconds: u32,
+    /// Nanoseconds, less than 1 000 000 000.
+    pub nanoseconds: u32,
+}
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
+
+impl Timestamp {
+    /// Decode a 64-bit timestamp, 32 bits of seconds followed by 32 bits of nanoseconds.
+    #[must_use]
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+        Self {
+            seconds: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            nanoseconds: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        }
+    }
+
+    /// Total nanoseconds.
+    #[must_use]
+    pub fn as_nanos(&self) -> i64 {
+        i64::from(self.seconds) * NANOS_PER_SEC + i64::from(self.nanoseconds)
+    }
+
+    /// Timestamp from total nanoseconds, wrapping the seconds like the chip timer does.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_nanos(nanos: i64) -> Self {
+        Self {
+            seconds: nanos.div_euclid(NANOS_PER_SEC) as u32,
+            nanoseconds: nanos.rem_euclid(NANOS_PER_SEC) as u32,
+        }
+    }
+}
+
+/// PTP event message, identified by its type and sequence id.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PtpEvent {
+    /// `messageType`, such as 0 for `Sync` or 1 for `Delay_Req`.
+    pub message_type: u8,
+    /// `sequenceId`
+    pub sequence_id: u16,
+}
+
+/// Timestamp of a PTP event message sent or received on a port.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EventTimestamp {
+    /// The message.
+    pub event: PtpEvent,
+    /// The port it was sent or received on.
+    pub port: Port,
+    /// Time at the start of frame delimiter.
+    pub timestamp: Timestamp,
+}
+
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_PTP: u16 = 0x88F7;
+const IP_PROTO_UDP: u8 = 17;
+const PTP_EVENT_PORT: u16 = 319;
+
+/// Returns the PTP event message carried by an Ethernet `frame`, either directly over
+/// Ethernet or over UDP port 319, IPv4 or IPv6.
+///
+/// General messages such as `Follow_Up` don't need timestamps, so `None` is returned.
+#[must_use]
+pub fn ptp_event(frame: &[u8]) -> Option<PtpEvent> {
+    let be16 = |data: &[u8], at: usize| data.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+
+    let mut offset = 12;
+    let mut ethertype = be16(frame, offset)?;
+    if ethertype == ETHERTYPE_VLAN {
+        offset += 4;
+        ethertype = be16(frame, offset)?;
+    }
+    let payload = frame.get(offset + 2..)?;
+
+    let ptp = match ethertype {
+        ETHERTYPE_PTP => payload,
+        ETHERTYPE_IPV4 => {
+            let ihl = usize::from(payload.first()? & 0x0f) * 4;
+            if *payload.get(9)? != IP_PROTO_UDP {
+                return None;
+            }
+            udp_event_payload(payload.get(ihl..)?)?
+        }
+        // Extension headers are not supported.
+        ETHERTYPE_IPV6 if *payload.get(6)? == IP_PROTO_UDP => udp_event_payload(payload.get(40..)?)?,
+        _ => return None,
+    };
+
+    let message_type = ptp.first()? & 0x0f;
+    // Event messages: Sync, Delay_Req, Pdelay_Req, Pdelay_Resp.
+    if message_type > 3 {
+        return None;
+    }
+    Some(PtpEvent {
+        message_type,
+        sequence_id: be16(ptp, 30)?,
+    })
+}
+
+fn udp_event_payload(udp: &[u8]) -> Option<&[u8]> {
+    let dst_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    (dst_port == PTP_EVENT_PORT).then_some(udp.get(8..)?)
+}
+
+/// Ring of the most recent event timestamps.
+pub(crate) struct TimestampRing {
+    entries: [Option<EventTimestamp>; 8],
+    next: usize,
+}
+
+impl TimestampRing {
+    pub(crate) const fn new() -> Self {
+        Self {
+            entries: [None; 8],
+            next: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, ts: EventTimestamp) {
+        self.entries[self.next] = Some(ts);
+        self.next = (self.next + 1) % self.entries.len();
+    }
+
+    /// Take the timestamp of `event`, if it's still in the ring.
+    pub(crate) fn take(&mut self, event: PtpEvent) -> Option<EventTimestamp> {
+        self.entries
+            .iter_mut()
+            .find(|e| e.map_or(false, |e| e.event == event))
+            .and_then(Option::take)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ptp_message(message_type: u8, sequence_id: u16) -> std::vec::Vec<u8> {
+        let mut msg = std::vec![0; 44];
+        msg[0] = message_type;
+        msg[1] = 2;
+        msg[30..32].copy_from_slice(&sequence_id.to_be_bytes());
+        msg
+    }
+
+    fn ethernet(ethertype: u16, payload: &[u8]) -> std::vec::Vec<u8> {
+        let mut frame = std::vec![0x01, 0x1b, 0x19, 0, 0, 0, 0x02, 0, 0, 0, 0, 1];
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    fn udp(dst_port: u16, payload: &[u8]) -> std::vec::Vec<u8> {
+        let mut udp = std::vec![0x01, 0x3f];
+        udp.extend_from_slice(&dst_port.to_be_bytes());
+        udp.extend_from_slice(&[0, 0, 0, 0]);
+        udp.extend_from_slice(payload);
+        udp
+    }
+
+    #[test]
+    fn timestamp_nanos() {
+        let ts = Timestamp::from_be_bytes([0, 0, 0, 2, 0x3b, 0x9a, 0xc9, 0xff]);
+        assert_eq!(
+            ts,
+            Timestamp {
+                seconds: 2,
+                nanoseconds: 999_999_999
+            }
+        );
+        assert_eq!(
+            Timestamp::from_nanos(ts.as_nanos() + 1),
+            Timestamp {
+                seconds: 3,
+                nanoseconds: 0
+            }
+        );
+        assert_eq!(
+            Timestamp::from_nanos(-1),
+            Timestamp {
+                seconds: u32::MAX,
+                nanoseconds: 999_999_999
+            }
+        );
+    }
+
+    #[test]
+    fn layer2_event() {
+        let frame = ethernet(ETHERTYPE_PTP, &ptp_message(0x10 | 1, 0x1234));
+        assert_eq!(
+            ptp_event(&frame),
+            Some(PtpEvent {
+                message_type: 1,
+                sequence_id: 0x1234
+            })
+        );
+
+        // Follow_Up is a general message.
+        assert_eq!(ptp_event(&ethernet(ETHERTYPE_PTP, &ptp_message(8, 1))), None);
+    }
+
+    #[test]
+    fn udp_ipv4_event() {
+        let mut ip = std::vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, IP_PROTO_UDP];
+        ip.resize(20, 0);
+        ip.extend(udp(PTP_EVENT_PORT, &ptp_message(0, 7)));
+        assert_eq!(
+            ptp_event(&ethernet(ETHERTYPE_IPV4, &ip)),
+            Some(PtpEvent {
+                message_type: 0,
+                sequence_id: 7
+            })
+        );
+
+        // General messages go to port 320.
+        let mut ip = std::vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, IP_PROTO_UDP];
+        ip.resize(20, 0);
+        ip.extend(udp(320, &ptp_message(0, 7)));
+        assert_eq!(ptp_event(&ethernet(ETHERTYPE_IPV4, &ip)), None);
+    }
+
+    #[test]
+    fn udp_ipv6_event_with_vlan() {
+        let mut ip = std::vec![0x60, 0, 0, 0, 0, 0, IP_PROTO_UDP, 64];
+        ip.resize(40, 0);
+        ip.extend(udp(PTP_EVENT_PORT, &ptp_message(2, 9)));
+        let mut vlan = std::vec![0x00, 0x05];
+        vlan.extend_from_slice(&ETHERTYPE_IPV6.to_be_bytes());
+        vlan.extend(ip);
+        assert_eq!(
+            ptp_event(&ethernet(ETHERTYPE_VLAN, &vlan)),
+            Some(PtpEvent {
+                message_type: 2,
+                sequence_id: 9
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let frame = ethernet(ETHERTYPE_PTP, &ptp_message(0, 1));
+        // The sequence id ends 32 bytes into the PTP header.
+        for len in 0..14 + 32 {
+            assert_eq!(ptp_event(&frame[..len]), None);
+        }
+    }
+
+    #[test]
+    fn ring() {
+        let event = |sequence_id| PtpEvent {
+            message_type: 0,
+            sequence_id,
+        };
+        let ts = |sequence_id| EventTimestamp {
+            event: event(sequence_id),
+            port: Port::P1,
+            timestamp: Timestamp::default(),
+        };
+
+        let mut ring = TimestampRing::new();
+        for i in 0..10 {
+            ring.push(ts(i));
+        }
+        // The oldest ones were overwritten.
+        assert_eq!(ring.take(event(1)), None);
+        assert_eq!(ring.take(event(2)), Some(ts(2)));
+        assert_eq!(ring.take(event(2)), None);
+    }
+}
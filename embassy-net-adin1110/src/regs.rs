@@ -20,6 +20,12 @@ pub enum SpiRegisters {
     STATUS1 = 0x09,
     IMASK0 = 0x0C,
     IMASK1 = 0x0D,
+    TTSCAH = 0x10,
+    TTSCAL = 0x11,
+    TTSCBH = 0x12,
+    TTSCBL = 0x13,
+    TTSCCH = 0x14,
+    TTSCCL = 0x15,
     MDIO_ACC = 0x20,
     TX_FSIZE = 0x30,
     TX = 0x31,
@@ -33,8 +39,14 @@ pub enum SpiRegisters {
     ADDR_MSK_UPR0 = 0x71,
     ADDR_MSK_LWR1 = 0x72,
     ADDR_MSK_UPR1 = 0x73,
+    TS_ADDEND = 0x80,
+    TS_SEC_CNT = 0x82,
+    TS_NS_CNT = 0x83,
+    TS_CFG = 0x84,
     RX_FSIZE = 0x90,
     RX = 0x91,
+    RX_P2_FSIZE = 0xC0,
+    RX_P2 = 0xC1,
 }
 
 impl Display for SpiRegisters {
@@ -62,6 +74,12 @@ impl From<u16> for SpiRegisters {
             0x09 => Self::STATUS1,
             0x0C => Self::IMASK0,
             0x0D => Self::IMASK1,
+            0x10 => Self::TTSCAH,
+            0x11 => Self::TTSCAL,
+            0x12 => Self::TTSCBH,
+            0x13 => Self::TTSCBL,
+            0x14 => Self::TTSCCH,
+            0x15 => Self::TTSCCL,
             0x20 => Self::MDIO_ACC,
             0x30 => Self::TX_FSIZE,
             0x31 => Self::TX,
@@ -75,8 +93,14 @@ impl From<u16> for SpiRegisters {
             0x71 => Self::ADDR_MSK_UPR0,
             0x72 => Self::ADDR_MSK_LWR1,
             0x73 => Self::ADDR_MSK_UPR1,
+            0x80 => Self::TS_ADDEND,
+            0x82 => Self::TS_SEC_CNT,
+            0x83 => Self::TS_NS_CNT,
+            0x84 => Self::TS_CFG,
             0x90 => Self::RX_FSIZE,
             0x91 => Self::RX,
+            0xC0 => Self::RX_P2_FSIZE,
+            0xC1 => Self::RX_P2,
             e => panic!("Unknown value {}", e),
         }
     }
@@ -121,6 +145,8 @@ bitfield! {
     pub struct Status1(u32);
     impl Debug;
     u32;
+    /// Port 2 Rx FIFO Contains Data, ADIN2111 only
+    pub p2_rx_rdy, set_p2_rx_rdy : 17;
     /// ECC Error on Reading the Frame Size from a Tx FIFO
     pub tx_ecc_err, set_tx_ecc_err: 12;
     /// ECC Error on Reading the Frame Size from an Rx FIFO
@@ -177,6 +203,12 @@ bitfield! {
     pub struct Config2(u32);
     impl Debug;
     u32;
+    /// Forward Frames Not Matching Any MAC Address from Port 2 to Port 1, ADIN2111 only
+    pub p2_fwd_unk2p1, set_p2_fwd_unk2p1 : 13;
+    /// Forward Frames Not Matching Any MAC Address from Port 2 to the Host, ADIN2111 only
+    pub p2_fwd_unk2host, set_p2_fwd_unk2host : 12;
+    /// Cut Through Forwarding Between Ports, ADIN2111 only
+    pub port_cut_thru_en, set_port_cut_thru_en : 11;
     /// Assert TX_RDY When the Tx FIFO is Empty
     pub tx_rdy_on_empty, set_tx_rdy_on_empty : 8;
     /// Determines If the SFD is Detected in the PHY or MAC
@@ -187,6 +219,8 @@ bitfield! {
     pub crc_append, set_crc_append : 5;
     /// Admit Frames with IFG Errors on Port 1 (P1)
     pub p1_rcv_ifg_err_frm, set_p1_rcv_ifg_err_frm : 4;
+    /// Forward Frames Not Matching Any MAC Address from Port 1 to Port 2, ADIN2111 only
+    pub p1_fwd_unk2p2, set_p1_fwd_unk2p2 : 3;
     /// Forward Frames Not Matching Any MAC Address to the Host
     pub p1_fwd_unk2host, set_p1_fwd_unk2host : 2;
     /// SPI to MDIO Bridge MDC Clock Speed
@@ -231,6 +265,8 @@ bitfield! {
     pub struct IMask1(u32);
     impl Debug;
     u32;
+    /// Mask Bit for P2_RX_RDY, ADIN2111 only
+    pub p2_rx_rdy_mask, set_p2_rx_rdy_mask : 17;
     /// Mask Bit for TXF_ECC_ERR
     pub tx_ecc_err_mask, set_tx_ecc_err_mask : 12;
     /// Mask Bit for RXF_ECC_ERR
@@ -415,3 +451,33 @@ impl SpiHeader {
         pub from into SpiRegisters, addr, set_addr: 11, 0;
     }
 }
+
+/// Frame header, prepended to frames written to the Tx FIFO
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TxFrameHeader(pub u16);
+bitfield_bitrange! {struct TxFrameHeader(u16)}
+
+impl TxFrameHeader {
+    bitfield_fields! {
+        u16;
+        /// Capture the egress timestamp in TTSCA (1), TTSCB (2) or TTSCC (3), 0 for none
+        pub egress_capture, set_egress_capture : 15, 14;
+        /// Port to send the frame on, 0 = P1, 1 = P2, ADIN2111 only
+        pub port, set_port : 0;
+    }
+}
+
+bitfield! {
+    /// TS_CFG Register bits
+    pub struct TsCfg(u32);
+    impl Debug;
+    u32;
+    /// Capture the Free Running Counter with the Timer
+    pub ts_capt_freecnt, set_ts_capt_freecnt : 3;
+    /// Stop the Timer
+    pub ts_timer_stop, set_ts_timer_stop : 2;
+    /// Clear the Timer
+    pub ts_clr, set_ts_clr : 1;
+    /// Enable the Timer
+    pub ts_en, set_ts_en : 0;
+}
@@ -0,0 +1,244 @@
+//! ADIN2111 ports and forwarding table.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+/// Ethernet port of the chip. The ADIN1110 only has `P1`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Port {
+    /// Port 1
+    P1,
+    /// Port 2, ADIN2111 only.
+    P2,
+}
+
+impl Port {
+    /// MDIO address of the internal PHY of the port.
+    #[must_use]
+    pub fn phy_addr(self) -> u8 {
+        match self {
+            Port::P1 => 0x01,
+            Port::P2 => 0x02,
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Port::P1 => 0,
+            Port::P2 => 1,
+        }
+    }
+}
+
+/// Number of entries in the forwarding table.
+pub const FORWARDING_TABLE_SIZE: usize = 16;
+
+/// Time after which a learned entry is dropped, when its station wasn't heard from.
+/// 300 seconds, the IEEE 802.1D default.
+pub const FORWARDING_AGEING_TIME: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    mac: [u8; 6],
+    port: Port,
+    /// `None` for static entries, which never age.
+    last_seen: Option<Instant>,
+}
+
+/// Table of which port stations are reachable through, used by the ADIN2111 to send frames
+/// from the host only on the port the destination is on.
+///
+/// Entries are learned from the source address of received frames and age out after
+/// [`FORWARDING_AGEING_TIME`]. Static entries can be added and never age. Frames to
+/// unknown, multicast or broadcast destinations are sent on both ports.
+#[derive(Debug)]
+pub struct ForwardingTable {
+    entries: Vec<Entry, FORWARDING_TABLE_SIZE>,
+}
+
+impl Default for ForwardingTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ForwardingTable {
+    /// Create an empty table.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Learn that `mac` is reachable through `port`, as seen at `now`.
+    ///
+    /// Static entries are not changed. When the table is full, the least recently seen learned
+    /// entry is replaced.
+    pub fn learn(&mut self, mac: [u8; 6], port: Port, now: Instant) {
+        if !is_unicast(mac) {
+            return;
+        }
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.mac == mac) {
+            if entry.last_seen.is_some() {
+                entry.port = port;
+                entry.last_seen = Some(now);
+            }
+            return;
+        }
+
+        let entry = Entry {
+            mac,
+            port,
+            last_seen: Some(now),
+        };
+        if self.entries.push(entry).is_err() {
+            let oldest = self
+                .entries
+                .iter_mut()
+                .filter(|e| e.last_seen.is_some())
+                .min_by_key(|e| e.last_seen);
+            if let Some(oldest) = oldest {
+                *oldest = entry;
+            }
+        }
+    }
+
+    /// Add a static entry for `mac` on `port`, replacing any entry for `mac`.
+    ///
+    /// Returns `false` if the table is full of static entries.
+    pub fn insert_static(&mut self, mac: [u8; 6], port: Port) -> bool {
+        self.remove(mac);
+        let entry = Entry {
+            mac,
+            port,
+            last_seen: None,
+        };
+        if self.entries.push(entry).is_ok() {
+            return true;
+        }
+        match self
+            .entries
+            .iter_mut()
+            .filter(|e| e.last_seen.is_some())
+            .min_by_key(|e| e.last_seen)
+        {
+            Some(oldest) => {
+                *oldest = entry;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the entry for `mac`, if any.
+    pub fn remove(&mut self, mac: [u8; 6]) {
+        self.entries.retain(|e| e.mac != mac);
+    }
+
+    /// Remove the learned entries on `port`, for example when its link goes down.
+    pub fn flush(&mut self, port: Port) {
+        self.entries.retain(|e| e.port != port || e.last_seen.is_none());
+    }
+
+    /// Remove all entries, learned and static.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Get the port `mac` is reachable through, or `None` if unknown or not unicast.
+    #[must_use]
+    pub fn lookup(&self, mac: [u8; 6], now: Instant) -> Option<Port> {
+        self.entries
+            .iter()
+            .find(|e| e.mac == mac)
+            .filter(|e| {
+                e.last_seen
+                    .map_or(true, |t| now.saturating_duration_since(t) < FORWARDING_AGEING_TIME)
+            })
+            .map(|e| e.port)
+    }
+
+    /// Remove the learned entries older than [`FORWARDING_AGEING_TIME`].
+    pub fn age(&mut self, now: Instant) {
+        self.entries.retain(|e| {
+            e.last_seen
+                .map_or(true, |t| now.saturating_duration_since(t) < FORWARDING_AGEING_TIME)
+        });
+    }
+}
+
+fn is_unicast(mac: [u8; 6]) -> bool {
+    mac[0] & 0x01 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0a];
+    const B: [u8; 6] = [0x02, 0, 0, 0, 0, 0x0b];
+    const BROADCAST: [u8; 6] = [0xff; 6];
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    #[test]
+    fn learn_and_move() {
+        let mut table = ForwardingTable::new();
+        table.learn(A, Port::P1, at(0));
+        table.learn(B, Port::P2, at(0));
+        table.learn(BROADCAST, Port::P2, at(0));
+        assert_eq!(table.lookup(A, at(1)), Some(Port::P1));
+        assert_eq!(table.lookup(B, at(1)), Some(Port::P2));
+        assert_eq!(table.lookup(BROADCAST, at(1)), None);
+
+        // The station moved to the other port.
+        table.learn(A, Port::P2, at(2));
+        assert_eq!(table.lookup(A, at(3)), Some(Port::P2));
+    }
+
+    #[test]
+    fn ageing() {
+        let mut table = ForwardingTable::new();
+        table.learn(A, Port::P1, at(0));
+        assert!(table.insert_static(B, Port::P2));
+        assert_eq!(table.lookup(A, at(299)), Some(Port::P1));
+        assert_eq!(table.lookup(A, at(300)), None);
+
+        table.age(at(1000));
+        assert_eq!(table.entries.len(), 1);
+        assert_eq!(table.lookup(B, at(1000)), Some(Port::P2));
+    }
+
+    #[test]
+    fn static_entries_are_not_learned_over() {
+        let mut table = ForwardingTable::new();
+        assert!(table.insert_static(A, Port::P1));
+        table.learn(A, Port::P2, at(0));
+        assert_eq!(table.lookup(A, at(0)), Some(Port::P1));
+    }
+
+    #[test]
+    fn flush_on_link_down() {
+        let mut table = ForwardingTable::new();
+        table.learn(A, Port::P1, at(0));
+        table.learn(B, Port::P2, at(0));
+        table.flush(Port::P1);
+        assert_eq!(table.lookup(A, at(0)), None);
+        assert_eq!(table.lookup(B, at(0)), Some(Port::P2));
+    }
+
+    #[test]
+    fn full_table_replaces_oldest_learned() {
+        let mut table = ForwardingTable::new();
+        assert!(table.insert_static(A, Port::P1));
+        for i in 0..u8::try_from(FORWARDING_TABLE_SIZE).unwrap() {
+            table.learn([0x02, 0, 0, 0, 1, i], Port::P2, at(u64::from(i)));
+        }
+        // The first learned entry, seen at 0, was replaced.
+        assert_eq!(table.lookup([0x02, 0, 0, 0, 1, 0], at(20)), None);
+        assert_eq!(table.lookup([0x02, 0, 0, 0, 1, 15], at(20)), Some(Port::P2));
+        assert_eq!(table.lookup(A, at(20)), Some(Port::P1));
+    }
+}
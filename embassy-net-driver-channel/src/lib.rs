@@ -10,7 +10,7 @@ use core::mem::MaybeUninit;
 use core::task::{Context, Poll};
 
 pub use embassy_net_driver as driver;
-use embassy_net_driver::{Capabilities, LinkState};
+use embassy_net_driver::{Capabilities, LinkState, Timestamp, TimestampCapabilities};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::waitqueue::WakerRegistration;
@@ -23,6 +23,8 @@ pub struct State<const MTU: usize, const N_RX: usize, const N_TX: usize> {
     rx: [PacketBuf<MTU>; N_RX],
     tx: [PacketBuf<MTU>; N_TX],
     inner: MaybeUninit<StateInner<'static, MTU>>,
+    headroom: usize,
+    tailroom: usize,
 }
 
 impl<const MTU: usize, const N_RX: usize, const N_TX: usize> State<MTU, N_RX, N_TX> {
@@ -30,10 +32,23 @@ impl<const MTU: usize, const N_RX: usize, const N_TX: usize> State<MTU, N_RX, N_
 
     /// Create a new channel state.
     pub const fn new() -> Self {
+        Self::with_room(0, 0)
+    }
+
+    /// Create a new channel state, reserving `headroom` bytes before and `tailroom` bytes after
+    /// each outbound packet.
+    ///
+    /// The driver can write its bus headers and trailers there, and send the packet from the
+    /// buffer it was written to by the stack, see [`TxPacket`]. The MTU reported to the stack
+    /// is reduced to `MTU - headroom - tailroom`.
+    pub const fn with_room(headroom: usize, tailroom: usize) -> Self {
+        assert!(headroom + tailroom < MTU);
         Self {
             rx: [Self::NEW_PACKET; N_RX],
             tx: [Self::NEW_PACKET; N_TX],
             inner: MaybeUninit::uninit(),
+            headroom,
+            tailroom,
         }
     }
 }
@@ -44,10 +59,30 @@ struct StateInner<'d, const MTU: usize> {
     shared: Mutex<NoopRawMutex, RefCell<Shared>>,
 }
 
+/// Number of transmit timestamps held until the stack picks them up.
+const TX_TIMESTAMP_QUEUE: usize = 4;
+
 struct Shared {
     link_state: LinkState,
     waker: WakerRegistration,
     hardware_address: driver::HardwareAddress,
+    tx_timestamps: [Option<(u32, Timestamp)>; TX_TIMESTAMP_QUEUE],
+    tx_timestamps_next: usize,
+}
+
+impl Shared {
+    fn push_tx_timestamp(&mut self, id: u32, timestamp: Timestamp) {
+        // When full, the oldest timestamp is dropped.
+        self.tx_timestamps[self.tx_timestamps_next] = Some((id, timestamp));
+        self.tx_timestamps_next = (self.tx_timestamps_next + 1) % TX_TIMESTAMP_QUEUE;
+        self.waker.wake();
+    }
+
+    fn pop_tx_timestamp(&mut self) -> Option<(u32, Timestamp)> {
+        (0..TX_TIMESTAMP_QUEUE)
+            .map(|i| (self.tx_timestamps_next + i) % TX_TIMESTAMP_QUEUE)
+            .find_map(|i| self.tx_timestamps[i].take())
+    }
 }
 
 /// Channel runner.
@@ -127,6 +162,11 @@ impl<'d, const MTU: usize> Runner<'d, MTU> {
         });
     }
 
+    /// Report the time an outbound packet that requested a timestamp with `id` was sent at.
+    pub fn tx_timestamp(&mut self, id: u32, timestamp: Timestamp) {
+        self.shared.lock(|s| s.borrow_mut().push_tx_timestamp(id, timestamp));
+    }
+
     /// Wait until there is space for more inbound packets and return a slice they can be copied into.
     pub async fn rx_buf(&mut self) -> &mut [u8] {
         let p = self.rx_chan.send().await;
@@ -150,30 +190,77 @@ impl<'d, const MTU: usize> Runner<'d, MTU> {
     /// Mark packet of len bytes as pushed to the inbound channel.
     pub fn rx_done(&mut self, len: usize) {
         let p = self.rx_chan.try_send().unwrap();
+        p.offset = 0;
         p.len = len;
+        p.timestamp = None;
+        self.rx_chan.send_done();
+    }
+
+    /// Mark packet of len bytes, starting `offset` bytes into the buffer, as pushed to the inbound channel.
+    ///
+    /// This allows reading a frame along with its bus headers into the buffer, without moving it afterwards.
+    pub fn rx_done_with_offset(&mut self, offset: usize, len: usize) {
+        let p = self.rx_chan.try_send().unwrap();
+        p.offset = offset;
+        p.len = len;
+        p.timestamp = None;
+        self.rx_chan.send_done();
+    }
+
+    /// Mark packet of len bytes, received at `timestamp`, as pushed to the inbound channel.
+    pub fn rx_done_with_timestamp(&mut self, len: usize, timestamp: Timestamp) {
+        let p = self.rx_chan.try_send().unwrap();
+        p.offset = 0;
+        p.len = len;
+        p.timestamp = Some(timestamp);
         self.rx_chan.send_done();
     }
 
     /// Wait until there is space for more outbound packets and return a slice they can be copied into.
     pub async fn tx_buf(&mut self) -> &mut [u8] {
         let p = self.tx_chan.receive().await;
-        &mut p.buf[..p.len]
+        p.frame_mut()
     }
 
     /// Check if there is space for more outbound packets right now.
     pub fn try_tx_buf(&mut self) -> Option<&mut [u8]> {
         let p = self.tx_chan.try_receive()?;
-        Some(&mut p.buf[..p.len])
+        Some(p.frame_mut())
     }
 
     /// Polling the outbound channel if there is space for packets.
     pub fn poll_tx_buf(&mut self, cx: &mut Context) -> Poll<&mut [u8]> {
         match self.tx_chan.poll_receive(cx) {
-            Poll::Ready(p) => Poll::Ready(&mut p.buf[..p.len]),
+            Poll::Ready(p) => Poll::Ready(p.frame_mut()),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Wait until there is an outbound packet and return it, along with the room around it.
+    pub async fn tx_packet(&mut self) -> TxPacket<'_> {
+        self.tx_chan.receive().await.tx_packet()
+    }
+
+    /// Check if there is an outbound packet right now.
+    pub fn try_tx_packet(&mut self) -> Option<TxPacket<'_>> {
+        Some(self.tx_chan.try_receive()?.tx_packet())
+    }
+
+    /// Polling the outbound channel for packets, along with the room around them.
+    pub fn poll_tx_packet(&mut self, cx: &mut Context) -> Poll<TxPacket<'_>> {
+        match self.tx_chan.poll_receive(cx) {
+            Poll::Ready(p) => Poll::Ready(p.tx_packet()),
             Poll::Pending => Poll::Pending,
         }
     }
 
+    /// Get the id of the timestamp requested for the next outbound packet, if any.
+    ///
+    /// Once the packet is sent, report its timestamp with this id to [`StateRunner::tx_timestamp`].
+    pub fn tx_timestamp_id(&mut self) -> Option<u32> {
+        self.tx_chan.try_receive()?.timestamp_id
+    }
+
     /// Mark outbound packet as copied.
     pub fn tx_done(&mut self) {
         self.tx_chan.receive_done();
@@ -198,6 +285,11 @@ impl<'d> StateRunner<'d> {
             s.waker.wake();
         });
     }
+
+    /// Report the time an outbound packet that requested a timestamp with `id` was sent at.
+    pub fn tx_timestamp(&self, id: u32, timestamp: Timestamp) {
+        self.shared.lock(|s| s.borrow_mut().push_tx_timestamp(id, timestamp));
+    }
 }
 
 impl<'d, const MTU: usize> RxRunner<'d, MTU> {
@@ -224,7 +316,29 @@ impl<'d, const MTU: usize> RxRunner<'d, MTU> {
     /// Mark packet of len bytes as pushed to the inbound channel.
     pub fn rx_done(&mut self, len: usize) {
         let p = self.rx_chan.try_send().unwrap();
+        p.offset = 0;
+        p.len = len;
+        p.timestamp = None;
+        self.rx_chan.send_done();
+    }
+
+    /// Mark packet of len bytes, starting `offset` bytes into the buffer, as pushed to the inbound channel.
+    ///
+    /// This allows reading a frame along with its bus headers into the buffer, without moving it afterwards.
+    pub fn rx_done_with_offset(&mut self, offset: usize, len: usize) {
+        let p = self.rx_chan.try_send().unwrap();
+        p.offset = offset;
+        p.len = len;
+        p.timestamp = None;
+        self.rx_chan.send_done();
+    }
+
+    /// Mark packet of len bytes, received at `timestamp`, as pushed to the inbound channel.
+    pub fn rx_done_with_timestamp(&mut self, len: usize, timestamp: Timestamp) {
+        let p = self.rx_chan.try_send().unwrap();
+        p.offset = 0;
         p.len = len;
+        p.timestamp = Some(timestamp);
         self.rx_chan.send_done();
     }
 }
@@ -233,23 +347,48 @@ impl<'d, const MTU: usize> TxRunner<'d, MTU> {
     /// Wait until there is space for more outbound packets and return a slice they can be copied into.
     pub async fn tx_buf(&mut self) -> &mut [u8] {
         let p = self.tx_chan.receive().await;
-        &mut p.buf[..p.len]
+        p.frame_mut()
     }
 
     /// Check if there is space for more outbound packets right now.
     pub fn try_tx_buf(&mut self) -> Option<&mut [u8]> {
         let p = self.tx_chan.try_receive()?;
-        Some(&mut p.buf[..p.len])
+        Some(p.frame_mut())
     }
 
     /// Polling the outbound channel if there is space for packets.
     pub fn poll_tx_buf(&mut self, cx: &mut Context) -> Poll<&mut [u8]> {
         match self.tx_chan.poll_receive(cx) {
-            Poll::Ready(p) => Poll::Ready(&mut p.buf[..p.len]),
+            Poll::Ready(p) => Poll::Ready(p.frame_mut()),
             Poll::Pending => Poll::Pending,
         }
     }
 
+    /// Wait until there is an outbound packet and return it, along with the room around it.
+    pub async fn tx_packet(&mut self) -> TxPacket<'_> {
+        self.tx_chan.receive().await.tx_packet()
+    }
+
+    /// Check if there is an outbound packet right now.
+    pub fn try_tx_packet(&mut self) -> Option<TxPacket<'_>> {
+        Some(self.tx_chan.try_receive()?.tx_packet())
+    }
+
+    /// Polling the outbound channel for packets, along with the room around them.
+    pub fn poll_tx_packet(&mut self, cx: &mut Context) -> Poll<TxPacket<'_>> {
+        match self.tx_chan.poll_receive(cx) {
+            Poll::Ready(p) => Poll::Ready(p.tx_packet()),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Get the id of the timestamp requested for the next outbound packet, if any.
+    ///
+    /// Once the packet is sent, report its timestamp with this id to [`StateRunner::tx_timestamp`].
+    pub fn tx_timestamp_id(&mut self) -> Option<u32> {
+        self.tx_chan.try_receive()?.timestamp_id
+    }
+
     /// Mark outbound packet as copied.
     pub fn tx_done(&mut self) {
         self.tx_chan.receive_done();
@@ -267,7 +406,8 @@ pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
     hardware_address: driver::HardwareAddress,
 ) -> (Runner<'d, MTU>, Device<'d, MTU>) {
     let mut caps = Capabilities::default();
-    caps.max_transmission_unit = MTU;
+    caps.max_transmission_unit = MTU - state.headroom - state.tailroom;
+    let headroom = state.headroom;
 
     // safety: this is a self-referential struct, however:
     // - it can't move while the `'d` borrow is active.
@@ -281,6 +421,8 @@ pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
             link_state: LinkState::Down,
             hardware_address,
             waker: WakerRegistration::new(),
+            tx_timestamps: [None; TX_TIMESTAMP_QUEUE],
+            tx_timestamps_next: 0,
         })),
     });
 
@@ -298,20 +440,138 @@ pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
             shared: &state.shared,
             rx: rx_receiver,
             tx: tx_sender,
+            headroom,
         },
     )
 }
 
 /// Represents a packet of size MTU.
+///
+/// The buffer is 4-byte aligned, so drivers for word-oriented buses can send a packet in place
+/// when their headroom keeps the words aligned.
+#[repr(C, align(4))]
 pub struct PacketBuf<const MTU: usize> {
-    len: usize,
     buf: [u8; MTU],
+    offset: usize,
+    len: usize,
+    /// Time an inbound packet was received at.
+    timestamp: Option<Timestamp>,
+    /// Id of the timestamp requested for an outbound packet.
+    timestamp_id: Option<u32>,
 }
 
 impl<const MTU: usize> PacketBuf<MTU> {
     /// Create a new packet buffer.
     pub const fn new() -> Self {
-        Self { len: 0, buf: [0; MTU] }
+        Self {
+            offset: 0,
+            len: 0,
+            buf: [0; MTU],
+            timestamp: None,
+            timestamp_id: None,
+        }
+    }
+
+    fn frame_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.offset..][..self.len]
+    }
+
+    fn tx_packet(&mut self) -> TxPacket<'_> {
+        TxPacket {
+            buf: &mut self.buf,
+            offset: self.offset,
+            len: self.len,
+        }
+    }
+}
+
+/// An outbound packet, in the buffer the stack wrote it to.
+///
+/// The room before and after the frame, reserved with [`State::with_room`], is free for the
+/// driver to use, so it can add its bus headers and trailers and send the packet without copying it.
+/// Drivers for buses or DMA engines that gather a transfer from several buffers can instead send
+/// the packet as a [`TxChain`].
+pub struct TxPacket<'a> {
+    buf: &'a mut [u8],
+    offset: usize,
+    len: usize,
+}
+
+impl<'a> TxPacket<'a> {
+    /// Length of the frame.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the frame is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of free bytes before the frame.
+    pub fn headroom(&self) -> usize {
+        self.offset
+    }
+
+    /// Number of free bytes after the frame.
+    pub fn tailroom(&self) -> usize {
+        self.buf.len() - self.offset - self.len
+    }
+
+    /// The frame.
+    pub fn frame(&mut self) -> &mut [u8] {
+        &mut self.buf[self.offset..][..self.len]
+    }
+
+    /// The frame, preceded by `head` bytes and followed by `tail` bytes of room to write into.
+    ///
+    /// Panics if `head` is more than [`headroom`](Self::headroom) or `tail` is more than [`tailroom`](Self::tailroom).
+    pub fn with_room(&mut self, head: usize, tail: usize) -> &mut [u8] {
+        assert!(head <= self.headroom() && tail <= self.tailroom());
+        &mut self.buf[self.offset - head..self.offset + self.len + tail]
+    }
+
+    /// The packet as a chain of buffers: `head`, the frame, then `tail`.
+    pub fn chain<'c>(&'c self, head: &'c [u8], tail: &'c [u8]) -> TxChain<'c> {
+        TxChain {
+            segments: [head, &self.buf[self.offset..][..self.len], tail],
+            next: 0,
+        }
+    }
+}
+
+/// A packet as a chain of buffers, see [`TxPacket::chain`].
+///
+/// Iterates over the non-empty buffers of the chain, in order.
+#[derive(Clone)]
+pub struct TxChain<'a> {
+    segments: [&'a [u8]; 3],
+    next: usize,
+}
+
+impl<'a> TxChain<'a> {
+    /// Total length of the remaining buffers.
+    pub fn len(&self) -> usize {
+        self.segments[self.next..].iter().map(|s| s.len()).sum()
+    }
+
+    /// Whether the remaining buffers are all empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a> Iterator for TxChain<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        while let Some(segment) = self.segments.get(self.next) {
+            self.next += 1;
+            if !segment.is_empty() {
+                return Some(segment);
+            }
+        }
+        None
     }
 }
 
@@ -323,6 +583,17 @@ pub struct Device<'d, const MTU: usize> {
     tx: zerocopy_channel::Sender<'d, NoopRawMutex, PacketBuf<MTU>>,
     shared: &'d Mutex<NoopRawMutex, RefCell<Shared>>,
     caps: Capabilities,
+    headroom: usize,
+}
+
+impl<'d, const MTU: usize> Device<'d, MTU> {
+    /// Set the packet timestamping capabilities reported to the stack.
+    ///
+    /// Drivers that timestamp packets pass received timestamps to [`Runner::rx_done_with_timestamp`],
+    /// and report transmitted ones with [`StateRunner::tx_timestamp`].
+    pub fn set_timestamp_capabilities(&mut self, timestamps: TimestampCapabilities) {
+        self.caps.timestamps = timestamps;
+    }
 }
 
 impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
@@ -330,8 +601,22 @@ impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
     type TxToken<'a> = TxToken<'a, MTU> where Self: 'a ;
 
     fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        if self.rx.poll_receive(cx).is_ready() && self.tx.poll_send(cx).is_ready() {
-            Some((RxToken { rx: self.rx.borrow() }, TxToken { tx: self.tx.borrow() }))
+        let Poll::Ready(pkt) = self.rx.poll_receive(cx) else {
+            return None;
+        };
+        let timestamp = pkt.timestamp;
+        if self.tx.poll_send(cx).is_ready() {
+            Some((
+                RxToken {
+                    rx: self.rx.borrow(),
+                    timestamp,
+                },
+                TxToken {
+                    tx: self.tx.borrow(),
+                    headroom: self.headroom,
+                    timestamp_id: None,
+                },
+            ))
         } else {
             None
         }
@@ -340,7 +625,11 @@ impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
     /// Construct a transmit token.
     fn transmit(&mut self, cx: &mut Context) -> Option<Self::TxToken<'_>> {
         if self.tx.poll_send(cx).is_ready() {
-            Some(TxToken { tx: self.tx.borrow() })
+            Some(TxToken {
+                tx: self.tx.borrow(),
+                headroom: self.headroom,
+                timestamp_id: None,
+            })
         } else {
             None
         }
@@ -362,6 +651,14 @@ impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
             s.link_state
         })
     }
+
+    fn transmit_timestamp(&mut self, cx: &mut Context) -> Option<(u32, Timestamp)> {
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.waker.register(cx.waker());
+            s.pop_tx_timestamp()
+        })
+    }
 }
 
 /// A rx token.
@@ -369,6 +666,7 @@ impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
 /// Holds inbound receive channel and interfaces with embassy-net-driver.
 pub struct RxToken<'a, const MTU: usize> {
     rx: zerocopy_channel::Receiver<'a, NoopRawMutex, PacketBuf<MTU>>,
+    timestamp: Option<Timestamp>,
 }
 
 impl<'a, const MTU: usize> embassy_net_driver::RxToken for RxToken<'a, MTU> {
@@ -378,10 +676,14 @@ impl<'a, const MTU: usize> embassy_net_driver::RxToken for RxToken<'a, MTU> {
     {
         // NOTE(unwrap): we checked the queue wasn't full when creating the token.
         let pkt = unwrap!(self.rx.try_receive());
-        let r = f(&mut pkt.buf[..pkt.len]);
+        let r = f(pkt.frame_mut());
         self.rx.receive_done();
         r
     }
+
+    fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
 }
 
 /// A tx token.
@@ -389,6 +691,8 @@ impl<'a, const MTU: usize> embassy_net_driver::RxToken for RxToken<'a, MTU> {
 /// Holds outbound transmit channel and interfaces with embassy-net-driver.
 pub struct TxToken<'a, const MTU: usize> {
     tx: zerocopy_channel::Sender<'a, NoopRawMutex, PacketBuf<MTU>>,
+    headroom: usize,
+    timestamp_id: Option<u32>,
 }
 
 impl<'a, const MTU: usize> embassy_net_driver::TxToken for TxToken<'a, MTU> {
@@ -398,9 +702,147 @@ impl<'a, const MTU: usize> embassy_net_driver::TxToken for TxToken<'a, MTU> {
     {
         // NOTE(unwrap): we checked the queue wasn't full when creating the token.
         let pkt = unwrap!(self.tx.try_send());
-        let r = f(&mut pkt.buf[..len]);
+        let r = f(&mut pkt.buf[self.headroom..][..len]);
+        pkt.offset = self.headroom;
         pkt.len = len;
+        pkt.timestamp_id = self.timestamp_id;
         self.tx.send_done();
         r
     }
+
+    fn request_timestamp(&mut self, id: u32) {
+        self.timestamp_id = Some(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr;
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    use embassy_net_driver::{Driver, RxToken as _, TxToken as _};
+
+    use super::*;
+
+    const HW_ADDR: driver::HardwareAddress = driver::HardwareAddress::Ethernet([2, 0, 0, 0, 0, 1]);
+
+    static VTABLE: RawWakerVTable =
+        RawWakerVTable::new(|_| RawWaker::new(ptr::null(), &VTABLE), |_| {}, |_| {}, |_| {});
+
+    /// A waker for calling the poll-based `Driver` methods.
+    fn noop_waker() -> Waker {
+        unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+    }
+
+    #[test]
+    fn with_room_reduces_mtu() {
+        let mut state = State::<64, 1, 1>::with_room(4, 2);
+        let (_runner, device) = new(&mut state, HW_ADDR);
+        assert_eq!(device.capabilities().max_transmission_unit, 58);
+
+        let mut state = State::<64, 1, 1>::new();
+        let (_runner, device) = new(&mut state, HW_ADDR);
+        assert_eq!(device.capabilities().max_transmission_unit, 64);
+    }
+
+    #[test]
+    fn tx_packet_room() {
+        let mut state = State::<64, 1, 1>::with_room(4, 2);
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let token = device.transmit(&mut Context::from_waker(&noop_waker())).unwrap();
+        token.consume(10, |buf| buf.copy_from_slice(&[0xaa; 10]));
+
+        let mut packet = runner.try_tx_packet().unwrap();
+        assert_eq!(packet.len(), 10);
+        assert_eq!(packet.headroom(), 4);
+        assert_eq!(packet.tailroom(), 50);
+        assert_eq!(packet.frame(), &[0xaa; 10]);
+
+        let buf = packet.with_room(4, 2);
+        assert_eq!(buf.len(), 16);
+        assert_eq!(&buf[4..14], &[0xaa; 10]);
+        buf[..4].copy_from_slice(&[1, 2, 3, 4]);
+        buf[14..].copy_from_slice(&[5, 6]);
+        assert_eq!(packet.frame(), &[0xaa; 10]);
+        runner.tx_done();
+
+        assert!(runner.try_tx_packet().is_none());
+    }
+
+    #[test]
+    fn tx_packet_aligned() {
+        let mut state = State::<67, 1, 1>::with_room(6, 1);
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let token = device.transmit(&mut Context::from_waker(&noop_waker())).unwrap();
+        token.consume(5, |_| {});
+        assert_eq!(runner.try_tx_packet().unwrap().with_room(6, 1).as_ptr() as usize % 4, 0);
+    }
+
+    #[test]
+    fn tx_buf_skips_room() {
+        let mut state = State::<64, 1, 1>::with_room(4, 0);
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let token = device.transmit(&mut Context::from_waker(&noop_waker())).unwrap();
+        token.consume(3, |buf| buf.copy_from_slice(&[1, 2, 3]));
+        assert_eq!(runner.try_tx_buf().unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn tx_packet_room_too_big() {
+        let mut state = State::<64, 1, 1>::with_room(4, 2);
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let token = device.transmit(&mut Context::from_waker(&noop_waker())).unwrap();
+        token.consume(10, |_| {});
+        runner.try_tx_packet().unwrap().with_room(5, 0);
+    }
+
+    #[test]
+    fn tx_chain() {
+        let mut state = State::<64, 1, 1>::with_room(4, 0);
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let token = device.transmit(&mut Context::from_waker(&noop_waker())).unwrap();
+        token.consume(3, |buf| buf.copy_from_slice(&[1, 2, 3]));
+        let packet = runner.try_tx_packet().unwrap();
+
+        let mut chain = packet.chain(&[9, 9], &[8]);
+        assert_eq!(chain.len(), 6);
+        assert_eq!(chain.next(), Some(&[9, 9][..]));
+        assert_eq!(chain.len(), 4);
+        assert_eq!(chain.next(), Some(&[1, 2, 3][..]));
+        assert_eq!(chain.next(), Some(&[8][..]));
+        assert_eq!(chain.next(), None);
+        assert!(chain.is_empty());
+
+        // Empty buffers are skipped.
+        let chain = packet.chain(&[], &[]);
+        assert_eq!(chain.clone().count(), 1);
+        assert!(chain.flatten().copied().eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn rx_done_with_offset() {
+        let mut state = State::<64, 1, 1>::new();
+        let (mut runner, mut device) = new(&mut state, HW_ADDR);
+
+        let buf = runner.try_rx_buf().unwrap();
+        buf[..6].copy_from_slice(&[0xff, 0xff, 1, 2, 3, 4]);
+        runner.rx_done_with_offset(2, 4);
+
+        let (rx, _) = device.receive(&mut Context::from_waker(&noop_waker())).unwrap();
+        rx.consume(|buf| assert_eq!(buf, &[1, 2, 3, 4]));
+
+        // The offset doesn't stick to the buffer.
+        let buf = runner.try_rx_buf().unwrap();
+        buf[..2].copy_from_slice(&[5, 6]);
+        runner.rx_done(2);
+
+        let (rx, _) = device.receive(&mut Context::from_waker(&noop_waker())).unwrap();
+        rx.consume(|buf| assert_eq!(buf, &[5, 6]));
+    }
 }
@@ -80,6 +80,18 @@ pub trait Driver {
     /// what kind of packet the sent/received bytes are, and determines some behaviors of
     /// the interface. For example, ARP/NDISC address resolution is only done for Ethernet mediums.
     fn hardware_address(&self) -> HardwareAddress;
+
+    /// Get the timestamp of a transmitted packet.
+    ///
+    /// Drivers that timestamp transmitted packets (see [`TimestampCapabilities::tx`]) report here
+    /// the time packets were sent at, along with the id they were given by [`TxToken::request_timestamp`].
+    /// If there is no timestamp ready, this function must return `None`, and wake `cx.waker()` when there is.
+    ///
+    /// The default implementation never returns a timestamp.
+    fn transmit_timestamp(&mut self, cx: &mut Context) -> Option<(u32, Timestamp)> {
+        let _ = cx;
+        None
+    }
 }
 
 impl<T: ?Sized + Driver> Driver for &mut T {
@@ -105,6 +117,9 @@ impl<T: ?Sized + Driver> Driver for &mut T {
     fn hardware_address(&self) -> HardwareAddress {
         T::hardware_address(self)
     }
+    fn transmit_timestamp(&mut self, cx: &mut Context) -> Option<(u32, Timestamp)> {
+        T::transmit_timestamp(self, cx)
+    }
 }
 
 /// A token to receive a single network packet.
@@ -116,6 +131,13 @@ pub trait RxToken {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R;
+
+    /// Time the packet was received at, if the device timestamps received packets.
+    ///
+    /// The default implementation returns `None`.
+    fn timestamp(&self) -> Option<Timestamp> {
+        None
+    }
 }
 
 /// A token to transmit a single network packet.
@@ -129,6 +151,16 @@ pub trait TxToken {
     fn consume<R, F>(self, len: usize, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R;
+
+    /// Request a timestamp of the time the packet is sent at.
+    ///
+    /// Called before [`consume`](TxToken::consume). The timestamp is reported later by
+    /// [`Driver::transmit_timestamp`] along with `id`.
+    ///
+    /// The default implementation ignores the request.
+    fn request_timestamp(&mut self, id: u32) {
+        let _ = id;
+    }
 }
 
 /// A description of device capabilities.
@@ -167,6 +199,50 @@ pub struct Capabilities {
     /// If the network device is capable of verifying or computing checksums for some protocols,
     /// it can request that the stack not do so in software to improve performance.
     pub checksum: ChecksumCapabilities,
+
+    /// Packet timestamping behavior.
+    ///
+    /// If the network device can timestamp packets, for example with the clock used for IEEE 1588
+    /// (PTP), the timestamps are made available to the stack.
+    pub timestamps: TimestampCapabilities,
+}
+
+/// A description of packet timestamping behavior.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct TimestampCapabilities {
+    /// Received packets are timestamped, see [`RxToken::timestamp`].
+    pub rx: bool,
+    /// Transmitted packets are timestamped on request, see [`TxToken::request_timestamp`].
+    pub tx: bool,
+}
+
+/// Time a packet was sent or received at, from the device clock.
+///
+/// The epoch depends on the device. For PTP capable devices, it's usually the PTP timescale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    /// Seconds.
+    pub seconds: u64,
+    /// Nanoseconds, less than 1 000 000 000.
+    pub nanoseconds: u32,
+}
+
+impl Timestamp {
+    /// Create a timestamp from a total number of nanoseconds.
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self {
+            seconds: nanos / 1_000_000_000,
+            nanoseconds: (nanos % 1_000_000_000) as u32,
+        }
+    }
+
+    /// Total number of nanoseconds.
+    pub const fn as_nanos(&self) -> u64 {
+        self.seconds * 1_000_000_000 + self.nanoseconds as u64
+    }
 }
 
 /// A description of checksum behavior for every supported protocol.
@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 
@@ -18,10 +18,13 @@ mod traits;
 
 use core::cmp;
 
-use embassy_net_driver::{Capabilities, HardwareAddress, LinkState};
-use embassy_time::Duration;
+use embassy_futures::select::{select, Either};
+use embassy_net_driver_channel as ch;
+use embassy_net_driver_channel::driver::LinkState;
+use embassy_time::Timer;
 use embedded_hal::digital::OutputPin;
-use embedded_hal::spi::{Operation, SpiDevice};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::{Operation, SpiDevice};
 use traits::U16Ext;
 
 // Total buffer size (see section 3.2)
@@ -43,12 +46,146 @@ const _TXND: u16 = 0x1fff;
 
 const MTU: usize = 1514; // 1500 IP + 14 ethernet header
 
-/// ENC28J60 embassy-net driver
-pub struct Enc28j60<S, O> {
+/// Type alias for the embassy-net driver for ENC28J60
+pub type Device<'d> = embassy_net_driver_channel::Device<'d, MTU>;
+
+/// Internal state for the embassy-net integration.
+pub struct State<const N_RX: usize, const N_TX: usize> {
+    ch_state: ch::State<MTU, N_RX, N_TX>,
+}
+
+impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
+    /// Create a new `State`.
+    pub const fn new() -> Self {
+        Self {
+            ch_state: ch::State::new(),
+        }
+    }
+}
+
+/// Background runner for the ENC28J60.
+///
+/// You must call `.run()` in a background task for the ENC28J60 to operate.
+pub struct Runner<'d, S, I, O> {
+    mac: Enc28j60<S>,
+    ch: ch::Runner<'d, MTU>,
+    int: I,
+    _rst: Option<O>,
+}
+
+impl<'d, S, I, O> Runner<'d, S, I, O>
+where
+    S: SpiDevice,
+    I: Wait,
+    O: OutputPin,
+{
+    /// Adjusts the receive filter to *accept* these packet types
+    pub async fn accept(&mut self, packets: &[Packet]) {
+        self.mac.accept(packets).await
+    }
+
+    /// Adjusts the receive filter to *ignore* these packet types
+    pub async fn ignore(&mut self, packets: &[Packet]) {
+        self.mac.ignore(packets).await
+    }
+
+    /// Run the driver.
+    pub async fn run(mut self) -> ! {
+        let (state_chan, mut rx_chan, mut tx_chan) = self.ch.split();
+
+        let link_state = match self.mac.is_link_up().await {
+            true => LinkState::Up,
+            false => LinkState::Down,
+        };
+        state_chan.set_link_state(link_state);
+
+        // INT stays asserted for as long as there are unread packets, so once we know there are
+        // some we stop waiting on it until the channel has room for them.
+        let mut rx_pending = self.mac.pending_packets().await > 0;
+        loop {
+            let int = &mut self.int;
+            let rx = async {
+                if rx_pending {
+                    Some(rx_chan.rx_buf().await)
+                } else {
+                    int.wait_for_low().await.unwrap();
+                    None
+                }
+            };
+            match select(rx, tx_chan.tx_buf()).await {
+                Either::First(Some(buf)) => {
+                    if let Some(n) = self.mac.receive(buf).await {
+                        rx_chan.rx_done(n);
+                    }
+                    rx_pending = self.mac.pending_packets().await > 0;
+                }
+                Either::First(None) => {
+                    if let Some(up) = self.mac.service_interrupts().await {
+                        let link_state = match up {
+                            true => LinkState::Up,
+                            false => LinkState::Down,
+                        };
+                        state_chan.set_link_state(link_state);
+                    }
+                    // Errata #6: PKTIF is unreliable, so whatever the interrupt source was, check
+                    // EPKTCNT instead.
+                    rx_pending = self.mac.pending_packets().await > 0;
+                }
+                Either::Second(buf) => {
+                    self.mac.transmit(buf).await;
+                    tx_chan.tx_done();
+                }
+            }
+        }
+    }
+}
+
+/// Obtain a driver for using the ENC28J60 with [`embassy-net`](https://crates.io/crates/embassy-net).
+///
+/// `int` is the ENC28J60's active-low INT pin. The RST pin is optional. If None, reset will be
+/// done with a SPI soft reset command, instead of via the RST pin.
+pub async fn new<const N_RX: usize, const N_TX: usize, S, I, O>(
     mac_addr: [u8; 6],
+    state: &'_ mut State<N_RX, N_TX>,
+    spi: S,
+    int: I,
+    mut rst: Option<O>,
+) -> (Device<'_>, Runner<'_, S, I, O>)
+where
+    S: SpiDevice,
+    I: Wait,
+    O: OutputPin,
+{
+    let mut mac = Enc28j60::new(spi);
+
+    if let Some(rst) = &mut rst {
+        rst.set_low().unwrap();
+        Timer::after_millis(5).await;
+        rst.set_high().unwrap();
+        Timer::after_millis(5).await;
+    } else {
+        Timer::after_millis(5).await;
+        mac.soft_reset().await;
+        Timer::after_millis(5).await;
+    }
+
+    mac.init(mac_addr).await;
+
+    let (runner, device) = ch::new(&mut state.ch_state, ch::driver::HardwareAddress::Ethernet(mac_addr));
+
+    (
+        device,
+        Runner {
+            mac,
+            ch: runner,
+            int,
+            _rst: rst,
+        },
+    )
+}
 
+struct Enc28j60<S> {
     spi: S,
-    rst: Option<O>,
 
     bank: Bank,
 
@@ -56,62 +193,42 @@ pub struct Enc28j60<S, O> {
     next_packet: u16,
 }
 
-impl<S, O> Enc28j60<S, O>
+impl<S> Enc28j60<S>
 where
     S: SpiDevice,
-    O: OutputPin,
 {
-    /// Create a new ENC28J60 driver instance.
-    ///
-    /// The RST pin is optional. If None, reset will be done with a SPI
-    /// soft reset command, instead of via the RST pin.
-    pub fn new(spi: S, rst: Option<O>, mac_addr: [u8; 6]) -> Self {
-        let mut res = Self {
-            mac_addr,
+    fn new(spi: S) -> Self {
+        Self {
             spi,
-            rst,
 
             bank: Bank::Bank0,
             next_packet: RXST,
-        };
-        res.init();
-        res
-    }
-
-    fn init(&mut self) {
-        if let Some(rst) = &mut self.rst {
-            rst.set_low().unwrap();
-            embassy_time::block_for(Duration::from_millis(5));
-            rst.set_high().unwrap();
-            embassy_time::block_for(Duration::from_millis(5));
-        } else {
-            embassy_time::block_for(Duration::from_millis(5));
-            self.soft_reset();
-            embassy_time::block_for(Duration::from_millis(5));
         }
+    }
 
+    async fn init(&mut self, mac_addr: [u8; 6]) {
         debug!(
             "enc28j60: erevid {=u8:x}",
-            self.read_control_register(bank3::Register::EREVID)
+            self.read_control_register(bank3::Register::EREVID).await
         );
         debug!("enc28j60: waiting for clk");
-        while common::ESTAT(self.read_control_register(common::Register::ESTAT)).clkrdy() == 0 {}
+        while common::ESTAT(self.read_control_register(common::Register::ESTAT).await).clkrdy() == 0 {}
         debug!("enc28j60: clk ok");
 
-        if self.read_control_register(bank3::Register::EREVID) == 0 {
+        if self.read_control_register(bank3::Register::EREVID).await == 0 {
             panic!("ErevidIsZero");
         }
 
         // disable CLKOUT output
-        self.write_control_register(bank3::Register::ECOCON, 0);
+        self.write_control_register(bank3::Register::ECOCON, 0).await;
 
-        self.init_rx();
+        self.init_rx().await;
 
         // TX start
         // "It is recommended that an even address be used for ETXST"
         debug_assert_eq!(TXST % 2, 0);
-        self.write_control_register(bank0::Register::ETXSTL, TXST.low());
-        self.write_control_register(bank0::Register::ETXSTH, TXST.high());
+        self.write_control_register(bank0::Register::ETXSTL, TXST.low()).await;
+        self.write_control_register(bank0::Register::ETXSTH, TXST.high()).await;
 
         // TX end is set in `transmit`
 
@@ -120,89 +237,105 @@ where
         self.write_control_register(
             bank2::Register::MACON1,
             bank2::MACON1::default().marxen(1).passall(0).rxpaus(1).txpaus(1).bits(),
-        );
+        )
+        .await;
 
         // 2. Configure the PADCFG, TXCRCEN and FULDPX bits of MACON3.
         self.write_control_register(
             bank2::Register::MACON3,
             bank2::MACON3::default().frmlnen(1).txcrcen(1).padcfg(0b001).bits(),
-        );
+        )
+        .await;
 
         // 4. Program the MAMXFL registers with the maximum frame length to be permitted to be
         // received or transmitted
-        self.write_control_register(bank2::Register::MAMXFLL, MAX_FRAME_LENGTH.low());
-        self.write_control_register(bank2::Register::MAMXFLH, MAX_FRAME_LENGTH.high());
+        self.write_control_register(bank2::Register::MAMXFLL, MAX_FRAME_LENGTH.low())
+            .await;
+        self.write_control_register(bank2::Register::MAMXFLH, MAX_FRAME_LENGTH.high())
+            .await;
 
         // 5. Configure the Back-to-Back Inter-Packet Gap register, MABBIPG.
         // Use recommended value of 0x12
-        self.write_control_register(bank2::Register::MABBIPG, 0x12);
+        self.write_control_register(bank2::Register::MABBIPG, 0x12).await;
 
         // 6. Configure the Non-Back-to-Back Inter-Packet Gap register low byte, MAIPGL.
         // Use recommended value of 0x12
-        self.write_control_register(bank2::Register::MAIPGL, 0x12);
-        self.write_control_register(bank2::Register::MAIPGH, 0x0c);
+        self.write_control_register(bank2::Register::MAIPGL, 0x12).await;
+        self.write_control_register(bank2::Register::MAIPGH, 0x0c).await;
 
         // 9. Program the local MAC address into the MAADR1:MAADR6 registers
-        self.write_control_register(bank3::Register::MAADR1, self.mac_addr[0]);
-        self.write_control_register(bank3::Register::MAADR2, self.mac_addr[1]);
-        self.write_control_register(bank3::Register::MAADR3, self.mac_addr[2]);
-        self.write_control_register(bank3::Register::MAADR4, self.mac_addr[3]);
-        self.write_control_register(bank3::Register::MAADR5, self.mac_addr[4]);
-        self.write_control_register(bank3::Register::MAADR6, self.mac_addr[5]);
+        self.write_control_register(bank3::Register::MAADR1, mac_addr[0]).await;
+        self.write_control_register(bank3::Register::MAADR2, mac_addr[1]).await;
+        self.write_control_register(bank3::Register::MAADR3, mac_addr[2]).await;
+        self.write_control_register(bank3::Register::MAADR4, mac_addr[3]).await;
+        self.write_control_register(bank3::Register::MAADR5, mac_addr[4]).await;
+        self.write_control_register(bank3::Register::MAADR6, mac_addr[5]).await;
 
         // Set the PHCON2.HDLDIS bit to prevent automatic loopback of the data which is transmitted
-        self.write_phy_register(phy::Register::PHCON2, phy::PHCON2::default().hdldis(1).bits());
+        self.write_phy_register(phy::Register::PHCON2, phy::PHCON2::default().hdldis(1).bits())
+            .await;
+
+        // Link change interrupts are generated by the PHY and forwarded to EIR.LINKIF
+        self.write_phy_register(phy::Register::PHIE, phy::PHIE::default().pgeie(1).plnkie(1).bits())
+            .await;
 
         // Globally enable interrupts
-        //self.bit_field_set(common::Register::EIE, common::EIE::mask().intie());
+        self.bit_field_set(common::Register::EIE, {
+            let mask = common::EIE::mask();
+            mask.intie() | mask.pktie() | mask.linkie() | mask.rxerie()
+        })
+        .await;
 
         // Set the per packet control byte; we'll always use the value 0
-        self.write_buffer_memory(Some(TXST), &mut [0]);
+        self.write_buffer_memory(Some(TXST), &[0]).await;
 
         // Enable reception
-        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxen());
+        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxen())
+            .await;
     }
 
-    fn init_rx(&mut self) {
+    async fn init_rx(&mut self) {
         // RX start
         // "It is recommended that the ERXST Pointer be programmed with an even address"
-        self.write_control_register(bank0::Register::ERXSTL, RXST.low());
-        self.write_control_register(bank0::Register::ERXSTH, RXST.high());
+        self.write_control_register(bank0::Register::ERXSTL, RXST.low()).await;
+        self.write_control_register(bank0::Register::ERXSTH, RXST.high()).await;
 
         // RX read pointer
         // NOTE Errata #14 so we are using an *odd* address here instead of ERXST
-        self.write_control_register(bank0::Register::ERXRDPTL, RXND.low());
-        self.write_control_register(bank0::Register::ERXRDPTH, RXND.high());
+        self.write_control_register(bank0::Register::ERXRDPTL, RXND.low()).await;
+        self.write_control_register(bank0::Register::ERXRDPTH, RXND.high()).await;
 
         // RX end
-        self.write_control_register(bank0::Register::ERXNDL, RXND.low());
-        self.write_control_register(bank0::Register::ERXNDH, RXND.high());
+        self.write_control_register(bank0::Register::ERXNDL, RXND.low()).await;
+        self.write_control_register(bank0::Register::ERXNDH, RXND.high()).await;
 
         // decrease the packet count to 0
-        while self.read_control_register(bank1::Register::EPKTCNT) != 0 {
-            self.bit_field_set(common::Register::ECON2, common::ECON2::mask().pktdec());
+        while self.read_control_register(bank1::Register::EPKTCNT).await != 0 {
+            self.bit_field_set(common::Register::ECON2, common::ECON2::mask().pktdec())
+                .await;
         }
 
         self.next_packet = RXST;
     }
 
-    fn reset_rx(&mut self) {
-        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxrst());
-        self.bit_field_clear(common::Register::ECON1, common::ECON1::mask().rxrst());
-        self.init_rx();
-        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxen());
+    async fn reset_rx(&mut self) {
+        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxrst())
+            .await;
+        self.bit_field_clear(common::Register::ECON1, common::ECON1::mask().rxrst())
+            .await;
+        self.init_rx().await;
+        self.bit_field_clear(common::Register::EIR, common::EIR::mask().rxerif())
+            .await;
+        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().rxen())
+            .await;
     }
 
-    /// Returns the device's MAC address
-    pub fn address(&self) -> [u8; 6] {
-        self.mac_addr
-    }
-
-    /// Flushes the transmit buffer, ensuring all pending transmissions have completed
-    /// NOTE: The returned packet *must* be `read` or `ignore`-d, otherwise this method will always
-    /// return `None` on subsequent invocations
-    pub fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
-        if self.pending_packets() == 0 {
+    /// Reads the next pending packet into `buf`.
+    ///
+    /// Returns `None` if there was no packet, the packet was dropped, or the RX buffer turned out
+    /// to be corrupted and had to be reset.
+    async fn receive(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.pending_packets().await == 0 {
             // Errata #6: we can't rely on PKTIF so we check PKTCNT
             return None;
         }
@@ -211,7 +344,7 @@ where
 
         // read out the first 6 bytes
         let mut temp_buf = [0; 6];
-        self.read_buffer_memory(Some(curr_packet), &mut temp_buf);
+        self.read_buffer_memory(Some(curr_packet), &mut temp_buf).await;
 
         // next packet pointer
         let next_packet = u16::from_parts(temp_buf[0], temp_buf[1]);
@@ -219,50 +352,85 @@ where
         let status = header::RxStatus(u32::from_le_bytes(temp_buf[2..].try_into().unwrap()));
         let len_with_crc = status.byte_count() as u16;
 
-        if len_with_crc < CRC_SZ || len_with_crc > 1600 || next_packet > RXND {
+        // The receive hardware may corrupt the buffer (errata #14 among others), in which case the
+        // header is garbage. Packets always start at an even address inside the RX buffer.
+        if len_with_crc < CRC_SZ || len_with_crc > 1600 || next_packet > RXND || next_packet % 2 != 0 {
             warn!("RX buffer corrupted, resetting RX logic to recover...");
-            self.reset_rx();
+            self.reset_rx().await;
             return None;
         }
 
         let len = len_with_crc - CRC_SZ;
-        self.read_buffer_memory(None, &mut buf[..len as usize]);
+        let ok = status.received_ok() == 1 && len as usize <= buf.len();
+        if ok {
+            self.read_buffer_memory(None, &mut buf[..len as usize]).await;
+        } else {
+            warn!("dropping bad packet");
+        }
 
         // update ERXRDPT
         // due to Errata #14 we must write an odd address to ERXRDPT
         // we know that ERXST = 0, that ERXND is odd and that next_packet is even
-        let rxrdpt = if self.next_packet < 1 || self.next_packet > RXND + 1 {
+        let rxrdpt = if next_packet < 1 || next_packet > RXND + 1 {
             RXND
         } else {
-            self.next_packet - 1
+            next_packet - 1
         };
         // "To move ERXRDPT, the host controller must write to ERXRDPTL first."
-        self.write_control_register(bank0::Register::ERXRDPTL, rxrdpt.low());
-        self.write_control_register(bank0::Register::ERXRDPTH, rxrdpt.high());
+        self.write_control_register(bank0::Register::ERXRDPTL, rxrdpt.low()).await;
+        self.write_control_register(bank0::Register::ERXRDPTH, rxrdpt.high()).await;
 
         // decrease the packet count
-        self.bit_field_set(common::Register::ECON2, common::ECON2::mask().pktdec());
+        self.bit_field_set(common::Register::ECON2, common::ECON2::mask().pktdec())
+            .await;
 
         self.next_packet = next_packet;
 
-        Some(len as usize)
+        ok.then_some(len as usize)
+    }
+
+    /// Handles and clears the pending interrupt flags.
+    ///
+    /// Returns the new link state if it changed. Received packets are left alone, they're
+    /// cleared by `receive`.
+    async fn service_interrupts(&mut self) -> Option<bool> {
+        let eir = common::EIR(self.read_control_register(common::Register::EIR).await);
+
+        let mut link = None;
+        if eir.linkif() == 1 {
+            // reading PHIR clears LINKIF
+            self.read_phy_register(phy::Register::PHIR).await;
+            link = Some(self.is_link_up().await);
+        }
+
+        if eir.rxerif() == 1 {
+            // The RX buffer was full or EPKTCNT overflowed, some packets have been dropped.
+            warn!("RX buffer overflow");
+            self.bit_field_clear(common::Register::EIR, common::EIR::mask().rxerif())
+                .await;
+        }
+
+        link
     }
 
-    fn wait_tx_ready(&mut self) {
+    async fn wait_tx_ready(&mut self) {
         for _ in 0u32..10000 {
-            if common::ECON1(self.read_control_register(common::Register::ECON1)).txrts() == 0 {
+            if common::ECON1(self.read_control_register(common::Register::ECON1).await).txrts() == 0 {
                 return;
             }
         }
 
         // work around errata #12 by resetting the transmit logic before every new
         // transmission
-        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().txrst());
-        self.bit_field_clear(common::Register::ECON1, common::ECON1::mask().txrst());
-        //self.bit_field_clear(common::Register::EIR, {
-        //    let mask = common::EIR::mask();
-        //    mask.txerif() | mask.txif()
-        //});
+        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().txrst())
+            .await;
+        self.bit_field_clear(common::Register::ECON1, common::ECON1::mask().txrst())
+            .await;
+        self.bit_field_clear(common::Register::EIR, {
+            let mask = common::EIR::mask();
+            mask.txerif() | mask.txif()
+        })
+        .await;
     }
 
     /// Starts the transmission of `bytes`
@@ -277,10 +445,10 @@ where
     ///
     /// If `bytes` length is greater than 1514, the maximum frame length allowed by the interface,
     /// or greater than the transmit buffer
-    pub fn transmit(&mut self, bytes: &[u8]) {
+    async fn transmit(&mut self, bytes: &[u8]) {
         assert!(bytes.len() <= self.mtu() as usize);
 
-        self.wait_tx_ready();
+        self.wait_tx_ready().await;
 
         // NOTE the plus one is to not overwrite the per packet control byte
         let wrpt = TXST + 1;
@@ -288,43 +456,22 @@ where
         // 1. ETXST was set during initialization
 
         // 2. write the frame to the IC memory
-        self.write_buffer_memory(Some(wrpt), bytes);
+        self.write_buffer_memory(Some(wrpt), bytes).await;
 
         let txnd = wrpt + bytes.len() as u16 - 1;
 
         // 3. Set the end address of the transmit buffer
-        self.write_control_register(bank0::Register::ETXNDL, txnd.low());
-        self.write_control_register(bank0::Register::ETXNDH, txnd.high());
-
-        // 4. reset interrupt flag
-        //self.bit_field_clear(common::Register::EIR, common::EIR::mask().txif());
-
-        // 5. start transmission
-        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().txrts());
-
-        // Wait until transmission finishes
-        //while common::ECON1(self.read_control_register(common::Register::ECON1)).txrts() == 1 {}
+        self.write_control_register(bank0::Register::ETXNDL, txnd.low()).await;
+        self.write_control_register(bank0::Register::ETXNDH, txnd.high()).await;
 
-        /*
-        // read the transmit status vector
-        let mut tx_stat = [0; 7];
-        self.read_buffer_memory(None, &mut tx_stat);
-
-        let stat = common::ESTAT(self.read_control_register(common::Register::ESTAT));
-
-        if stat.txabrt() == 1 {
-            // work around errata #12 by reading the transmit status vector
-            if stat.latecol() == 1 || (tx_stat[2] & (1 << 5)) != 0 {
-                panic!("LateCollision")
-            } else {
-                panic!("TransmitAbort")
-            }
-        }*/
+        // 4. start transmission
+        self.bit_field_set(common::Register::ECON1, common::ECON1::mask().txrts())
+            .await;
     }
 
     /// Get whether the link is up
-    pub fn is_link_up(&mut self) -> bool {
-        let bits = self.read_phy_register(phy::Register::PHSTAT2);
+    async fn is_link_up(&mut self) -> bool {
+        let bits = self.read_phy_register(phy::Register::PHSTAT2).await;
         phy::PHSTAT2(bits).lstat() == 1
     }
 
@@ -332,18 +479,18 @@ where
     ///
     /// The value returned by this function will never exceed 1514 bytes. The actual value depends
     /// on the memory assigned to the transmission buffer when initializing the device
-    pub fn mtu(&self) -> u16 {
+    fn mtu(&self) -> u16 {
         cmp::min(BUF_SZ - RXND - 1, MAX_FRAME_LENGTH - CRC_SZ)
     }
 
     /* Miscellaneous */
     /// Returns the number of packets that have been received but have not been processed yet
-    pub fn pending_packets(&mut self) -> u8 {
-        self.read_control_register(bank1::Register::EPKTCNT)
+    async fn pending_packets(&mut self) -> u8 {
+        self.read_control_register(bank1::Register::EPKTCNT).await
     }
 
     /// Adjusts the receive filter to *accept* these packet types
-    pub fn accept(&mut self, packets: &[Packet]) {
+    async fn accept(&mut self, packets: &[Packet]) {
         let mask = bank1::ERXFCON::mask();
         let mut val = 0;
         for packet in packets {
@@ -354,11 +501,11 @@ where
             }
         }
 
-        self.bit_field_set(bank1::Register::ERXFCON, val)
+        self.bit_field_set(bank1::Register::ERXFCON, val).await
     }
 
     /// Adjusts the receive filter to *ignore* these packet types
-    pub fn ignore(&mut self, packets: &[Packet]) {
+    async fn ignore(&mut self, packets: &[Packet]) {
         let mask = bank1::ERXFCON::mask();
         let mut val = 0;
         for packet in packets {
@@ -369,96 +516,85 @@ where
             }
         }
 
-        self.bit_field_clear(bank1::Register::ERXFCON, val)
+        self.bit_field_clear(bank1::Register::ERXFCON, val).await
     }
 
     /* Private */
     /* Read */
-    fn read_control_register<R>(&mut self, register: R) -> u8
+    async fn read_control_register<R>(&mut self, register: R) -> u8
     where
         R: Into<Register>,
     {
-        self._read_control_register(register.into())
+        self._read_control_register(register.into()).await
     }
 
-    fn _read_control_register(&mut self, register: Register) -> u8 {
-        self.change_bank(register);
+    async fn _read_control_register(&mut self, register: Register) -> u8 {
+        self.change_bank(register).await;
 
         if register.is_eth_register() {
             let mut buffer = [Instruction::RCR.opcode() | register.addr(), 0];
-            self.spi.transfer_in_place(&mut buffer).unwrap();
+            self.spi.transfer_in_place(&mut buffer).await.unwrap();
             buffer[1]
         } else {
             // MAC, MII regs need a dummy byte.
             let mut buffer = [Instruction::RCR.opcode() | register.addr(), 0, 0];
-            self.spi.transfer_in_place(&mut buffer).unwrap();
+            self.spi.transfer_in_place(&mut buffer).await.unwrap();
             buffer[2]
         }
     }
 
-    fn read_phy_register(&mut self, register: phy::Register) -> u16 {
+    async fn read_phy_register(&mut self, register: phy::Register) -> u16 {
         // set PHY register address
-        self.write_control_register(bank2::Register::MIREGADR, register.addr());
+        self.write_control_register(bank2::Register::MIREGADR, register.addr())
+            .await;
 
         // start read operation
-        self.write_control_register(bank2::Register::MICMD, bank2::MICMD::default().miird(1).bits());
+        self.write_control_register(bank2::Register::MICMD, bank2::MICMD::default().miird(1).bits())
+            .await;
 
         // wait until the read operation finishes
-        while self.read_control_register(bank3::Register::MISTAT) & 0b1 != 0 {}
+        while self.read_control_register(bank3::Register::MISTAT).await & 0b1 != 0 {}
 
-        self.write_control_register(bank2::Register::MICMD, bank2::MICMD::default().miird(0).bits());
+        self.write_control_register(bank2::Register::MICMD, bank2::MICMD::default().miird(0).bits())
+            .await;
 
-        let l = self.read_control_register(bank2::Register::MIRDL);
-        let h = self.read_control_register(bank2::Register::MIRDH);
+        let l = self.read_control_register(bank2::Register::MIRDL).await;
+        let h = self.read_control_register(bank2::Register::MIRDH).await;
         (l as u16) | (h as u16) << 8
     }
 
     /* Write */
-    fn _write_control_register(&mut self, register: Register, value: u8) {
-        self.change_bank(register);
+    async fn _write_control_register(&mut self, register: Register, value: u8) {
+        self.change_bank(register).await;
 
         let buffer = [Instruction::WCR.opcode() | register.addr(), value];
-        self.spi.write(&buffer).unwrap();
+        self.spi.write(&buffer).await.unwrap();
     }
 
-    fn write_control_register<R>(&mut self, register: R, value: u8)
+    async fn write_control_register<R>(&mut self, register: R, value: u8)
     where
         R: Into<Register>,
     {
-        self._write_control_register(register.into(), value)
+        self._write_control_register(register.into(), value).await
     }
 
-    fn write_phy_register(&mut self, register: phy::Register, value: u16) {
+    async fn write_phy_register(&mut self, register: phy::Register, value: u16) {
         // set PHY register address
-        self.write_control_register(bank2::Register::MIREGADR, register.addr());
+        self.write_control_register(bank2::Register::MIREGADR, register.addr())
+            .await;
 
-        self.write_control_register(bank2::Register::MIWRL, (value & 0xff) as u8);
+        self.write_control_register(bank2::Register::MIWRL, (value & 0xff) as u8)
+            .await;
         // this starts the write operation
-        self.write_control_register(bank2::Register::MIWRH, (value >> 8) as u8);
+        self.write_control_register(bank2::Register::MIWRH, (value >> 8) as u8)
+            .await;
 
         // wait until the write operation finishes
-        while self.read_control_register(bank3::Register::MISTAT) & 0b1 != 0 {}
-    }
-
-    /* RMW */
-    fn modify_control_register<R, F>(&mut self, register: R, f: F)
-    where
-        F: FnOnce(u8) -> u8,
-        R: Into<Register>,
-    {
-        self._modify_control_register(register.into(), f)
-    }
-
-    fn _modify_control_register<F>(&mut self, register: Register, f: F)
-    where
-        F: FnOnce(u8) -> u8,
-    {
-        let r = self._read_control_register(register);
-        self._write_control_register(register, f(r))
+        while self.read_control_register(bank3::Register::MISTAT).await & 0b1 != 0 {}
     }
 
     /* Auxiliary */
-    fn change_bank(&mut self, register: Register) {
+    async fn change_bank(&mut self, register: Register) {
         let bank = register.bank();
 
         if let Some(bank) = bank {
@@ -468,12 +604,21 @@ where
             }
 
             // change bank
+            // ECON1 is a common register, so this is done with raw commands instead of going
+            // through `bit_field_set`/`bit_field_clear`, which would recurse.
             self.bank = bank;
-            match bank {
-                Bank::Bank0 => self.bit_field_clear(common::Register::ECON1, 0b11),
-                Bank::Bank1 => self.modify_control_register(common::Register::ECON1, |r| (r & !0b11) | 0b01),
-                Bank::Bank2 => self.modify_control_register(common::Register::ECON1, |r| (r & !0b11) | 0b10),
-                Bank::Bank3 => self.bit_field_set(common::Register::ECON1, 0b11),
+            let econ1 = common::Register::ECON1.addr();
+            if bank != Bank::Bank3 {
+                self.spi
+                    .write(&[Instruction::BFC.opcode() | econ1, 0b11])
+                    .await
+                    .unwrap();
+            }
+            if bank != Bank::Bank0 {
+                self.spi
+                    .write(&[Instruction::BFS.opcode() | econ1, bank as u8])
+                    .await
+                    .unwrap();
             }
         } else {
             // common register
@@ -481,73 +626,77 @@ where
     }
 
     /* Primitive operations */
-    fn bit_field_clear<R>(&mut self, register: R, mask: u8)
+    async fn bit_field_clear<R>(&mut self, register: R, mask: u8)
     where
         R: Into<Register>,
     {
-        self._bit_field_clear(register.into(), mask)
+        self._bit_field_clear(register.into(), mask).await
     }
 
-    fn _bit_field_clear(&mut self, register: Register, mask: u8) {
+    async fn _bit_field_clear(&mut self, register: Register, mask: u8) {
         debug_assert!(register.is_eth_register());
 
-        self.change_bank(register);
+        self.change_bank(register).await;
 
         self.spi
             .write(&[Instruction::BFC.opcode() | register.addr(), mask])
+            .await
             .unwrap();
     }
 
-    fn bit_field_set<R>(&mut self, register: R, mask: u8)
+    async fn bit_field_set<R>(&mut self, register: R, mask: u8)
     where
         R: Into<Register>,
     {
-        self._bit_field_set(register.into(), mask)
+        self._bit_field_set(register.into(), mask).await
     }
 
-    fn _bit_field_set(&mut self, register: Register, mask: u8) {
+    async fn _bit_field_set(&mut self, register: Register, mask: u8) {
         debug_assert!(register.is_eth_register());
 
-        self.change_bank(register);
+        self.change_bank(register).await;
 
         self.spi
             .write(&[Instruction::BFS.opcode() | register.addr(), mask])
+            .await
             .unwrap();
     }
 
-    fn read_buffer_memory(&mut self, addr: Option<u16>, buf: &mut [u8]) {
+    async fn read_buffer_memory(&mut self, addr: Option<u16>, buf: &mut [u8]) {
         if let Some(addr) = addr {
-            self.write_control_register(bank0::Register::ERDPTL, addr.low());
-            self.write_control_register(bank0::Register::ERDPTH, addr.high());
+            self.write_control_register(bank0::Register::ERDPTL, addr.low()).await;
+            self.write_control_register(bank0::Register::ERDPTH, addr.high()).await;
         }
 
         self.spi
             .transaction(&mut [Operation::Write(&[Instruction::RBM.opcode()]), Operation::Read(buf)])
+            .await
             .unwrap();
     }
 
-    fn soft_reset(&mut self) {
-        self.spi.write(&[Instruction::SRC.opcode()]).unwrap();
+    async fn soft_reset(&mut self) {
+        self.spi.write(&[Instruction::SRC.opcode()]).await.unwrap();
     }
 
-    fn write_buffer_memory(&mut self, addr: Option<u16>, buffer: &[u8]) {
+    async fn write_buffer_memory(&mut self, addr: Option<u16>, buffer: &[u8]) {
         if let Some(addr) = addr {
-            self.write_control_register(bank0::Register::EWRPTL, addr.low());
-            self.write_control_register(bank0::Register::EWRPTH, addr.high());
+            self.write_control_register(bank0::Register::EWRPTL, addr.low()).await;
+            self.write_control_register(bank0::Register::EWRPTH, addr.high()).await;
         }
 
         self.spi
             .transaction(&mut [Operation::Write(&[Instruction::WBM.opcode()]), Operation::Write(buffer)])
+            .await
             .unwrap();
     }
 }
 
 #[derive(Clone, Copy, PartialEq)]
 enum Bank {
-    Bank0,
-    Bank1,
-    Bank2,
-    Bank3,
+    Bank0 = 0b00,
+    Bank1 = 0b01,
+    Bank2 = 0b10,
+    Bank3 = 0b11,
 }
 
 #[derive(Clone, Copy)]
@@ -627,93 +776,267 @@ pub enum Packet {
     Unicast,
 }
 
-static mut TX_BUF: [u8; MTU] = [0; MTU];
-static mut RX_BUF: [u8; MTU] = [0; MTU];
+#[cfg(test)]
+mod tests {
+    use embedded_hal_mock::eh1::spi::{Mock as SpiMock, Transaction as SpiTransaction};
 
-impl<S, O> embassy_net_driver::Driver for Enc28j60<S, O>
-where
-    S: SpiDevice,
-    O: OutputPin,
-{
-    type RxToken<'a> = RxToken<'a>
-    where
-        Self: 'a;
+    use super::*;
 
-    type TxToken<'a> = TxToken<'a, S, O>
-    where
-        Self: 'a;
+    fn wrap(ops: Vec<SpiTransaction<u8>>) -> Vec<SpiTransaction<u8>> {
+        let mut t = vec![SpiTransaction::transaction_start()];
+        t.extend(ops);
+        t.push(SpiTransaction::transaction_end());
+        t
+    }
 
-    fn receive(&mut self, cx: &mut core::task::Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        let rx_buf = unsafe { &mut *core::ptr::addr_of_mut!(RX_BUF) };
-        let tx_buf = unsafe { &mut *core::ptr::addr_of_mut!(TX_BUF) };
-        if let Some(n) = self.receive(rx_buf) {
-            Some((RxToken { buf: &mut rx_buf[..n] }, TxToken { buf: tx_buf, eth: self }))
-        } else {
-            cx.waker().wake_by_ref();
-            None
-        }
+    /// Read an ETH control register.
+    fn rcr(addr: u8, value: u8) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::transfer_in_place(vec![addr, 0], vec![0, value])])
     }
 
-    fn transmit(&mut self, _cx: &mut core::task::Context) -> Option<Self::TxToken<'_>> {
-        let tx_buf = unsafe { &mut *core::ptr::addr_of_mut!(TX_BUF) };
-        Some(TxToken { buf: tx_buf, eth: self })
+    /// Read a MAC or MII control register, which has a dummy byte.
+    fn rcr_mii(addr: u8, value: u8) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::transfer_in_place(
+            vec![addr, 0, 0],
+            vec![0, 0, value],
+        )])
     }
 
-    fn link_state(&mut self, cx: &mut core::task::Context) -> LinkState {
-        cx.waker().wake_by_ref();
-        match self.is_link_up() {
-            true => LinkState::Up,
-            false => LinkState::Down,
+    fn wcr(addr: u8, value: u8) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::write_vec(vec![0x40 | addr, value])])
+    }
+
+    fn bfs(addr: u8, mask: u8) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::write_vec(vec![0x80 | addr, mask])])
+    }
+
+    fn bfc(addr: u8, mask: u8) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::write_vec(vec![0xa0 | addr, mask])])
+    }
+
+    fn rbm(data: Vec<u8>) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::write_vec(vec![0x3a]), SpiTransaction::read_vec(data)])
+    }
+
+    fn wbm(data: Vec<u8>) -> Vec<SpiTransaction<u8>> {
+        wrap(vec![SpiTransaction::write_vec(vec![0x7a]), SpiTransaction::write_vec(data)])
+    }
+
+    fn bank(n: u8) -> Vec<SpiTransaction<u8>> {
+        match n {
+            0 => bfc(0x1f, 0b11),
+            3 => bfs(0x1f, 0b11),
+            n => [bfc(0x1f, 0b11), bfs(0x1f, n)].concat(),
         }
     }
 
-    fn capabilities(&self) -> Capabilities {
-        let mut caps = Capabilities::default();
-        caps.max_transmission_unit = MTU;
-        caps
+    /// Read a PHY register, starting and ending in bank 2.
+    fn read_phy(addr: u8, value: u16) -> Vec<SpiTransaction<u8>> {
+        [
+            wcr(0x14, addr),
+            wcr(0x12, 1),
+            bank(3),
+            rcr_mii(0x0a, 0),
+            bank(2),
+            wcr(0x12, 0),
+            rcr_mii(0x18, value as u8),
+            rcr_mii(0x19, (value >> 8) as u8),
+        ]
+        .concat()
     }
 
-    fn hardware_address(&self) -> HardwareAddress {
-        HardwareAddress::Ethernet(self.mac_addr)
+    fn rx_header(next_packet: u16, byte_count: u16, received_ok: bool) -> Vec<u8> {
+        let status = byte_count as u32 | (received_ok as u32) << 23;
+        let mut header = next_packet.to_le_bytes().to_vec();
+        header.extend(status.to_le_bytes());
+        header
     }
-}
 
-/// embassy-net RX token.
-pub struct RxToken<'a> {
-    buf: &'a mut [u8],
-}
+    #[futures_test::test]
+    async fn receive() {
+        let payload: Vec<u8> = (0..60).collect();
+        let expectations = [
+            // EPKTCNT
+            bank(1),
+            rcr(0x19, 1),
+            // header at ERXST
+            bank(0),
+            wcr(0x00, 0x00),
+            wcr(0x01, 0x00),
+            rbm(rx_header(0x0048, 64, true)),
+            rbm(payload.clone()),
+            // ERXRDPT is set to the odd address right before the next packet
+            wcr(0x0c, 0x47),
+            wcr(0x0d, 0x00),
+            // PKTDEC
+            bfs(0x1e, 0x40),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        let mut buf = [0; MTU];
+        assert_eq!(mac.receive(&mut buf).await, Some(60));
+        assert_eq!(&buf[..60], &payload[..]);
+        assert_eq!(mac.next_packet, 0x0048);
+
+        spi.done();
+    }
 
-impl<'a> embassy_net_driver::RxToken for RxToken<'a> {
-    fn consume<R, F>(self, f: F) -> R
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
-        f(self.buf)
+    #[futures_test::test]
+    async fn receive_drops_bad_packet() {
+        let expectations = [
+            bank(1),
+            rcr(0x19, 1),
+            bank(0),
+            wcr(0x00, 0x00),
+            wcr(0x01, 0x00),
+            // CRC error, the payload isn't read but the packet is still consumed
+            rbm(rx_header(0x0048, 64, false)),
+            wcr(0x0c, 0x47),
+            wcr(0x0d, 0x00),
+            bfs(0x1e, 0x40),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        let mut buf = [0; MTU];
+        assert_eq!(mac.receive(&mut buf).await, None);
+        assert_eq!(mac.next_packet, 0x0048);
+
+        spi.done();
     }
-}
 
-/// embassy-net TX token.
-pub struct TxToken<'a, S, O>
-where
-    S: SpiDevice,
-    O: OutputPin,
-{
-    eth: &'a mut Enc28j60<S, O>,
-    buf: &'a mut [u8],
-}
+    #[futures_test::test]
+    async fn receive_resets_corrupted_buffer() {
+        let expectations = [
+            bank(1),
+            rcr(0x19, 1),
+            bank(0),
+            wcr(0x00, 0x20),
+            wcr(0x01, 0x00),
+            // the next packet pointer is odd, so the header is garbage
+            rbm(rx_header(0x0049, 64, true)),
+            // RXRST
+            bfs(0x1f, 0x40),
+            bfc(0x1f, 0x40),
+            // ERXST, ERXRDPT, ERXND
+            wcr(0x08, 0x00),
+            wcr(0x09, 0x00),
+            wcr(0x0c, 0xff),
+            wcr(0x0d, 0x19),
+            wcr(0x0a, 0xff),
+            wcr(0x0b, 0x19),
+            // drain EPKTCNT
+            bank(1),
+            rcr(0x19, 1),
+            bfs(0x1e, 0x40),
+            rcr(0x19, 0),
+            // clear RXERIF, set RXEN
+            bfc(0x1c, 0x01),
+            bfs(0x1f, 0x04),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+        mac.next_packet = 0x0020;
+
+        let mut buf = [0; MTU];
+        assert_eq!(mac.receive(&mut buf).await, None);
+        assert_eq!(mac.next_packet, RXST);
+
+        spi.done();
+    }
 
-impl<'a, S, O> embassy_net_driver::TxToken for TxToken<'a, S, O>
-where
-    S: SpiDevice,
-    O: OutputPin,
-{
-    fn consume<R, F>(self, len: usize, f: F) -> R
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
-        assert!(len <= self.buf.len());
-        let r = f(&mut self.buf[..len]);
-        self.eth.transmit(&self.buf[..len]);
-        r
+    #[futures_test::test]
+    async fn link_change_interrupt() {
+        let expectations = [
+            // LINKIF
+            rcr(0x1c, 0x10),
+            // reading PHIR clears the interrupt
+            bank(2),
+            read_phy(0x13, 0x0014),
+            // PHSTAT2.LSTAT
+            read_phy(0x11, 1 << 10),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        assert_eq!(mac.service_interrupts().await, Some(true));
+
+        spi.done();
+    }
+
+    #[futures_test::test]
+    async fn rx_error_interrupt() {
+        let expectations = [
+            // PKTIF | RXERIF
+            rcr(0x1c, 0x41),
+            bfc(0x1c, 0x01),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        assert_eq!(mac.service_interrupts().await, None);
+
+        spi.done();
+    }
+
+    #[futures_test::test]
+    async fn transmit() {
+        let frame: Vec<u8> = (0..64).collect();
+        let expectations = [
+            // TXRTS is clear
+            rcr(0x1f, 0x00),
+            // EWRPT is right after the per packet control byte
+            wcr(0x02, 0x01),
+            wcr(0x03, 0x1a),
+            wbm(frame.clone()),
+            // ETXND
+            wcr(0x06, 0x40),
+            wcr(0x07, 0x1a),
+            // TXRTS
+            bfs(0x1f, 0x08),
+        ]
+        .concat();
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        mac.transmit(&frame).await;
+
+        spi.done();
+    }
+
+    #[futures_test::test]
+    async fn transmit_resets_stuck_tx_logic() {
+        let frame: Vec<u8> = (0..64).collect();
+        let mut expectations = Vec::new();
+        for _ in 0..10000 {
+            expectations.extend(rcr(0x1f, 0x08));
+        }
+        expectations.extend(
+            [
+                // errata #12: TXRST, then clear TXERIF and TXIF
+                bfs(0x1f, 0x80),
+                bfc(0x1f, 0x80),
+                bfc(0x1c, 0x0a),
+                wcr(0x02, 0x01),
+                wcr(0x03, 0x1a),
+                wbm(frame.clone()),
+                wcr(0x06, 0x40),
+                wcr(0x07, 0x1a),
+                bfs(0x1f, 0x08),
+            ]
+            .concat(),
+        );
+        let mut spi = SpiMock::new(&expectations);
+        let mut mac = Enc28j60::new(spi.clone());
+
+        mac.transmit(&frame).await;
+
+        spi.done();
     }
 }
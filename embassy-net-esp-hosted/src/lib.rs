@@ -8,7 +8,7 @@ use embassy_net_driver_channel::driver::LinkState;
 use embassy_time::{Duration, Instant, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal_async::digital::Wait;
-use embedded_hal_async::spi::SpiDevice;
+use embedded_hal_async::spi::{Operation, SpiDevice};
 
 use crate::ioctl::{PendingIoctl, Shared};
 use crate::proto::{CtrlMsg, CtrlMsgPayload};
@@ -191,7 +191,7 @@ where
             self.handshake.wait_for_high().await.unwrap();
 
             let ioctl = self.shared.ioctl_wait_pending();
-            let tx = self.ch.tx_buf();
+            let tx = self.ch.tx_packet();
             let ev = async { self.ready.wait_for_high().await.unwrap() };
             let hb = Timer::at(self.heartbeat_deadline);
 
@@ -214,10 +214,11 @@ where
                     tx_buf[0..12].copy_from_slice(&header.to_bytes());
                     header.checksum = checksum(&tx_buf[..26 + req_len]);
                     tx_buf[0..12].copy_from_slice(&header.to_bytes());
+
+                    trace!("tx: {:02x}", &tx_buf[..40]);
+                    self.spi.transfer(&mut rx_buf, &tx_buf).await.unwrap();
                 }
                 Either4::Second(packet) => {
-                    tx_buf[12..][..packet.len()].copy_from_slice(packet);
-
                     let mut header = PayloadHeader {
                         if_type_and_num: InterfaceType::Sta as _,
                         len: packet.len() as _,
@@ -228,26 +229,26 @@ where
                     self.next_seq = self.next_seq.wrapping_add(1);
 
                     // Calculate checksum
-                    tx_buf[0..12].copy_from_slice(&header.to_bytes());
-                    header.checksum = checksum(&tx_buf[..12 + packet.len()]);
-                    tx_buf[0..12].copy_from_slice(&header.to_bytes());
-
+                    header.checksum = packet
+                        .chain(&header.to_bytes(), &[])
+                        .map(checksum)
+                        .fold(0, u16::wrapping_add);
+                    let header = header.to_bytes();
+
+                    // Send the frame straight from the channel buffer, after the header.
+                    trace!("tx: {:02x}", &header);
+                    transfer_chain(&mut self.spi, &mut rx_buf, packet.chain(&header, &[])).await;
                     self.ch.tx_done();
                 }
                 Either4::Third(()) => {
                     tx_buf[..PayloadHeader::SIZE].fill(0);
+                    self.spi.transfer(&mut rx_buf, &tx_buf).await.unwrap();
                 }
                 Either4::Fourth(()) => {
                     panic!("heartbeat from esp32 stopped")
                 }
             }
 
-            if tx_buf[0] != 0 {
-                trace!("tx: {:02x}", &tx_buf[..40]);
-            }
-
-            self.spi.transfer(&mut rx_buf, &tx_buf).await.unwrap();
-
             // The esp-hosted firmware deasserts the HANSHAKE pin a few us AFTER ending the SPI transfer
             // If we check it again too fast, we'll see it's high from the previous transfer, and if we send it
             // data it will get lost.
@@ -353,6 +354,19 @@ where
     }
 }
 
+/// Transfer the buffers of `chain` in one SPI transaction, reading a whole `rx_buf` meanwhile.
+async fn transfer_chain<SPI: SpiDevice>(spi: &mut SPI, rx_buf: &mut [u8], chain: ch::TxChain<'_>) {
+    let mut ops = heapless::Vec::<Operation<'_, u8>, 4>::new();
+    let mut rx = rx_buf;
+    for segment in chain {
+        let (read, rest) = rx.split_at_mut(segment.len());
+        unwrap!(ops.push(Operation::Transfer(read, segment)).ok());
+        rx = rest;
+    }
+    unwrap!(ops.push(Operation::Read(rx)).ok());
+    spi.transaction(&mut ops).await.unwrap();
+}
+
 fn checksum(buf: &[u8]) -> u16 {
     let mut res = 0u16;
     for &b in buf {
@@ -0,0 +1,371 @@
+#![no_std]
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::pin;
+use core::task::Context;
+
+use embassy_net_driver::{Capabilities, HardwareAddress, LinkState, Timestamp};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, Instant, Timer};
+
+/// Link simulation parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Maximum transmission unit, including the Ethernet header for Ethernet devices.
+    ///
+    /// Must not be larger than the `MTU` of the [`State`].
+    pub mtu: usize,
+    /// Time between a frame being transmitted and it being available on the other side.
+    pub latency: Duration,
+    /// Probability of a frame being lost, from `0.0` to `1.0`.
+    pub loss: f32,
+    /// Probability of a frame being delayed, so it's received after frames transmitted later,
+    /// from `0.0` to `1.0`.
+    pub reorder: f32,
+    /// Maximum additional delay of a reordered frame.
+    pub reorder_delay: Duration,
+    /// Seed of the random generator deciding loss and reordering.
+    ///
+    /// The same seed gives the same sequence of decisions, so failures can be reproduced.
+    pub seed: u64,
+    /// Timestamp received frames, and transmitted frames on request, with [`Instant::now()`].
+    pub timestamps: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mtu: 1514,
+            latency: Duration::from_ticks(0),
+            loss: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            seed: 0x2545_f491_4f6c_dd1d,
+            timestamps: false,
+        }
+    }
+}
+
+struct Frame<const MTU: usize> {
+    data: [u8; MTU],
+    len: usize,
+    deliver_at: Instant,
+    seq: u32,
+}
+
+struct Queue<const MTU: usize, const N: usize> {
+    frames: heapless::Vec<Frame<MTU>, N>,
+    waker: WakerRegistration,
+}
+
+impl<const MTU: usize, const N: usize> Queue<MTU, N> {
+    const fn new() -> Self {
+        Self {
+            frames: heapless::Vec::new(),
+            waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Index of the next frame to deliver, whether it's due or not.
+    fn next(&self) -> Option<usize> {
+        self.frames
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, f)| (f.deliver_at, f.seq))
+            .map(|(i, _)| i)
+    }
+}
+
+struct Inner<const MTU: usize, const N: usize> {
+    config: Config,
+    queues: [Queue<MTU, N>; 2],
+    link_up: bool,
+    link_waker: [WakerRegistration; 2],
+    rng: u64,
+    seq: u32,
+    tx_timestamps: [heapless::Deque<(u32, Timestamp), N>; 2],
+    tx_timestamp_waker: [WakerRegistration; 2],
+}
+
+impl<const MTU: usize, const N: usize> Inner<MTU, N> {
+    /// Random number in `0.0..1.0`.
+    fn random(&mut self) -> f32 {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let r = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (r >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn timestamp(instant: Instant) -> Timestamp {
+    Timestamp::from_nanos(instant.as_micros() * 1000)
+}
+
+/// Shared state of a link.
+///
+/// Each direction of the link can hold up to `N` frames of up to `MTU` bytes in flight. Frames
+/// transmitted while the queue is full are dropped, like a real network would.
+pub struct State<M: RawMutex, const MTU: usize, const N: usize> {
+    inner: Mutex<M, RefCell<Inner<MTU, N>>>,
+}
+
+impl<M: RawMutex, const MTU: usize, const N: usize> State<M, MTU, N> {
+    /// Create a new `State`.
+    pub const fn new(config: Config) -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(Inner {
+                rng: config.seed,
+                config,
+                queues: [Queue::new(), Queue::new()],
+                link_up: true,
+                link_waker: [WakerRegistration::new(), WakerRegistration::new()],
+                seq: 0,
+                tx_timestamps: [heapless::Deque::new(), heapless::Deque::new()],
+                tx_timestamp_waker: [WakerRegistration::new(), WakerRegistration::new()],
+            })),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Inner<MTU, N>) -> R) -> R {
+        self.inner.lock(|i| f(&mut i.borrow_mut()))
+    }
+
+    /// Change the link simulation parameters.
+    ///
+    /// Frames already in flight are not affected.
+    pub fn set_config(&self, config: Config) {
+        self.with(|i| {
+            i.rng = config.seed;
+            i.config = config;
+        })
+    }
+
+    /// Bring the link up or down.
+    ///
+    /// While the link is down, frames in flight are dropped, and transmitted frames are lost.
+    pub fn set_link_state(&self, state: LinkState) {
+        self.with(|i| {
+            i.link_up = state == LinkState::Up;
+            if !i.link_up {
+                for q in &mut i.queues {
+                    q.frames.clear();
+                }
+            }
+            for w in &mut i.link_waker {
+                w.wake();
+            }
+        })
+    }
+}
+
+/// Create a loopback device.
+///
+/// Frames transmitted by the device are received back by itself.
+pub fn loopback<M: RawMutex, const MTU: usize, const N: usize>(
+    state: &State<M, MTU, N>,
+    hardware_address: HardwareAddress,
+) -> Device<'_, M, MTU, N> {
+    Device {
+        state,
+        rx: 0,
+        tx: 0,
+        hardware_address,
+    }
+}
+
+/// Create a pair of devices connected to each other.
+///
+/// Frames transmitted by one device are received by the other one.
+pub fn link<M: RawMutex, const MTU: usize, const N: usize>(
+    state: &State<M, MTU, N>,
+    hardware_addresses: [HardwareAddress; 2],
+) -> (Device<'_, M, MTU, N>, Device<'_, M, MTU, N>) {
+    (
+        Device {
+            state,
+            rx: 0,
+            tx: 1,
+            hardware_address: hardware_addresses[0],
+        },
+        Device {
+            state,
+            rx: 1,
+            tx: 0,
+            hardware_address: hardware_addresses[1],
+        },
+    )
+}
+
+/// An in-memory network device.
+pub struct Device<'d, M: RawMutex, const MTU: usize, const N: usize> {
+    state: &'d State<M, MTU, N>,
+    rx: usize,
+    tx: usize,
+    hardware_address: HardwareAddress,
+}
+
+impl<'d, M: RawMutex, const MTU: usize, const N: usize> embassy_net_driver::Driver for Device<'d, M, MTU, N> {
+    type RxToken<'a> = RxToken<MTU> where Self: 'a;
+    type TxToken<'a> = TxToken<'a, M, MTU, N> where Self: 'a;
+
+    fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let now = Instant::now();
+        let (frame, timestamps) = self.state.with(|i| {
+            let timestamps = i.config.timestamps;
+            let q = &mut i.queues[self.rx];
+            q.waker.register(cx.waker());
+            let next = q.next()?;
+            if q.frames[next].deliver_at > now {
+                // Not due yet, wake up when it is.
+                let t = pin!(Timer::at(q.frames[next].deliver_at));
+                if t.poll(cx).is_ready() {
+                    cx.waker().wake_by_ref();
+                }
+                return None;
+            }
+            Some((q.frames.swap_remove(next), timestamps))
+        })?;
+
+        Some((
+            RxToken {
+                frame,
+                timestamp: timestamps.then(|| timestamp(now)),
+            },
+            TxToken {
+                state: self.state,
+                rx: self.rx,
+                tx: self.tx,
+                timestamp_id: None,
+            },
+        ))
+    }
+
+    fn transmit(&mut self, _cx: &mut Context) -> Option<Self::TxToken<'_>> {
+        Some(TxToken {
+            state: self.state,
+            rx: self.rx,
+            tx: self.tx,
+            timestamp_id: None,
+        })
+    }
+
+    fn transmit_timestamp(&mut self, cx: &mut Context) -> Option<(u32, Timestamp)> {
+        self.state.with(|i| {
+            i.tx_timestamp_waker[self.rx].register(cx.waker());
+            i.tx_timestamps[self.rx].pop_front()
+        })
+    }
+
+    fn link_state(&mut self, cx: &mut Context) -> LinkState {
+        self.state.with(|i| {
+            i.link_waker[self.rx].register(cx.waker());
+            if i.link_up {
+                LinkState::Up
+            } else {
+                LinkState::Down
+            }
+        })
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut caps = Capabilities::default();
+        let config = self.state.with(|i| i.config);
+        caps.max_transmission_unit = config.mtu.min(MTU);
+        caps.timestamps.rx = config.timestamps;
+        caps.timestamps.tx = config.timestamps;
+        caps
+    }
+
+    fn hardware_address(&self) -> HardwareAddress {
+        self.hardware_address
+    }
+}
+
+#[doc(hidden)]
+pub struct RxToken<const MTU: usize> {
+    frame: Frame<MTU>,
+    timestamp: Option<Timestamp>,
+}
+
+impl<const MTU: usize> embassy_net_driver::RxToken for RxToken<MTU> {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.frame.data[..self.frame.len])
+    }
+
+    fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp
+    }
+}
+
+#[doc(hidden)]
+pub struct TxToken<'a, M: RawMutex, const MTU: usize, const N: usize> {
+    state: &'a State<M, MTU, N>,
+    /// Index of the transmitting device, for its timestamps.
+    rx: usize,
+    tx: usize,
+    timestamp_id: Option<u32>,
+}
+
+impl<'a, M: RawMutex, const MTU: usize, const N: usize> embassy_net_driver::TxToken for TxToken<'a, M, MTU, N> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut data = [0; MTU];
+        let r = f(&mut data[..len]);
+
+        let now = Instant::now();
+        self.state.with(|i| {
+            if let (true, Some(id)) = (i.config.timestamps, self.timestamp_id) {
+                let queue = &mut i.tx_timestamps[self.rx];
+                if queue.is_full() {
+                    queue.pop_front();
+                }
+                let _ = queue.push_back((id, timestamp(now)));
+                i.tx_timestamp_waker[self.rx].wake();
+            }
+
+            if !i.link_up || len > i.config.mtu || i.random() < i.config.loss {
+                return;
+            }
+
+            let mut deliver_at = now + i.config.latency;
+            if i.random() < i.config.reorder {
+                let extra = i.config.reorder_delay.as_ticks() as f32 * i.random();
+                deliver_at += Duration::from_ticks(extra as u64);
+            }
+
+            let seq = i.seq;
+            i.seq = i.seq.wrapping_add(1);
+            let q = &mut i.queues[self.tx];
+            if q.frames
+                .push(Frame {
+                    data,
+                    len,
+                    deliver_at,
+                    seq,
+                })
+                .is_ok()
+            {
+                q.waker.wake();
+            }
+        });
+
+        r
+    }
+
+    fn request_timestamp(&mut self, id: u32) {
+        self.timestamp_id = Some(id);
+    }
+}
@@ -0,0 +1,626 @@
+use embassy_futures::join::join;
+use embassy_futures::select::{select3, Either3};
+use embassy_net::raw::{self, RawSocket};
+use embassy_net::tcp::{self, TcpSocket};
+use embassy_net::udp::{PacketMetadata, TimestampMetadata, UdpSocket};
+use embassy_net::{
+    Config, Ipv4Address, Ipv4Cidr, Ipv6Address, Ipv6Cidr, Stack, StackResources, StaticConfigV4, StaticConfigV6,
+};
+use embassy_net_driver::HardwareAddress;
+use embassy_net_loopback::{link, Device, State};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{with_timeout, Duration, Instant, TimeoutError, Timer};
+use smoltcp::wire::{IpProtocol, IpVersion};
+use static_cell::StaticCell;
+
+type LinkState = State<CriticalSectionRawMutex, 1514, 16>;
+type LinkDevice = Device<'static, CriticalSectionRawMutex, 1514, 16>;
+
+fn ipv4_config(n: u8) -> Config {
+    Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(Ipv4Address::new(10, 0, 0, n), 24),
+        gateway: None,
+        dns_servers: Default::default(),
+    })
+}
+
+fn ipv6_address(n: u16) -> Ipv6Address {
+    Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, n)
+}
+
+fn ipv6_config(n: u8) -> Config {
+    Config::ipv6_static(StaticConfigV6 {
+        address: Ipv6Cidr::new(ipv6_address(n as u16), 64),
+        gateway: None,
+        dns_servers: Default::default(),
+    })
+}
+
+fn stacks(
+    state: &'static LinkState,
+    resources: &'static mut [StackResources<2>; 2],
+    config: fn(u8) -> Config,
+) -> (&'static Stack<LinkDevice>, &'static Stack<LinkDevice>) {
+    let (a, b) = link(
+        state,
+        [
+            HardwareAddress::Ethernet([0x02, 0, 0, 0, 0, 1]),
+            HardwareAddress::Ethernet([0x02, 0, 0, 0, 0, 2]),
+        ],
+    );
+    let [ra, rb] = resources;
+    let a = Box::leak(Box::new(Stack::new(a, config(1), ra, 1)));
+    let b = Box::leak(Box::new(Stack::new(b, config(2), rb, 2)));
+    (a, b)
+}
+
+#[test]
+fn udp_echo() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let mut config = embassy_net_loopback::Config::default();
+    config.latency = Duration::from_millis(5);
+    let state = STATE.init(State::new(config));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+            );
+            let mut server = UdpSocket::new(b, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            server.bind(7).unwrap();
+
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+            );
+            let mut client = UdpSocket::new(a, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            client.bind(1234).unwrap();
+
+            let echo = async {
+                let mut buf = [0; 64];
+                let (n, meta) = server.recv_from(&mut buf).await.unwrap();
+                server.send_to(&buf[..n], meta).await.unwrap();
+            };
+            let request = async {
+                client
+                    .send_to(b"hello", (Ipv4Address::new(10, 0, 0, 2), 7))
+                    .await
+                    .unwrap();
+                let mut buf = [0; 64];
+                let (n, _) = client.recv_from(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"hello");
+            };
+            with_timeout(Duration::from_secs(5), join(echo, request)).await.unwrap();
+        };
+
+        match select3(a.run(), b.run(), test).await {
+            Either3::Third(()) => {}
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn udp_timestamps() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let mut config = embassy_net_loopback::Config::default();
+    config.latency = Duration::from_millis(20);
+    config.timestamps = true;
+    let state = STATE.init(State::new(config));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+            );
+            let mut rx_timestamps = [TimestampMetadata::EMPTY; 4];
+            let mut server = UdpSocket::new_timestamped(
+                b,
+                &mut rx_meta,
+                &mut rx_buf,
+                &mut tx_meta,
+                &mut tx_buf,
+                &mut rx_timestamps,
+            );
+            server.bind(319).unwrap();
+
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 4],
+                [0; 1024],
+            );
+            let mut client = UdpSocket::new(a, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            client.bind(319).unwrap();
+
+            let start = Instant::now().as_micros() * 1000;
+            let receive = async {
+                let mut buf = [0; 64];
+                let (n, _, timestamp) = server.recv_from_timestamped(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"sync");
+                timestamp.unwrap()
+            };
+            let send = async {
+                client
+                    .send_to_timestamped(b"sync", (Ipv4Address::new(10, 0, 0, 2), 319))
+                    .await
+                    .unwrap()
+                    .unwrap()
+            };
+            let (rx, tx) = with_timeout(Duration::from_secs(5), join(receive, send)).await.unwrap();
+
+            assert!(tx.as_nanos() >= start);
+            // Sent, then received one link latency later.
+            assert!(rx.as_nanos() - tx.as_nanos() >= 20_000_000);
+        };
+
+        match select3(a.run(), b.run(), test).await {
+            Either3::Third(()) => {}
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn udp_timestamps_queued() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<3>; 2]> = StaticCell::new();
+    let mut config = embassy_net_loopback::Config::default();
+    config.timestamps = true;
+    let state = STATE.init(State::new(config));
+    let (a, b) = link(
+        state,
+        [
+            HardwareAddress::Ethernet([0x02, 0, 0, 0, 0, 1]),
+            HardwareAddress::Ethernet([0x02, 0, 0, 0, 0, 2]),
+        ],
+    );
+    let [ra, rb] = RESOURCES.init([StackResources::new(), StackResources::new()]);
+    let a: &Stack<LinkDevice> = Box::leak(Box::new(Stack::new(a, ipv4_config(1), ra, 1)));
+    let b: &Stack<LinkDevice> = Box::leak(Box::new(Stack::new(b, ipv4_config(2), rb, 2)));
+
+    futures_executor::block_on(async {
+        let test = async {
+            // More datagrams than the stack used to keep timestamps for, with traffic to another
+            // socket in between.
+            const COUNT: usize = 24;
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; COUNT],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 1],
+                [0; 64],
+            );
+            let mut rx_timestamps = [TimestampMetadata::EMPTY; COUNT];
+            let mut event = UdpSocket::new_timestamped(
+                b,
+                &mut rx_meta,
+                &mut rx_buf,
+                &mut tx_meta,
+                &mut tx_buf,
+                &mut rx_timestamps,
+            );
+            event.bind(319).unwrap();
+
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; COUNT],
+                [0; 1024],
+                [PacketMetadata::EMPTY; 1],
+                [0; 64],
+            );
+            let mut general = UdpSocket::new(b, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            general.bind(320).unwrap();
+
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 1],
+                [0; 64],
+                [PacketMetadata::EMPTY; 2 * COUNT],
+                [0; 1024],
+            );
+            let mut client = UdpSocket::new(a, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            client.bind(1234).unwrap();
+
+            for i in 0..COUNT as u8 {
+                client
+                    .send_to(&[i], (Ipv4Address::new(10, 0, 0, 2), 319))
+                    .await
+                    .unwrap();
+                client
+                    .send_to(&[i], (Ipv4Address::new(10, 0, 0, 2), 320))
+                    .await
+                    .unwrap();
+                // Don't overflow the link.
+                Timer::after_millis(1).await;
+            }
+            // Let everything be received before reading any of it.
+            Timer::after_millis(100).await;
+            assert!(general.may_recv());
+
+            let mut last = None;
+            for i in 0..COUNT as u8 {
+                let mut buf = [0; 8];
+                let (n, _, timestamp) = with_timeout(Duration::from_secs(1), event.recv_from_timestamped(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(&buf[..n], &[i]);
+                let timestamp = timestamp.unwrap();
+                assert!(last <= Some(timestamp));
+                last = Some(timestamp);
+            }
+        };
+
+        match select3(a.run(), b.run(), test).await {
+            Either3::Third(()) => {}
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn tcp_lossy_link() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let mut config = embassy_net_loopback::Config::default();
+    config.latency = Duration::from_millis(2);
+    config.loss = 0.05;
+    config.reorder = 0.2;
+    let state = STATE.init(State::new(config));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_buf, mut tx_buf) = ([0; 2048], [0; 2048]);
+            let mut server = TcpSocket::new(b, &mut rx_buf, &mut tx_buf);
+            let (mut rx_buf, mut tx_buf) = ([0; 2048], [0; 2048]);
+            let mut client = TcpSocket::new(a, &mut rx_buf, &mut tx_buf);
+
+            let data: Vec<u8> = (0..8192).map(|i| i as u8).collect();
+
+            let receive = async {
+                server.accept(80).await.unwrap();
+                let mut received = Vec::new();
+                let mut buf = [0; 512];
+                loop {
+                    let n = server.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    received.extend_from_slice(&buf[..n]);
+                }
+                received
+            };
+            let send = async {
+                client.connect((Ipv4Address::new(10, 0, 0, 2), 80)).await.unwrap();
+                let mut data = &data[..];
+                while !data.is_empty() {
+                    let n = client.write(data).await.unwrap();
+                    data = &data[n..];
+                }
+                client.shutdown_write().await.unwrap();
+            };
+            let (received, ()) = with_timeout(Duration::from_secs(30), join(receive, send))
+                .await
+                .unwrap();
+            assert_eq!(received, data);
+        };
+
+        match select3(a.run(), b.run(), test).await {
+            Either3::Third(()) => {}
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn tcp_read_timeout() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let state = STATE.init(State::new(embassy_net_loopback::Config::default()));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_buf, mut tx_buf) = ([0; 1024], [0; 1024]);
+            let mut server = TcpSocket::new(b, &mut rx_buf, &mut tx_buf);
+            let (mut rx_buf, mut tx_buf) = ([0; 1024], [0; 1024]);
+            let mut client = TcpSocket::new(a, &mut rx_buf, &mut tx_buf);
+
+            let receive = async {
+                server.accept(80).await.unwrap();
+                let mut buf = [0; 64];
+
+                let start = Instant::now();
+                assert_eq!(
+                    server.read_with_timeout(&mut buf, Duration::from_millis(50)).await,
+                    Err(TimeoutError)
+                );
+                assert!(start.elapsed() >= Duration::from_millis(50));
+
+                // The connection is still usable after a timeout.
+                let n = server
+                    .read_with_timeout(&mut buf, Duration::from_secs(5))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(&buf[..n], b"late");
+            };
+            let send = async {
+                client.connect((Ipv4Address::new(10, 0, 0, 2), 80)).await.unwrap();
+                embassy_time::Timer::after_millis(200).await;
+                client.write(b"late").await.unwrap();
+                client.flush().await.unwrap();
+            };
+            with_timeout(Duration::from_secs(5), join(receive, send)).await.unwrap();
+        };
+
+        match select3(a.run(), b.run(), test).await {
+            Either3::Third(()) => {}
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn tcp_write_timeout() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let state = STATE.init(State::new(embassy_net_loopback::Config::default()));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_buf, mut tx_buf) = ([0; 256], [0; 256]);
+            let mut server = TcpSocket::new(b, &mut rx_buf, &mut tx_buf);
+            let (mut rx_buf, mut tx_buf) = ([0; 256], [0; 256]);
+            let mut client = TcpSocket::new(a, &mut rx_buf, &mut tx_buf);
+
+            let accept = async { server.accept(80).await.unwrap() };
+            let connect = async { client.connect((Ipv4Address::new(10, 0, 0, 2), 80)).await.unwrap() };
+            join(accept, connect).await;
+
+            // The server never reads, so the client's buffer fills up and the data stays unACKed.
+            let data = [0xaa; 1024];
+            let mut written = 0;
+            loop {
+                match client.write_with_timeout(&data, Duration::from_millis(100)).await {
+                    Ok(n) => written += n.unwrap(),
+                    Err(TimeoutError) => break,
+                }
+            }
+            assert!(written >= 256);
+            // The data is never ACKed, but the connection is still up.
+            let acked = with_timeout(Duration::from_millis(100), client.wait_write_flushed_and_acked()).await;
+            assert_eq!(acked, Err(TimeoutError));
+            assert_eq!(client.state(), tcp::State::Established);
+        };
+
+        match select3(a.run(), b.run(), with_timeout(Duration::from_secs(5), test)).await {
+            Either3::Third(r) => r.unwrap(),
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn tcp_shutdown_write_reset() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let state = STATE.init(State::new(embassy_net_loopback::Config::default()));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_buf, mut tx_buf) = ([0; 256], [0; 256]);
+            let mut server = TcpSocket::new(b, &mut rx_buf, &mut tx_buf);
+            let (mut rx_buf, mut tx_buf) = ([0; 1024], [0; 1024]);
+            let mut client = TcpSocket::new(a, &mut rx_buf, &mut tx_buf);
+
+            let accept = async { server.accept(80).await.unwrap() };
+            let connect = async { client.connect((Ipv4Address::new(10, 0, 0, 2), 80)).await.unwrap() };
+            join(accept, connect).await;
+
+            // More than the server's window, so part of it is still queued when the server resets.
+            client.write(&[0xaa; 1024]).await.unwrap();
+            let shutdown = async { client.shutdown_write().await };
+            let reset = async {
+                embassy_time::Timer::after_millis(50).await;
+                server.abort();
+                server.flush().await.unwrap();
+            };
+            let (result, ()) = join(shutdown, reset).await;
+            assert_eq!(result, Err(tcp::Error::ConnectionReset));
+
+            // The socket is already closed, the data is still reported as lost.
+            assert_eq!(client.state(), tcp::State::Closed);
+            assert_eq!(
+                client.wait_write_flushed_and_acked().await,
+                Err(tcp::Error::ConnectionReset)
+            );
+        };
+
+        match select3(a.run(), b.run(), with_timeout(Duration::from_secs(5), test)).await {
+            Either3::Third(r) => r.unwrap(),
+            _ => unreachable!(),
+        }
+    });
+}
+
+#[test]
+fn tcp_shutdown_write_acked() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let state = STATE.init(State::new(embassy_net_loopback::Config::default()));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv4_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_buf, mut tx_buf) = ([0; 1024], [0; 1024]);
+            let mut server = TcpSocket::new(b, &mut rx_buf, &mut tx_buf);
+            let (mut rx_buf, mut tx_buf) = ([0; 1024], [0; 1024]);
+            let mut client = TcpSocket::new(a, &mut rx_buf, &mut tx_buf);
+
+            let receive = async {
+                server.accept(80).await.unwrap();
+                let mut buf = [0; 64];
+                let n = server.read(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"bye");
+                assert_eq!(server.read(&mut buf).await, Ok(0));
+                // The read half of the client is still open.
+                server.write(b"ok").await.unwrap();
+                server.shutdown_write().await.unwrap();
+            };
+            let send = async {
+                client.connect((Ipv4Address::new(10, 0, 0, 2), 80)).await.unwrap();
+                client.write(b"bye").await.unwrap();
+                client.shutdown_write().await.unwrap();
+                let mut buf = [0; 64];
+                let n = client.read(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"ok");
+            };
+            join(receive, send).await;
+        };
+
+        match select3(a.run(), b.run(), with_timeout(Duration::from_secs(5), test)).await {
+            Either3::Third(r) => r.unwrap(),
+            _ => unreachable!(),
+        }
+    });
+}
+
+/// Build the fragments of an IPv6 UDP datagram, `split` bytes of UDP data in the first one.
+fn udp_fragments(src: Ipv6Address, dst: Ipv6Address, port: u16, payload: &[u8], split: usize) -> [Vec<u8>; 2] {
+    let udp_len = 8 + payload.len();
+    let mut udp = Vec::new();
+    udp.extend_from_slice(&port.to_be_bytes());
+    udp.extend_from_slice(&port.to_be_bytes());
+    udp.extend_from_slice(&(udp_len as u16).to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+
+    // Checksum over the pseudo-header and the datagram.
+    let mut pseudo = Vec::new();
+    pseudo.extend_from_slice(src.as_bytes());
+    pseudo.extend_from_slice(dst.as_bytes());
+    pseudo.extend_from_slice(&(udp_len as u32).to_be_bytes());
+    pseudo.extend_from_slice(&[0, 0, 0, 17]);
+    let mut sum: u32 = pseudo
+        .chunks(2)
+        .chain(udp.chunks(2))
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    udp[6..8].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+
+    let fragment = |offset: usize, data: &[u8], more: bool| {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(8 + data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[44, 64]);
+        packet.extend_from_slice(src.as_bytes());
+        packet.extend_from_slice(dst.as_bytes());
+        packet.extend_from_slice(&[17, 0]);
+        packet.extend_from_slice(&(offset as u16 | more as u16).to_be_bytes());
+        packet.extend_from_slice(&0x1234u32.to_be_bytes());
+        packet.extend_from_slice(data);
+        packet
+    };
+    [fragment(0, &udp[..split], true), fragment(split, &udp[split..], false)]
+}
+
+#[test]
+fn ipv6_reassembly() {
+    static STATE: StaticCell<LinkState> = StaticCell::new();
+    static RESOURCES: StaticCell<[StackResources<2>; 2]> = StaticCell::new();
+    let state = STATE.init(State::new(embassy_net_loopback::Config::default()));
+    let (a, b) = stacks(
+        state,
+        RESOURCES.init([StackResources::new(), StackResources::new()]),
+        ipv6_config,
+    );
+
+    futures_executor::block_on(async {
+        let test = async {
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [PacketMetadata::EMPTY; 4],
+                [0; 2048],
+                [PacketMetadata::EMPTY; 4],
+                [0; 2048],
+            );
+            let mut server = UdpSocket::new(b, &mut rx_meta, &mut rx_buf, &mut tx_meta, &mut tx_buf);
+            server.bind(5683).unwrap();
+
+            let (mut rx_meta, mut rx_buf, mut tx_meta, mut tx_buf) = (
+                [raw::PacketMetadata::EMPTY; 4],
+                [0; 64],
+                [raw::PacketMetadata::EMPTY; 4],
+                [0; 2048],
+            );
+            let client = RawSocket::new(
+                a,
+                IpVersion::Ipv6,
+                IpProtocol::Ipv6Frag,
+                &mut rx_meta,
+                &mut rx_buf,
+                &mut tx_meta,
+                &mut tx_buf,
+            );
+
+            let payload: Vec<u8> = (0..1200).map(|i| i as u8).collect();
+            // Sent in reverse order, reassembly doesn't depend on it.
+            let [first, last] = udp_fragments(ipv6_address(1), ipv6_address(2), 5683, &payload, 600);
+            client.send(&last).await;
+            client.send(&first).await;
+
+            let mut buf = [0; 2048];
+            let (n, meta) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], &payload[..]);
+            assert_eq!(meta.endpoint.addr, ipv6_address(1).into());
+        };
+
+        match select3(a.run(), b.run(), with_timeout(Duration::from_secs(5), test)).await {
+            Either3::Third(r) => r.unwrap(),
+            _ => unreachable!(),
+        }
+    });
+}
@@ -0,0 +1,366 @@
+//! AT command client, as used to control cellular modems.
+//!
+//! Commands are sent with [`AtClient::command`], which returns the information lines of the
+//! response once the modem sends a final result code such as `OK`. Lines the modem sends on
+//! its own, called unsolicited result codes (URCs), are handed to a [`UrcHandler`], both while
+//! waiting for a response and when idle with [`AtClient::poll_urc`].
+
+use core::fmt::{self, Write as _};
+
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{BufRead, Write};
+
+const LINE_LEN: usize = 256;
+const RESPONSE_LEN: usize = 1024;
+const COMMAND_LEN: usize = 128;
+
+/// Default timeout for a command to complete.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Error returned by [`AtClient`] commands.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Reading from the serial port failed.
+    Read(E),
+    /// Writing to the serial port failed.
+    Write(E),
+    /// Reading from the serial port got EOF.
+    Eof,
+    /// The modem didn't send a final result code in time.
+    Timeout,
+    /// The modem answered `ERROR`.
+    Error,
+    /// The modem answered `+CME ERROR: <code>`, an equipment error.
+    CmeError(u16),
+    /// The modem answered `+CMS ERROR: <code>`, a message service error.
+    CmsError(u16),
+    /// The modem answered `NO CARRIER`.
+    NoCarrier,
+    /// The modem answered `BUSY`.
+    Busy,
+    /// The modem answered `NO ANSWER`.
+    NoAnswer,
+    /// The modem answered `NO DIALTONE`.
+    NoDialtone,
+    /// The command doesn't fit in the command buffer.
+    CommandTooLong,
+    /// The response doesn't fit in the response buffer.
+    ResponseTooLong,
+    /// The response is not what was expected for the command.
+    UnexpectedResponse,
+}
+
+/// Handler for unsolicited result codes.
+///
+/// Implemented for closures `FnMut(&[u8]) -> bool`, and for `()` to not handle any.
+pub trait UrcHandler {
+    /// Called with every line that is not a response to the command in progress.
+    ///
+    /// Returns `true` if the line was an unsolicited result code. Otherwise, the line is
+    /// included in the response of the command in progress, or dropped when idle.
+    fn handle(&mut self, line: &[u8]) -> bool;
+}
+
+impl UrcHandler for () {
+    fn handle(&mut self, _line: &[u8]) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&[u8]) -> bool> UrcHandler for F {
+    fn handle(&mut self, line: &[u8]) -> bool {
+        self(line)
+    }
+}
+
+/// Response to a command.
+#[derive(Debug, Copy, Clone)]
+pub struct Response<'a> {
+    data: &'a [u8],
+    connected: bool,
+}
+
+impl<'a> Response<'a> {
+    /// Information lines of the response, without the final result code.
+    pub fn lines(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.data.split(|&b| b == b'\n').filter(|line| !line.is_empty())
+    }
+
+    /// Value of the first line starting with `prefix` followed by a colon, such as
+    /// `20,99` for `info("+CSQ")` and a line `+CSQ: 20,99`.
+    pub fn info(&self, prefix: &str) -> Option<&'a [u8]> {
+        self.lines().find_map(|line| {
+            let value = line.strip_prefix(prefix.as_bytes())?.strip_prefix(b":")?;
+            Some(trim(value))
+        })
+    }
+
+    /// Whether the final result code was `CONNECT`, meaning the modem switched to data mode.
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+}
+
+fn trim(mut data: &[u8]) -> &[u8] {
+    while let [b' ', rest @ ..] = data {
+        data = rest;
+    }
+    while let [rest @ .., b' '] = data {
+        data = rest;
+    }
+    data
+}
+
+/// Splits a comma-separated response value into its fields, without surrounding quotes.
+pub fn fields(value: &[u8]) -> impl Iterator<Item = &[u8]> {
+    value.split(|&b| b == b',').map(|field| {
+        let field = trim(field);
+        match field {
+            [b'"', inner @ .., b'"'] => inner,
+            field => field,
+        }
+    })
+}
+
+/// Parses a decimal number from a response field.
+pub fn parse_int(field: &[u8]) -> Option<u16> {
+    let field = trim(field);
+    if field.is_empty() {
+        return None;
+    }
+    field.iter().try_fold(0u16, |n, &b| match b {
+        b'0'..=b'9' => n.checked_mul(10)?.checked_add((b - b'0') as u16),
+        _ => None,
+    })
+}
+
+/// Returns the final result of a response if `line` is a final result code,
+/// or `None` if it's an information line.
+fn final_result<E>(line: &[u8]) -> Option<Result<bool, Error<E>>> {
+    let code = |prefix: &[u8]| line.strip_prefix(prefix).and_then(parse_int);
+    let res = match line {
+        b"OK" => Ok(false),
+        b"ERROR" => Err(Error::Error),
+        b"NO CARRIER" => Err(Error::NoCarrier),
+        b"BUSY" => Err(Error::Busy),
+        b"NO ANSWER" => Err(Error::NoAnswer),
+        b"NO DIALTONE" => Err(Error::NoDialtone),
+        _ if line.starts_with(b"CONNECT") => Ok(true),
+        // Verbose error messages, as with `AT+CMEE=2`, have no code.
+        _ if line.starts_with(b"+CME ERROR:") => Err(code(b"+CME ERROR:").map_or(Error::Error, Error::CmeError)),
+        _ if line.starts_with(b"+CMS ERROR:") => Err(code(b"+CMS ERROR:").map_or(Error::Error, Error::CmsError)),
+        _ => return None,
+    };
+    Some(res)
+}
+
+/// Returns the prefix of the information lines answering `cmd`, such as `+CSQ` for `AT+CSQ`.
+fn response_prefix(cmd: &[u8]) -> Option<&[u8]> {
+    let cmd = cmd.strip_prefix(b"AT").or_else(|| cmd.strip_prefix(b"at"))?;
+    if !cmd.starts_with(b"+") {
+        return None;
+    }
+    let end = cmd
+        .iter()
+        .position(|b| matches!(b, b'=' | b'?' | b';'))
+        .unwrap_or(cmd.len());
+    Some(&cmd[..end])
+}
+
+struct CommandBuf {
+    buf: [u8; COMMAND_LEN],
+    len: usize,
+}
+
+impl fmt::Write for CommandBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let buf = self.buf.get_mut(self.len..self.len + s.len()).ok_or(fmt::Error)?;
+        buf.copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// AT command client.
+pub struct AtClient<RW, U> {
+    port: RW,
+    urc: U,
+    timeout: Duration,
+
+    line: [u8; LINE_LEN],
+    line_len: usize,
+    line_overflow: bool,
+
+    response: [u8; RESPONSE_LEN],
+    response_len: usize,
+}
+
+impl<RW: BufRead + Write, U: UrcHandler> AtClient<RW, U> {
+    /// Create a new `AtClient` talking to a modem on `port`.
+    pub fn new(port: RW, urc: U) -> Self {
+        Self {
+            port,
+            urc,
+            timeout: DEFAULT_TIMEOUT,
+            line: [0; LINE_LEN],
+            line_len: 0,
+            line_overflow: false,
+            response: [0; RESPONSE_LEN],
+            response_len: 0,
+        }
+    }
+
+    /// Set the timeout used by [`command`](Self::command). Defaults to 5 seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the serial port, for example to use it in data mode.
+    pub fn port(&mut self) -> &mut RW {
+        &mut self.port
+    }
+
+    /// Get the URC handler.
+    pub fn urc_handler(&mut self) -> &mut U {
+        &mut self.urc
+    }
+
+    /// Consume the client, returning the serial port.
+    pub fn into_inner(self) -> RW {
+        self.port
+    }
+
+    /// Send a command, such as `AT+CSQ`, and wait for its response.
+    ///
+    /// Returns an error if the modem answers with an error result code, or doesn't answer in time.
+    pub async fn command(&mut self, cmd: &str) -> Result<Response<'_>, Error<RW::Error>> {
+        self.command_with_timeout(cmd, self.timeout).await
+    }
+
+    /// Send a command and wait for its response, with a timeout other than the default.
+    ///
+    /// This is useful for commands that take long to complete, such as network registration.
+    pub async fn command_with_timeout(
+        &mut self,
+        cmd: &str,
+        timeout: Duration,
+    ) -> Result<Response<'_>, Error<RW::Error>> {
+        self.send(cmd.as_bytes(), timeout).await
+    }
+
+    /// Format a command, send it and wait for its response.
+    pub async fn command_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<Response<'_>, Error<RW::Error>> {
+        let mut cmd = CommandBuf {
+            buf: [0; COMMAND_LEN],
+            len: 0,
+        };
+        cmd.write_fmt(args).map_err(|_| Error::CommandTooLong)?;
+        self.send(&cmd.buf[..cmd.len], self.timeout).await
+    }
+
+    /// Wait for one line from the modem while no command is in progress, and dispatch it
+    /// to the URC handler.
+    pub async fn poll_urc(&mut self) -> Result<(), Error<RW::Error>> {
+        let n = self.read_line().await?;
+        let line = &self.line[..n];
+        if !self.urc.handle(line) {
+            debug!("AT: dropping unexpected line {:?}", line);
+        }
+        Ok(())
+    }
+
+    async fn send(&mut self, cmd: &[u8], timeout: Duration) -> Result<Response<'_>, Error<RW::Error>> {
+        debug!("AT: tx {:?}", cmd);
+        self.port.write_all(cmd).await.map_err(Error::Write)?;
+        self.port.write_all(b"\r").await.map_err(Error::Write)?;
+        self.port.flush().await.map_err(Error::Write)?;
+        self.response(cmd, timeout).await
+    }
+
+    /// Waits for the final result code answering `cmd`.
+    pub(crate) async fn response(&mut self, cmd: &[u8], timeout: Duration) -> Result<Response<'_>, Error<RW::Error>> {
+        self.response_len = 0;
+        let mut overflow = false;
+        let prefix = response_prefix(cmd);
+
+        let connected = with_timeout(timeout, async {
+            loop {
+                let n = self.read_line().await?;
+                let line = &self.line[..n];
+                trace!("AT: rx {:?}", line);
+
+                // Echo, when the modem has it enabled.
+                if line == cmd {
+                    continue;
+                }
+                if let Some(res) = final_result(line) {
+                    return res;
+                }
+
+                let is_response = prefix.map_or(false, |p| line.starts_with(p) && line.get(p.len()) == Some(&b':'));
+                if !is_response && self.urc.handle(line) {
+                    continue;
+                }
+
+                match self.response.get_mut(self.response_len..self.response_len + n + 1) {
+                    Some(buf) => {
+                        buf[..n].copy_from_slice(line);
+                        buf[n] = b'\n';
+                        self.response_len += n + 1;
+                    }
+                    None => overflow = true,
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        if overflow {
+            return Err(Error::ResponseTooLong);
+        }
+        Ok(Response {
+            data: &self.response[..self.response_len],
+            connected,
+        })
+    }
+
+    /// Reads a non-empty line, returning its length in `self.line`.
+    ///
+    /// Lines too long for the buffer are dropped.
+    async fn read_line(&mut self) -> Result<usize, Error<RW::Error>> {
+        loop {
+            let data = self.port.fill_buf().await.map_err(Error::Read)?;
+            if data.is_empty() {
+                return Err(Error::Eof);
+            }
+
+            // Only consume up to the end of the line, the data after it may not be for us,
+            // for example when switching to data mode.
+            for (i, &b) in data.iter().enumerate() {
+                if b == b'\r' || b == b'\n' {
+                    let len = self.line_len;
+                    let overflow = self.line_overflow;
+                    self.line_len = 0;
+                    self.line_overflow = false;
+
+                    if overflow {
+                        warn!("AT: dropping line too long");
+                    } else if len > 0 {
+                        // Take the `\n` of a `\r\n` too, so that none is left before data.
+                        let end = if data[i..].starts_with(b"\r\n") { i + 2 } else { i + 1 };
+                        self.port.consume(end);
+                        return Ok(len);
+                    }
+                } else if self.line_len < LINE_LEN {
+                    self.line[self.line_len] = b;
+                    self.line_len += 1;
+                } else {
+                    self.line_overflow = true;
+                }
+            }
+            let n = data.len();
+            self.port.consume(n);
+        }
+    }
+}
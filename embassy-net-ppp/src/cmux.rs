@@ -0,0 +1,492 @@
+//! GSM 07.10 (3GPP TS 27.010) multiplexer, basic option.
+//!
+//! Multiplexes several virtual serial ports, called channels, over a single serial port to a
+//! modem. This allows for example running PPP on one channel while sending AT commands for
+//! signal quality on another. Each [`Channel`] implements `BufRead + Write`, so it can be
+//! passed to [`AtClient`](crate::at::AtClient), [`Modem`](crate::modem::Modem) or
+//! [`Runner::run`](crate::Runner::run).
+//!
+//! The modem must first be switched to multiplexing mode with `AT+CMUX=0`, see
+//! [`Modem::enter_cmux`](crate::modem::Modem::enter_cmux).
+
+use core::convert::Infallible;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::pipe::{Pipe, Reader, Writer};
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{BufRead, ErrorType, Read, Write};
+
+/// Maximum supported frame size, the largest usual value of the modem's `N1` parameter.
+pub const MAX_FRAME_SIZE: usize = 1509;
+
+const FLAG: u8 = 0xf9;
+const EA: u8 = 0x01;
+const CR: u8 = 0x02;
+const PF: u8 = 0x10;
+
+// Frame types, without the P/F bit.
+const SABM: u8 = 0x2f;
+const UA: u8 = 0x63;
+const DM: u8 = 0x0f;
+const DISC: u8 = 0x43;
+const UIH: u8 = 0xef;
+const UI: u8 = 0x03;
+
+// Control channel message types, without the C/R bit.
+const MSG_MSC: u8 = 0xe1;
+const MSG_CLD: u8 = 0xc1;
+const MSG_TEST: u8 = 0x21;
+const MSG_NSC: u8 = 0x11;
+
+/// V.24 signals sent with MSC: ready to communicate, ready to receive, data valid.
+const V24_SIGNALS: u8 = 0x8d;
+
+const CRC_INIT: u8 = 0xff;
+
+fn crc(mut crc: u8, data: &[u8]) -> u8 {
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xe0 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// CRC of a frame's checked fields followed by its FCS, when it's correct.
+const GOOD_CRC: u8 = 0xcf;
+
+/// Error returned by [`Runner::run`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Reading from the serial port failed.
+    Read(E),
+    /// Writing to the serial port failed.
+    Write(E),
+    /// Reading from the serial port got EOF.
+    Eof,
+    /// The modem didn't answer when opening a channel.
+    Timeout,
+    /// The modem refused to open the channel with the given DLCI.
+    ChannelRejected(u8),
+    /// The modem closed the multiplexer.
+    Closed,
+}
+
+/// Multiplexer configuration.
+#[non_exhaustive]
+pub struct Config {
+    /// Maximum number of data bytes in a frame, the modem's `N1` parameter. Defaults to 127.
+    ///
+    /// Must not exceed [`MAX_FRAME_SIZE`].
+    pub max_frame_size: usize,
+    /// Time to wait for the modem to answer when opening a channel. Defaults to 1 second.
+    pub ack_timeout: Duration,
+    /// Number of times opening a channel is retried. Defaults to 3.
+    pub retries: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_frame_size: 127,
+            ack_timeout: Duration::from_secs(1),
+            retries: 3,
+        }
+    }
+}
+
+/// Internal state for the multiplexer, with `N` channels buffering `BUF` bytes each way.
+pub struct State<const N: usize, const BUF: usize> {
+    rx: [Pipe<NoopRawMutex, BUF>; N],
+    tx: [Pipe<NoopRawMutex, BUF>; N],
+    tx_signal: Signal<NoopRawMutex, ()>,
+}
+
+impl<const N: usize, const BUF: usize> State<N, BUF> {
+    const PIPE: Pipe<NoopRawMutex, BUF> = Pipe::new();
+
+    /// Create a new `State`.
+    pub const fn new() -> Self {
+        Self {
+            rx: [Self::PIPE; N],
+            tx: [Self::PIPE; N],
+            tx_signal: Signal::new(),
+        }
+    }
+}
+
+/// A multiplexed channel, a virtual serial port to the modem.
+pub struct Channel<'d, const BUF: usize> {
+    rx: Reader<'d, NoopRawMutex, BUF>,
+    tx: &'d Pipe<NoopRawMutex, BUF>,
+    tx_signal: &'d Signal<NoopRawMutex, ()>,
+}
+
+impl<'d, const BUF: usize> ErrorType for Channel<'d, BUF> {
+    type Error = Infallible;
+}
+
+impl<'d, const BUF: usize> Read for Channel<'d, BUF> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(self.rx.read(buf).await)
+    }
+}
+
+impl<'d, const BUF: usize> BufRead for Channel<'d, BUF> {
+    async fn fill_buf(&mut self) -> Result<&[u8], Infallible> {
+        Ok(self.rx.fill_buf().await)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx.consume(amt)
+    }
+}
+
+impl<'d, const BUF: usize> Write for Channel<'d, BUF> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        let n = self.tx.write(buf).await;
+        self.tx_signal.signal(());
+        Ok(n)
+    }
+}
+
+/// Background runner for the multiplexer.
+///
+/// You must call `.run()` in a background task for the channels to operate.
+pub struct Runner<'d, const N: usize, const BUF: usize> {
+    rx: [Writer<'d, NoopRawMutex, BUF>; N],
+    tx: &'d [Pipe<NoopRawMutex, BUF>; N],
+    tx_signal: &'d Signal<NoopRawMutex, ()>,
+    config: Config,
+}
+
+/// Create a multiplexer.
+///
+/// This returns a `Runner`, which you must call `.run()` on in a background task, and the
+/// channels. Channel `i` is DLCI `i + 1` on the modem.
+pub fn new<const N: usize, const BUF: usize>(
+    state: &mut State<N, BUF>,
+    config: Config,
+) -> (Runner<'_, N, BUF>, [Channel<'_, BUF>; N]) {
+    assert!(config.max_frame_size > 0 && config.max_frame_size <= MAX_FRAME_SIZE);
+    // DLCIs are 6 bits, and DLCI 0 is the control channel.
+    assert!(N < 64);
+
+    let State { rx, tx, tx_signal } = state;
+    let (tx, tx_signal) = (&*tx, &*tx_signal);
+
+    let mut writers = [None; N];
+    let mut i = 0;
+    let channels = rx.each_mut().map(|pipe| {
+        let (reader, writer) = pipe.split();
+        writers[i] = Some(writer);
+        let channel = Channel {
+            rx: reader,
+            tx: &tx[i],
+            tx_signal,
+        };
+        i += 1;
+        channel
+    });
+
+    let runner = Runner {
+        rx: writers.map(Option::unwrap),
+        tx,
+        tx_signal,
+        config,
+    };
+    (runner, channels)
+}
+
+impl<'d, const N: usize, const BUF: usize> Runner<'d, N, BUF> {
+    /// Run the multiplexer over `port`, which must already be in multiplexing mode.
+    ///
+    /// This opens the control channel and all data channels, then forwards data between
+    /// the channels and the modem. It only returns on error.
+    pub async fn run<RW: BufRead + Write>(&mut self, mut port: RW) -> Result<Infallible, Error<RW::Error>> {
+        let mut reader = FrameReader::new();
+
+        for dlci in 0..=N as u8 {
+            self.open(&mut port, &mut reader, dlci).await?;
+        }
+        for dlci in 1..=N as u8 {
+            send_control(&mut port, MSG_MSC | CR, &[dlci << 2 | CR | EA, V24_SIGNALS]).await?;
+        }
+        info!("CMUX: {} channels open", N);
+
+        loop {
+            match select(read_frame(&mut port, &mut reader), self.tx_signal.wait()).await {
+                Either::First(frame) => self.handle(&mut port, frame?).await?,
+                Either::Second(()) => self.send_pending(&mut port).await?,
+            }
+        }
+    }
+
+    async fn open<RW: BufRead + Write>(
+        &mut self,
+        port: &mut RW,
+        reader: &mut FrameReader,
+        dlci: u8,
+    ) -> Result<(), Error<RW::Error>> {
+        let timeout = self.config.ack_timeout;
+        for _ in 0..=self.config.retries {
+            debug!("CMUX: opening DLCI {}", dlci);
+            send_frame(port, dlci, SABM | PF, true, &[]).await?;
+
+            let answer = async {
+                loop {
+                    let frame = read_frame(port, reader).await?;
+                    match (frame.dlci == dlci, frame.control) {
+                        (true, UA) => return Ok(()),
+                        (true, DM) => return Err(Error::ChannelRejected(dlci)),
+                        _ => self.handle(port, frame).await?,
+                    }
+                }
+            };
+            match with_timeout(timeout, answer).await {
+                Ok(res) => return res,
+                Err(_) => warn!("CMUX: no answer opening DLCI {}", dlci),
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    async fn handle<RW: Write>(&mut self, port: &mut RW, frame: Frame<'_>) -> Result<(), Error<RW::Error>> {
+        trace!(
+            "CMUX: rx DLCI {} control {:02x} {:?}",
+            frame.dlci,
+            frame.control,
+            frame.info
+        );
+        match (frame.dlci, frame.control) {
+            (0, UIH) => self.handle_control(port, frame.info).await?,
+            (dlci, UIH | UI) => match self.rx.get_mut(dlci as usize - 1) {
+                Some(rx) => unwrap!(rx.write_all(frame.info).await),
+                None => warn!("CMUX: dropping data for unknown DLCI {}", dlci),
+            },
+            (dlci, SABM) => {
+                let control = if dlci as usize <= N { UA } else { DM };
+                send_frame(port, dlci, control | PF, false, &[]).await?
+            }
+            (dlci, DISC) => {
+                send_frame(port, dlci, UA | PF, false, &[]).await?;
+                if dlci == 0 {
+                    return Err(Error::Closed);
+                }
+                warn!("CMUX: modem closed DLCI {}", dlci);
+            }
+            (dlci, control) => debug!("CMUX: ignoring frame {:02x} on DLCI {}", control, dlci),
+        }
+        Ok(())
+    }
+
+    async fn handle_control<RW: Write>(&mut self, port: &mut RW, data: &[u8]) -> Result<(), Error<RW::Error>> {
+        let [msg_type, len, value @ ..] = data else {
+            warn!("CMUX: control message too short");
+            return Ok(());
+        };
+        let Some(value) = value.get(..(len >> 1) as usize) else {
+            warn!("CMUX: bad control message length");
+            return Ok(());
+        };
+
+        if msg_type & CR == 0 {
+            // Response to one of our commands.
+            return Ok(());
+        }
+        match msg_type & !CR {
+            MSG_MSC | MSG_TEST => send_control(port, msg_type & !CR, value).await?,
+            MSG_CLD => {
+                send_control(port, MSG_CLD, &[]).await?;
+                return Err(Error::Closed);
+            }
+            _ => send_control(port, MSG_NSC, &[*msg_type]).await?,
+        }
+        Ok(())
+    }
+
+    async fn send_pending<RW: Write>(&mut self, port: &mut RW) -> Result<(), Error<RW::Error>> {
+        let mut buf = [0; MAX_FRAME_SIZE];
+        let buf = &mut buf[..self.config.max_frame_size];
+        for (i, tx) in self.tx.iter().enumerate() {
+            while let Ok(n) = tx.try_read(buf) {
+                send_frame(port, i as u8 + 1, UIH, true, &buf[..n]).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn send_frame<RW: Write>(
+    port: &mut RW,
+    dlci: u8,
+    control: u8,
+    command: bool,
+    info: &[u8],
+) -> Result<(), Error<RW::Error>> {
+    // We're the initiator, so the C/R bit is set for commands and cleared for responses.
+    let address = dlci << 2 | if command { CR } else { 0 } | EA;
+    let len = info.len();
+    let mut header = [FLAG, address, control, (len << 1) as u8 | EA, 0];
+    let header = match len {
+        0..=0x7f => &header[..4],
+        _ => {
+            header[3] = (len << 1) as u8;
+            header[4] = (len >> 7) as u8;
+            &header[..]
+        }
+    };
+    // We only send UIH frames with data, for which the FCS doesn't cover the data.
+    let fcs = 0xff - crc(CRC_INIT, &header[1..]);
+
+    port.write_all(header).await.map_err(Error::Write)?;
+    port.write_all(info).await.map_err(Error::Write)?;
+    port.write_all(&[fcs, FLAG]).await.map_err(Error::Write)
+}
+
+async fn send_control<RW: Write>(port: &mut RW, msg_type: u8, value: &[u8]) -> Result<(), Error<RW::Error>> {
+    let mut msg = [0; 8];
+    msg[0] = msg_type;
+    msg[1] = (value.len() << 1) as u8 | EA;
+    msg[2..][..value.len()].copy_from_slice(value);
+    send_frame(port, 0, UIH, true, &msg[..value.len() + 2]).await
+}
+
+/// Reads the next valid frame from `port`.
+///
+/// This is cancel-safe, partially received frames are kept in `reader`.
+async fn read_frame<'r, RW: BufRead>(
+    port: &mut RW,
+    reader: &'r mut FrameReader,
+) -> Result<Frame<'r>, Error<RW::Error>> {
+    loop {
+        let data = port.fill_buf().await.map_err(Error::Read)?;
+        if data.is_empty() {
+            return Err(Error::Eof);
+        }
+        let (n, complete) = reader.consume(data);
+        port.consume(n);
+        if complete {
+            return Ok(reader.frame());
+        }
+    }
+}
+
+struct Frame<'a> {
+    dlci: u8,
+    /// Frame type, without the P/F bit.
+    control: u8,
+    info: &'a [u8],
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum ReaderState {
+    Flag,
+    Header,
+    Info,
+    Fcs,
+    End,
+}
+
+struct FrameReader {
+    state: ReaderState,
+    header: [u8; 4],
+    header_len: usize,
+    info: [u8; MAX_FRAME_SIZE],
+    info_len: usize,
+    len: usize,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        Self {
+            state: ReaderState::Flag,
+            header: [0; 4],
+            header_len: 0,
+            info: [0; MAX_FRAME_SIZE],
+            info_len: 0,
+            len: 0,
+        }
+    }
+
+    fn frame(&self) -> Frame<'_> {
+        Frame {
+            dlci: self.header[0] >> 2,
+            control: self.header[1] & !PF,
+            info: &self.info[..self.info_len],
+        }
+    }
+
+    /// Consumes `data` until the end of a frame.
+    ///
+    /// Returns how many bytes were consumed, and whether a frame is complete.
+    fn consume(&mut self, data: &[u8]) -> (usize, bool) {
+        for (i, &b) in data.iter().enumerate() {
+            match self.state {
+                ReaderState::Flag => {
+                    if b == FLAG {
+                        self.start();
+                    }
+                }
+                // Flags may be repeated between frames.
+                ReaderState::Header if b == FLAG && self.header_len == 0 => {}
+                ReaderState::Header => {
+                    self.header[self.header_len] = b;
+                    self.header_len += 1;
+
+                    let len = match self.header_len {
+                        3 if b & EA != 0 => (b >> 1) as usize,
+                        4 => (self.header[2] >> 1) as usize | (b as usize) << 7,
+                        _ => continue,
+                    };
+                    if len > MAX_FRAME_SIZE {
+                        warn!("CMUX: dropping frame too long");
+                        self.state = ReaderState::Flag;
+                        continue;
+                    }
+                    self.info_len = len;
+                    self.len = 0;
+                    self.state = if len == 0 { ReaderState::Fcs } else { ReaderState::Info };
+                }
+                ReaderState::Info => {
+                    self.info[self.len] = b;
+                    self.len += 1;
+                    if self.len == self.info_len {
+                        self.state = ReaderState::Fcs;
+                    }
+                }
+                ReaderState::Fcs => {
+                    let mut crc = crc(CRC_INIT, &self.header[..self.header_len]);
+                    if self.header[1] & !PF == UI {
+                        crc = self::crc(crc, &self.info[..self.info_len]);
+                    }
+                    if self::crc(crc, &[b]) == GOOD_CRC {
+                        self.state = ReaderState::End;
+                    } else {
+                        warn!("CMUX: dropping frame with bad FCS");
+                        self.state = ReaderState::Flag;
+                    }
+                }
+                ReaderState::End => {
+                    if b == FLAG {
+                        // The closing flag may also open the next frame.
+                        self.start();
+                        return (i + 1, true);
+                    }
+                    warn!("CMUX: dropping frame without closing flag");
+                    self.state = ReaderState::Flag;
+                }
+            }
+        }
+        (data.len(), false)
+    }
+
+    fn start(&mut self) {
+        self.state = ReaderState::Header;
+        self.header_len = 0;
+    }
+}
@@ -5,18 +5,30 @@
 // must be first
 mod fmt;
 
+pub mod at;
+pub mod cmux;
+mod md5;
+pub mod modem;
+mod ppp;
+mod pppos;
+
 use core::convert::Infallible;
 use core::mem::MaybeUninit;
 
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select3, Either3};
 use embassy_net_driver_channel as ch;
 use embassy_net_driver_channel::driver::LinkState;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_io_async::{BufRead, Write};
-use ppproto::pppos::{BufferFullError, PPPoS, PPPoSAction};
-pub use ppproto::{Config, Ipv4Status};
+pub use ppp::{AuthProtocol, Config, Ipv4Address, Ipv4Status, Ipv6Status, Phase, ServerConfig, Status};
+
+use crate::pppos::{Action, BufferFullError, PppOs};
 
 const MTU: usize = 1500;
 
+/// Interval between retransmissions of unanswered control packets, RFC 1661 section 4.6.
+const RESTART_INTERVAL: Duration = Duration::from_secs(3);
+
 /// Type alias for the embassy-net driver.
 pub type Device<'d> = embassy_net_driver_channel::Device<'d, MTU>;
 
@@ -53,6 +65,8 @@ pub enum RunError<E> {
     Eof,
     /// PPP protocol was terminated by the peer
     Terminated,
+    /// Authentication failed, either the peer rejected our credentials or we rejected its.
+    AuthFailed,
 }
 
 impl<'d> Runner<'d> {
@@ -66,14 +80,17 @@ impl<'d> Runner<'d> {
     ///
     /// After this function returns or is canceled, you can call it again to establish
     /// a new PPP connection.
+    ///
+    /// `on_up` is called with the negotiated [`Status`] every time the link comes up. Use it
+    /// to configure the `embassy-net` stack with the IPv4 and IPv6 addresses.
     pub async fn run<RW: BufRead + Write>(
         &mut self,
         mut rw: RW,
-        config: ppproto::Config<'_>,
-        mut on_ipv4_up: impl FnMut(Ipv4Status),
+        config: Config<'_>,
+        mut on_up: impl FnMut(Status),
     ) -> Result<Infallible, RunError<RW::Error>> {
-        let mut ppp = PPPoS::new(config);
-        ppp.open().unwrap();
+        let mut ppp = PppOs::new(config);
+        ppp.open();
 
         let (state_chan, mut rx_chan, mut tx_chan) = self.ch.borrow_split();
         state_chan.set_link_state(LinkState::Down);
@@ -84,6 +101,7 @@ impl<'d> Runner<'d> {
 
         let mut needs_poll = true;
         let mut was_up = false;
+        let mut next_timeout = Instant::now() + RESTART_INTERVAL;
 
         loop {
             let rx_fut = async {
@@ -99,8 +117,9 @@ impl<'d> Runner<'d> {
                 Ok((buf, rx_data))
             };
             let tx_fut = tx_chan.tx_buf();
-            match select(rx_fut, tx_fut).await {
-                Either::First(r) => {
+            let timeout_fut = Timer::at(next_timeout);
+            match select3(rx_fut, tx_fut, timeout_fut).await {
+                Either3::First(r) => {
                     needs_poll = false;
 
                     let (buf, rx_data) = r?;
@@ -108,40 +127,44 @@ impl<'d> Runner<'d> {
                     rw.consume(n);
 
                     match ppp.poll(&mut tx_buf, &mut rx_buf) {
-                        PPPoSAction::None => {}
-                        PPPoSAction::Received(rg) => {
+                        Action::None => {}
+                        Action::Received(rg) => {
                             let pkt = &rx_buf[rg];
                             buf[..pkt.len()].copy_from_slice(pkt);
                             rx_chan.rx_done(pkt.len());
                         }
-                        PPPoSAction::Transmit(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
-                    }
-
-                    let status = ppp.status();
-                    match status.phase {
-                        ppproto::Phase::Dead => {
-                            return Err(RunError::Terminated);
-                        }
-                        ppproto::Phase::Open => {
-                            if !was_up {
-                                on_ipv4_up(status.ipv4.unwrap());
-                            }
-                            was_up = true;
-                            state_chan.set_link_state(LinkState::Up);
-                        }
-                        _ => {
-                            was_up = false;
-                            state_chan.set_link_state(LinkState::Down);
-                        }
+                        Action::Transmit(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
                     }
                 }
-                Either::Second(pkt) => {
+                Either3::Second(pkt) => {
                     match ppp.send(pkt, &mut tx_buf) {
                         Ok(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
                         Err(BufferFullError) => unreachable!(),
                     }
                     tx_chan.tx_done();
                 }
+                Either3::Third(()) => {
+                    next_timeout = Instant::now() + RESTART_INTERVAL;
+                    let n = ppp.timeout(&mut tx_buf);
+                    rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?;
+                }
+            }
+
+            let status = ppp.status();
+            match status.phase {
+                Phase::Dead if ppp.auth_failed() => return Err(RunError::AuthFailed),
+                Phase::Dead => return Err(RunError::Terminated),
+                Phase::Open => {
+                    if !was_up {
+                        on_up(status);
+                    }
+                    was_up = true;
+                    state_chan.set_link_state(LinkState::Up);
+                }
+                _ => {
+                    was_up = false;
+                    state_chan.set_link_state(LinkState::Down);
+                }
             }
         }
     }
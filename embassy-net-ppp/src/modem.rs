@@ -0,0 +1,247 @@
+//! Cellular modem lifecycle, from power-up to a PPP data connection.
+//!
+//! ```text
+//! init -> set_apn -> attach -> dial -> (PPP) -> escape -> (AT commands) -> resume -> (PPP)
+//! ```
+//!
+//! [`Modem::dial`] and [`Modem::resume`] return the serial port in data mode, to be passed
+//! to [`Runner::run`](crate::Runner::run). Once the runner is stopped, [`Modem::escape`]
+//! takes the port back to command mode with `+++`. Alternatively, [`Modem::enter_cmux`]
+//! switches the modem to multiplexed mode, so AT commands and PPP can run at the same time,
+//! see [`cmux`](crate::cmux).
+
+use embassy_time::{Duration, Instant, Timer};
+use embedded_io_async::{BufRead, Write};
+
+use crate::at::{fields, parse_int, AtClient, Error, UrcHandler};
+
+/// Modem configuration.
+#[non_exhaustive]
+pub struct Config {
+    /// Timeout for regular commands. Defaults to 5 seconds.
+    pub command_timeout: Duration,
+    /// Timeout for dialing to get `CONNECT`. Defaults to 30 seconds.
+    pub connect_timeout: Duration,
+    /// Timeout for registering to the network and attaching. Defaults to 60 seconds.
+    pub registration_timeout: Duration,
+    /// Silence required before and after the `+++` escape sequence. Defaults to 1 second.
+    pub escape_guard_time: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command_timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(30),
+            registration_timeout: Duration::from_secs(60),
+            escape_guard_time: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Network registration status, from `AT+CEREG?` or `AT+CGREG?`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Registration {
+    /// Not registered, and not searching for an operator.
+    NotRegistered,
+    /// Registered to the home network.
+    Home,
+    /// Not registered, searching for an operator.
+    Searching,
+    /// Registration denied.
+    Denied,
+    /// Unknown, for example out of coverage.
+    Unknown,
+    /// Registered, roaming.
+    Roaming,
+}
+
+impl Registration {
+    fn from_stat(stat: u16) -> Self {
+        match stat {
+            0 => Self::NotRegistered,
+            1 => Self::Home,
+            2 => Self::Searching,
+            3 => Self::Denied,
+            5 => Self::Roaming,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Whether the modem is registered to a network, home or roaming.
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Self::Home | Self::Roaming)
+    }
+}
+
+/// Signal quality, from `AT+CSQ`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SignalQuality {
+    /// Received signal strength in dBm, or `None` if unknown.
+    pub rssi: Option<i16>,
+    /// Bit error rate, from 0 to 7, or `None` if unknown.
+    pub ber: Option<u8>,
+}
+
+/// Cellular modem controlled with AT commands.
+pub struct Modem<RW, U> {
+    at: AtClient<RW, U>,
+    config: Config,
+}
+
+impl<RW: BufRead + Write, U: UrcHandler> Modem<RW, U> {
+    /// Create a new `Modem` on `port`, which must be in command mode.
+    pub fn new(port: RW, urc: U, config: Config) -> Self {
+        let mut at = AtClient::new(port, urc);
+        at.set_timeout(config.command_timeout);
+        Self { at, config }
+    }
+
+    /// Get the AT command client, to send commands not covered by `Modem`.
+    pub fn at(&mut self) -> &mut AtClient<RW, U> {
+        &mut self.at
+    }
+
+    /// Consume the modem, returning the serial port.
+    pub fn into_inner(self) -> RW {
+        self.at.into_inner()
+    }
+
+    /// Wait for the modem to answer, then disable echo and enable numeric error codes.
+    pub async fn init(&mut self) -> Result<(), Error<RW::Error>> {
+        // Modems with autobauding only answer after having seen a few `AT`s.
+        let mut tries = 10;
+        loop {
+            match self.at.command_with_timeout("AT", Duration::from_millis(500)).await {
+                Ok(_) => break,
+                Err(Error::Timeout) if tries > 1 => tries -= 1,
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.at.command("ATE0").await?;
+        self.at.command("AT+CMEE=1").await?;
+        Ok(())
+    }
+
+    /// Set the access point name of the first PDP context, used when dialing.
+    pub async fn set_apn(&mut self, apn: &str) -> Result<(), Error<RW::Error>> {
+        self.at
+            .command_fmt(format_args!("AT+CGDCONT=1,\"IP\",\"{}\"", apn))
+            .await?;
+        Ok(())
+    }
+
+    /// Get the network registration status, for LTE or else for GPRS.
+    pub async fn registration(&mut self) -> Result<Registration, Error<RW::Error>> {
+        let mut registration = Registration::Unknown;
+        for (cmd, prefix) in [("AT+CEREG?", "+CEREG"), ("AT+CGREG?", "+CGREG")] {
+            let stat = match self.at.command(cmd).await {
+                Ok(resp) => resp
+                    .info(prefix)
+                    .and_then(|value| fields(value).nth(1))
+                    .and_then(parse_int),
+                // Modems without LTE don't know `AT+CEREG`.
+                Err(Error::Error | Error::CmeError(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            registration = Registration::from_stat(stat.ok_or(Error::UnexpectedResponse)?);
+            if registration.is_registered() {
+                break;
+            }
+        }
+        Ok(registration)
+    }
+
+    /// Wait for the modem to register to a network, then attach to the packet domain.
+    pub async fn attach(&mut self) -> Result<(), Error<RW::Error>> {
+        let deadline = Instant::now() + self.config.registration_timeout;
+        loop {
+            let registration = self.registration().await?;
+            if registration.is_registered() {
+                break;
+            }
+            debug!("modem: waiting for registration, {:?}", registration);
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+            Timer::after_secs(1).await;
+        }
+
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.at.command_with_timeout("AT+CGATT=1", timeout).await?;
+        Ok(())
+    }
+
+    /// Get the signal quality.
+    pub async fn signal_quality(&mut self) -> Result<SignalQuality, Error<RW::Error>> {
+        let resp = self.at.command("AT+CSQ").await?;
+        let mut values = fields(resp.info("+CSQ").ok_or(Error::UnexpectedResponse)?).map(parse_int);
+        let (Some(Some(rssi)), Some(Some(ber))) = (values.next(), values.next()) else {
+            return Err(Error::UnexpectedResponse);
+        };
+
+        Ok(SignalQuality {
+            // 0 is -113 dBm or less, 31 is -51 dBm or more, 99 is unknown.
+            rssi: (rssi <= 31).then(|| -113 + 2 * rssi as i16),
+            ber: (ber <= 7).then_some(ber as u8),
+        })
+    }
+
+    /// Dial the packet data service with `ATD*99#`, switching the modem to data mode.
+    ///
+    /// Returns the serial port, to be passed to [`Runner::run`](crate::Runner::run).
+    pub async fn dial(&mut self) -> Result<&mut RW, Error<RW::Error>> {
+        self.connect("ATD*99#").await
+    }
+
+    /// Switch back to data mode after [`escape`](Self::escape), with `ATO`.
+    ///
+    /// Returns the serial port, to be passed to [`Runner::run`](crate::Runner::run).
+    pub async fn resume(&mut self) -> Result<&mut RW, Error<RW::Error>> {
+        self.connect("ATO").await
+    }
+
+    /// Switch from data mode to command mode with the `+++` escape sequence.
+    ///
+    /// The data connection stays up, and can be resumed with [`resume`](Self::resume).
+    pub async fn escape(&mut self) -> Result<(), Error<RW::Error>> {
+        Timer::after(self.config.escape_guard_time).await;
+        let port = self.at.port();
+        port.write_all(b"+++").await.map_err(Error::Write)?;
+        port.flush().await.map_err(Error::Write)?;
+        Timer::after(self.config.escape_guard_time).await;
+
+        // Data received before the modem left data mode is dropped as unexpected lines.
+        let timeout = self.config.command_timeout;
+        match self.at.response(b"", timeout).await {
+            Ok(_) | Err(Error::ResponseTooLong) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Hang up the data connection. The modem must be in command mode.
+    pub async fn hang_up(&mut self) -> Result<(), Error<RW::Error>> {
+        self.at.command("ATH").await?;
+        Ok(())
+    }
+
+    /// Switch the modem to GSM 07.10 multiplexing mode with `AT+CMUX=0`.
+    ///
+    /// Afterwards, take the serial port with [`into_inner`](Self::into_inner) and pass it to
+    /// [`cmux::Runner::run`](crate::cmux::Runner::run).
+    pub async fn enter_cmux(&mut self) -> Result<(), Error<RW::Error>> {
+        self.at.command("AT+CMUX=0").await?;
+        Ok(())
+    }
+
+    async fn connect(&mut self, cmd: &str) -> Result<&mut RW, Error<RW::Error>> {
+        let resp = self.at.command_with_timeout(cmd, self.config.connect_timeout).await?;
+        if !resp.connected() {
+            return Err(Error::UnexpectedResponse);
+        }
+        Ok(self.at.port())
+    }
+}
@@ -0,0 +1,155 @@
+//! Challenge-Handshake Authentication Protocol with MD5, RFC 1994.
+
+use super::{proto, AuthState, Tx, MAX_AUTH_RETRIES};
+use crate::md5::Md5;
+
+const CHALLENGE: u8 = 1;
+const RESPONSE: u8 = 2;
+const SUCCESS: u8 = 3;
+const FAILURE: u8 = 4;
+
+fn response(id: u8, secret: &[u8], challenge: &[u8]) -> [u8; 16] {
+    let mut md5 = Md5::new();
+    md5.update(&[id]);
+    md5.update(secret);
+    md5.update(challenge);
+    md5.finish()
+}
+
+/// Splits a Challenge or Response packet into its value and name.
+fn parse_value(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&value_len, data) = data.split_first()?;
+    let value = data.get(..value_len as usize)?;
+    Some((value, &data[value.len()..]))
+}
+
+/// Authenticates us to the peer.
+pub(crate) struct ChapClient<'a> {
+    state: AuthState,
+
+    username: &'a [u8],
+    password: &'a [u8],
+}
+
+impl<'a> ChapClient<'a> {
+    pub fn new(username: &'a [u8], password: &'a [u8]) -> Self {
+        Self {
+            state: AuthState::Closed,
+            username,
+            password,
+        }
+    }
+
+    pub fn state(&self) -> AuthState {
+        self.state
+    }
+
+    /// Starts waiting for the peer's challenge.
+    pub fn open(&mut self) {
+        self.state = AuthState::Pending;
+    }
+
+    pub fn close(&mut self) {
+        self.state = AuthState::Closed;
+    }
+
+    pub fn handle(&mut self, code: u8, id: u8, data: &[u8], tx: &mut Tx<'_>) {
+        match (code, self.state) {
+            // The peer may challenge us again at any time once opened.
+            (CHALLENGE, AuthState::Pending | AuthState::Opened) => {
+                let Some((challenge, _name)) = parse_value(data) else {
+                    warn!("CHAP: malformed challenge");
+                    return;
+                };
+                debug!("CHAP: tx Response");
+                let value = response(id, self.password, challenge);
+                tx(proto::CHAP, RESPONSE, id, &[&[value.len() as u8], &value, self.username]);
+            }
+            (SUCCESS, AuthState::Pending) => self.state = AuthState::Opened,
+            (FAILURE, AuthState::Pending | AuthState::Opened) => {
+                warn!("CHAP: peer rejected our credentials");
+                self.state = AuthState::Failed
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Authenticates the peer.
+pub(crate) struct ChapServer<'a> {
+    state: AuthState,
+    id: u8,
+    retries: u8,
+
+    challenge: [u8; 16],
+    secrets: &'a [(&'a [u8], &'a [u8])],
+}
+
+impl<'a> ChapServer<'a> {
+    pub fn new(challenge: [u8; 16], secrets: &'a [(&'a [u8], &'a [u8])]) -> Self {
+        Self {
+            state: AuthState::Closed,
+            id: 1,
+            retries: 0,
+            challenge,
+            secrets,
+        }
+    }
+
+    pub fn state(&self) -> AuthState {
+        self.state
+    }
+
+    pub fn open(&mut self, tx: &mut Tx<'_>) {
+        self.state = AuthState::Pending;
+        self.retries = MAX_AUTH_RETRIES;
+        self.id = self.id.wrapping_add(1);
+        self.send_challenge(tx)
+    }
+
+    pub fn close(&mut self) {
+        self.state = AuthState::Closed;
+    }
+
+    pub fn timeout(&mut self, tx: &mut Tx<'_>) {
+        if self.state == AuthState::Pending {
+            if self.retries == 0 {
+                self.state = AuthState::Failed;
+            } else {
+                self.retries -= 1;
+                self.send_challenge(tx)
+            }
+        }
+    }
+
+    pub fn handle(&mut self, code: u8, id: u8, data: &[u8], tx: &mut Tx<'_>) {
+        // Responses are also answered when opened, in case our Success got lost.
+        if code != RESPONSE || id != self.id || !matches!(self.state, AuthState::Pending | AuthState::Opened) {
+            return;
+        }
+
+        let ok = parse_value(data).map_or(false, |(value, name)| {
+            self.secrets
+                .iter()
+                .any(|&(u, p)| u == name && value == response(id, p, &self.challenge))
+        });
+        if ok {
+            self.state = AuthState::Opened;
+            tx(proto::CHAP, SUCCESS, id, &[]);
+        } else {
+            warn!("CHAP: peer sent wrong credentials");
+            self.state = AuthState::Failed;
+            tx(proto::CHAP, FAILURE, id, &[]);
+        }
+    }
+
+    fn send_challenge(&mut self, tx: &mut Tx<'_>) {
+        debug!("CHAP: tx Challenge");
+        tx(
+            proto::CHAP,
+            CHALLENGE,
+            self.id,
+            &[&[self.challenge.len() as u8], &self.challenge],
+        )
+    }
+}
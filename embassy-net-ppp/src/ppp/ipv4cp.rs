@@ -0,0 +1,190 @@
+use super::option_fsm::{Protocol, Verdict};
+use super::{proto, ServerConfig};
+
+/// IPv4 address.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ipv4Address(pub [u8; 4]);
+
+impl Ipv4Address {
+    /// The unspecified address `0.0.0.0`.
+    pub const UNSPECIFIED: Self = Self([0; 4]);
+
+    /// Return whether this address is the unspecified address `0.0.0.0`.
+    pub fn is_unspecified(&self) -> bool {
+        *self == Self::UNSPECIFIED
+    }
+}
+
+const OPTION_IP_ADDRESS: u8 = 3;
+const OPTION_DNS1: u8 = 129;
+const OPTION_DNS2: u8 = 131;
+
+struct IpOption {
+    address: Ipv4Address,
+    is_rejected: bool,
+}
+
+impl IpOption {
+    fn new() -> Self {
+        Self {
+            address: Ipv4Address::UNSPECIFIED,
+            is_rejected: false,
+        }
+    }
+
+    fn get(&self) -> Option<Ipv4Address> {
+        if self.is_rejected || self.address.is_unspecified() {
+            None
+        } else {
+            Some(self.address)
+        }
+    }
+
+    fn nacked(&mut self, data: &[u8], is_rej: bool) {
+        match data.try_into() {
+            Ok(address) if !is_rej => self.address = Ipv4Address(address),
+            // Also give up on addresses that aren't 4 bytes, to avoid an endless loop.
+            _ => self.is_rejected = true,
+        }
+    }
+}
+
+/// Status of the IPv4 connection.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ipv4Status {
+    /// Our adress
+    pub address: Option<Ipv4Address>,
+    /// The peer's address
+    pub peer_address: Option<Ipv4Address>,
+    /// DNS servers provided by the peer, or offered to it in server mode.
+    pub dns_servers: [Option<Ipv4Address>; 2],
+}
+
+/// Addresses handed out in server mode.
+struct Assignment {
+    peer_address: Ipv4Address,
+    dns_servers: [Option<Ipv4Address>; 2],
+}
+
+pub(crate) struct Ipv4cp {
+    peer_address: Ipv4Address,
+
+    address: IpOption,
+    dns_server_1: IpOption,
+    dns_server_2: IpOption,
+
+    server: Option<Assignment>,
+}
+
+impl Ipv4cp {
+    pub fn new(server: Option<&ServerConfig<'_>>) -> Self {
+        let mut address = IpOption::new();
+        let server = server.map(|server| {
+            address.address = server.address;
+            Assignment {
+                peer_address: server.peer_address,
+                dns_servers: server.dns_servers,
+            }
+        });
+
+        // We don't ask the peer for DNS servers when we're the one handing them out.
+        let mut dns_server_1 = IpOption::new();
+        let mut dns_server_2 = IpOption::new();
+        dns_server_1.is_rejected = server.is_some();
+        dns_server_2.is_rejected = server.is_some();
+
+        Self {
+            peer_address: Ipv4Address::UNSPECIFIED,
+            address,
+            dns_server_1,
+            dns_server_2,
+            server,
+        }
+    }
+
+    pub fn status(&self) -> Ipv4Status {
+        let peer_address = if self.peer_address.is_unspecified() {
+            None
+        } else {
+            Some(self.peer_address)
+        };
+        let dns_servers = match &self.server {
+            Some(server) => server.dns_servers,
+            None => [self.dns_server_1.get(), self.dns_server_2.get()],
+        };
+
+        Ipv4Status {
+            address: self.address.get(),
+            peer_address,
+            dns_servers,
+        }
+    }
+}
+
+impl Protocol for Ipv4cp {
+    const PROTOCOL: u16 = proto::IPV4CP;
+
+    fn peer_options_start(&mut self) {}
+
+    fn peer_option_received(&mut self, code: u8, data: &[u8]) -> Verdict<'_> {
+        trace!("IPv4CP: rx option {} {:?}", code, data);
+        let Ok(address) = <[u8; 4]>::try_from(data) else {
+            return Verdict::Rej;
+        };
+        let address = Ipv4Address(address);
+
+        match (&self.server, code) {
+            // Client: we take whatever address the peer wants to use.
+            (None, OPTION_IP_ADDRESS) => {
+                self.peer_address = address;
+                Verdict::Ack
+            }
+            (None, _) => Verdict::Rej,
+
+            // Server: the peer must use the addresses we assign.
+            (Some(server), OPTION_IP_ADDRESS) => {
+                if address == server.peer_address {
+                    self.peer_address = address;
+                    Verdict::Ack
+                } else {
+                    Verdict::Nack(&server.peer_address.0)
+                }
+            }
+            (Some(server), OPTION_DNS1 | OPTION_DNS2) => {
+                let i = (code == OPTION_DNS2) as usize;
+                match &server.dns_servers[i] {
+                    Some(dns) if *dns == address => Verdict::Ack,
+                    Some(dns) => Verdict::Nack(&dns.0),
+                    None => Verdict::Rej,
+                }
+            }
+            (Some(_), _) => Verdict::Rej,
+        }
+    }
+
+    fn own_options(&mut self, f: &mut dyn FnMut(u8, &[u8])) {
+        if !self.address.is_rejected {
+            f(OPTION_IP_ADDRESS, &self.address.address.0);
+        }
+        if !self.dns_server_1.is_rejected {
+            f(OPTION_DNS1, &self.dns_server_1.address.0);
+        }
+        if !self.dns_server_2.is_rejected {
+            f(OPTION_DNS2, &self.dns_server_2.address.0);
+        }
+    }
+
+    fn own_option_nacked(&mut self, code: u8, data: &[u8], is_rej: bool) {
+        trace!("IPv4CP: nak {} {:?} {:?}", code, data, is_rej);
+        match code {
+            // In server mode our own address isn't up for negotiation.
+            OPTION_IP_ADDRESS if self.server.is_some() => self.address.is_rejected |= is_rej,
+            OPTION_IP_ADDRESS => self.address.nacked(data, is_rej),
+            OPTION_DNS1 => self.dns_server_1.nacked(data, is_rej),
+            OPTION_DNS2 => self.dns_server_2.nacked(data, is_rej),
+            _ => {}
+        }
+    }
+}
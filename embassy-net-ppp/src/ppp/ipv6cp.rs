@@ -0,0 +1,103 @@
+use super::option_fsm::{Protocol, Verdict};
+use super::proto;
+
+const OPTION_INTERFACE_IDENTIFIER: u8 = 1;
+
+/// Status of the IPv6 connection.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ipv6Status {
+    /// Our interface identifier.
+    pub interface_identifier: [u8; 8],
+    /// The peer's interface identifier.
+    pub peer_interface_identifier: [u8; 8],
+}
+
+impl Ipv6Status {
+    /// Our link-local address, `fe80::` followed by our interface identifier.
+    pub fn link_local_address(&self) -> [u8; 16] {
+        link_local(self.interface_identifier)
+    }
+
+    /// The peer's link-local address, `fe80::` followed by its interface identifier.
+    pub fn peer_link_local_address(&self) -> [u8; 16] {
+        link_local(self.peer_interface_identifier)
+    }
+}
+
+fn link_local(interface_identifier: [u8; 8]) -> [u8; 16] {
+    let mut address = [0; 16];
+    address[..2].copy_from_slice(&[0xfe, 0x80]);
+    address[8..].copy_from_slice(&interface_identifier);
+    address
+}
+
+/// IPv6 Control Protocol, RFC 5072.
+pub(crate) struct Ipv6cp {
+    interface_identifier: [u8; 8],
+    is_rejected: bool,
+
+    peer_interface_identifier: [u8; 8],
+    /// Identifier we suggest when the peer's is missing or collides with ours.
+    suggestion: [u8; 8],
+}
+
+impl Ipv6cp {
+    pub fn new(interface_identifier: [u8; 8]) -> Self {
+        Self {
+            interface_identifier,
+            is_rejected: false,
+            peer_interface_identifier: [0; 8],
+            suggestion: [0; 8],
+        }
+    }
+
+    pub fn status(&self) -> Ipv6Status {
+        Ipv6Status {
+            interface_identifier: self.interface_identifier,
+            peer_interface_identifier: self.peer_interface_identifier,
+        }
+    }
+}
+
+impl Protocol for Ipv6cp {
+    const PROTOCOL: u16 = proto::IPV6CP;
+
+    fn peer_options_start(&mut self) {}
+
+    fn peer_option_received(&mut self, code: u8, data: &[u8]) -> Verdict<'_> {
+        trace!("IPv6CP: rx option {} {:?}", code, data);
+        match (code, <[u8; 8]>::try_from(data)) {
+            (OPTION_INTERFACE_IDENTIFIER, Ok(id)) => {
+                if id == [0; 8] || id == self.interface_identifier {
+                    // Any identifier that's unique on the link will do.
+                    self.suggestion = [0, 0, 0, 0, 0, 0, 0, 1];
+                    if self.suggestion == self.interface_identifier {
+                        self.suggestion[7] = 2;
+                    }
+                    Verdict::Nack(&self.suggestion)
+                } else {
+                    self.peer_interface_identifier = id;
+                    Verdict::Ack
+                }
+            }
+            _ => Verdict::Rej,
+        }
+    }
+
+    fn own_options(&mut self, f: &mut dyn FnMut(u8, &[u8])) {
+        if !self.is_rejected {
+            f(OPTION_INTERFACE_IDENTIFIER, &self.interface_identifier);
+        }
+    }
+
+    fn own_option_nacked(&mut self, code: u8, data: &[u8], is_rej: bool) {
+        trace!("IPv6CP: nak {} {:?} {:?}", code, data, is_rej);
+        if code == OPTION_INTERFACE_IDENTIFIER {
+            match data.try_into() {
+                Ok(id) if !is_rej => self.interface_identifier = id,
+                _ => self.is_rejected = true,
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::option_fsm::{Protocol, Verdict};
+use super::{proto, AuthProtocol};
+
+const OPTION_ASYNCMAP: u8 = 2;
+const OPTION_AUTH: u8 = 3;
+const OPTION_MRU: u8 = 1;
+const OPTION_MAGIC: u8 = 5;
+
+const AUTH_PAP: &[u8] = &proto::PAP.to_be_bytes();
+/// CHAP with MD5, algorithm 5.
+const AUTH_CHAP_MD5: &[u8] = &[0xc2, 0x23, 0x05];
+
+pub(crate) struct Lcp {
+    /// Authentication the peer asked us to do.
+    pub peer_auth: Option<AuthProtocol>,
+    /// Authentication we ask the peer to do.
+    pub own_auth: Option<AuthProtocol>,
+    /// The peer refused to authenticate.
+    pub own_auth_rejected: bool,
+
+    pub asyncmap_remote: u32,
+    pub asyncmap: u32,
+    pub asyncmap_rej: bool,
+}
+
+impl Lcp {
+    pub fn new(own_auth: Option<AuthProtocol>) -> Self {
+        Self {
+            peer_auth: None,
+            own_auth,
+            own_auth_rejected: false,
+            asyncmap_remote: 0xFFFFFFFF,
+            asyncmap: 0x00000000,
+            asyncmap_rej: false,
+        }
+    }
+}
+
+impl Protocol for Lcp {
+    const PROTOCOL: u16 = proto::LCP;
+
+    fn peer_options_start(&mut self) {
+        self.peer_auth = None;
+    }
+
+    fn peer_option_received(&mut self, code: u8, data: &[u8]) -> Verdict<'_> {
+        trace!("LCP: rx option {} {:?}", code, data);
+        match code {
+            OPTION_MRU if data.len() == 2 => Verdict::Ack,
+            OPTION_MAGIC if data.len() == 4 => Verdict::Ack,
+            OPTION_ASYNCMAP if data.len() == 4 => {
+                self.asyncmap_remote = u32::from_be_bytes(data.try_into().unwrap());
+                Verdict::Ack
+            }
+            OPTION_AUTH if data == AUTH_PAP => {
+                self.peer_auth = Some(AuthProtocol::Pap);
+                Verdict::Ack
+            }
+            OPTION_AUTH if data == AUTH_CHAP_MD5 => {
+                self.peer_auth = Some(AuthProtocol::Chap);
+                Verdict::Ack
+            }
+            OPTION_AUTH => Verdict::Nack(AUTH_CHAP_MD5),
+            _ => Verdict::Rej,
+        }
+    }
+
+    fn own_options(&mut self, f: &mut dyn FnMut(u8, &[u8])) {
+        if !self.asyncmap_rej {
+            f(OPTION_ASYNCMAP, &self.asyncmap.to_be_bytes());
+        }
+        if !self.own_auth_rejected {
+            match self.own_auth {
+                Some(AuthProtocol::Pap) => f(OPTION_AUTH, AUTH_PAP),
+                Some(AuthProtocol::Chap) => f(OPTION_AUTH, AUTH_CHAP_MD5),
+                None => {}
+            }
+        }
+    }
+
+    fn own_option_nacked(&mut self, code: u8, data: &[u8], is_rej: bool) {
+        trace!("LCP: nak {} {:?} {:?}", code, data, is_rej);
+        match code {
+            OPTION_ASYNCMAP => {
+                if !is_rej && data.len() == 4 {
+                    self.asyncmap = u32::from_be_bytes(data.try_into().unwrap())
+                } else {
+                    self.asyncmap_rej = true
+                }
+            }
+            // We only offer the authentication protocol we were configured with.
+            OPTION_AUTH => self.own_auth_rejected = true,
+            _ => {}
+        }
+    }
+}
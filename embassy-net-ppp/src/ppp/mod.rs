@@ -0,0 +1,446 @@
+mod chap;
+mod ipv4cp;
+mod ipv6cp;
+mod lcp;
+mod option_fsm;
+mod pap;
+
+use self::chap::{ChapClient, ChapServer};
+use self::ipv4cp::Ipv4cp;
+use self::ipv6cp::Ipv6cp;
+use self::lcp::Lcp;
+use self::option_fsm::{OptionFsm, State};
+use self::pap::{PapClient, PapServer};
+pub use self::ipv4cp::{Ipv4Address, Ipv4Status};
+pub use self::ipv6cp::Ipv6Status;
+
+/// PPP protocol numbers.
+pub(crate) mod proto {
+    pub const LCP: u16 = 0xc021;
+    pub const PAP: u16 = 0xc023;
+    pub const CHAP: u16 = 0xc223;
+    pub const IPV4: u16 = 0x0021;
+    pub const IPV4CP: u16 = 0x8021;
+    pub const IPV6: u16 = 0x0057;
+    pub const IPV6CP: u16 = 0x8057;
+}
+
+/// Control packet codes, shared by LCP and the NCPs.
+pub(crate) mod code {
+    pub const CONFIGURE_REQ: u8 = 1;
+    pub const CONFIGURE_ACK: u8 = 2;
+    pub const CONFIGURE_NACK: u8 = 3;
+    pub const CONFIGURE_REJ: u8 = 4;
+    pub const TERMINATE_REQ: u8 = 5;
+    pub const TERMINATE_ACK: u8 = 6;
+    pub const PROTOCOL_REJ: u8 = 8;
+    pub const ECHO_REQ: u8 = 9;
+    pub const ECHO_REPLY: u8 = 10;
+    pub const DISCARD_REQ: u8 = 11;
+}
+
+/// Sends a control packet: `tx(protocol, code, id, data)`.
+///
+/// The data is given in parts, which are sent back to back.
+pub(crate) type Tx<'a> = dyn FnMut(u16, u8, u8, &[&[u8]]) + 'a;
+
+/// Number of times an authentication request is retried before giving up.
+pub(crate) const MAX_AUTH_RETRIES: u8 = 5;
+
+/// Maximum length of a rejected packet echoed back in a Protocol-Reject.
+const MAX_REJECTED_LEN: usize = 64;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct MalformedError;
+
+/// Calls `f` with the code and data of each option in a Configure packet.
+pub(crate) fn parse_options(mut data: &[u8], mut f: impl FnMut(u8, &[u8])) -> Result<(), MalformedError> {
+    while !data.is_empty() {
+        if data.len() < 2 {
+            return Err(MalformedError);
+        }
+        let code = data[0];
+        let len = data[1] as usize;
+        if len < 2 || len > data.len() {
+            return Err(MalformedError);
+        }
+        f(code, &data[2..len]);
+        data = &data[len..];
+    }
+    Ok(())
+}
+
+/// Splits a control packet, starting at the code field, into its code, id and data.
+fn parse_packet(pkt: &[u8]) -> Option<(u8, u8, &[u8])> {
+    let len = u16::from_be_bytes([*pkt.get(2)?, *pkt.get(3)?]) as usize;
+    let data = pkt.get(4..len)?;
+    Some((pkt[0], pkt[1], data))
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum AuthState {
+    Closed,
+    Pending,
+    Opened,
+    Failed,
+}
+
+/// Authentication protocol.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuthProtocol {
+    /// Password Authentication Protocol, RFC 1334. Sends the password in clear text.
+    Pap,
+    /// Challenge-Handshake Authentication Protocol with MD5, RFC 1994.
+    Chap,
+}
+
+/// PPP configuration.
+#[non_exhaustive]
+pub struct Config<'a> {
+    /// Username to authenticate to the peer with, if it asks for PAP or CHAP.
+    pub username: &'a [u8],
+    /// Password to authenticate to the peer with, if it asks for PAP or CHAP.
+    pub password: &'a [u8],
+    /// Negotiate IPv4 with IPv4CP. Defaults to `true`.
+    pub ipv4: bool,
+    /// Negotiate IPv6 with IPv6CP. Defaults to `false`.
+    pub ipv6: bool,
+    /// IPv6 interface identifier, the lower 64 bits of our link-local address.
+    ///
+    /// It must be unique on the link, for example derived from a serial number.
+    /// If left as zero, the peer is asked to pick one.
+    pub interface_identifier: [u8; 8],
+    /// Act as the server side of the link, see [`ServerConfig`].
+    ///
+    /// If `None`, we act as a client and take the addresses the peer assigns to us.
+    pub server: Option<ServerConfig<'a>>,
+}
+
+impl<'a> Default for Config<'a> {
+    fn default() -> Self {
+        Self {
+            username: &[],
+            password: &[],
+            ipv4: true,
+            ipv6: false,
+            interface_identifier: [0; 8],
+            server: None,
+        }
+    }
+}
+
+/// Server mode configuration.
+///
+/// In server mode we assign the peer its IPv4 address and DNS servers, and
+/// optionally require it to authenticate.
+#[non_exhaustive]
+pub struct ServerConfig<'a> {
+    /// Our IPv4 address.
+    pub address: Ipv4Address,
+    /// IPv4 address assigned to the peer.
+    pub peer_address: Ipv4Address,
+    /// DNS servers offered to the peer.
+    pub dns_servers: [Option<Ipv4Address>; 2],
+    /// Authentication the peer is required to do. Defaults to `None`.
+    pub auth: Option<AuthProtocol>,
+    /// Accepted `(username, password)` pairs.
+    pub secrets: &'a [(&'a [u8], &'a [u8])],
+    /// CHAP challenge value. It should be random, and different for each connection.
+    pub challenge: [u8; 16],
+}
+
+impl<'a> ServerConfig<'a> {
+    /// Create a new `ServerConfig` assigning `peer_address` to the peer, without authentication.
+    pub fn new(address: Ipv4Address, peer_address: Ipv4Address) -> Self {
+        Self {
+            address,
+            peer_address,
+            dns_servers: [None; 2],
+            auth: None,
+            secrets: &[],
+            challenge: [0; 16],
+        }
+    }
+}
+
+/// Phase of the PPP connection.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Ord, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    /// Dead, not connected.
+    Dead,
+    /// Establishing initial connection.
+    Establish,
+    /// Authenticating with PAP or CHAP.
+    Auth,
+    /// Negotiating network parameters, with IPv4CP and IPv6CP.
+    Network,
+    /// Connection is open, all layers are setup.
+    Open,
+}
+
+/// Status of the PPP connection.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// Phase
+    pub phase: Phase,
+    /// IPv4 configuration obtained from IPv4CP. None if IPv4CP is not up.
+    pub ipv4: Option<Ipv4Status>,
+    /// IPv6 configuration obtained from IPv6CP. None if IPv6CP is not up.
+    pub ipv6: Option<Ipv6Status>,
+}
+
+pub(crate) struct Ppp<'a> {
+    phase: Phase,
+    opening: bool,
+    auth_failed: bool,
+
+    ipv4: bool,
+    ipv6: bool,
+
+    lcp: OptionFsm<Lcp>,
+    pap_client: PapClient<'a>,
+    chap_client: ChapClient<'a>,
+    pap_server: PapServer<'a>,
+    chap_server: ChapServer<'a>,
+    ipv4cp: OptionFsm<Ipv4cp>,
+    ipv6cp: OptionFsm<Ipv6cp>,
+}
+
+impl<'a> Ppp<'a> {
+    pub fn new(config: Config<'a>) -> Self {
+        let server = config.server.as_ref();
+        let (secrets, challenge) = match server {
+            Some(server) => (server.secrets, server.challenge),
+            None => (&[][..], [0; 16]),
+        };
+
+        Self {
+            phase: Phase::Dead,
+            opening: false,
+            auth_failed: false,
+            ipv4: config.ipv4,
+            ipv6: config.ipv6,
+            lcp: OptionFsm::new(Lcp::new(server.and_then(|s| s.auth))),
+            pap_client: PapClient::new(config.username, config.password),
+            chap_client: ChapClient::new(config.username, config.password),
+            pap_server: PapServer::new(secrets),
+            chap_server: ChapServer::new(challenge, secrets),
+            ipv4cp: OptionFsm::new(Ipv4cp::new(server)),
+            ipv6cp: OptionFsm::new(Ipv6cp::new(config.interface_identifier)),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            phase: self.phase,
+            ipv4: (self.ipv4cp.state() == State::Opened).then(|| self.ipv4cp.proto().status()),
+            ipv6: (self.ipv6cp.state() == State::Opened).then(|| self.ipv6cp.proto().status()),
+        }
+    }
+
+    /// Whether the link went down because authentication failed, on either side.
+    pub fn auth_failed(&self) -> bool {
+        self.auth_failed
+    }
+
+    /// Async-Control-Character-Map the peer asked us to use.
+    pub fn asyncmap_remote(&self) -> u32 {
+        self.lcp.proto().asyncmap_remote
+    }
+
+    /// Whether packets of network protocol `proto` can be exchanged.
+    pub fn is_network_open(&self, proto: u16) -> bool {
+        let fsm_state = match proto {
+            proto::IPV4 => self.ipv4cp.state(),
+            proto::IPV6 => self.ipv6cp.state(),
+            _ => return false,
+        };
+        self.phase == Phase::Open && fsm_state == State::Opened
+    }
+
+    pub fn open(&mut self) {
+        if self.phase == Phase::Dead {
+            self.phase = Phase::Establish;
+            self.opening = true;
+            self.auth_failed = false;
+        }
+    }
+
+    /// Handles a received control packet, starting at the protocol field.
+    pub fn received(&mut self, pkt: &[u8], tx: &mut Tx<'_>) {
+        let Some(data) = pkt.get(2..) else {
+            return;
+        };
+        let protocol = u16::from_be_bytes([pkt[0], pkt[1]]);
+
+        match protocol {
+            proto::LCP => match parse_packet(data) {
+                Some((code::PROTOCOL_REJ, _, rejected)) if rejected.len() >= 2 => {
+                    match u16::from_be_bytes([rejected[0], rejected[1]]) {
+                        proto::IPV4CP => self.ipv4cp.stop(),
+                        proto::IPV6CP => self.ipv6cp.stop(),
+                        p => debug!("peer rejected protocol {:04x}", p),
+                    }
+                }
+                _ => self.lcp.handle(data, tx),
+            },
+            proto::PAP => {
+                if let Some((code, id, data)) = parse_packet(data) {
+                    self.pap_client.handle(code, id, data);
+                    self.pap_server.handle(code, id, data, tx);
+                }
+            }
+            proto::CHAP => {
+                if let Some((code, id, data)) = parse_packet(data) {
+                    self.chap_client.handle(code, id, data, tx);
+                    self.chap_server.handle(code, id, data, tx);
+                }
+            }
+            proto::IPV4CP if self.ipv4 => self.ipv4cp.handle(data, tx),
+            proto::IPV6CP if self.ipv6 => self.ipv6cp.handle(data, tx),
+            _ => {
+                // Protocol-Reject may only be sent when LCP is opened.
+                if self.lcp.state() == State::Opened {
+                    debug!("rejecting protocol {:04x}", protocol);
+                    let id = self.lcp.next_id();
+                    let len = pkt.len().min(MAX_REJECTED_LEN);
+                    tx(proto::LCP, code::PROTOCOL_REJ, id, &[&pkt[..len]]);
+                }
+            }
+        }
+    }
+
+    /// The restart timer expired.
+    pub fn timeout(&mut self, tx: &mut Tx<'_>) {
+        self.lcp.timeout(tx);
+        self.pap_client.timeout(tx);
+        self.chap_server.timeout(tx);
+        self.ipv4cp.timeout(tx);
+        self.ipv6cp.timeout(tx);
+    }
+
+    /// Advances the link phase after the state of any protocol changed.
+    pub fn poll(&mut self, tx: &mut Tx<'_>) {
+        let old_phase = self.phase;
+        loop {
+            let phase = self.phase;
+            self.poll_phase(tx);
+
+            // Whenever LCP leaves the opened state, everything on top of it must restart.
+            if self.phase > Phase::Establish && self.lcp.state() != State::Opened {
+                self.close_upper();
+                self.phase = Phase::Establish;
+            }
+            if !self.opening && matches!(self.lcp.state(), State::Closed | State::Stopped) {
+                self.phase = Phase::Dead;
+            }
+
+            if phase == self.phase {
+                break;
+            }
+        }
+
+        if old_phase != self.phase {
+            info!("PPP link phase {:?} -> {:?}", old_phase, self.phase);
+        }
+    }
+
+    fn poll_phase(&mut self, tx: &mut Tx<'_>) {
+        match self.phase {
+            Phase::Dead => {}
+            Phase::Establish => {
+                if self.opening {
+                    self.opening = false;
+                    self.lcp.open(tx);
+                }
+                if self.lcp.state() == State::Opened {
+                    self.open_auth(tx);
+                    self.phase = Phase::Auth;
+                }
+            }
+            Phase::Auth => match self.auth_state() {
+                AuthState::Opened => {
+                    if self.ipv4 {
+                        self.ipv4cp.open(tx);
+                    }
+                    if self.ipv6 {
+                        self.ipv6cp.open(tx);
+                    }
+                    self.phase = Phase::Network;
+                }
+                AuthState::Failed => {
+                    warn!("authentication failed");
+                    self.auth_failed = true;
+                    self.lcp.terminate(tx);
+                }
+                _ => {}
+            },
+            Phase::Network | Phase::Open => {
+                let ncps = [(self.ipv4, self.ipv4cp.state()), (self.ipv6, self.ipv6cp.state())];
+                let ncps = ncps.iter().filter(|(enabled, _)| *enabled).map(|(_, state)| *state);
+
+                // Wait for all enabled NCPs, unless the peer rejected or didn't answer some.
+                let done = ncps.clone().all(|s| matches!(s, State::Opened | State::Stopped));
+                let opened = ncps.clone().any(|s| s == State::Opened);
+                if done && !opened {
+                    warn!("no network protocol could be negotiated");
+                    self.lcp.terminate(tx);
+                } else if done {
+                    self.phase = Phase::Open;
+                } else {
+                    self.phase = Phase::Network;
+                }
+            }
+        }
+    }
+
+    fn auth_state(&self) -> AuthState {
+        let lcp = self.lcp.proto();
+        let client = match lcp.peer_auth {
+            None => AuthState::Opened,
+            Some(AuthProtocol::Pap) => self.pap_client.state(),
+            Some(AuthProtocol::Chap) => self.chap_client.state(),
+        };
+        let server = match lcp.own_auth {
+            None => AuthState::Opened,
+            Some(_) if lcp.own_auth_rejected => AuthState::Failed,
+            Some(AuthProtocol::Pap) => self.pap_server.state(),
+            Some(AuthProtocol::Chap) => self.chap_server.state(),
+        };
+
+        match (client, server) {
+            (AuthState::Failed, _) | (_, AuthState::Failed) => AuthState::Failed,
+            (AuthState::Opened, AuthState::Opened) => AuthState::Opened,
+            _ => AuthState::Pending,
+        }
+    }
+
+    fn open_auth(&mut self, tx: &mut Tx<'_>) {
+        let lcp = self.lcp.proto();
+        match lcp.peer_auth {
+            Some(AuthProtocol::Pap) => self.pap_client.open(tx),
+            Some(AuthProtocol::Chap) => self.chap_client.open(),
+            None => {}
+        }
+        match lcp.own_auth {
+            _ if lcp.own_auth_rejected => {}
+            Some(AuthProtocol::Pap) => self.pap_server.open(),
+            Some(AuthProtocol::Chap) => self.chap_server.open(tx),
+            None => {}
+        }
+    }
+
+    fn close_upper(&mut self) {
+        self.pap_client.close();
+        self.chap_client.close();
+        self.pap_server.close();
+        self.chap_server.close();
+        self.ipv4cp.close();
+        self.ipv6cp.close();
+    }
+}
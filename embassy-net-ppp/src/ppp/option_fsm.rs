@@ -0,0 +1,282 @@
+use super::{code, parse_options, Tx};
+
+/// Number of Configure-Requests sent without response before giving up.
+const MAX_CONFIGURE: u8 = 10;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum Verdict<'a> {
+    Ack,
+    Nack(&'a [u8]),
+    Rej,
+}
+
+/// A protocol negotiated with Configure-Request options, such as LCP or IPv4CP.
+pub(crate) trait Protocol {
+    const PROTOCOL: u16;
+
+    fn own_options(&mut self, f: &mut dyn FnMut(u8, &[u8]));
+    fn own_option_nacked(&mut self, code: u8, data: &[u8], is_rej: bool);
+
+    fn peer_options_start(&mut self);
+    fn peer_option_received(&mut self, code: u8, data: &[u8]) -> Verdict<'_>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum State {
+    /// Not started, or closed.
+    Closed,
+    ReqSent,
+    AckReceived,
+    AckSent,
+    Opened,
+    /// Gave up, either because the peer rejected the protocol or it didn't respond.
+    Stopped,
+}
+
+/// The option negotiation automaton from RFC 1661 section 4, shared by all control protocols.
+pub(crate) struct OptionFsm<P> {
+    id: u8,
+    state: State,
+    restarts: u8,
+    proto: P,
+}
+
+impl<P: Protocol> OptionFsm<P> {
+    pub fn new(proto: P) -> Self {
+        Self {
+            id: 1,
+            state: State::Closed,
+            restarts: 0,
+            proto,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn proto(&self) -> &P {
+        &self.proto
+    }
+
+    pub fn open(&mut self, tx: &mut Tx<'_>) {
+        self.state = State::ReqSent;
+        self.restarts = MAX_CONFIGURE;
+        self.send_configure_request(tx)
+    }
+
+    pub fn close(&mut self) {
+        self.state = State::Closed;
+    }
+
+    /// The peer doesn't support the protocol.
+    pub fn stop(&mut self) {
+        if self.state != State::Closed {
+            self.state = State::Stopped;
+        }
+    }
+
+    /// Closes the protocol, telling the peer with a Terminate-Request.
+    pub fn terminate(&mut self, tx: &mut Tx<'_>) {
+        let id = self.next_id();
+        tx(P::PROTOCOL, code::TERMINATE_REQ, id, &[]);
+        self.state = State::Closed;
+    }
+
+    /// The restart timer expired.
+    pub fn timeout(&mut self, tx: &mut Tx<'_>) {
+        match self.state {
+            State::ReqSent | State::AckReceived | State::AckSent => {
+                if self.restarts == 0 {
+                    warn!("{:04x}: no response, giving up", P::PROTOCOL);
+                    self.state = State::Stopped;
+                    return;
+                }
+                self.restarts -= 1;
+                if self.state == State::AckReceived {
+                    self.state = State::ReqSent;
+                }
+                self.send_configure_request(tx)
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles a received packet, starting at the code field.
+    pub fn handle(&mut self, pkt: &[u8], tx: &mut Tx<'_>) {
+        if pkt.len() < 4 {
+            warn!("{:04x}: packet too short", P::PROTOCOL);
+            return;
+        }
+        let code = pkt[0];
+        let id = pkt[1];
+        let len = u16::from_be_bytes([pkt[2], pkt[3]]) as usize;
+        if len < 4 || len > pkt.len() {
+            warn!("{:04x}: bad packet length", P::PROTOCOL);
+            return;
+        }
+        let data = &pkt[4..len];
+
+        debug!("{:04x}: rx code {}", P::PROTOCOL, code);
+        let old_state = self.state;
+        match (code, self.state) {
+            // Reply EchoReq only when opened. The magic number is 0, we don't negotiate one.
+            (code::ECHO_REQ, State::Opened) if data.len() >= 4 => {
+                tx(P::PROTOCOL, code::ECHO_REPLY, id, &[&[0; 4], &data[4..]])
+            }
+            (code::ECHO_REQ | code::ECHO_REPLY | code::DISCARD_REQ, _) => {}
+
+            // The protocol isn't running, ask the peer to stop too.
+            (code::TERMINATE_REQ, State::Closed | State::Stopped) => tx(P::PROTOCOL, code::TERMINATE_ACK, id, &[]),
+            (_, State::Closed | State::Stopped) => {}
+
+            (code::CONFIGURE_REQ, _) => {
+                let acked = self.received_configure_req(id, data, tx);
+
+                match (acked, self.state) {
+                    (true, State::ReqSent) => self.state = State::AckSent,
+                    (true, State::AckReceived) => self.state = State::Opened,
+                    (true, State::Opened) => {
+                        self.send_configure_request(tx);
+                        self.state = State::AckSent;
+                    }
+                    (false, State::AckSent) => self.state = State::ReqSent,
+                    (false, State::Opened) => {
+                        self.send_configure_request(tx);
+                        self.state = State::ReqSent;
+                    }
+                    _ => {}
+                }
+            }
+
+            (code::CONFIGURE_ACK, _) if id != self.id => debug!("ignoring ConfigureAck with stale id"),
+            (code::CONFIGURE_ACK, State::ReqSent) => {
+                self.restarts = MAX_CONFIGURE;
+                self.state = State::AckReceived
+            }
+            (code::CONFIGURE_ACK, State::AckSent) => {
+                self.restarts = MAX_CONFIGURE;
+                self.state = State::Opened
+            }
+            (code::CONFIGURE_ACK, State::AckReceived | State::Opened) => {
+                self.state = State::ReqSent;
+                self.send_configure_request(tx)
+            }
+
+            (code::CONFIGURE_NACK | code::CONFIGURE_REJ, _) => {
+                if id != self.id {
+                    debug!("ignoring ConfigureNack with stale id");
+                    return;
+                }
+                let is_rej = code == code::CONFIGURE_REJ;
+                let proto = &mut self.proto;
+                if parse_options(data, |code, data| proto.own_option_nacked(code, data, is_rej)).is_err() {
+                    warn!("{:04x}: malformed options", P::PROTOCOL);
+                    return;
+                }
+
+                if self.state != State::AckSent {
+                    self.state = State::ReqSent;
+                }
+                self.send_configure_request(tx)
+            }
+
+            (code::TERMINATE_REQ, State::Opened) => {
+                self.state = State::Closed;
+                tx(P::PROTOCOL, code::TERMINATE_ACK, id, &[])
+            }
+            (code::TERMINATE_REQ, _) => {
+                self.state = State::ReqSent;
+                tx(P::PROTOCOL, code::TERMINATE_ACK, id, &[])
+            }
+            (code::TERMINATE_ACK, State::Opened) => {
+                self.state = State::ReqSent;
+                self.send_configure_request(tx)
+            }
+
+            (code, state) => debug!("ignoring unexpected code {} in state {:?}", code, state),
+        }
+
+        if old_state != self.state {
+            debug!("{:04x}: state {:?} -> {:?}", P::PROTOCOL, old_state, self.state);
+        }
+    }
+
+    pub fn next_id(&mut self) -> u8 {
+        self.id = self.id.wrapping_add(1);
+        self.id
+    }
+
+    fn send_configure_request(&mut self, tx: &mut Tx<'_>) {
+        let mut opts = OptionsBuf::new();
+        self.proto.own_options(&mut |code, data| opts.push(code, data));
+
+        let id = self.next_id();
+        tx(P::PROTOCOL, code::CONFIGURE_REQ, id, &[opts.as_slice()])
+    }
+
+    /// Responds to a Configure-Request, returning whether it was acked.
+    fn received_configure_req(&mut self, id: u8, data: &[u8], tx: &mut Tx<'_>) -> bool {
+        let mut code = code::CONFIGURE_ACK;
+        let mut opts = OptionsBuf::new();
+
+        let proto = &mut self.proto;
+        proto.peer_options_start();
+        let res = parse_options(data, |ocode, odata| {
+            let (ret_code, data) = match proto.peer_option_received(ocode, odata) {
+                Verdict::Ack => (code::CONFIGURE_ACK, odata),
+                Verdict::Nack(data) => (code::CONFIGURE_NACK, data),
+                Verdict::Rej => (code::CONFIGURE_REJ, odata),
+            };
+
+            // Only the options with the "worst" verdict are sent back.
+            if code < ret_code {
+                code = ret_code;
+                opts.clear();
+            }
+            if code == ret_code {
+                opts.push(ocode, data);
+            }
+        });
+        if res.is_err() {
+            warn!("{:04x}: malformed options", P::PROTOCOL);
+            return false;
+        }
+
+        tx(P::PROTOCOL, code, id, &[opts.as_slice()]);
+        code == code::CONFIGURE_ACK
+    }
+}
+
+/// Encoded options of a Configure packet.
+struct OptionsBuf {
+    buf: [u8; 64],
+    len: usize,
+}
+
+impl OptionsBuf {
+    fn new() -> Self {
+        Self { buf: [0; 64], len: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn push(&mut self, code: u8, data: &[u8]) {
+        let buf = &mut self.buf[self.len..];
+        if buf.len() < data.len() + 2 {
+            panic!("too many options");
+        }
+        buf[0] = code;
+        buf[1] = data.len() as u8 + 2;
+        buf[2..][..data.len()].copy_from_slice(data);
+        self.len += data.len() + 2;
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
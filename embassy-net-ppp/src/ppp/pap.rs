@@ -0,0 +1,140 @@
+//! Password Authentication Protocol, RFC 1334.
+
+use super::{proto, AuthState, Tx, MAX_AUTH_RETRIES};
+
+const AUTHENTICATE_REQ: u8 = 1;
+const AUTHENTICATE_ACK: u8 = 2;
+const AUTHENTICATE_NAK: u8 = 3;
+
+/// Authenticates us to the peer.
+pub(crate) struct PapClient<'a> {
+    state: AuthState,
+    id: u8,
+    retries: u8,
+
+    username: &'a [u8],
+    password: &'a [u8],
+}
+
+impl<'a> PapClient<'a> {
+    pub fn new(username: &'a [u8], password: &'a [u8]) -> Self {
+        assert!(username.len() <= u8::MAX as usize);
+        assert!(password.len() <= u8::MAX as usize);
+        Self {
+            state: AuthState::Closed,
+            id: 1,
+            retries: 0,
+            username,
+            password,
+        }
+    }
+
+    pub fn state(&self) -> AuthState {
+        self.state
+    }
+
+    pub fn open(&mut self, tx: &mut Tx<'_>) {
+        self.state = AuthState::Pending;
+        self.retries = MAX_AUTH_RETRIES;
+        self.id = self.id.wrapping_add(1);
+        self.send_request(tx)
+    }
+
+    pub fn close(&mut self) {
+        self.state = AuthState::Closed;
+    }
+
+    pub fn timeout(&mut self, tx: &mut Tx<'_>) {
+        if self.state == AuthState::Pending {
+            if self.retries == 0 {
+                self.state = AuthState::Failed;
+            } else {
+                self.retries -= 1;
+                self.send_request(tx)
+            }
+        }
+    }
+
+    pub fn handle(&mut self, code: u8, id: u8, _data: &[u8]) {
+        if self.state != AuthState::Pending || id != self.id {
+            return;
+        }
+        match code {
+            AUTHENTICATE_ACK => self.state = AuthState::Opened,
+            AUTHENTICATE_NAK => {
+                warn!("PAP: peer rejected our credentials");
+                self.state = AuthState::Failed
+            }
+            _ => {}
+        }
+    }
+
+    fn send_request(&mut self, tx: &mut Tx<'_>) {
+        debug!("PAP: tx AuthenticateReq");
+        tx(
+            proto::PAP,
+            AUTHENTICATE_REQ,
+            self.id,
+            &[
+                &[self.username.len() as u8],
+                self.username,
+                &[self.password.len() as u8],
+                self.password,
+            ],
+        )
+    }
+}
+
+/// Authenticates the peer.
+pub(crate) struct PapServer<'a> {
+    state: AuthState,
+    secrets: &'a [(&'a [u8], &'a [u8])],
+}
+
+impl<'a> PapServer<'a> {
+    pub fn new(secrets: &'a [(&'a [u8], &'a [u8])]) -> Self {
+        Self {
+            state: AuthState::Closed,
+            secrets,
+        }
+    }
+
+    pub fn state(&self) -> AuthState {
+        self.state
+    }
+
+    pub fn open(&mut self) {
+        self.state = AuthState::Pending;
+    }
+
+    pub fn close(&mut self) {
+        self.state = AuthState::Closed;
+    }
+
+    pub fn handle(&mut self, code: u8, id: u8, data: &[u8], tx: &mut Tx<'_>) {
+        // Requests are also answered when opened, in case our Ack got lost.
+        if code != AUTHENTICATE_REQ || !matches!(self.state, AuthState::Pending | AuthState::Opened) {
+            return;
+        }
+
+        let ok = parse_request(data).map_or(false, |(username, password)| {
+            self.secrets.iter().any(|&(u, p)| u == username && p == password)
+        });
+        if ok {
+            self.state = AuthState::Opened;
+            tx(proto::PAP, AUTHENTICATE_ACK, id, &[&[0]]);
+        } else {
+            warn!("PAP: peer sent wrong credentials");
+            self.state = AuthState::Failed;
+            tx(proto::PAP, AUTHENTICATE_NAK, id, &[&[0]]);
+        }
+    }
+}
+
+fn parse_request(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&username_len, data) = data.split_first()?;
+    let username = data.get(..username_len as usize)?;
+    let (&password_len, data) = data[username.len()..].split_first()?;
+    let password = data.get(..password_len as usize)?;
+    Some((username, password))
+}
@@ -0,0 +1,298 @@
+//! PPP in HDLC-like framing, RFC 1662.
+
+use core::ops::Range;
+
+use crate::ppp::{proto, Config, Ppp, Status};
+
+/// Frame check sequence of a frame, including its own FCS, when it's correct.
+const GOOD_FCS: u16 = 0xf0b8;
+
+fn crc16(mut seed: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        let e = seed as u8 ^ b;
+        let f = e ^ (e << 4);
+        let f = f as u16;
+        seed = (seed >> 8) ^ (f << 8) ^ (f << 3) ^ (f >> 4);
+    }
+    seed
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ReaderState {
+    Start,
+    Address,
+    Data,
+    Complete,
+}
+
+/// Deframes received data.
+pub(crate) struct FrameReader {
+    state: ReaderState,
+    escape: bool,
+    len: usize,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self {
+            state: ReaderState::Start,
+            escape: false,
+            len: 0,
+        }
+    }
+
+    /// Returns the range of `buf` holding the last complete frame, without the address,
+    /// control and FCS fields.
+    pub fn receive(&mut self) -> Option<Range<usize>> {
+        match self.state {
+            ReaderState::Complete => {
+                let len = self.len;
+                self.len = 0;
+                self.state = ReaderState::Address;
+                Some(1..len - 2)
+            }
+            _ => None,
+        }
+    }
+
+    /// Consumes `data` into `buf`, stopping after a complete frame.
+    ///
+    /// Returns how many bytes were consumed.
+    pub fn consume(&mut self, buf: &mut [u8], data: &[u8]) -> usize {
+        for (i, &b) in data.iter().enumerate() {
+            match (self.state, b) {
+                (ReaderState::Start, 0x7e) => self.state = ReaderState::Address,
+                (ReaderState::Start, _) => {}
+                (ReaderState::Address, 0xff) => self.state = ReaderState::Data,
+                (ReaderState::Address, 0x7e) => self.state = ReaderState::Address,
+                (ReaderState::Address, _) => self.state = ReaderState::Start,
+                (ReaderState::Data, 0x7e) => {
+                    // End of frame
+                    let ok = self.len >= 5 && buf[0] == 0x03 && crc16(0x00ff, &buf[..self.len]) == GOOD_FCS;
+                    if ok {
+                        self.state = ReaderState::Complete;
+                    } else {
+                        self.state = ReaderState::Address;
+                        self.len = 0;
+                    }
+                }
+                (ReaderState::Data, 0x7d) => self.escape = true,
+                (ReaderState::Data, mut b) => {
+                    if self.escape {
+                        self.escape = false;
+                        b ^= 0x20;
+                    }
+                    if self.len >= buf.len() {
+                        self.state = ReaderState::Start;
+                        self.len = 0;
+                    } else {
+                        buf[self.len] = b;
+                        self.len += 1;
+                    }
+                }
+                // Don't consume more data until the frame is processed with `receive`.
+                (ReaderState::Complete, _) => return i,
+            }
+        }
+
+        data.len()
+    }
+}
+
+/// Given buffer is too small.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct BufferFullError;
+
+/// Frames data to be transmitted, appending to a buffer.
+pub(crate) struct FrameWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    crc: u16,
+    asyncmap: u32,
+}
+
+impl<'a> FrameWriter<'a> {
+    pub fn new(buf: &'a mut [u8], asyncmap: u32) -> Self {
+        Self {
+            buf,
+            len: 0,
+            crc: 0,
+            asyncmap,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn start(&mut self) -> Result<(), BufferFullError> {
+        self.crc = crc16(0xffff, &[0xff, 0x03]);
+        self.append_raw(&[0x7e, 0xff])?;
+        self.append_escaped(&[0x03])?;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), BufferFullError> {
+        let crc = self.crc ^ 0xffff;
+        self.append_escaped(&crc.to_le_bytes())?;
+        self.append_raw(&[0x7e])?;
+        Ok(())
+    }
+
+    pub fn append(&mut self, data: &[u8]) -> Result<(), BufferFullError> {
+        self.append_escaped(data)?;
+        self.crc = crc16(self.crc, data);
+        Ok(())
+    }
+
+    fn append_raw(&mut self, data: &[u8]) -> Result<(), BufferFullError> {
+        if self.len + data.len() > self.buf.len() {
+            Err(BufferFullError)
+        } else {
+            self.buf[self.len..][..data.len()].copy_from_slice(data);
+            self.len += data.len();
+            Ok(())
+        }
+    }
+
+    fn append_escaped(&mut self, data: &[u8]) -> Result<(), BufferFullError> {
+        for &b in data {
+            let escape = match b {
+                0..=0x1f => self.asyncmap & (1 << b) != 0,
+                0x7d | 0x7e => true,
+                _ => false,
+            };
+
+            if escape {
+                self.append_raw(&[0x7d, b ^ 0x20])?;
+            } else {
+                self.append_raw(&[b])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_packet(w: &mut FrameWriter<'_>, proto: u16, code: u8, id: u8, parts: &[&[u8]]) -> Result<(), BufferFullError> {
+    let len = 4 + parts.iter().map(|p| p.len()).sum::<usize>();
+    w.start()?;
+    w.append(&proto.to_be_bytes())?;
+    w.append(&[code, id])?;
+    w.append(&(len as u16).to_be_bytes())?;
+    for part in parts {
+        w.append(part)?;
+    }
+    w.finish()
+}
+
+/// Return value from [`PppOs::poll()`].
+pub(crate) enum Action {
+    /// No action needed to take.
+    None,
+    /// An IP packet was received, it's located in `rx_buf[range]`.
+    Received(Range<usize>),
+    /// `tx_buf[..n]` must be transmitted over the serial connection.
+    Transmit(usize),
+}
+
+/// PPP over a serial connection.
+pub(crate) struct PppOs<'a> {
+    frame_reader: FrameReader,
+    ppp: Ppp<'a>,
+}
+
+impl<'a> PppOs<'a> {
+    pub fn new(config: Config<'a>) -> Self {
+        Self {
+            frame_reader: FrameReader::new(),
+            ppp: Ppp::new(config),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.ppp.status()
+    }
+
+    pub fn auth_failed(&self) -> bool {
+        self.ppp.auth_failed()
+    }
+
+    pub fn open(&mut self) {
+        self.ppp.open()
+    }
+
+    /// Consumes data received from the serial connection, returning how many bytes were consumed.
+    ///
+    /// `poll` must be called afterwards to process the consumed data.
+    pub fn consume(&mut self, data: &[u8], rx_buf: &mut [u8]) -> usize {
+        self.frame_reader.consume(rx_buf, data)
+    }
+
+    /// Processes received data and generates control packets to be sent.
+    pub fn poll(&mut self, tx_buf: &mut [u8], rx_buf: &mut [u8]) -> Action {
+        // Control packets always escape all control characters, as LCP must before it's opened.
+        let mut w = FrameWriter::new(tx_buf, 0xffffffff);
+        let mut tx = |proto: u16, code: u8, id: u8, parts: &[&[u8]]| {
+            if write_packet(&mut w, proto, code, id, parts).is_err() {
+                warn!("tx buffer full, dropping control packet");
+            }
+        };
+
+        if let Some(range) = self.frame_reader.receive() {
+            // Frames are at least 2 bytes, and we don't negotiate protocol field compression.
+            let pkt = &rx_buf[range.clone()];
+            let protocol = u16::from_be_bytes([pkt[0], pkt[1]]);
+            match protocol {
+                proto::IPV4 | proto::IPV6 if self.ppp.is_network_open(protocol) => {
+                    return Action::Received(range.start + 2..range.end)
+                }
+                proto::IPV4 | proto::IPV6 => debug!("dropping packet, network protocol is not open"),
+                _ => self.ppp.received(pkt, &mut tx),
+            }
+        }
+
+        self.ppp.poll(&mut tx);
+
+        match w.len() {
+            0 => Action::None,
+            n => Action::Transmit(n),
+        }
+    }
+
+    /// Handles the restart timer expiring, returning how many bytes of `tx_buf` must be transmitted.
+    pub fn timeout(&mut self, tx_buf: &mut [u8]) -> usize {
+        let mut w = FrameWriter::new(tx_buf, 0xffffffff);
+        let mut tx = |proto: u16, code: u8, id: u8, parts: &[&[u8]]| {
+            if write_packet(&mut w, proto, code, id, parts).is_err() {
+                warn!("tx buffer full, dropping control packet");
+            }
+        };
+
+        self.ppp.timeout(&mut tx);
+        self.ppp.poll(&mut tx);
+        w.len()
+    }
+
+    /// Frames an IP packet, returning how many bytes of `tx_buf` must be transmitted.
+    ///
+    /// Packets are dropped if their network protocol is not open.
+    pub fn send(&mut self, pkt: &[u8], tx_buf: &mut [u8]) -> Result<usize, BufferFullError> {
+        let proto = match pkt.first().map(|b| b >> 4) {
+            Some(4) => proto::IPV4,
+            Some(6) => proto::IPV6,
+            _ => 0,
+        };
+        if !self.ppp.is_network_open(proto) {
+            debug!("dropping packet, network protocol is not open");
+            return Ok(0);
+        }
+
+        let mut w = FrameWriter::new(tx_buf, self.ppp.asyncmap_remote());
+        w.start()?;
+        w.append(&proto.to_be_bytes())?;
+        w.append(pkt)?;
+        w.finish()?;
+        Ok(w.len())
+    }
+}
@@ -0,0 +1,233 @@
+use core::convert::Infallible;
+
+use embassy_futures::join::join;
+use embassy_futures::select::{select, Either};
+use embassy_net_ppp::cmux::{self, Channel, Error, State};
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{BufRead, Read, Write};
+
+use crate::common::{serial_link, Port};
+
+mod common;
+
+const FLAG: u8 = 0xf9;
+const SABM: u8 = 0x3f;
+const UA: u8 = 0x73;
+const DM: u8 = 0x1f;
+const UIH: u8 = 0xef;
+
+/// Frame, as seen by the fake modem.
+#[derive(Debug, PartialEq)]
+struct Frame {
+    dlci: u8,
+    command: bool,
+    control: u8,
+    info: Vec<u8>,
+}
+
+fn crc(data: &[u8]) -> u8 {
+    let mut crc = 0xff;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xe0 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Scripted modem on the other end of the serial link.
+struct FakeModem {
+    port: Port,
+}
+
+impl FakeModem {
+    async fn read_byte(&mut self) -> u8 {
+        let b = self.port.fill_buf().await.unwrap()[0];
+        self.port.consume(1);
+        b
+    }
+
+    /// Reads a frame, using its length field as basic mode frames can contain flags.
+    async fn read_raw(&mut self) -> Vec<u8> {
+        let mut raw = vec![FLAG];
+        // Skip consecutive flags.
+        let mut b = FLAG;
+        while b == FLAG {
+            b = self.read_byte().await;
+        }
+        raw.push(b);
+        raw.push(self.read_byte().await);
+        raw.push(self.read_byte().await);
+        let mut len = (raw[3] >> 1) as usize;
+        if raw[3] & 1 == 0 {
+            raw.push(self.read_byte().await);
+            len |= (raw[4] as usize) << 7;
+        }
+        for _ in 0..len + 2 {
+            raw.push(self.read_byte().await);
+        }
+        assert_eq!(raw.last(), Some(&FLAG));
+        raw
+    }
+
+    async fn read(&mut self) -> Frame {
+        let raw = self.read_raw().await;
+        let header_len = if raw[3] & 1 == 1 { 3 } else { 4 };
+        let info = &raw[1 + header_len..raw.len() - 2];
+        assert_eq!(
+            crc(&[&raw[1..1 + header_len], &raw[raw.len() - 2..raw.len() - 1]].concat()),
+            0xcf
+        );
+        Frame {
+            dlci: raw[1] >> 2,
+            command: raw[1] & 2 != 0,
+            control: raw[2],
+            info: info.to_vec(),
+        }
+    }
+
+    /// Sends a frame as the responder, so the C/R bit is cleared for commands.
+    async fn send(&mut self, dlci: u8, command: bool, control: u8, info: &[u8]) {
+        let header = [
+            dlci << 2 | if command { 0 } else { 2 } | 1,
+            control,
+            (info.len() << 1) as u8 | 1,
+        ];
+        let fcs = 0xff - crc(&header);
+        self.port
+            .write_all(&[&[FLAG][..], &header, info, &[fcs, FLAG]].concat())
+            .await
+            .unwrap();
+    }
+
+    /// Answers the opening of the control channel and `channels` data channels.
+    async fn accept(&mut self, channels: u8) {
+        for dlci in 0..=channels {
+            let frame = self.read().await;
+            assert_eq!((frame.dlci, frame.command, frame.control), (dlci, true, SABM));
+            self.send(dlci, false, UA, &[]).await;
+        }
+        for dlci in 1..=channels {
+            let frame = self.read().await;
+            assert_eq!((frame.dlci, frame.control), (0, UIH));
+            // MSC command for the channel, with RTC and RTR set.
+            assert_eq!(frame.info, [0xe3, 0x05, dlci << 2 | 3, 0x8d]);
+        }
+    }
+}
+
+async fn read_until(channel: &mut Channel<'_, 512>, len: usize) -> Vec<u8> {
+    let mut data = vec![0; len];
+    channel.read_exact(&mut data).await.unwrap();
+    data
+}
+
+/// Runs a multiplexer with 2 channels against a fake modem, until `test` completes.
+async fn run<F: core::future::Future<Output = ()>>(
+    config: cmux::Config,
+    test: impl FnOnce([Channel<'static, 512>; 2], FakeModem) -> F,
+) -> Option<Error<Infallible>> {
+    let (port, modem) = serial_link();
+    let (mut runner, channels) = cmux::new(Box::leak(Box::new(State::<2, 512>::new())), config);
+
+    let res = select(runner.run(port), test(channels, FakeModem { port: modem }));
+    match with_timeout(Duration::from_secs(10), res).await.expect("timed out") {
+        Either::First(res) => Some(res.unwrap_err()),
+        Either::Second(()) => None,
+    }
+}
+
+#[test]
+fn open_channels() {
+    let mut config = cmux::Config::default();
+    config.ack_timeout = Duration::from_millis(100);
+
+    let res = futures_executor::block_on(run(config, |_, mut modem| async move {
+        // Known SABM frame for DLCI 0.
+        assert_eq!(modem.read_raw().await, [0xf9, 0x03, 0x3f, 0x01, 0x1c, 0xf9]);
+        modem.send(0, false, UA, &[]).await;
+
+        // DLCI 1 is retried when not answered.
+        let frame = modem.read().await;
+        assert_eq!((frame.dlci, frame.control), (1, SABM));
+        let frame = modem.read().await;
+        assert_eq!((frame.dlci, frame.control), (1, SABM));
+        modem.send(1, false, UA, &[]).await;
+        let frame = modem.read().await;
+        assert_eq!((frame.dlci, frame.control), (2, SABM));
+        modem.send(2, false, UA, &[]).await;
+
+        // Control messages are answered once open.
+        modem.read().await;
+        modem.read().await;
+        modem.send(0, true, UIH, &[0x23, 0x07, 1, 2, 3]).await;
+        let frame = modem.read().await;
+        assert_eq!(
+            (frame.dlci, frame.control, &frame.info[..]),
+            (0, UIH, &[0x21, 0x07, 1, 2, 3][..])
+        );
+    }));
+    assert!(res.is_none());
+}
+
+#[test]
+fn channel_rejected() {
+    let res = futures_executor::block_on(run(cmux::Config::default(), |_, mut modem| async move {
+        modem.read().await;
+        modem.send(0, false, UA, &[]).await;
+        modem.read().await;
+        modem.send(1, false, DM, &[]).await;
+        core::future::pending().await
+    }));
+    assert!(matches!(res, Some(Error::ChannelRejected(1))));
+}
+
+#[test]
+fn concurrent_channels() {
+    let mut config = cmux::Config::default();
+    config.max_frame_size = 200;
+
+    let res = futures_executor::block_on(run(config, |[mut at, mut data], mut modem| async move {
+        modem.accept(2).await;
+
+        let channels = async {
+            at.write_all(b"AT+CSQ\r").await.unwrap();
+            let payload: Vec<u8> = (0..300).map(|i| i as u8).collect();
+            data.write_all(&payload).await.unwrap();
+
+            let (resp, echo) = join(read_until(&mut at, 21), read_until(&mut data, 300)).await;
+            assert_eq!(resp, b"\r\n+CSQ: 20,99\r\n\r\nOK\r\n");
+            assert_eq!(echo, payload);
+        };
+        let modem = async {
+            let mut payload = Vec::new();
+            let mut sizes = Vec::new();
+            while payload.len() < 300 {
+                let frame = modem.read().await;
+                assert_eq!(frame.control, UIH);
+                match frame.dlci {
+                    1 => {
+                        assert_eq!(frame.info, b"AT+CSQ\r");
+                        // Interleave the response with the data channel.
+                        modem.send(1, true, UIH, b"\r\n+CSQ: 20,99\r\n").await;
+                    }
+                    2 => {
+                        sizes.push(frame.info.len());
+                        payload.extend(frame.info);
+                    }
+                    dlci => panic!("unexpected DLCI {}", dlci),
+                }
+            }
+            // The payload doesn't fit in one frame, and the first one needs a 2-octet length.
+            assert_eq!(sizes, [200, 100]);
+
+            modem.send(2, true, UIH, &payload[..100]).await;
+            modem.send(1, true, UIH, b"\r\nOK\r\n").await;
+            modem.send(2, true, UIH, &payload[100..200]).await;
+            modem.send(2, true, UIH, &payload[200..]).await;
+        };
+        join(channels, modem).await;
+    }));
+    assert!(res.is_none());
+}
@@ -0,0 +1,183 @@
+use core::cell::RefCell;
+use core::future::Future;
+
+use embassy_futures::join::join;
+use embassy_net_ppp::at::{AtClient, Error};
+use embassy_net_ppp::modem::{self, Modem, Registration, SignalQuality};
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::{BufRead, Write};
+
+use crate::common::{serial_link, Port};
+
+mod common;
+
+/// Scripted modem on the other end of the serial link.
+struct FakeModem {
+    port: Port,
+}
+
+impl FakeModem {
+    /// Reads a command up to its `\r` and checks it's `cmd`.
+    async fn expect(&mut self, cmd: &str) {
+        let mut line = Vec::new();
+        loop {
+            let data = self.port.fill_buf().await.unwrap();
+            let n = data.len();
+            match data.iter().position(|&b| b == b'\r') {
+                Some(i) => {
+                    line.extend_from_slice(&data[..i]);
+                    self.port.consume(i + 1);
+                    break;
+                }
+                None => line.extend_from_slice(data),
+            }
+            self.port.consume(n);
+        }
+        assert_eq!(core::str::from_utf8(&line).unwrap(), cmd);
+    }
+
+    /// Discards data until the `+++` escape sequence.
+    async fn expect_escape(&mut self) {
+        let mut plus = 0;
+        while plus < 3 {
+            let data = self.port.fill_buf().await.unwrap();
+            let b = data[0];
+            self.port.consume(1);
+            plus = if b == b'+' { plus + 1 } else { 0 };
+        }
+    }
+
+    async fn send(&mut self, data: &str) {
+        self.port.write_all(data.as_bytes()).await.unwrap();
+    }
+
+    async fn answer(&mut self, cmd: &str, resp: &str) {
+        self.expect(cmd).await;
+        self.send(resp).await;
+    }
+}
+
+fn run<A: Future, B: Future>(client: impl FnOnce(Port) -> A, modem: impl FnOnce(FakeModem) -> B) -> A::Output {
+    let (a, b) = serial_link();
+    futures_executor::block_on(async {
+        let (res, _) = with_timeout(Duration::from_secs(10), join(client(a), modem(FakeModem { port: b })))
+            .await
+            .expect("timed out");
+        res
+    })
+}
+
+#[test]
+fn command_with_urcs() {
+    let urcs = RefCell::new(Vec::new());
+    let urc = |line: &[u8]| {
+        let is_urc = line.starts_with(b"+CREG:") || line == b"RING";
+        if is_urc {
+            urcs.borrow_mut().push(line.to_vec());
+        }
+        is_urc
+    };
+
+    run(
+        |port| async {
+            let mut at = AtClient::new(port, urc);
+            let resp = at.command("AT+CSQ").await.unwrap();
+            assert_eq!(resp.info("+CSQ"), Some(&b"20,99"[..]));
+            assert_eq!(resp.lines().count(), 1);
+
+            // A line with the prefix of the command is a response, not a URC.
+            let resp = at.command("AT+CREG?").await.unwrap();
+            assert_eq!(resp.info("+CREG"), Some(&b"0,1"[..]));
+
+            at.poll_urc().await.unwrap();
+        },
+        |mut modem| async move {
+            modem
+                .answer("AT+CSQ", "AT+CSQ\r\r\n+CREG: 5\r\n\r\n+CSQ: 20,99\r\n\r\nOK\r\n")
+                .await;
+            modem.answer("AT+CREG?", "\r\n+CREG: 0,1\r\n\r\nOK\r\n").await;
+            modem.send("\r\nRING\r\n").await;
+        },
+    );
+    assert_eq!(*urcs.borrow(), [b"+CREG: 5".to_vec(), b"RING".to_vec()]);
+}
+
+#[test]
+fn command_errors() {
+    run(
+        |port| async {
+            let mut at = AtClient::new(port, ());
+            assert_eq!(at.command("AT+CPIN?").await.unwrap_err(), Error::CmeError(10));
+            assert_eq!(at.command("AT+FOO").await.unwrap_err(), Error::Error);
+            assert_eq!(
+                at.command_fmt(format_args!("AT+CGDCONT=1,\"IP\",\"{}\"", "x".repeat(200)))
+                    .await
+                    .unwrap_err(),
+                Error::CommandTooLong
+            );
+
+            at.set_timeout(Duration::from_millis(100));
+            assert_eq!(at.command("AT").await.unwrap_err(), Error::Timeout);
+        },
+        |mut modem| async move {
+            modem.answer("AT+CPIN?", "\r\n+CME ERROR: 10\r\n").await;
+            modem.answer("AT+FOO", "\r\nERROR\r\n").await;
+            modem.expect("AT").await;
+        },
+    );
+}
+
+#[test]
+fn modem_lifecycle() {
+    let mut config = modem::Config::default();
+    config.escape_guard_time = Duration::from_millis(50);
+
+    run(
+        |port| async {
+            let mut modem = Modem::new(port, (), config);
+            modem.init().await.unwrap();
+            modem.set_apn("internet").await.unwrap();
+            assert_eq!(modem.registration().await.unwrap(), Registration::Searching);
+            modem.attach().await.unwrap();
+
+            // Data following `CONNECT` is left for PPP.
+            let port = modem.dial().await.unwrap();
+            assert_eq!(port.fill_buf().await.unwrap(), b"~data~");
+            port.consume(6);
+            port.write_all(b"~data~").await.unwrap();
+
+            modem.escape().await.unwrap();
+            assert_eq!(
+                modem.signal_quality().await.unwrap(),
+                SignalQuality {
+                    rssi: Some(-73),
+                    ber: None
+                }
+            );
+
+            let port = modem.resume().await.unwrap();
+            assert_eq!(port.fill_buf().await.unwrap(), b"~more~");
+        },
+        |mut modem| async move {
+            // Not answering the first `AT`, as when autobauding.
+            modem.expect("AT").await;
+            modem.answer("AT", "AT\r\r\nOK\r\n").await;
+            modem.answer("ATE0", "ATE0\r\r\nOK\r\n").await;
+            modem.answer("AT+CMEE=1", "\r\nOK\r\n").await;
+            modem.answer("AT+CGDCONT=1,\"IP\",\"internet\"", "\r\nOK\r\n").await;
+
+            modem.answer("AT+CEREG?", "\r\n+CEREG: 0,2\r\n\r\nOK\r\n").await;
+            modem.answer("AT+CGREG?", "\r\n+CGREG: 0,2\r\n\r\nOK\r\n").await;
+            // No LTE.
+            modem.answer("AT+CEREG?", "\r\nERROR\r\n").await;
+            modem.answer("AT+CGREG?", "\r\n+CGREG: 0,5\r\n\r\nOK\r\n").await;
+            modem.answer("AT+CGATT=1", "\r\nOK\r\n").await;
+
+            modem.answer("ATD*99#", "\r\nCONNECT 150000000\r\n~data~").await;
+            modem.expect_escape().await;
+            modem.send("~late~\r\nOK\r\n").await;
+            modem.answer("AT+CSQ", "\r\n+CSQ: 20,99\r\n\r\nOK\r\n").await;
+            modem.answer("ATO", "\r\nCONNECT\r\n~more~").await;
+        },
+    );
+}
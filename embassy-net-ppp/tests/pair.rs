@@ -0,0 +1,240 @@
+use core::convert::Infallible;
+use core::future::{poll_fn, Future};
+use core::task::Poll;
+
+use embassy_futures::join::join;
+use embassy_futures::select::{select, Either};
+use embassy_net_driver_channel::driver::{Driver, RxToken, TxToken};
+use embassy_net_ppp::{AuthProtocol, Config, Device, Ipv4Address, RunError, ServerConfig, State, Status};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_timeout, Duration};
+
+use crate::common::serial_link;
+
+mod common;
+
+const SERVER_ADDRESS: Ipv4Address = Ipv4Address([10, 0, 0, 1]);
+const CLIENT_ADDRESS: Ipv4Address = Ipv4Address([10, 0, 0, 2]);
+const DNS_SERVER: Ipv4Address = Ipv4Address([10, 0, 0, 53]);
+
+const SECRETS: &[(&[u8], &[u8])] = &[(b"alice", b"hunter2"), (b"bob", b"swordfish")];
+
+/// Runs two runners connected over an in-memory serial link.
+///
+/// Once both links are up, `test` runs with both devices and negotiated statuses, and `None`
+/// is returned when it completes. If a runner stops first, the errors of both are returned.
+fn pair<F: Future<Output = ()>>(
+    a: Config<'static>,
+    b: Config<'static>,
+    test: impl FnOnce(Device<'static>, Device<'static>, Status, Status) -> F,
+) -> Option<(RunError<Infallible>, RunError<Infallible>)> {
+    let (device_a, mut runner_a) = embassy_net_ppp::new(Box::leak(Box::new(State::<4, 4>::new())));
+    let (device_b, mut runner_b) = embassy_net_ppp::new(Box::leak(Box::new(State::<4, 4>::new())));
+    let (port_a, port_b) = serial_link();
+    let up_a = Signal::<NoopRawMutex, Status>::new();
+    let up_b = Signal::<NoopRawMutex, Status>::new();
+
+    futures_executor::block_on(async {
+        let runners = join(
+            runner_a.run(port_a, a, |status| up_a.signal(status)),
+            runner_b.run(port_b, b, |status| up_b.signal(status)),
+        );
+        let test = async {
+            let (status_a, status_b) = join(up_a.wait(), up_b.wait()).await;
+            test(device_a, device_b, status_a, status_b).await
+        };
+
+        match with_timeout(Duration::from_secs(10), select(runners, test)).await {
+            Ok(Either::First((a, b))) => Some((a.unwrap_err(), b.unwrap_err())),
+            Ok(Either::Second(())) => None,
+            Err(_) => panic!("timed out"),
+        }
+    })
+}
+
+async fn send(device: &mut Device<'_>, pkt: &[u8]) {
+    poll_fn(|cx| match device.transmit(cx) {
+        Some(token) => {
+            token.consume(pkt.len(), |buf| buf.copy_from_slice(pkt));
+            Poll::Ready(())
+        }
+        None => Poll::Pending,
+    })
+    .await
+}
+
+async fn receive(device: &mut Device<'_>) -> Vec<u8> {
+    poll_fn(|cx| match device.receive(cx) {
+        Some((token, _)) => Poll::Ready(token.consume(|buf| buf.to_vec())),
+        None => Poll::Pending,
+    })
+    .await
+}
+
+/// Sends a packet each way and checks it arrives intact.
+async fn exchange(a: &mut Device<'_>, b: &mut Device<'_>, pkt: &[u8]) {
+    send(a, pkt).await;
+    assert_eq!(receive(b).await, pkt);
+    send(b, pkt).await;
+    assert_eq!(receive(a).await, pkt);
+}
+
+fn ipv4_packet() -> Vec<u8> {
+    // Include bytes that need escaping on the serial link.
+    let mut pkt = vec![0x45, 0x00, 0x00, 28, 0x7e, 0x7d, 0x11, 0x03];
+    pkt.extend(0..20);
+    pkt
+}
+
+fn ipv6_packet() -> Vec<u8> {
+    let mut pkt = vec![0x60, 0x00, 0x00, 0x00];
+    pkt.extend(0..36);
+    pkt
+}
+
+fn client(username: &'static [u8], password: &'static [u8]) -> Config<'static> {
+    let mut config = Config::default();
+    config.username = username;
+    config.password = password;
+    config
+}
+
+fn server(auth: Option<AuthProtocol>) -> Config<'static> {
+    let mut server = ServerConfig::new(SERVER_ADDRESS, CLIENT_ADDRESS);
+    server.dns_servers = [Some(DNS_SERVER), None];
+    server.auth = auth;
+    server.secrets = SECRETS;
+    server.challenge = *b"0123456789abcdef";
+
+    let mut config = Config::default();
+    config.server = Some(server);
+    config
+}
+
+#[test]
+fn server_assigns_addresses() {
+    let res = pair(
+        client(b"", b""),
+        server(None),
+        |mut a, mut b, client, server| async move {
+            let client = client.ipv4.unwrap();
+            assert_eq!(client.address, Some(CLIENT_ADDRESS));
+            assert_eq!(client.peer_address, Some(SERVER_ADDRESS));
+            assert_eq!(client.dns_servers, [Some(DNS_SERVER), None]);
+            assert!(server.ipv6.is_none());
+
+            let server = server.ipv4.unwrap();
+            assert_eq!(server.address, Some(SERVER_ADDRESS));
+            assert_eq!(server.peer_address, Some(CLIENT_ADDRESS));
+
+            exchange(&mut a, &mut b, &ipv4_packet()).await;
+        },
+    );
+    assert!(res.is_none());
+}
+
+#[test]
+fn pap() {
+    let res = pair(
+        client(b"bob", b"swordfish"),
+        server(Some(AuthProtocol::Pap)),
+        |mut a, mut b, _, _| async move { exchange(&mut a, &mut b, &ipv4_packet()).await },
+    );
+    assert!(res.is_none());
+}
+
+#[test]
+fn chap() {
+    let res = pair(
+        client(b"alice", b"hunter2"),
+        server(Some(AuthProtocol::Chap)),
+        |mut a, mut b, _, _| async move { exchange(&mut a, &mut b, &ipv4_packet()).await },
+    );
+    assert!(res.is_none());
+}
+
+#[test]
+fn pap_wrong_password() {
+    let res = pair(
+        client(b"bob", b"hunter2"),
+        server(Some(AuthProtocol::Pap)),
+        |_, _, _, _| async { panic!("link came up") },
+    );
+    assert!(matches!(res, Some((RunError::AuthFailed, RunError::AuthFailed))));
+}
+
+#[test]
+fn chap_wrong_password() {
+    let res = pair(
+        client(b"alice", b"swordfish"),
+        server(Some(AuthProtocol::Chap)),
+        |_, _, _, _| async { panic!("link came up") },
+    );
+    assert!(matches!(res, Some((RunError::AuthFailed, RunError::AuthFailed))));
+}
+
+#[test]
+fn ipv6() {
+    let mut a = client(b"", b"");
+    a.ipv6 = true;
+    a.interface_identifier = [0x02, 0, 0, 0, 0, 0, 0, 0xaa];
+    let mut b = server(None);
+    b.ipv6 = true;
+    b.interface_identifier = [0x02, 0, 0, 0, 0, 0, 0, 0xbb];
+
+    let res = pair(a, b, |mut a, mut b, client, server| async move {
+        let client = client.ipv6.unwrap();
+        let server = server.ipv6.unwrap();
+        assert_eq!(client.interface_identifier, server.peer_interface_identifier);
+        assert_eq!(client.peer_interface_identifier, server.interface_identifier);
+        assert_eq!(
+            client.link_local_address(),
+            [0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0x02, 0, 0, 0, 0, 0, 0, 0xaa]
+        );
+        assert_eq!(client.peer_link_local_address(), server.link_local_address());
+
+        exchange(&mut a, &mut b, &ipv6_packet()).await;
+        exchange(&mut a, &mut b, &ipv4_packet()).await;
+    });
+    assert!(res.is_none());
+}
+
+#[test]
+fn ipv6_only_picks_interface_identifier() {
+    let mut a = client(b"", b"");
+    a.ipv4 = false;
+    a.ipv6 = true;
+    let mut b = client(b"", b"");
+    b.ipv4 = false;
+    b.ipv6 = true;
+    b.interface_identifier = [0x02, 0, 0, 0, 0, 0, 0, 0xbb];
+
+    let res = pair(a, b, |mut a, mut b, status_a, status_b| async move {
+        assert!(status_a.ipv4.is_none());
+        let status_a = status_a.ipv6.unwrap();
+        assert_eq!(status_a.interface_identifier, [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(
+            status_a.peer_interface_identifier,
+            status_b.ipv6.unwrap().interface_identifier
+        );
+
+        exchange(&mut a, &mut b, &ipv6_packet()).await;
+    });
+    assert!(res.is_none());
+}
+
+#[test]
+fn ipv6_rejected_by_peer() {
+    let mut a = client(b"", b"");
+    a.ipv6 = true;
+    a.interface_identifier = [0x02, 0, 0, 0, 0, 0, 0, 0xaa];
+
+    let res = pair(a, server(None), |mut a, mut b, client, _| async move {
+        assert!(client.ipv4.is_some());
+        assert!(client.ipv6.is_none());
+
+        exchange(&mut a, &mut b, &ipv4_packet()).await;
+    });
+    assert!(res.is_none());
+}
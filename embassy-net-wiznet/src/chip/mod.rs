@@ -1,41 +1,84 @@
-//! Wiznet W5100s and W5500 family driver.
+//! Wiznet W5100, W5100s, W5500 and W6100 family driver.
 mod w5500;
 pub use w5500::W5500;
 mod w5100s;
 use embedded_hal_async::spi::SpiDevice;
 pub use w5100s::W5100S;
+mod w5100;
+pub use w5100::W5100;
+mod w6100;
+pub use w6100::W6100;
 
 pub(crate) trait SealedChip {
     type Address;
 
-    const COMMON_MODE: Self::Address;
+    const COMMON_GATEWAY: Self::Address;
+    const COMMON_SUBNET_MASK: Self::Address;
     const COMMON_MAC: Self::Address;
+    const COMMON_IP: Self::Address;
     const COMMON_SOCKET_INTR: Self::Address;
-    const COMMON_PHY_CFG: Self::Address;
     const SOCKET_MODE: Self::Address;
     const SOCKET_COMMAND: Self::Address;
-    const SOCKET_RXBUF_SIZE: Self::Address;
-    const SOCKET_TXBUF_SIZE: Self::Address;
     const SOCKET_TX_FREE_SIZE: Self::Address;
     const SOCKET_TX_DATA_WRITE_PTR: Self::Address;
     const SOCKET_RECVD_SIZE: Self::Address;
     const SOCKET_RX_DATA_READ_PTR: Self::Address;
     const SOCKET_INTR_MASK: Self::Address;
+    /// Register to write to clear socket interrupts.
     const SOCKET_INTR: Self::Address;
 
+    /// Socket mode register value for MACRAW mode.
+    ///
+    /// Multicast and IPv6 blocking must be disabled, so IPv6 neighbor discovery works.
     const SOCKET_MODE_VALUE: u8;
 
+    /// Number of hardware sockets.
+    const SOCKETS: u8;
+    /// Total size of the RX and TX buffers, shared by all sockets.
     const BUF_SIZE: u16;
     const AUTO_WRAP: bool;
 
     fn rx_addr(addr: u16) -> Self::Address;
     fn tx_addr(addr: u16) -> Self::Address;
 
+    /// Address of a register of a socket, from its offset in the socket register block.
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address;
+    /// Address in the RX buffer of a socket, when each socket has `BUF_SIZE / SOCKETS` bytes.
+    ///
+    /// On chips without `AUTO_WRAP`, `addr` must be smaller than the socket's buffer size.
+    fn socket_rx_addr(socket: u8, addr: u16) -> Self::Address;
+    /// Address in the TX buffer of a socket, when each socket has `BUF_SIZE / SOCKETS` bytes.
+    ///
+    /// On chips without `AUTO_WRAP`, `addr` must be smaller than the socket's buffer size.
+    fn socket_tx_addr(socket: u8, addr: u16) -> Self::Address;
+
+    /// Length of a frame received in MACRAW mode, from the header preceding it in the RX buffer.
+    fn macraw_frame_len(header: [u8; 2]) -> usize;
+
     async fn bus_read<SPI: SpiDevice>(spi: &mut SPI, address: Self::Address, data: &mut [u8])
         -> Result<(), SPI::Error>;
     async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, address: Self::Address, data: &[u8]) -> Result<(), SPI::Error>;
+
+    /// Software reset the chip, and unlock the registers needed to configure it.
+    async fn reset<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error>;
+    /// Set the size of the RX and TX buffers of a socket, in KiB.
+    async fn set_socket_buf_size<SPI: SpiDevice>(spi: &mut SPI, socket: u8, kib: u8) -> Result<(), SPI::Error>;
+    async fn is_link_up<SPI: SpiDevice>(spi: &mut SPI) -> Result<bool, SPI::Error>;
+
+    /// Configure the chip-wide packet filters for MACRAW mode.
+    async fn init_macraw<SPI: SpiDevice>(_spi: &mut SPI) -> Result<(), SPI::Error> {
+        Ok(())
+    }
 }
 
 /// Trait for Wiznet chips.
 #[allow(private_bounds)]
 pub trait Chip: SealedChip {}
+
+pub(crate) trait SealedSocketChip {}
+
+/// Trait for Wiznet chips supported by the hardware [`socket`](crate::socket) mode.
+///
+/// These chips share the W5100 socket register layout.
+#[allow(private_bounds)]
+pub trait SocketChip: Chip + SealedSocketChip {}
@@ -0,0 +1,173 @@
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+const SOCKET_BASE: u16 = 0x400;
+const TX_BASE: u16 = 0x4000;
+const RX_BASE: u16 = 0x6000;
+
+/// RX memory size register, 2 bits per socket.
+const RMSR: u16 = 0x1A;
+/// TX memory size register, 2 bits per socket.
+const TMSR: u16 = 0x1B;
+
+/// Wiznet W5100 chip.
+pub enum W5100 {}
+
+impl super::Chip for W5100 {}
+impl super::SocketChip for W5100 {}
+impl super::SealedSocketChip for W5100 {}
+impl super::SealedChip for W5100 {
+    type Address = u16;
+
+    const COMMON_GATEWAY: Self::Address = 0x01;
+    const COMMON_SUBNET_MASK: Self::Address = 0x05;
+    const COMMON_MAC: Self::Address = 0x09;
+    const COMMON_IP: Self::Address = 0x0F;
+    const COMMON_SOCKET_INTR: Self::Address = 0x16;
+
+    const SOCKET_MODE: Self::Address = SOCKET_BASE;
+    const SOCKET_COMMAND: Self::Address = SOCKET_BASE + 0x01;
+    const SOCKET_TX_FREE_SIZE: Self::Address = SOCKET_BASE + 0x20;
+    const SOCKET_TX_DATA_WRITE_PTR: Self::Address = SOCKET_BASE + 0x24;
+    const SOCKET_RECVD_SIZE: Self::Address = SOCKET_BASE + 0x26;
+    const SOCKET_RX_DATA_READ_PTR: Self::Address = SOCKET_BASE + 0x28;
+    const SOCKET_INTR_MASK: Self::Address = SOCKET_BASE + 0x2C;
+    const SOCKET_INTR: Self::Address = SOCKET_BASE + 0x02;
+
+    // MACRAW. The W5100 has no MAC filter, so all frames are received, including multicast and IPv6.
+    const SOCKET_MODE_VALUE: u8 = 1 << 2;
+
+    const SOCKETS: u8 = 4;
+    const BUF_SIZE: u16 = 0x2000;
+    const AUTO_WRAP: bool = false;
+
+    fn rx_addr(addr: u16) -> Self::Address {
+        RX_BASE + addr
+    }
+
+    fn tx_addr(addr: u16) -> Self::Address {
+        TX_BASE + addr
+    }
+
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address {
+        SOCKET_BASE + socket as u16 * 0x100 + offset
+    }
+
+    fn socket_rx_addr(socket: u8, addr: u16) -> Self::Address {
+        RX_BASE + socket as u16 * (Self::BUF_SIZE / Self::SOCKETS as u16) + addr
+    }
+
+    fn socket_tx_addr(socket: u8, addr: u16) -> Self::Address {
+        TX_BASE + socket as u16 * (Self::BUF_SIZE / Self::SOCKETS as u16) + addr
+    }
+
+    fn macraw_frame_len(header: [u8; 2]) -> usize {
+        // The length includes the header itself.
+        u16::from_be_bytes(header) as usize - 2
+    }
+
+    async fn bus_read<SPI: SpiDevice>(
+        spi: &mut SPI,
+        address: Self::Address,
+        data: &mut [u8],
+    ) -> Result<(), SPI::Error> {
+        // The W5100 has no burst mode, each SPI frame transfers a single byte.
+        for (i, byte) in data.iter_mut().enumerate() {
+            let address = address + i as u16;
+            spi.transaction(&mut [
+                Operation::Write(&[0x0F, (address >> 8) as u8, address as u8]),
+                Operation::Read(core::slice::from_mut(byte)),
+            ])
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, address: Self::Address, data: &[u8]) -> Result<(), SPI::Error> {
+        for (i, byte) in data.iter().enumerate() {
+            let address = address + i as u16;
+            spi.transaction(&mut [Operation::Write(&[0xF0, (address >> 8) as u8, address as u8, *byte])])
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn reset<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, 0x00, &[0x80]).await?;
+        let mut mode = [0x80];
+        while mode[0] & 0x80 != 0 {
+            Self::bus_read(spi, 0x00, &mut mode).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_socket_buf_size<SPI: SpiDevice>(spi: &mut SPI, socket: u8, kib: u8) -> Result<(), SPI::Error> {
+        // Sizes of 1, 2, 4 and 8 KiB are encoded as 0 to 3.
+        let shift = socket * 2;
+        let value = (kib.trailing_zeros() as u8) << shift;
+        for reg in [RMSR, TMSR] {
+            let mut sizes = [0];
+            Self::bus_read(spi, reg, &mut sizes).await?;
+            sizes[0] = (sizes[0] & !(0b11 << shift)) | value;
+            Self::bus_write(spi, reg, &sizes).await?;
+        }
+        Ok(())
+    }
+
+    async fn is_link_up<SPI: SpiDevice>(_spi: &mut SPI) -> Result<bool, SPI::Error> {
+        // The W5100 doesn't report the link state.
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::chip::SealedChip;
+
+    /// W5100 address space, only accepting single byte frames.
+    struct Memory([u8; 0x8000]);
+
+    impl embedded_hal_async::spi::ErrorType for Memory {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Memory {
+        async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            match operations {
+                [Operation::Write([0xF0, hi, lo, data])] => self.0[u16::from_be_bytes([*hi, *lo]) as usize] = *data,
+                [Operation::Write([0x0F, hi, lo]), Operation::Read([data])] => {
+                    *data = self.0[u16::from_be_bytes([*hi, *lo]) as usize]
+                }
+                _ => panic!("unexpected transaction"),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn byte_frames() {
+        let mut spi = Memory([0; 0x8000]);
+        block_on(async {
+            W5100::bus_write(&mut spi, W5100::COMMON_MAC, &[1, 2, 3, 4, 5, 6]).await.unwrap();
+            let mut mac = [0; 6];
+            W5100::bus_read(&mut spi, W5100::COMMON_MAC, &mut mac).await.unwrap();
+            assert_eq!(mac, [1, 2, 3, 4, 5, 6]);
+        });
+    }
+
+    #[test]
+    fn socket_buf_size() {
+        let mut spi = Memory([0; 0x8000]);
+        block_on(async {
+            W5100::set_socket_buf_size(&mut spi, 0, 8).await.unwrap();
+            W5100::set_socket_buf_size(&mut spi, 1, 2).await.unwrap();
+            W5100::set_socket_buf_size(&mut spi, 0, 1).await.unwrap();
+        });
+        assert_eq!(spi.0[RMSR as usize], 0b0100);
+        assert_eq!(spi.0[TMSR as usize], 0b0100);
+    }
+}
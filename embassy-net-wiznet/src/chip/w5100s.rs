@@ -8,18 +8,19 @@ const RX_BASE: u16 = 0x6000;
 pub enum W5100S {}
 
 impl super::Chip for W5100S {}
+impl super::SocketChip for W5100S {}
+impl super::SealedSocketChip for W5100S {}
 impl super::SealedChip for W5100S {
     type Address = u16;
 
-    const COMMON_MODE: Self::Address = 0x00;
+    const COMMON_GATEWAY: Self::Address = 0x01;
+    const COMMON_SUBNET_MASK: Self::Address = 0x05;
     const COMMON_MAC: Self::Address = 0x09;
+    const COMMON_IP: Self::Address = 0x0F;
     const COMMON_SOCKET_INTR: Self::Address = 0x16;
-    const COMMON_PHY_CFG: Self::Address = 0x3c;
 
     const SOCKET_MODE: Self::Address = SOCKET_BASE + 0x00;
     const SOCKET_COMMAND: Self::Address = SOCKET_BASE + 0x01;
-    const SOCKET_RXBUF_SIZE: Self::Address = SOCKET_BASE + 0x1E;
-    const SOCKET_TXBUF_SIZE: Self::Address = SOCKET_BASE + 0x1F;
     const SOCKET_TX_FREE_SIZE: Self::Address = SOCKET_BASE + 0x20;
     const SOCKET_TX_DATA_WRITE_PTR: Self::Address = SOCKET_BASE + 0x24;
     const SOCKET_RECVD_SIZE: Self::Address = SOCKET_BASE + 0x26;
@@ -27,8 +28,10 @@ impl super::SealedChip for W5100S {
     const SOCKET_INTR_MASK: Self::Address = SOCKET_BASE + 0x2C;
     const SOCKET_INTR: Self::Address = SOCKET_BASE + 0x02;
 
+    // MACRAW with MAC filtering. Multicast (MMB) and IPv6 (MIP6B) blocking are left disabled.
     const SOCKET_MODE_VALUE: u8 = (1 << 2) | (1 << 6);
 
+    const SOCKETS: u8 = 4;
     const BUF_SIZE: u16 = 0x2000;
     const AUTO_WRAP: bool = false;
 
@@ -40,6 +43,23 @@ impl super::SealedChip for W5100S {
         TX_BASE + addr
     }
 
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address {
+        SOCKET_BASE + socket as u16 * 0x100 + offset
+    }
+
+    fn socket_rx_addr(socket: u8, addr: u16) -> Self::Address {
+        RX_BASE + socket as u16 * (Self::BUF_SIZE / Self::SOCKETS as u16) + addr
+    }
+
+    fn socket_tx_addr(socket: u8, addr: u16) -> Self::Address {
+        TX_BASE + socket as u16 * (Self::BUF_SIZE / Self::SOCKETS as u16) + addr
+    }
+
+    fn macraw_frame_len(header: [u8; 2]) -> usize {
+        // The length includes the header itself.
+        u16::from_be_bytes(header) as usize - 2
+    }
+
     async fn bus_read<SPI: SpiDevice>(
         spi: &mut SPI,
         address: Self::Address,
@@ -59,4 +79,24 @@ impl super::SealedChip for W5100S {
         ])
         .await
     }
+
+    async fn reset<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, 0x00, &[0x80]).await?;
+        let mut mode = [0x80];
+        while mode[0] & 0x80 != 0 {
+            Self::bus_read(spi, 0x00, &mut mode).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_socket_buf_size<SPI: SpiDevice>(spi: &mut SPI, socket: u8, kib: u8) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, Self::socket_addr(socket, 0x1E), &[kib]).await?;
+        Self::bus_write(spi, Self::socket_addr(socket, 0x1F), &[kib]).await
+    }
+
+    async fn is_link_up<SPI: SpiDevice>(spi: &mut SPI) -> Result<bool, SPI::Error> {
+        let mut phy_status = [0];
+        Self::bus_read(spi, 0x3C, &mut phy_status).await?;
+        Ok(phy_status[0] & 1 == 1)
+    }
 }
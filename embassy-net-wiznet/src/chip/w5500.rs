@@ -1,47 +1,77 @@
 use embedded_hal_async::spi::{Operation, SpiDevice};
 
-#[repr(u8)]
-pub enum RegisterBlock {
-    Common = 0x00,
-    Socket0 = 0x01,
-    TxBuf = 0x02,
-    RxBuf = 0x03,
+/// Block select bits of the common registers.
+const COMMON: u8 = 0x00;
+
+/// Block select bits of the registers of socket `n`.
+const fn socket_regs(n: u8) -> u8 {
+    (n << 2) | 0x01
+}
+
+/// Block select bits of the TX buffer of socket `n`.
+const fn socket_tx(n: u8) -> u8 {
+    (n << 2) | 0x02
+}
+
+/// Block select bits of the RX buffer of socket `n`.
+const fn socket_rx(n: u8) -> u8 {
+    (n << 2) | 0x03
 }
 
 /// Wiznet W5500 chip.
 pub enum W5500 {}
 
 impl super::Chip for W5500 {}
+impl super::SocketChip for W5500 {}
+impl super::SealedSocketChip for W5500 {}
 impl super::SealedChip for W5500 {
-    type Address = (RegisterBlock, u16);
-
-    const COMMON_MODE: Self::Address = (RegisterBlock::Common, 0x00);
-    const COMMON_MAC: Self::Address = (RegisterBlock::Common, 0x09);
-    const COMMON_SOCKET_INTR: Self::Address = (RegisterBlock::Common, 0x18);
-    const COMMON_PHY_CFG: Self::Address = (RegisterBlock::Common, 0x2E);
-
-    const SOCKET_MODE: Self::Address = (RegisterBlock::Socket0, 0x00);
-    const SOCKET_COMMAND: Self::Address = (RegisterBlock::Socket0, 0x01);
-    const SOCKET_RXBUF_SIZE: Self::Address = (RegisterBlock::Socket0, 0x1E);
-    const SOCKET_TXBUF_SIZE: Self::Address = (RegisterBlock::Socket0, 0x1F);
-    const SOCKET_TX_FREE_SIZE: Self::Address = (RegisterBlock::Socket0, 0x20);
-    const SOCKET_TX_DATA_WRITE_PTR: Self::Address = (RegisterBlock::Socket0, 0x24);
-    const SOCKET_RECVD_SIZE: Self::Address = (RegisterBlock::Socket0, 0x26);
-    const SOCKET_RX_DATA_READ_PTR: Self::Address = (RegisterBlock::Socket0, 0x28);
-    const SOCKET_INTR_MASK: Self::Address = (RegisterBlock::Socket0, 0x2C);
-    const SOCKET_INTR: Self::Address = (RegisterBlock::Socket0, 0x02);
+    type Address = (u8, u16);
+
+    const COMMON_GATEWAY: Self::Address = (COMMON, 0x01);
+    const COMMON_SUBNET_MASK: Self::Address = (COMMON, 0x05);
+    const COMMON_MAC: Self::Address = (COMMON, 0x09);
+    const COMMON_IP: Self::Address = (COMMON, 0x0F);
+    const COMMON_SOCKET_INTR: Self::Address = (COMMON, 0x18);
 
+    const SOCKET_MODE: Self::Address = (socket_regs(0), 0x00);
+    const SOCKET_COMMAND: Self::Address = (socket_regs(0), 0x01);
+    const SOCKET_TX_FREE_SIZE: Self::Address = (socket_regs(0), 0x20);
+    const SOCKET_TX_DATA_WRITE_PTR: Self::Address = (socket_regs(0), 0x24);
+    const SOCKET_RECVD_SIZE: Self::Address = (socket_regs(0), 0x26);
+    const SOCKET_RX_DATA_READ_PTR: Self::Address = (socket_regs(0), 0x28);
+    const SOCKET_INTR_MASK: Self::Address = (socket_regs(0), 0x2C);
+    const SOCKET_INTR: Self::Address = (socket_regs(0), 0x02);
+
+    // MACRAW with MAC filtering. Multicast (MMB) and IPv6 (MIP6B) blocking are left disabled.
     const SOCKET_MODE_VALUE: u8 = (1 << 2) | (1 << 7);
 
+    const SOCKETS: u8 = 8;
     const BUF_SIZE: u16 = 0x4000;
     const AUTO_WRAP: bool = true;
 
     fn rx_addr(addr: u16) -> Self::Address {
-        (RegisterBlock::RxBuf, addr)
+        (socket_rx(0), addr)
     }
 
     fn tx_addr(addr: u16) -> Self::Address {
-        (RegisterBlock::TxBuf, addr)
+        (socket_tx(0), addr)
+    }
+
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address {
+        (socket_regs(socket), offset)
+    }
+
+    fn socket_rx_addr(socket: u8, addr: u16) -> Self::Address {
+        (socket_rx(socket), addr)
+    }
+
+    fn socket_tx_addr(socket: u8, addr: u16) -> Self::Address {
+        (socket_tx(socket), addr)
+    }
+
+    fn macraw_frame_len(header: [u8; 2]) -> usize {
+        // The length includes the header itself.
+        u16::from_be_bytes(header) as usize - 2
     }
 
     async fn bus_read<SPI: SpiDevice>(
@@ -50,7 +80,7 @@ impl super::SealedChip for W5500 {
         data: &mut [u8],
     ) -> Result<(), SPI::Error> {
         let address_phase = address.1.to_be_bytes();
-        let control_phase = [(address.0 as u8) << 3];
+        let control_phase = [address.0 << 3];
         let operations = &mut [
             Operation::Write(&address_phase),
             Operation::Write(&control_phase),
@@ -61,7 +91,7 @@ impl super::SealedChip for W5500 {
 
     async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, address: Self::Address, data: &[u8]) -> Result<(), SPI::Error> {
         let address_phase = address.1.to_be_bytes();
-        let control_phase = [(address.0 as u8) << 3 | 0b0000_0100];
+        let control_phase = [address.0 << 3 | 0b0000_0100];
         let data_phase = data;
         let operations = &mut [
             Operation::Write(&address_phase[..]),
@@ -70,4 +100,24 @@ impl super::SealedChip for W5500 {
         ];
         spi.transaction(operations).await
     }
+
+    async fn reset<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, (COMMON, 0x00), &[0x80]).await?;
+        let mut mode = [0x80];
+        while mode[0] & 0x80 != 0 {
+            Self::bus_read(spi, (COMMON, 0x00), &mut mode).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_socket_buf_size<SPI: SpiDevice>(spi: &mut SPI, socket: u8, kib: u8) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, (socket_regs(socket), 0x1E), &[kib]).await?;
+        Self::bus_write(spi, (socket_regs(socket), 0x1F), &[kib]).await
+    }
+
+    async fn is_link_up<SPI: SpiDevice>(spi: &mut SPI) -> Result<bool, SPI::Error> {
+        let mut phy_cfg = [0];
+        Self::bus_read(spi, (COMMON, 0x2E), &mut phy_cfg).await?;
+        Ok(phy_cfg[0] & 1 == 1)
+    }
 }
@@ -0,0 +1,245 @@
+use embedded_hal_async::spi::{Operation, SpiDevice};
+
+/// Block select bits of the common registers.
+const COMMON: u8 = 0x00;
+
+/// Block select bits of the registers of socket `n`.
+const fn socket_regs(n: u8) -> u8 {
+    (n << 2) | 0x01
+}
+
+/// Block select bits of the TX buffer of socket `n`.
+const fn socket_tx(n: u8) -> u8 {
+    (n << 2) | 0x02
+}
+
+/// Block select bits of the RX buffer of socket `n`.
+const fn socket_rx(n: u8) -> u8 {
+    (n << 2) | 0x03
+}
+
+/// System config register 0, writing 0 resets the chip.
+const SYCR0: u16 = 0x2004;
+/// Network mode register, with the IPv4/IPv6 blocking bits.
+const NETMR: u16 = 0x4000;
+const PHYSR: u16 = 0x3000;
+const CHPLCKR: u16 = 0x41F4;
+const NETLCKR: u16 = 0x41F5;
+
+/// Wiznet W6100 chip.
+///
+/// The W6100 has a different socket register layout than the other chips, so it's only
+/// supported in MACRAW mode.
+pub enum W6100 {}
+
+impl super::Chip for W6100 {}
+impl super::SealedChip for W6100 {
+    type Address = (u8, u16);
+
+    const COMMON_GATEWAY: Self::Address = (COMMON, 0x4130);
+    const COMMON_SUBNET_MASK: Self::Address = (COMMON, 0x4134);
+    const COMMON_MAC: Self::Address = (COMMON, 0x4120);
+    const COMMON_IP: Self::Address = (COMMON, 0x4138);
+    const COMMON_SOCKET_INTR: Self::Address = (COMMON, 0x2114);
+
+    const SOCKET_MODE: Self::Address = (socket_regs(0), 0x0000);
+    const SOCKET_COMMAND: Self::Address = (socket_regs(0), 0x0010);
+    const SOCKET_TX_FREE_SIZE: Self::Address = (socket_regs(0), 0x0204);
+    const SOCKET_TX_DATA_WRITE_PTR: Self::Address = (socket_regs(0), 0x020C);
+    const SOCKET_RECVD_SIZE: Self::Address = (socket_regs(0), 0x0224);
+    const SOCKET_RX_DATA_READ_PTR: Self::Address = (socket_regs(0), 0x0228);
+    const SOCKET_INTR_MASK: Self::Address = (socket_regs(0), 0x0024);
+    // Sn_IR is read-only, interrupts are cleared through Sn_IRCLR.
+    const SOCKET_INTR: Self::Address = (socket_regs(0), 0x0028);
+
+    // MACRAW with MAC filtering. Broadcast (BRDB), multicast (MMB) and IPv6 multicast (MMB6)
+    // blocking are left disabled.
+    const SOCKET_MODE_VALUE: u8 = 0b0111 | (1 << 7);
+
+    const SOCKETS: u8 = 8;
+    const BUF_SIZE: u16 = 0x4000;
+    const AUTO_WRAP: bool = true;
+
+    fn rx_addr(addr: u16) -> Self::Address {
+        (socket_rx(0), addr)
+    }
+
+    fn tx_addr(addr: u16) -> Self::Address {
+        (socket_tx(0), addr)
+    }
+
+    fn socket_addr(socket: u8, offset: u16) -> Self::Address {
+        (socket_regs(socket), offset)
+    }
+
+    fn socket_rx_addr(socket: u8, addr: u16) -> Self::Address {
+        (socket_rx(socket), addr)
+    }
+
+    fn socket_tx_addr(socket: u8, addr: u16) -> Self::Address {
+        (socket_tx(socket), addr)
+    }
+
+    fn macraw_frame_len(header: [u8; 2]) -> usize {
+        // The low 11 bits are the length, not including the header.
+        (u16::from_be_bytes(header) & 0x07FF) as usize
+    }
+
+    async fn bus_read<SPI: SpiDevice>(
+        spi: &mut SPI,
+        address: Self::Address,
+        data: &mut [u8],
+    ) -> Result<(), SPI::Error> {
+        let address_phase = address.1.to_be_bytes();
+        let control_phase = [address.0 << 3];
+        spi.transaction(&mut [
+            Operation::Write(&address_phase),
+            Operation::Write(&control_phase),
+            Operation::TransferInPlace(data),
+        ])
+        .await
+    }
+
+    async fn bus_write<SPI: SpiDevice>(spi: &mut SPI, address: Self::Address, data: &[u8]) -> Result<(), SPI::Error> {
+        let address_phase = address.1.to_be_bytes();
+        let control_phase = [address.0 << 3 | 0b0000_0100];
+        spi.transaction(&mut [
+            Operation::Write(&address_phase),
+            Operation::Write(&control_phase),
+            Operation::Write(data),
+        ])
+        .await
+    }
+
+    async fn reset<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error> {
+        // The reset and network registers are write-protected until unlocked.
+        Self::bus_write(spi, (COMMON, CHPLCKR), &[0xCE]).await?;
+        Self::bus_write(spi, (COMMON, SYCR0), &[0x00]).await?;
+        Self::bus_write(spi, (COMMON, NETLCKR), &[0x3A]).await
+    }
+
+    async fn set_socket_buf_size<SPI: SpiDevice>(spi: &mut SPI, socket: u8, kib: u8) -> Result<(), SPI::Error> {
+        Self::bus_write(spi, (socket_regs(socket), 0x0200), &[kib]).await?;
+        Self::bus_write(spi, (socket_regs(socket), 0x0220), &[kib]).await
+    }
+
+    async fn is_link_up<SPI: SpiDevice>(spi: &mut SPI) -> Result<bool, SPI::Error> {
+        let mut phy_status = [0];
+        Self::bus_read(spi, (COMMON, PHYSR), &mut phy_status).await?;
+        Ok(phy_status[0] & 1 == 1)
+    }
+
+    async fn init_macraw<SPI: SpiDevice>(spi: &mut SPI) -> Result<(), SPI::Error> {
+        // Don't block IPv4, IPv6 or IPv6 multicast packets from reaching the MACRAW socket.
+        Self::bus_write(spi, (COMMON, NETMR), &[0x00]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use std::collections::BTreeMap;
+    use std::vec::Vec;
+
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::chip::SealedChip;
+
+    /// W6100 address space, keeping a log of the writes.
+    #[derive(Default)]
+    struct Memory {
+        bytes: BTreeMap<(u8, u16), u8>,
+        writes: Vec<((u8, u16), Vec<u8>)>,
+    }
+
+    impl embedded_hal_async::spi::ErrorType for Memory {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for Memory {
+        async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            match operations {
+                [Operation::Write([hi, lo]), Operation::Write([control]), data] => {
+                    let block = *control >> 3;
+                    let start = u16::from_be_bytes([*hi, *lo]);
+                    match data {
+                        Operation::Write(data) if *control & 0b111 == 0b100 => {
+                            for (i, byte) in data.iter().enumerate() {
+                                self.bytes.insert((block, start + i as u16), *byte);
+                            }
+                            self.writes.push(((block, start), data.to_vec()));
+                        }
+                        Operation::TransferInPlace(data) if *control & 0b111 == 0 => {
+                            for (i, byte) in data.iter_mut().enumerate() {
+                                *byte = self.bytes.get(&(block, start + i as u16)).copied().unwrap_or(0);
+                            }
+                        }
+                        _ => panic!("unexpected data phase"),
+                    }
+                }
+                _ => panic!("unexpected transaction"),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn address_mapping() {
+        assert_eq!(W6100::socket_addr(0, 0x0010), (0b00001, 0x0010));
+        assert_eq!(W6100::socket_addr(7, 0x0204), (0b11101, 0x0204));
+        assert_eq!(W6100::socket_tx_addr(3, 0x0100), (0b01110, 0x0100));
+        assert_eq!(W6100::socket_rx_addr(3, 0x0100), (0b01111, 0x0100));
+        assert_eq!(W6100::tx_addr(0x1234), W6100::socket_tx_addr(0, 0x1234));
+        assert_eq!(W6100::rx_addr(0x1234), W6100::socket_rx_addr(0, 0x1234));
+        assert_eq!(W6100::SOCKET_COMMAND, W6100::socket_addr(0, 0x0010));
+        // MACRAW (0b0111) with MAC filtering (bit 7).
+        assert_eq!(W6100::SOCKET_MODE_VALUE, 0x87);
+    }
+
+    #[test]
+    fn frames() {
+        let mut spi = Memory::default();
+        block_on(async {
+            W6100::bus_write(&mut spi, W6100::COMMON_MAC, &[1, 2, 3, 4, 5, 6])
+                .await
+                .unwrap();
+            let mut mac = [0; 6];
+            W6100::bus_read(&mut spi, W6100::COMMON_MAC, &mut mac).await.unwrap();
+            assert_eq!(mac, [1, 2, 3, 4, 5, 6]);
+
+            W6100::set_socket_buf_size(&mut spi, 2, 4).await.unwrap();
+        });
+        assert_eq!(spi.bytes[&(COMMON, 0x4120)], 1);
+        assert_eq!(spi.bytes[&(socket_regs(2), 0x0200)], 4);
+        assert_eq!(spi.bytes[&(socket_regs(2), 0x0220)], 4);
+    }
+
+    #[test]
+    fn reset_unlock_sequence() {
+        let mut spi = Memory::default();
+        block_on(async {
+            W6100::reset(&mut spi).await.unwrap();
+            W6100::init_macraw(&mut spi).await.unwrap();
+        });
+        assert_eq!(
+            spi.writes,
+            &[
+                ((COMMON, CHPLCKR), std::vec![0xCE]),
+                ((COMMON, SYCR0), std::vec![0x00]),
+                ((COMMON, NETLCKR), std::vec![0x3A]),
+                ((COMMON, NETMR), std::vec![0x00]),
+            ]
+        );
+    }
+
+    #[test]
+    fn macraw_frame_len() {
+        assert_eq!(W6100::macraw_frame_len([0x00, 0x40]), 64);
+        assert_eq!(W6100::macraw_frame_len([0x05, 0xEE]), 1518);
+        // The top 5 bits are status flags, not part of the length.
+        assert_eq!(W6100::macraw_frame_len([0xF8, 0x40]), 64);
+    }
+}
@@ -33,7 +33,7 @@ impl<C: Chip, SPI: SpiDevice> WiznetDevice<C, SPI> {
         };
 
         // Reset device
-        this.bus_write(C::COMMON_MODE, &[0x80]).await?;
+        C::reset(&mut this.spi).await?;
 
         // Enable interrupt pin
         this.bus_write(C::COMMON_SOCKET_INTR, &[0x01]).await?;
@@ -45,10 +45,10 @@ impl<C: Chip, SPI: SpiDevice> WiznetDevice<C, SPI> {
 
         // Set the raw socket RX/TX buffer sizes.
         let buf_kbs = (C::BUF_SIZE / 1024) as u8;
-        this.bus_write(C::SOCKET_TXBUF_SIZE, &[buf_kbs]).await?;
-        this.bus_write(C::SOCKET_RXBUF_SIZE, &[buf_kbs]).await?;
+        C::set_socket_buf_size(&mut this.spi, 0, buf_kbs).await?;
 
         // MACRAW mode with MAC filtering.
+        C::init_macraw(&mut this.spi).await?;
         this.bus_write(C::SOCKET_MODE, &[C::SOCKET_MODE_VALUE]).await?;
         this.command(Command::Open).await?;
 
@@ -149,7 +149,7 @@ impl<C: Chip, SPI: SpiDevice> WiznetDevice<C, SPI> {
         let expected_frame_size: usize = {
             let mut frame_bytes = [0u8; 2];
             self.read_bytes(&mut read_ptr, &mut frame_bytes).await?;
-            u16::from_be_bytes(frame_bytes) as usize - 2
+            C::macraw_frame_len(frame_bytes)
         };
 
         // Read the ethernet frame
@@ -188,8 +188,6 @@ impl<C: Chip, SPI: SpiDevice> WiznetDevice<C, SPI> {
     }
 
     pub async fn is_link_up(&mut self) -> bool {
-        let mut link = [0];
-        self.bus_read(C::COMMON_PHY_CFG, &mut link).await.ok();
-        link[0] & 1 == 1
+        C::is_link_up(&mut self.spi).await.unwrap_or(false)
     }
 }
@@ -5,6 +5,7 @@
 
 pub mod chip;
 mod device;
+pub mod socket;
 
 use embassy_futures::select::{select3, Either3};
 use embassy_net_driver_channel as ch;
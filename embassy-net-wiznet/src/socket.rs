@@ -0,0 +1,1317 @@
+//! Hardware TCP/IP sockets.
+//!
+//! Instead of running the chip in MACRAW mode and handing frames to `embassy-net`, this uses the
+//! chip's own TCP/IP stack. Each [`TcpSocket`] or [`UdpSocket`] occupies one of the chip's
+//! hardware sockets (8 on the W5500, 4 on the W5100 and W5100S), so no TCP/IP stack or socket
+//! buffers are needed on the MCU. The W6100 is not supported.
+//!
+//! The chip only supports IPv4, with a static address.
+//!
+//! ```ignore
+//! let config = socket::Config::new(mac_addr, Ipv4Addr::new(192, 168, 1, 10), Ipv4Addr::new(255, 255, 255, 0), Ipv4Addr::new(192, 168, 1, 1));
+//! let stack = SocketStack::<W5500, _>::new(spi_dev, config).await?;
+//!
+//! // In a background task:
+//! stack.run(int).await;
+//!
+//! let mut socket = stack.connect(SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 80)).await?;
+//! socket.write_all(b"hello").await?;
+//! ```
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, Timer};
+use embedded_hal::spi::{Error as _, ErrorKind};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::spi::SpiDevice;
+use embedded_nal_async::SocketAddr;
+pub use embedded_nal_async::{Ipv4Addr, SocketAddrV4};
+
+use crate::chip::SocketChip;
+
+/// Maximum number of hardware sockets of all supported chips.
+const MAX_SOCKETS: usize = 8;
+
+/// Interval at which the chip is polled for conditions which don't raise an interrupt, such as
+/// transmitted data being acknowledged.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// First port used for sockets bound to port 0.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+mod regs {
+    // Socket register offsets, identical on all socket chips.
+    pub const MODE: u16 = 0x00;
+    pub const COMMAND: u16 = 0x01;
+    pub const INTR: u16 = 0x02;
+    pub const STATUS: u16 = 0x03;
+    pub const PORT: u16 = 0x04;
+    pub const DEST_IP: u16 = 0x0C;
+    pub const DEST_PORT: u16 = 0x10;
+    pub const TX_FREE_SIZE: u16 = 0x20;
+    pub const TX_DATA_WRITE_PTR: u16 = 0x24;
+    pub const RECVD_SIZE: u16 = 0x26;
+    pub const RX_DATA_READ_PTR: u16 = 0x28;
+    pub const INTR_MASK: u16 = 0x2C;
+
+    pub const MODE_TCP: u8 = 0x01;
+    pub const MODE_UDP: u8 = 0x02;
+
+    pub const CMD_OPEN: u8 = 0x01;
+    pub const CMD_LISTEN: u8 = 0x02;
+    pub const CMD_CONNECT: u8 = 0x04;
+    pub const CMD_DISCON: u8 = 0x08;
+    pub const CMD_CLOSE: u8 = 0x10;
+    pub const CMD_SEND: u8 = 0x20;
+    pub const CMD_RECV: u8 = 0x40;
+
+    pub const INTR_CON: u8 = 0x01;
+    pub const INTR_DISCON: u8 = 0x02;
+    pub const INTR_TIMEOUT: u8 = 0x08;
+    pub const INTR_SEND_OK: u8 = 0x10;
+    pub const INTR_ALL: u8 = 0x1F;
+
+    pub const STATUS_CLOSED: u8 = 0x00;
+    pub const STATUS_ESTABLISHED: u8 = 0x17;
+    pub const STATUS_CLOSE_WAIT: u8 = 0x1C;
+}
+
+/// Hardware socket error.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Communication with the chip failed.
+    Spi(ErrorKind),
+    /// All hardware sockets are in use.
+    NoFreeSocket,
+    /// The connection was refused, closed or reset by the remote host.
+    ConnectionReset,
+    /// The remote host did not respond, to ARP or to TCP retransmissions.
+    TimedOut,
+    /// The address is not an IPv4 address.
+    Unaddressable,
+    /// The datagram is larger than the socket's transmit buffer.
+    PacketTooLarge,
+    /// The received datagram was larger than the buffer, and was truncated.
+    Truncated,
+}
+
+impl embedded_io_async::Error for Error {
+    fn kind(&self) -> embedded_io_async::ErrorKind {
+        match self {
+            Error::Spi(_) => embedded_io_async::ErrorKind::Other,
+            Error::NoFreeSocket => embedded_io_async::ErrorKind::OutOfMemory,
+            Error::ConnectionReset => embedded_io_async::ErrorKind::ConnectionReset,
+            Error::TimedOut => embedded_io_async::ErrorKind::TimedOut,
+            Error::Unaddressable => embedded_io_async::ErrorKind::InvalidInput,
+            Error::PacketTooLarge => embedded_io_async::ErrorKind::InvalidInput,
+            Error::Truncated => embedded_io_async::ErrorKind::Other,
+        }
+    }
+}
+
+/// Network configuration of the chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// MAC address.
+    pub mac_addr: [u8; 6],
+    /// IPv4 address.
+    pub address: Ipv4Addr,
+    /// Subnet mask.
+    pub subnet_mask: Ipv4Addr,
+    /// Default gateway.
+    pub gateway: Ipv4Addr,
+}
+
+impl Config {
+    /// Create a new `Config`.
+    pub fn new(mac_addr: [u8; 6], address: Ipv4Addr, subnet_mask: Ipv4Addr, gateway: Ipv4Addr) -> Self {
+        Self {
+            mac_addr,
+            address,
+            subnet_mask,
+            gateway,
+        }
+    }
+}
+
+const NEW_WAKER: WakerRegistration = WakerRegistration::new();
+
+struct Shared {
+    /// Bit `n` is set while socket `n` is in use, or waiting to be closed.
+    allocated: u8,
+    /// Bit `n` is set when socket `n` was dropped, and must be closed by the runner.
+    to_close: u8,
+    /// Interrupts received for each socket, not yet handled by it.
+    events: [u8; MAX_SOCKETS],
+    wakers: [WakerRegistration; MAX_SOCKETS],
+    runner_waker: WakerRegistration,
+    next_port: u16,
+}
+
+/// Hardware TCP/IP stack of a Wiznet chip.
+///
+/// You must call [`run()`](SocketStack::run) in a background task for sockets to operate.
+pub struct SocketStack<C: SocketChip, SPI: SpiDevice, M: RawMutex = NoopRawMutex> {
+    spi: Mutex<M, SPI>,
+    shared: BlockingMutex<M, RefCell<Shared>>,
+    config: Config,
+    _phantom: PhantomData<C>,
+}
+
+impl<C: SocketChip, SPI: SpiDevice, M: RawMutex> SocketStack<C, SPI, M> {
+    /// Size of the RX and TX buffers of each socket.
+    const SOCKET_BUF_SIZE: u16 = C::BUF_SIZE / C::SOCKETS as u16;
+
+    /// Reset the chip, and configure it for hardware sockets.
+    ///
+    /// This does a software reset, so if the reset pin is used it must be released first.
+    pub async fn new(spi: SPI, config: Config) -> Result<Self, Error> {
+        let this = Self {
+            spi: Mutex::new(spi),
+            shared: BlockingMutex::new(RefCell::new(Shared {
+                allocated: 0,
+                to_close: 0,
+                events: [0; MAX_SOCKETS],
+                wakers: [NEW_WAKER; MAX_SOCKETS],
+                runner_waker: WakerRegistration::new(),
+                next_port: EPHEMERAL_PORT_START,
+            })),
+            config,
+            _phantom: PhantomData,
+        };
+
+        {
+            let mut spi = this.spi.lock().await;
+            C::reset(&mut *spi).await.map_err(|e| Error::Spi(e.kind()))?;
+            // Split the buffers evenly between sockets.
+            for s in 0..C::SOCKETS {
+                C::set_socket_buf_size(&mut *spi, s, (Self::SOCKET_BUF_SIZE / 1024) as u8)
+                    .await
+                    .map_err(|e| Error::Spi(e.kind()))?;
+            }
+        }
+
+        this.bus_write(C::COMMON_MAC, &config.mac_addr).await?;
+        this.bus_write(C::COMMON_IP, &config.address.octets()).await?;
+        this.bus_write(C::COMMON_SUBNET_MASK, &config.subnet_mask.octets())
+            .await?;
+        this.bus_write(C::COMMON_GATEWAY, &config.gateway.octets()).await?;
+
+        // Enable all socket interrupts.
+        for s in 0..C::SOCKETS {
+            this.write_reg(s, regs::INTR_MASK, &[regs::INTR_ALL]).await?;
+        }
+        this.bus_write(C::COMMON_SOCKET_INTR, &[((1u16 << C::SOCKETS) - 1) as u8])
+            .await?;
+
+        Ok(this)
+    }
+
+    /// Get the network configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Run the interrupt handling and socket cleanup.
+    ///
+    /// `int` is the chip's interrupt pin.
+    pub async fn run<INT: Wait>(&self, mut int: INT) -> ! {
+        loop {
+            let to_close = poll_fn(|cx| {
+                self.with(|s| {
+                    s.runner_waker.register(cx.waker());
+                    match s.to_close {
+                        0 => Poll::Pending,
+                        n => Poll::Ready(n),
+                    }
+                })
+            });
+
+            match select(int.wait_for_low(), to_close).await {
+                Either::First(_) => {
+                    for n in 0..C::SOCKETS {
+                        let mut intr = [0];
+                        if self.read_reg(n, regs::INTR, &mut intr).await.is_err() || intr[0] == 0 {
+                            continue;
+                        }
+                        self.write_reg(n, regs::INTR, &intr).await.ok();
+                        self.with(|s| {
+                            s.events[n as usize] |= intr[0];
+                            s.wakers[n as usize].wake();
+                        });
+                    }
+                }
+                Either::Second(to_close) => {
+                    for n in (0..C::SOCKETS).filter(|n| to_close & (1 << n) != 0) {
+                        self.command(n, regs::CMD_CLOSE).await.ok();
+                        self.write_reg(n, regs::INTR, &[regs::INTR_ALL]).await.ok();
+                        self.with(|s| {
+                            s.to_close &= !(1 << n);
+                            s.allocated &= !(1 << n);
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Connect a TCP socket to a remote host.
+    pub async fn connect(&self, remote: SocketAddrV4) -> Result<TcpSocket<'_, C, SPI, M>, Error> {
+        let socket = TcpSocket {
+            stack: self,
+            n: self.alloc()?,
+        };
+        let n = socket.n;
+
+        let port = self.ephemeral_port();
+        self.open(n, regs::MODE_TCP, port).await?;
+        self.write_reg(n, regs::DEST_IP, &remote.ip().octets()).await?;
+        self.write_reg(n, regs::DEST_PORT, &remote.port().to_be_bytes()).await?;
+        self.clear_events(n);
+        self.command(n, regs::CMD_CONNECT).await?;
+
+        loop {
+            let events = self.wait_event(n).await;
+            if events & regs::INTR_CON != 0 {
+                return Ok(socket);
+            } else if events & regs::INTR_TIMEOUT != 0 {
+                return Err(Error::TimedOut);
+            } else if events & regs::INTR_DISCON != 0 {
+                return Err(Error::ConnectionReset);
+            }
+        }
+    }
+
+    /// Wait for an incoming TCP connection on `port`.
+    ///
+    /// To accept several connections on the same port, call this concurrently once for each of them.
+    pub async fn accept(&self, port: u16) -> Result<TcpSocket<'_, C, SPI, M>, Error> {
+        let socket = TcpSocket {
+            stack: self,
+            n: self.alloc()?,
+        };
+        let n = socket.n;
+
+        loop {
+            self.open(n, regs::MODE_TCP, port).await?;
+            self.clear_events(n);
+            self.command(n, regs::CMD_LISTEN).await?;
+
+            loop {
+                let events = self.wait_event(n).await;
+                if events & regs::INTR_CON != 0 {
+                    return Ok(socket);
+                } else if events & (regs::INTR_TIMEOUT | regs::INTR_DISCON) != 0 {
+                    // The connection attempt failed, listen again.
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Open a UDP socket bound to `port`.
+    ///
+    /// If `port` is 0, an ephemeral port is picked.
+    pub async fn bind_udp(&self, port: u16) -> Result<UdpSocket<'_, C, SPI, M>, Error> {
+        let port = match port {
+            0 => self.ephemeral_port(),
+            port => port,
+        };
+        let socket = UdpSocket {
+            stack: self,
+            n: self.alloc()?,
+            port,
+        };
+        self.open(socket.n, regs::MODE_UDP, port).await?;
+        Ok(socket)
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut Shared) -> R) -> R {
+        self.shared.lock(|s| f(&mut s.borrow_mut()))
+    }
+
+    fn alloc(&self) -> Result<u8, Error> {
+        self.with(|s| {
+            let n = (0..C::SOCKETS)
+                .find(|n| s.allocated & (1 << n) == 0)
+                .ok_or(Error::NoFreeSocket)?;
+            s.allocated |= 1 << n;
+            s.events[n as usize] = 0;
+            Ok(n)
+        })
+    }
+
+    /// Release a socket. It's closed by the runner before being reused.
+    fn release(&self, n: u8) {
+        self.with(|s| {
+            s.to_close |= 1 << n;
+            s.runner_waker.wake();
+        })
+    }
+
+    fn ephemeral_port(&self) -> u16 {
+        self.with(|s| {
+            let port = s.next_port;
+            s.next_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+            port
+        })
+    }
+
+    fn clear_events(&self, n: u8) {
+        self.with(|s| s.events[n as usize] = 0)
+    }
+
+    /// Wait for interrupts of socket `n`, and return them.
+    async fn wait_event(&self, n: u8) -> u8 {
+        poll_fn(|cx| {
+            self.with(|s| match core::mem::take(&mut s.events[n as usize]) {
+                0 => {
+                    s.wakers[n as usize].register(cx.waker());
+                    Poll::Pending
+                }
+                events => Poll::Ready(events),
+            })
+        })
+        .await
+    }
+
+    /// Wait for interrupts of socket `n`, or for the poll interval to elapse.
+    async fn wait_event_or_poll(&self, n: u8) -> u8 {
+        match select(self.wait_event(n), Timer::after(POLL_INTERVAL)).await {
+            Either::First(events) => events,
+            Either::Second(()) => 0,
+        }
+    }
+
+    async fn bus_read(&self, address: C::Address, data: &mut [u8]) -> Result<(), Error> {
+        let mut spi = self.spi.lock().await;
+        C::bus_read(&mut *spi, address, data)
+            .await
+            .map_err(|e| Error::Spi(e.kind()))
+    }
+
+    async fn bus_write(&self, address: C::Address, data: &[u8]) -> Result<(), Error> {
+        let mut spi = self.spi.lock().await;
+        C::bus_write(&mut *spi, address, data)
+            .await
+            .map_err(|e| Error::Spi(e.kind()))
+    }
+
+    async fn read_reg(&self, n: u8, offset: u16, data: &mut [u8]) -> Result<(), Error> {
+        self.bus_read(C::socket_addr(n, offset), data).await
+    }
+
+    async fn write_reg(&self, n: u8, offset: u16, data: &[u8]) -> Result<(), Error> {
+        self.bus_write(C::socket_addr(n, offset), data).await
+    }
+
+    async fn read_u16(&self, n: u8, offset: u16) -> Result<u16, Error> {
+        let mut data = [0; 2];
+        self.read_reg(n, offset, &mut data).await?;
+        Ok(u16::from_be_bytes(data))
+    }
+
+    /// Read a register the chip may update while it's being read, until two sequential reads are equal.
+    async fn read_u16_stable(&self, n: u8, offset: u16) -> Result<u16, Error> {
+        loop {
+            let a = self.read_u16(n, offset).await?;
+            let b = self.read_u16(n, offset).await?;
+            if a == b {
+                break Ok(a);
+            }
+        }
+    }
+
+    async fn status(&self, n: u8) -> Result<u8, Error> {
+        let mut status = [0];
+        self.read_reg(n, regs::STATUS, &mut status).await?;
+        Ok(status[0])
+    }
+
+    /// Issue a command, and wait for the chip to accept it.
+    async fn command(&self, n: u8, command: u8) -> Result<(), Error> {
+        self.write_reg(n, regs::COMMAND, &[command]).await?;
+        let mut data = [command];
+        while data[0] != 0 {
+            self.read_reg(n, regs::COMMAND, &mut data).await?;
+        }
+        Ok(())
+    }
+
+    async fn open(&self, n: u8, mode: u8, port: u16) -> Result<(), Error> {
+        self.command(n, regs::CMD_CLOSE).await?;
+        self.write_reg(n, regs::INTR, &[regs::INTR_ALL]).await?;
+        self.write_reg(n, regs::MODE, &[mode]).await?;
+        self.write_reg(n, regs::PORT, &port.to_be_bytes()).await?;
+        self.command(n, regs::CMD_OPEN).await
+    }
+
+    /// Read from the RX buffer of socket `n`, advancing `ptr`.
+    async fn read_buf(&self, n: u8, ptr: &mut u16, buf: &mut [u8]) -> Result<(), Error> {
+        if C::AUTO_WRAP {
+            self.bus_read(C::socket_rx_addr(n, *ptr), buf).await?;
+        } else {
+            let addr = *ptr % Self::SOCKET_BUF_SIZE;
+            let split = ((Self::SOCKET_BUF_SIZE - addr) as usize).min(buf.len());
+            self.bus_read(C::socket_rx_addr(n, addr), &mut buf[..split]).await?;
+            if split < buf.len() {
+                self.bus_read(C::socket_rx_addr(n, 0), &mut buf[split..]).await?;
+            }
+        }
+        *ptr = ptr.wrapping_add(buf.len() as u16);
+        Ok(())
+    }
+
+    /// Write to the TX buffer of socket `n`, advancing `ptr`.
+    async fn write_buf(&self, n: u8, ptr: &mut u16, buf: &[u8]) -> Result<(), Error> {
+        if C::AUTO_WRAP {
+            self.bus_write(C::socket_tx_addr(n, *ptr), buf).await?;
+        } else {
+            let addr = *ptr % Self::SOCKET_BUF_SIZE;
+            let split = ((Self::SOCKET_BUF_SIZE - addr) as usize).min(buf.len());
+            self.bus_write(C::socket_tx_addr(n, addr), &buf[..split]).await?;
+            if split < buf.len() {
+                self.bus_write(C::socket_tx_addr(n, 0), &buf[split..]).await?;
+            }
+        }
+        *ptr = ptr.wrapping_add(buf.len() as u16);
+        Ok(())
+    }
+
+    /// Copy `data` to the TX buffer of socket `n`, send it, and wait for it to be sent.
+    ///
+    /// The caller must have checked there is enough free space.
+    async fn send(&self, n: u8, data: &[u8]) -> Result<(), Error> {
+        let mut ptr = self.read_u16(n, regs::TX_DATA_WRITE_PTR).await?;
+        self.write_buf(n, &mut ptr, data).await?;
+        self.write_reg(n, regs::TX_DATA_WRITE_PTR, &ptr.to_be_bytes()).await?;
+
+        self.clear_events(n);
+        self.command(n, regs::CMD_SEND).await?;
+        loop {
+            let events = self.wait_event(n).await;
+            if events & regs::INTR_SEND_OK != 0 {
+                return Ok(());
+            } else if events & regs::INTR_TIMEOUT != 0 {
+                return Err(Error::TimedOut);
+            } else if events & regs::INTR_DISCON != 0 {
+                return Err(Error::ConnectionReset);
+            }
+        }
+    }
+}
+
+/// A TCP connection using a hardware socket.
+///
+/// The socket is closed when dropped.
+pub struct TcpSocket<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex = NoopRawMutex> {
+    stack: &'d SocketStack<C, SPI, M>,
+    n: u8,
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> TcpSocket<'d, C, SPI, M> {
+    /// Get the remote endpoint of the connection.
+    pub async fn remote_endpoint(&self) -> Result<SocketAddrV4, Error> {
+        let mut ip = [0; 4];
+        self.stack.read_reg(self.n, regs::DEST_IP, &mut ip).await?;
+        let port = self.stack.read_u16(self.n, regs::DEST_PORT).await?;
+        Ok(SocketAddrV4::new(ip.into(), port))
+    }
+
+    /// Read data from the socket.
+    ///
+    /// Returns how many bytes were read, or 0 if the remote host closed the connection and all
+    /// the data it sent was read.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let stack = self.stack;
+        let n = self.n;
+        loop {
+            stack.clear_events(n);
+
+            let available = stack.read_u16_stable(n, regs::RECVD_SIZE).await? as usize;
+            if available > 0 {
+                let len = available.min(buf.len());
+                let mut ptr = stack.read_u16(n, regs::RX_DATA_READ_PTR).await?;
+                stack.read_buf(n, &mut ptr, &mut buf[..len]).await?;
+                stack.write_reg(n, regs::RX_DATA_READ_PTR, &ptr.to_be_bytes()).await?;
+                stack.command(n, regs::CMD_RECV).await?;
+                return Ok(len);
+            }
+
+            match stack.status(n).await? {
+                regs::STATUS_ESTABLISHED => {}
+                regs::STATUS_CLOSED => return Err(Error::ConnectionReset),
+                // The remote host, or we, closed the connection.
+                _ => return Ok(0),
+            }
+
+            stack.wait_event(n).await;
+        }
+    }
+
+    /// Write data to the socket.
+    ///
+    /// Returns how many bytes were written, which may be less than `buf.len()` if the transmit
+    /// buffer is full.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let stack = self.stack;
+        let n = self.n;
+        let free = loop {
+            stack.clear_events(n);
+            match stack.status(n).await? {
+                regs::STATUS_ESTABLISHED | regs::STATUS_CLOSE_WAIT => {}
+                _ => return Err(Error::ConnectionReset),
+            }
+            let free = stack.read_u16_stable(n, regs::TX_FREE_SIZE).await? as usize;
+            if free > 0 {
+                break free;
+            }
+            // Acknowledged data doesn't raise an interrupt, poll for it.
+            stack.wait_event_or_poll(n).await;
+        };
+
+        let len = free.min(buf.len());
+        stack.send(n, &buf[..len]).await?;
+        Ok(len)
+    }
+
+    /// Wait until all written data has been acknowledged by the remote host.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        let stack = self.stack;
+        let n = self.n;
+        loop {
+            match stack.status(n).await? {
+                regs::STATUS_ESTABLISHED | regs::STATUS_CLOSE_WAIT => {}
+                _ => return Err(Error::ConnectionReset),
+            }
+            if stack.read_u16_stable(n, regs::TX_FREE_SIZE).await? == SocketStack::<C, SPI, M>::SOCKET_BUF_SIZE {
+                return Ok(());
+            }
+            stack.wait_event_or_poll(n).await;
+        }
+    }
+
+    /// Gracefully close the connection, and wait for the remote host to acknowledge it.
+    ///
+    /// Data may still be read until the remote host closes its side too.
+    pub async fn close(&mut self) -> Result<(), Error> {
+        let stack = self.stack;
+        let n = self.n;
+        stack.clear_events(n);
+        stack.command(n, regs::CMD_DISCON).await?;
+        loop {
+            let events = stack.wait_event(n).await;
+            if events & regs::INTR_TIMEOUT != 0 {
+                return Err(Error::TimedOut);
+            } else if events & regs::INTR_DISCON != 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> Drop for TcpSocket<'d, C, SPI, M> {
+    fn drop(&mut self) {
+        self.stack.release(self.n);
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_io_async::ErrorType for TcpSocket<'d, C, SPI, M> {
+    type Error = Error;
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_io_async::Read for TcpSocket<'d, C, SPI, M> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        TcpSocket::read(self, buf).await
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_io_async::Write for TcpSocket<'d, C, SPI, M> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        TcpSocket::write(self, buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        TcpSocket::flush(self).await
+    }
+}
+
+/// A UDP socket using a hardware socket.
+///
+/// The socket is closed when dropped.
+pub struct UdpSocket<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex = NoopRawMutex> {
+    stack: &'d SocketStack<C, SPI, M>,
+    n: u8,
+    port: u16,
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> UdpSocket<'d, C, SPI, M> {
+    /// Get the local endpoint of the socket.
+    pub fn local_endpoint(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.stack.config.address, self.port)
+    }
+
+    /// Send a datagram to `remote`.
+    pub async fn send_to(&mut self, buf: &[u8], remote: SocketAddrV4) -> Result<(), Error> {
+        let stack = self.stack;
+        let n = self.n;
+        if buf.len() > SocketStack::<C, SPI, M>::SOCKET_BUF_SIZE as usize {
+            return Err(Error::PacketTooLarge);
+        }
+
+        while (stack.read_u16_stable(n, regs::TX_FREE_SIZE).await? as usize) < buf.len() {
+            stack.wait_event_or_poll(n).await;
+        }
+
+        stack.write_reg(n, regs::DEST_IP, &remote.ip().octets()).await?;
+        stack
+            .write_reg(n, regs::DEST_PORT, &remote.port().to_be_bytes())
+            .await?;
+        stack.send(n, buf).await
+    }
+
+    /// Receive a datagram.
+    ///
+    /// Returns the length of the datagram, and the remote endpoint it was received from. If the
+    /// datagram doesn't fit in `buf`, the rest of it is discarded and [`Error::Truncated`] is returned.
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, SocketAddrV4), Error> {
+        let stack = self.stack;
+        let n = self.n;
+        loop {
+            stack.clear_events(n);
+            if stack.read_u16_stable(n, regs::RECVD_SIZE).await? > 0 {
+                break;
+            }
+            stack.wait_event(n).await;
+        }
+
+        // Each datagram is preceded by a header with the remote address, port and the datagram length.
+        let mut ptr = stack.read_u16(n, regs::RX_DATA_READ_PTR).await?;
+        let mut header = [0; 8];
+        stack.read_buf(n, &mut ptr, &mut header).await?;
+        let remote = SocketAddrV4::new(
+            Ipv4Addr::new(header[0], header[1], header[2], header[3]),
+            u16::from_be_bytes([header[4], header[5]]),
+        );
+        let len = u16::from_be_bytes([header[6], header[7]]) as usize;
+
+        let copied = len.min(buf.len());
+        stack.read_buf(n, &mut ptr, &mut buf[..copied]).await?;
+        ptr = ptr.wrapping_add((len - copied) as u16);
+        stack.write_reg(n, regs::RX_DATA_READ_PTR, &ptr.to_be_bytes()).await?;
+        stack.command(n, regs::CMD_RECV).await?;
+
+        if copied < len {
+            return Err(Error::Truncated);
+        }
+        Ok((len, remote))
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> Drop for UdpSocket<'d, C, SPI, M> {
+    fn drop(&mut self) {
+        self.stack.release(self.n);
+    }
+}
+
+/// A UDP socket only exchanging datagrams with a single remote endpoint.
+///
+/// Created by [`embedded_nal_async::UdpStack::connect`].
+pub struct UdpConnection<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex = NoopRawMutex> {
+    socket: UdpSocket<'d, C, SPI, M>,
+    remote: SocketAddrV4,
+}
+
+fn to_v4(addr: SocketAddr) -> Result<SocketAddrV4, Error> {
+    match addr {
+        SocketAddr::V4(addr) => Ok(addr),
+        SocketAddr::V6(_) => Err(Error::Unaddressable),
+    }
+}
+
+impl<C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_nal_async::TcpConnect for SocketStack<C, SPI, M> {
+    type Error = Error;
+    type Connection<'a> = TcpSocket<'a, C, SPI, M> where Self: 'a;
+
+    async fn connect(&self, remote: SocketAddr) -> Result<Self::Connection<'_>, Self::Error> {
+        SocketStack::connect(self, to_v4(remote)?).await
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_nal_async::UdpStack for &'d SocketStack<C, SPI, M> {
+    type Error = Error;
+    type Connected = UdpConnection<'d, C, SPI, M>;
+    type UniquelyBound = UdpSocket<'d, C, SPI, M>;
+    type MultiplyBound = UdpSocket<'d, C, SPI, M>;
+
+    async fn connect_from(
+        &self,
+        local: SocketAddr,
+        remote: SocketAddr,
+    ) -> Result<(SocketAddr, Self::Connected), Self::Error> {
+        let remote = to_v4(remote)?;
+        let socket = self.bind_udp(to_v4(local)?.port()).await?;
+        let local = SocketAddr::V4(socket.local_endpoint());
+        Ok((local, UdpConnection { socket, remote }))
+    }
+
+    async fn bind_single(&self, local: SocketAddr) -> Result<(SocketAddr, Self::UniquelyBound), Self::Error> {
+        let socket = self.bind_udp(to_v4(local)?.port()).await?;
+        Ok((SocketAddr::V4(socket.local_endpoint()), socket))
+    }
+
+    async fn bind_multiple(&self, local: SocketAddr) -> Result<Self::MultiplyBound, Self::Error> {
+        self.bind_udp(to_v4(local)?.port()).await
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_nal_async::UnconnectedUdp for UdpSocket<'d, C, SPI, M> {
+    type Error = Error;
+
+    async fn send(&mut self, _local: SocketAddr, remote: SocketAddr, data: &[u8]) -> Result<(), Self::Error> {
+        self.send_to(data, to_v4(remote)?).await
+    }
+
+    async fn receive_into(&mut self, buffer: &mut [u8]) -> Result<(usize, SocketAddr, SocketAddr), Self::Error> {
+        let (len, remote) = self.recv_from(buffer).await?;
+        Ok((len, SocketAddr::V4(self.local_endpoint()), SocketAddr::V4(remote)))
+    }
+}
+
+impl<'d, C: SocketChip, SPI: SpiDevice, M: RawMutex> embedded_nal_async::ConnectedUdp for UdpConnection<'d, C, SPI, M> {
+    type Error = Error;
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.socket.send_to(data, self.remote).await
+    }
+
+    async fn receive_into(&mut self, buffer: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            let (len, remote) = self.socket.recv_from(buffer).await?;
+            // Datagrams from other hosts are discarded.
+            if remote == self.remote {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use core::future::Future;
+    use core::task::Waker;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use embassy_futures::yield_now;
+    use embedded_hal_async::spi::Operation;
+    use embedded_io_async::{Read, Write};
+    use embedded_nal_async::{ConnectedUdp, TcpConnect, UdpStack};
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::chip::W5500;
+
+    const BUF: usize = 2048;
+    const STATUS_INIT: u8 = 0x13;
+    const STATUS_LISTEN: u8 = 0x14;
+    const STATUS_UDP: u8 = 0x22;
+    const INTR_RECV: u8 = 0x04;
+
+    struct SocketModel {
+        regs: [u8; 0x30],
+        tx: [u8; BUF],
+        rx: [u8; BUF],
+        rx_write: u16,
+        /// Data sent by the chip, with its destination.
+        sent: Vec<(SocketAddrV4, Vec<u8>)>,
+    }
+
+    impl SocketModel {
+        fn new() -> Self {
+            Self {
+                regs: [0; 0x30],
+                tx: [0; BUF],
+                rx: [0; BUF],
+                rx_write: 0,
+                sent: Vec::new(),
+            }
+        }
+
+        fn u16(&self, offset: u16) -> u16 {
+            u16::from_be_bytes([self.regs[offset as usize], self.regs[offset as usize + 1]])
+        }
+
+        fn set_u16(&mut self, offset: u16, value: u16) {
+            self.regs[offset as usize..][..2].copy_from_slice(&value.to_be_bytes());
+        }
+
+        fn dest(&self) -> SocketAddrV4 {
+            let ip = &self.regs[regs::DEST_IP as usize..][..4];
+            SocketAddrV4::new(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]), self.u16(regs::DEST_PORT))
+        }
+
+        fn push_rx(&mut self, data: &[u8]) {
+            for &b in data {
+                self.rx[self.rx_write as usize % BUF] = b;
+                self.rx_write = self.rx_write.wrapping_add(1);
+            }
+            self.set_u16(
+                regs::RECVD_SIZE,
+                self.rx_write.wrapping_sub(self.u16(regs::RX_DATA_READ_PTR)),
+            );
+        }
+    }
+
+    /// Register model of a W5500, with a fake network behind it.
+    struct Model {
+        common: [u8; 0x40],
+        sockets: Vec<SocketModel>,
+        int_waker: Option<Waker>,
+        /// Hosts which don't respond to connection attempts or datagrams.
+        unreachable: Vec<Ipv4Addr>,
+    }
+
+    impl Model {
+        fn new() -> Rc<RefCell<Self>> {
+            Rc::new(RefCell::new(Self {
+                common: [0; 0x40],
+                sockets: (0..8).map(|_| SocketModel::new()).collect(),
+                int_waker: None,
+                unreachable: Vec::new(),
+            }))
+        }
+
+        fn int_low(&self) -> bool {
+            self.sockets.iter().enumerate().any(|(n, s)| {
+                self.common[0x18] & (1 << n) != 0 && s.regs[regs::INTR as usize] & s.regs[regs::INTR_MASK as usize] != 0
+            })
+        }
+
+        fn interrupt(&mut self, n: usize, intr: u8) {
+            self.sockets[n].regs[regs::INTR as usize] |= intr;
+            if let Some(w) = self.int_waker.take() {
+                w.wake();
+            }
+        }
+
+        fn status(&self, n: usize) -> u8 {
+            self.sockets[n].regs[regs::STATUS as usize]
+        }
+
+        fn set_status(&mut self, n: usize, status: u8) {
+            self.sockets[n].regs[regs::STATUS as usize] = status;
+        }
+
+        fn command(&mut self, n: usize, command: u8) {
+            let unreachable = self.unreachable.contains(self.sockets[n].dest().ip());
+            let s = &mut self.sockets[n];
+            match command {
+                regs::CMD_OPEN => {
+                    let status = match s.regs[regs::MODE as usize] & 0x0F {
+                        regs::MODE_TCP => STATUS_INIT,
+                        regs::MODE_UDP => STATUS_UDP,
+                        _ => unimplemented!(),
+                    };
+                    s.regs[regs::STATUS as usize] = status;
+                    s.set_u16(regs::TX_FREE_SIZE, BUF as u16);
+                    s.set_u16(regs::TX_DATA_WRITE_PTR, 0);
+                    s.set_u16(regs::RECVD_SIZE, 0);
+                    s.set_u16(regs::RX_DATA_READ_PTR, 0);
+                    s.rx_write = 0;
+                }
+                regs::CMD_LISTEN => self.set_status(n, STATUS_LISTEN),
+                regs::CMD_CONNECT if unreachable => {
+                    self.set_status(n, regs::STATUS_CLOSED);
+                    self.interrupt(n, regs::INTR_TIMEOUT);
+                }
+                regs::CMD_CONNECT => {
+                    self.set_status(n, regs::STATUS_ESTABLISHED);
+                    self.interrupt(n, regs::INTR_CON);
+                }
+                regs::CMD_DISCON => {
+                    self.set_status(n, regs::STATUS_CLOSED);
+                    self.interrupt(n, regs::INTR_DISCON);
+                }
+                regs::CMD_CLOSE => self.set_status(n, regs::STATUS_CLOSED),
+                regs::CMD_SEND if unreachable => self.interrupt(n, regs::INTR_TIMEOUT),
+                regs::CMD_SEND => {
+                    // Everything written is sent and acknowledged at once.
+                    let mut data = Vec::new();
+                    let mut ptr = s.tx_read();
+                    while ptr != s.u16(regs::TX_DATA_WRITE_PTR) {
+                        data.push(s.tx[ptr as usize % BUF]);
+                        ptr = ptr.wrapping_add(1);
+                    }
+                    s.set_tx_read(ptr);
+                    let dest = s.dest();
+                    s.sent.push((dest, data));
+                    self.interrupt(n, regs::INTR_SEND_OK);
+                }
+                regs::CMD_RECV => s.push_rx(&[]),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn read(&mut self, block: u8, addr: u16, data: &mut [u8]) {
+            let n = (block >> 2) as usize;
+            for (i, b) in data.iter_mut().enumerate() {
+                let addr = addr.wrapping_add(i as u16) as usize;
+                *b = match block & 0x03 {
+                    0 if block == 0 => self.common[addr],
+                    1 => self.sockets[n].regs[addr],
+                    2 => self.sockets[n].tx[addr % BUF],
+                    3 => self.sockets[n].rx[addr % BUF],
+                    _ => unimplemented!(),
+                };
+            }
+        }
+
+        fn write(&mut self, block: u8, addr: u16, data: &[u8]) {
+            let n = (block >> 2) as usize;
+            match block & 0x03 {
+                // Software reset.
+                0 if addr == 0 && data[0] & 0x80 != 0 => {
+                    self.common = [0; 0x40];
+                    for s in &mut self.sockets {
+                        *s = SocketModel::new();
+                    }
+                }
+                0 => self.common[addr as usize..][..data.len()].copy_from_slice(data),
+                1 if addr == regs::COMMAND => self.command(n, data[0]),
+                1 if addr == regs::INTR => self.sockets[n].regs[regs::INTR as usize] &= !data[0],
+                1 => self.sockets[n].regs[addr as usize..][..data.len()].copy_from_slice(data),
+                2 => {
+                    for (i, &b) in data.iter().enumerate() {
+                        self.sockets[n].tx[(addr as usize + i) % BUF] = b;
+                    }
+                }
+                _ => unimplemented!(),
+            }
+        }
+
+        /// A remote host connects to listening socket `n`.
+        fn peer_connect(&mut self, n: usize, remote: SocketAddrV4) {
+            assert_eq!(self.status(n), STATUS_LISTEN);
+            let s = &mut self.sockets[n];
+            s.regs[regs::DEST_IP as usize..][..4].copy_from_slice(&remote.ip().octets());
+            s.set_u16(regs::DEST_PORT, remote.port());
+            self.set_status(n, regs::STATUS_ESTABLISHED);
+            self.interrupt(n, regs::INTR_CON);
+        }
+
+        /// The remote host sends TCP data to socket `n`.
+        fn peer_send(&mut self, n: usize, data: &[u8]) {
+            self.sockets[n].push_rx(data);
+            self.interrupt(n, INTR_RECV);
+        }
+
+        /// A remote host sends a datagram to UDP socket `n`.
+        fn peer_send_to(&mut self, n: usize, remote: SocketAddrV4, data: &[u8]) {
+            let s = &mut self.sockets[n];
+            s.push_rx(&remote.ip().octets());
+            s.push_rx(&remote.port().to_be_bytes());
+            s.push_rx(&(data.len() as u16).to_be_bytes());
+            s.push_rx(data);
+            self.interrupt(n, INTR_RECV);
+        }
+
+        /// The remote host closes the TCP connection of socket `n`.
+        fn peer_close(&mut self, n: usize) {
+            self.set_status(n, regs::STATUS_CLOSE_WAIT);
+            self.interrupt(n, regs::INTR_DISCON);
+        }
+
+        fn take_sent(&mut self, n: usize) -> Vec<(SocketAddrV4, Vec<u8>)> {
+            core::mem::take(&mut self.sockets[n].sent)
+        }
+    }
+
+    impl SocketModel {
+        /// The TX read pointer isn't used by the driver, keep it in the unused register space.
+        fn tx_read(&self) -> u16 {
+            self.u16(0x22)
+        }
+
+        fn set_tx_read(&mut self, ptr: u16) {
+            self.set_u16(0x22, ptr)
+        }
+    }
+
+    struct ModelSpi(Rc<RefCell<Model>>);
+
+    impl embedded_hal_async::spi::ErrorType for ModelSpi {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for ModelSpi {
+        async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            let [Operation::Write(addr), Operation::Write(control), data] = operations else {
+                panic!("unexpected transaction");
+            };
+            let addr = u16::from_be_bytes([addr[0], addr[1]]);
+            let block = control[0] >> 3;
+            let mut model = self.0.borrow_mut();
+            match data {
+                Operation::TransferInPlace(data) if control[0] & 0x04 == 0 => model.read(block, addr, data),
+                Operation::Write(data) if control[0] & 0x04 != 0 => model.write(block, addr, data),
+                _ => panic!("unexpected transaction"),
+            }
+            Ok(())
+        }
+    }
+
+    struct ModelInt(Rc<RefCell<Model>>);
+
+    impl embedded_hal::digital::ErrorType for ModelInt {
+        type Error = Infallible;
+    }
+
+    impl Wait for ModelInt {
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            poll_fn(|cx| {
+                let mut model = self.0.borrow_mut();
+                if model.int_low() {
+                    Poll::Ready(Ok(()))
+                } else {
+                    model.int_waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            unimplemented!()
+        }
+    }
+
+    type Stack = SocketStack<W5500, ModelSpi>;
+
+    const LOCAL_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 10);
+    const REMOTE: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 2), 80);
+
+    async fn new_stack(model: &Rc<RefCell<Model>>) -> Stack {
+        let config = Config::new(
+            [0x02, 0, 0, 0, 0, 1],
+            LOCAL_IP,
+            Ipv4Addr::new(255, 255, 255, 0),
+            Ipv4Addr::new(192, 168, 1, 1),
+        );
+        SocketStack::new(ModelSpi(model.clone()), config).await.unwrap()
+    }
+
+    /// Run `test` while the stack's runner is running.
+    async fn run(stack: &Stack, model: &Rc<RefCell<Model>>, test: impl Future<Output = ()>) {
+        match select(stack.run(ModelInt(model.clone())), test).await {
+            Either::First(_) => unreachable!(),
+            Either::Second(()) => {}
+        }
+    }
+
+    async fn wait_until(f: impl Fn() -> bool) {
+        while !f() {
+            yield_now().await;
+        }
+    }
+
+    #[test]
+    fn init() {
+        let model = Model::new();
+        block_on(async {
+            new_stack(&model).await;
+        });
+
+        let model = model.borrow();
+        assert_eq!(model.common[0x09..0x0F], [0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(model.common[0x0F..0x13], [192, 168, 1, 10]);
+        assert_eq!(model.common[0x05..0x09], [255, 255, 255, 0]);
+        assert_eq!(model.common[0x01..0x05], [192, 168, 1, 1]);
+        assert_eq!(model.common[0x18], 0xFF);
+        for s in &model.sockets {
+            assert_eq!(s.regs[0x1E], 2);
+            assert_eq!(s.regs[0x1F], 2);
+        }
+    }
+
+    #[test]
+    fn tcp_client() {
+        let model = Model::new();
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                let mut socket = stack.connect(REMOTE).await.unwrap();
+                assert_eq!(socket.n, 0);
+                assert_eq!(model.borrow().sockets[0].dest(), REMOTE);
+                assert_eq!(model.borrow().sockets[0].u16(regs::PORT), EPHEMERAL_PORT_START);
+
+                socket.write_all(b"hello").await.unwrap();
+                socket.flush().await.unwrap();
+                assert_eq!(model.borrow_mut().take_sent(0), [(REMOTE, b"hello".to_vec())]);
+
+                model.borrow_mut().peer_send(0, b"world");
+                let mut buf = [0; 16];
+                let n = socket.read(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"world");
+
+                model.borrow_mut().peer_close(0);
+                assert_eq!(socket.read(&mut buf).await, Ok(0));
+
+                drop(socket);
+                wait_until(|| stack.with(|s| s.allocated) == 0).await;
+                assert_eq!(model.borrow().status(0), regs::STATUS_CLOSED);
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn tcp_server() {
+        let model = Model::new();
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                let (socket, ()) = embassy_futures::join::join(stack.accept(8080), async {
+                    wait_until(|| model.borrow().status(0) == STATUS_LISTEN).await;
+                    model.borrow_mut().peer_connect(0, REMOTE);
+                })
+                .await;
+                let mut socket = socket.unwrap();
+                assert_eq!(model.borrow().sockets[0].u16(regs::PORT), 8080);
+                assert_eq!(socket.remote_endpoint().await, Ok(REMOTE));
+
+                model.borrow_mut().peer_send(0, b"ping");
+                let mut buf = [0; 4];
+                socket.read_exact(&mut buf).await.unwrap();
+                socket.write_all(&buf).await.unwrap();
+                assert_eq!(model.borrow_mut().take_sent(0), [(REMOTE, b"ping".to_vec())]);
+
+                socket.close().await.unwrap();
+                assert_eq!(socket.write(b"late").await, Err(Error::ConnectionReset));
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn tcp_connect_timeout() {
+        let model = Model::new();
+        model.borrow_mut().unreachable.push(*REMOTE.ip());
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                assert_eq!(
+                    TcpConnect::connect(&stack, SocketAddr::V4(REMOTE)).await.err(),
+                    Some(Error::TimedOut)
+                );
+                wait_until(|| stack.with(|s| s.allocated) == 0).await;
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn udp() {
+        let model = Model::new();
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                let mut socket = stack.bind_udp(5000).await.unwrap();
+                assert_eq!(socket.local_endpoint(), SocketAddrV4::new(LOCAL_IP, 5000));
+
+                socket.send_to(b"request", REMOTE).await.unwrap();
+                assert_eq!(model.borrow_mut().take_sent(0), [(REMOTE, b"request".to_vec())]);
+
+                model.borrow_mut().peer_send_to(0, REMOTE, b"response");
+                model.borrow_mut().peer_send_to(0, REMOTE, b"too long response");
+                let mut buf = [0; 8];
+                assert_eq!(socket.recv_from(&mut buf).await, Ok((8, REMOTE)));
+                assert_eq!(&buf, b"response");
+                assert_eq!(socket.recv_from(&mut buf).await, Err(Error::Truncated));
+                assert_eq!(&buf, b"too long");
+                assert_eq!(model.borrow().sockets[0].u16(regs::RECVD_SIZE), 0);
+
+                assert_eq!(socket.send_to(&[0; BUF + 1], REMOTE).await, Err(Error::PacketTooLarge));
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn udp_connected() {
+        let model = Model::new();
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                let (local, mut socket) = UdpStack::connect(&&stack, SocketAddr::V4(REMOTE)).await.unwrap();
+                assert_eq!(local, SocketAddr::V4(SocketAddrV4::new(LOCAL_IP, EPHEMERAL_PORT_START)));
+
+                socket.send(b"hello").await.unwrap();
+                assert_eq!(model.borrow_mut().take_sent(0), [(REMOTE, b"hello".to_vec())]);
+
+                let other = SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 3), 80);
+                model.borrow_mut().peer_send_to(0, other, b"spam");
+                model.borrow_mut().peer_send_to(0, REMOTE, b"reply");
+                let mut buf = [0; 16];
+                let n = socket.receive_into(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"reply");
+            })
+            .await;
+        });
+    }
+
+    #[test]
+    fn no_free_socket() {
+        let model = Model::new();
+        block_on(async {
+            let stack = new_stack(&model).await;
+            run(&stack, &model, async {
+                let mut sockets = Vec::new();
+                for port in 0..8 {
+                    sockets.push(stack.bind_udp(1000 + port).await.unwrap());
+                }
+                assert_eq!(stack.bind_udp(2000).await.err(), Some(Error::NoFreeSocket));
+
+                // Sockets are reused once closed.
+                sockets.remove(3);
+                wait_until(|| stack.with(|s| s.allocated) != 0xFF).await;
+                assert_eq!(stack.bind_udp(2000).await.unwrap().n, 3);
+            })
+            .await;
+        });
+    }
+}
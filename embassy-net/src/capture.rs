@@ -0,0 +1,378 @@
+//! Packet capture.
+//!
+//! A [`Capture`] hook installed with [`Stack::set_capture`](crate::Stack::set_capture) is called
+//! with every frame received or transmitted by the stack's device.
+//!
+//! Two hooks producing the [pcap](https://wiki.wireshark.org/Development/LibpcapFileFormat) format
+//! understood by Wireshark are provided:
+//!
+//! - [`PcapStream`] buffers records, and streams them over any [`embedded_io_async::Write`], for example
+//!   a USB CDC-ACM port or a UART.
+//! - `PcapWriter` (requires the `std` feature) writes them to any [`std::io::Write`], for example a file.
+//!
+//! Timestamps are taken from [`embassy_time::Instant::now()`], so they are relative to boot
+//! unless the time driver says otherwise.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::Instant;
+pub use smoltcp::phy::Medium;
+
+/// Direction of a captured packet.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// The packet was received by the device.
+    Rx,
+    /// The packet was transmitted by the device.
+    Tx,
+}
+
+/// A captured packet.
+#[derive(Clone, Copy, Debug)]
+pub struct Packet<'a> {
+    /// Whether the packet was received or transmitted.
+    pub direction: Direction,
+    /// When the packet was received or transmitted.
+    pub timestamp: Instant,
+    /// Medium of the device, which tells how to interpret `data`.
+    pub medium: Medium,
+    /// The raw frame.
+    pub data: &'a [u8],
+}
+
+/// Packet capture hook.
+///
+/// This is called synchronously from the stack's processing, for every frame. Implementations should
+/// be quick, and must not call back into the stack.
+pub trait Capture {
+    /// Capture a packet.
+    fn capture(&self, packet: &Packet<'_>);
+}
+
+impl<T: Capture + ?Sized> Capture for &T {
+    fn capture(&self, packet: &Packet<'_>) {
+        T::capture(self, packet)
+    }
+}
+
+/// Length of the pcap global header.
+const PCAP_HEADER_LEN: usize = 24;
+/// Length of a pcap record header.
+const PCAP_RECORD_HEADER_LEN: usize = 16;
+
+fn link_type(medium: Medium) -> u32 {
+    match medium {
+        #[cfg(feature = "medium-ethernet")]
+        Medium::Ethernet => 1, // LINKTYPE_ETHERNET
+        #[cfg(feature = "medium-ip")]
+        Medium::Ip => 101, // LINKTYPE_RAW
+        #[cfg(feature = "medium-ieee802154")]
+        Medium::Ieee802154 => 230, // LINKTYPE_IEEE802_15_4_NOFCS
+    }
+}
+
+fn pcap_header(medium: Medium, snaplen: u32) -> [u8; PCAP_HEADER_LEN] {
+    let mut buf = [0; PCAP_HEADER_LEN];
+    buf[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes()); // magic, microsecond timestamps
+    buf[4..6].copy_from_slice(&2u16.to_le_bytes()); // major version
+    buf[6..8].copy_from_slice(&4u16.to_le_bytes()); // minor version
+                                                    // 8..16: timezone offset and timestamp accuracy, always 0.
+    buf[16..20].copy_from_slice(&snaplen.to_le_bytes());
+    buf[20..24].copy_from_slice(&link_type(medium).to_le_bytes());
+    buf
+}
+
+fn pcap_record_header(timestamp: Instant, incl_len: usize, orig_len: usize) -> [u8; PCAP_RECORD_HEADER_LEN] {
+    let micros = timestamp.as_micros();
+    let mut buf = [0; PCAP_RECORD_HEADER_LEN];
+    buf[0..4].copy_from_slice(&((micros / 1_000_000) as u32).to_le_bytes());
+    buf[4..8].copy_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+    buf[8..12].copy_from_slice(&(incl_len as u32).to_le_bytes());
+    buf[12..16].copy_from_slice(&(orig_len as u32).to_le_bytes());
+    buf
+}
+
+struct StreamState {
+    medium: Option<Medium>,
+    dropped: u32,
+}
+
+/// Capture hook streaming pcap data over an [`embedded_io_async::Write`].
+///
+/// Captured packets are queued in a buffer of `N` bytes, which [`run()`](PcapStream::run) drains
+/// into the writer. Packets that don't fit in the buffer are dropped, packets longer than the
+/// buffer are truncated.
+pub struct PcapStream<M: RawMutex, const N: usize> {
+    pipe: Pipe<M, N>,
+    state: Mutex<M, RefCell<StreamState>>,
+}
+
+impl<M: RawMutex, const N: usize> PcapStream<M, N> {
+    const BUFFER_CHECK: () = assert!(
+        N > PCAP_RECORD_HEADER_LEN,
+        "PcapStream buffer must be longer than a pcap record header"
+    );
+
+    /// Create a new `PcapStream`.
+    ///
+    /// `N` must be larger than the 16-byte pcap record header, this fails to compile otherwise.
+    pub const fn new() -> Self {
+        let () = Self::BUFFER_CHECK;
+        Self {
+            pipe: Pipe::new(),
+            state: Mutex::new(RefCell::new(StreamState {
+                medium: None,
+                dropped: 0,
+            })),
+        }
+    }
+
+    /// Number of packets dropped because the buffer was full.
+    pub fn dropped(&self) -> u32 {
+        self.state.lock(|s| s.borrow().dropped)
+    }
+
+    fn snaplen() -> usize {
+        N - PCAP_RECORD_HEADER_LEN
+    }
+
+    fn push(&self, data: &[u8]) {
+        let mut data = data;
+        while !data.is_empty() {
+            // can't fail: the caller checked there is enough space, and we're the only writer.
+            let n = unwrap!(self.pipe.try_write(data).ok());
+            data = &data[n..];
+        }
+    }
+
+    /// Stream the captured packets to `writer`.
+    ///
+    /// The pcap header is written once the first packet is captured, so the stream always starts
+    /// with it. This only returns if writing fails.
+    pub async fn run<W: embedded_io_async::Write>(&self, mut writer: W) -> Result<(), W::Error> {
+        let mut buf = [0; 64];
+
+        let n = self.pipe.read(&mut buf).await;
+        // The medium is always set before the first record is pushed.
+        let medium = unwrap!(self.state.lock(|s| s.borrow().medium));
+        writer.write_all(&pcap_header(medium, Self::snaplen() as u32)).await?;
+        writer.write_all(&buf[..n]).await?;
+
+        loop {
+            let n = self.pipe.read(&mut buf).await;
+            writer.write_all(&buf[..n]).await?;
+        }
+    }
+}
+
+impl<M: RawMutex, const N: usize> Default for PcapStream<M, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: RawMutex, const N: usize> Capture for PcapStream<M, N> {
+    fn capture(&self, packet: &Packet<'_>) {
+        self.state.lock(|s| {
+            let state = &mut *s.borrow_mut();
+
+            let medium = *state.medium.get_or_insert(packet.medium);
+            let data = &packet.data[..packet.data.len().min(Self::snaplen())];
+            if medium != packet.medium || self.pipe.free_capacity() < PCAP_RECORD_HEADER_LEN + data.len() {
+                state.dropped = state.dropped.wrapping_add(1);
+            } else {
+                self.push(&pcap_record_header(packet.timestamp, data.len(), packet.data.len()));
+                self.push(data);
+            }
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::std_writer::PcapWriter;
+
+#[cfg(feature = "std")]
+mod std_writer {
+    use std::io::Write;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Maximum length of a captured packet.
+    const SNAPLEN: usize = 65535;
+
+    struct State<W> {
+        writer: W,
+        medium: Option<Medium>,
+        failed: bool,
+    }
+
+    /// Capture hook writing pcap data to a [`std::io::Write`], such as a file.
+    pub struct PcapWriter<W: Write> {
+        state: Mutex<State<W>>,
+    }
+
+    impl<W: Write> PcapWriter<W> {
+        /// Create a new `PcapWriter`.
+        ///
+        /// The pcap header is written once the first packet is captured.
+        pub fn new(writer: W) -> Self {
+            Self {
+                state: Mutex::new(State {
+                    writer,
+                    medium: None,
+                    failed: false,
+                }),
+            }
+        }
+
+        /// Flush the underlying writer.
+        pub fn flush(&self) -> std::io::Result<()> {
+            self.state.lock().unwrap().writer.flush()
+        }
+
+        /// Get back the underlying writer.
+        pub fn into_inner(self) -> W {
+            self.state.into_inner().unwrap().writer
+        }
+
+        fn write(state: &mut State<W>, packet: &Packet<'_>) -> std::io::Result<()> {
+            if state.medium.is_none() {
+                state.writer.write_all(&pcap_header(packet.medium, SNAPLEN as u32))?;
+                state.medium = Some(packet.medium);
+            }
+            if state.medium != Some(packet.medium) {
+                return Ok(());
+            }
+
+            let data = &packet.data[..packet.data.len().min(SNAPLEN)];
+            state
+                .writer
+                .write_all(&pcap_record_header(packet.timestamp, data.len(), packet.data.len()))?;
+            state.writer.write_all(data)
+        }
+    }
+
+    impl<W: Write> Capture for PcapWriter<W> {
+        fn capture(&self, packet: &Packet<'_>) {
+            let state = &mut *self.state.lock().unwrap();
+            if state.failed {
+                return;
+            }
+            if Self::write(state, packet).is_err() {
+                warn!("pcap write failed, stopping capture");
+                state.failed = true;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "medium-ethernet"))]
+mod tests {
+    extern crate std;
+
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn header() {
+        let header = pcap_header(Medium::Ethernet, 1500);
+        assert_eq!(
+            header,
+            [
+                0xd4, 0xc3, 0xb2, 0xa1, // magic
+                2, 0, 4, 0, // version 2.4
+                0, 0, 0, 0, 0, 0, 0, 0, // timezone, accuracy
+                0xdc, 0x05, 0, 0, // snaplen
+                1, 0, 0, 0, // LINKTYPE_ETHERNET
+            ]
+        );
+    }
+
+    #[test]
+    fn record_header() {
+        let header = pcap_record_header(Instant::from_micros(3_000_042), 60, 1514);
+        assert_eq!(
+            header,
+            [
+                3, 0, 0, 0, // seconds
+                42, 0, 0, 0, // microseconds
+                60, 0, 0, 0, // captured length
+                0xea, 0x05, 0, 0, // original length
+            ]
+        );
+    }
+
+    fn packet(data: &[u8]) -> Packet<'_> {
+        Packet {
+            direction: Direction::Rx,
+            timestamp: Instant::from_micros(1_500_000),
+            medium: Medium::Ethernet,
+            data,
+        }
+    }
+
+    fn read_all<const N: usize>(stream: &PcapStream<NoopRawMutex, N>) -> std::vec::Vec<u8> {
+        let mut out = std::vec::Vec::new();
+        let mut buf = [0; N];
+        while let Ok(n) = stream.pipe.try_read(&mut buf) {
+            out.extend_from_slice(&buf[..n]);
+        }
+        out
+    }
+
+    #[test]
+    fn stream_records() {
+        let stream = PcapStream::<NoopRawMutex, 64>::new();
+        stream.capture(&packet(&[1, 2, 3, 4]));
+        stream.capture(&packet(&[5, 6]));
+
+        let out = read_all(&stream);
+        assert_eq!(out.len(), 2 * PCAP_RECORD_HEADER_LEN + 6);
+        assert_eq!(out[..16], pcap_record_header(Instant::from_micros(1_500_000), 4, 4));
+        assert_eq!(out[16..20], [1, 2, 3, 4]);
+        assert_eq!(out[20..36], pcap_record_header(Instant::from_micros(1_500_000), 2, 2));
+        assert_eq!(out[36..], [5, 6]);
+        assert_eq!(stream.dropped(), 0);
+    }
+
+    #[test]
+    fn stream_truncates_to_snaplen() {
+        let stream = PcapStream::<NoopRawMutex, 64>::new();
+        stream.capture(&packet(&[5; 100]));
+
+        let out = read_all(&stream);
+        assert_eq!(out[..16], pcap_record_header(Instant::from_micros(1_500_000), 48, 100));
+        assert_eq!(out[16..], [5; 48]);
+    }
+
+    #[test]
+    fn stream_drops_when_full() {
+        let stream = PcapStream::<NoopRawMutex, 64>::new();
+        stream.capture(&packet(&[0; 40]));
+        stream.capture(&packet(&[0; 40]));
+        assert_eq!(stream.dropped(), 1);
+        assert_eq!(stream.pipe.len(), PCAP_RECORD_HEADER_LEN + 40);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writer() {
+        let writer = PcapWriter::new(std::vec::Vec::new());
+        writer.capture(&packet(&[1, 2, 3]));
+        let out = writer.into_inner();
+
+        assert_eq!(out.len(), PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN + 3);
+        assert_eq!(out[..PCAP_HEADER_LEN], pcap_header(Medium::Ethernet, 65535));
+        assert_eq!(
+            out[PCAP_HEADER_LEN..][..PCAP_RECORD_HEADER_LEN],
+            pcap_record_header(Instant::from_micros(1_500_000), 3, 3)
+        );
+        assert_eq!(out[PCAP_HEADER_LEN + PCAP_RECORD_HEADER_LEN..], [1, 2, 3]);
+    }
+}
@@ -1,9 +1,18 @@
 use core::task::Context;
 
 use embassy_net_driver::{Capabilities, Checksum, Driver, RxToken, TxToken};
-use smoltcp::phy::{self, Medium};
+use smoltcp::phy::{self, Medium, PacketMeta};
 use smoltcp::time::Instant;
 
+#[cfg(feature = "packet-capture")]
+use crate::capture::{Capture, Direction, Packet};
+#[cfg(feature = "proto-ipv6-fragmentation")]
+use crate::reassembly::{Reassembler, Received};
+#[cfg(feature = "packet-timestamps")]
+use crate::timestamp::Timestamps;
+#[cfg(feature = "packet-timestamps")]
+use crate::Timestamp;
+
 pub(crate) struct DriverAdapter<'d, 'c, T>
 where
     T: Driver,
@@ -12,24 +21,55 @@ where
     pub cx: Option<&'d mut Context<'c>>,
     pub inner: &'d mut T,
     pub medium: Medium,
+    #[cfg(feature = "packet-capture")]
+    pub capture: Option<&'d dyn Capture>,
+    #[cfg(feature = "packet-timestamps")]
+    pub timestamps: &'d mut Timestamps,
+    #[cfg(feature = "proto-ipv6-fragmentation")]
+    pub ipv6_reassembly: &'d mut Reassembler,
 }
 
 impl<'d, 'c, T> phy::Device for DriverAdapter<'d, 'c, T>
 where
     T: Driver,
 {
-    type RxToken<'a> = RxTokenAdapter<T::RxToken<'a>> where Self: 'a;
-    type TxToken<'a> = TxTokenAdapter<T::TxToken<'a>> where Self: 'a;
+    type RxToken<'a> = RxTokenAdapter<'a, T::RxToken<'a>> where Self: 'a;
+    type TxToken<'a> = TxTokenAdapter<'a, T::TxToken<'a>> where Self: 'a;
 
-    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        self.inner
-            .receive(unwrap!(self.cx.as_deref_mut()))
-            .map(|(rx, tx)| (RxTokenAdapter(rx), TxTokenAdapter(tx)))
+    #[cfg_attr(not(feature = "proto-ipv6-fragmentation"), allow(unused_variables))]
+    fn receive(&mut self, now: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let tap = self.tap();
+        let (rx, tx) = self.inner.receive(unwrap!(self.cx.as_deref_mut()))?;
+
+        #[allow(unused_mut)]
+        let mut meta = PacketMeta::default();
+        #[cfg(feature = "packet-timestamps")]
+        let timestamp = match rx.timestamp() {
+            Some(timestamp) => {
+                meta.id = self.timestamps.next_id();
+                Some((&mut *self.timestamps, self.medium, timestamp))
+            }
+            None => None,
+        };
+
+        let rx = RxTokenAdapter {
+            inner: rx,
+            tap,
+            meta,
+            #[cfg(feature = "packet-timestamps")]
+            timestamp,
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            ipv6_reassembly: (&mut *self.ipv6_reassembly, now),
+        };
+        Some((rx, TxTokenAdapter(tx, tap)))
     }
 
     /// Construct a transmit token.
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
-        self.inner.transmit(unwrap!(self.cx.as_deref_mut())).map(TxTokenAdapter)
+        let tap = self.tap();
+        self.inner
+            .transmit(unwrap!(self.cx.as_deref_mut()))
+            .map(|tx| TxTokenAdapter(tx, tap))
     }
 
     /// Get a description of device capabilities.
@@ -64,11 +104,76 @@ where
     }
 }
 
-pub(crate) struct RxTokenAdapter<T>(T)
+impl<'d, 'c, T> DriverAdapter<'d, 'c, T>
+where
+    T: Driver,
+{
+    fn tap(&self) -> Tap<'d> {
+        Tap {
+            #[cfg(feature = "packet-capture")]
+            capture: self.capture,
+            #[cfg(feature = "packet-capture")]
+            medium: self.medium,
+            _phantom: core::marker::PhantomData,
+        }
+    }
+}
+
+/// Where the token adapters report the frames they see.
+#[derive(Clone, Copy)]
+pub(crate) struct Tap<'d> {
+    #[cfg(feature = "packet-capture")]
+    capture: Option<&'d dyn Capture>,
+    #[cfg(feature = "packet-capture")]
+    medium: Medium,
+    _phantom: core::marker::PhantomData<&'d ()>,
+}
+
+impl<'d> Tap<'d> {
+    #[allow(unused_variables)]
+    fn rx(&self, buf: &[u8]) {
+        #[cfg(feature = "packet-trace")]
+        trace!("rx: {:?}", buf);
+        #[cfg(feature = "packet-capture")]
+        self.capture(Direction::Rx, buf);
+    }
+
+    #[allow(unused_variables)]
+    fn tx(&self, buf: &[u8]) {
+        #[cfg(feature = "packet-trace")]
+        trace!("tx: {:?}", buf);
+        #[cfg(feature = "packet-capture")]
+        self.capture(Direction::Tx, buf);
+    }
+
+    #[cfg(feature = "packet-capture")]
+    fn capture(&self, direction: Direction, data: &[u8]) {
+        if let Some(capture) = self.capture {
+            capture.capture(&Packet {
+                direction,
+                timestamp: embassy_time::Instant::now(),
+                medium: self.medium,
+                data,
+            });
+        }
+    }
+}
+
+pub(crate) struct RxTokenAdapter<'d, T>
 where
-    T: RxToken;
+    T: RxToken,
+{
+    inner: T,
+    tap: Tap<'d>,
+    meta: PacketMeta,
+    /// Where to record the timestamp of the packet, tagged with the id in `meta`.
+    #[cfg(feature = "packet-timestamps")]
+    timestamp: Option<(&'d mut Timestamps, Medium, Timestamp)>,
+    #[cfg(feature = "proto-ipv6-fragmentation")]
+    ipv6_reassembly: (&'d mut Reassembler, Instant),
+}
 
-impl<T> phy::RxToken for RxTokenAdapter<T>
+impl<'d, T> phy::RxToken for RxTokenAdapter<'d, T>
 where
     T: RxToken,
 {
@@ -76,19 +181,38 @@ where
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        self.0.consume(|buf| {
-            #[cfg(feature = "packet-trace")]
-            trace!("rx: {:?}", buf);
+        let tap = self.tap;
+        #[cfg(feature = "packet-timestamps")]
+        let (timestamp, id) = (self.timestamp, self.meta.id);
+        #[cfg(feature = "proto-ipv6-fragmentation")]
+        let (reassembler, now) = self.ipv6_reassembly;
+        self.inner.consume(|buf| {
+            tap.rx(buf);
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            let buf = match reassembler.receive(buf, now) {
+                Received::NotFragment => buf,
+                // smoltcp still has to be given a frame, an empty one is dropped right away.
+                Received::Consumed => return f(&mut []),
+                Received::Complete(packet) => packet,
+            };
+            #[cfg(feature = "packet-timestamps")]
+            if let Some((timestamps, medium, timestamp)) = timestamp {
+                timestamps.push_rx(medium, buf, id, timestamp);
+            }
             f(buf)
         })
     }
+
+    fn meta(&self) -> PacketMeta {
+        self.meta
+    }
 }
 
-pub(crate) struct TxTokenAdapter<T>(T)
+pub(crate) struct TxTokenAdapter<'d, T>(T, Tap<'d>)
 where
     T: TxToken;
 
-impl<T> phy::TxToken for TxTokenAdapter<T>
+impl<'d, T> phy::TxToken for TxTokenAdapter<'d, T>
 where
     T: TxToken,
 {
@@ -96,11 +220,18 @@ where
     where
         F: FnOnce(&mut [u8]) -> R,
     {
+        let tap = self.1;
         self.0.consume(len, |buf| {
             let r = f(buf);
-            #[cfg(feature = "packet-trace")]
-            trace!("tx: {:?}", buf);
+            tap.tx(buf);
             r
         })
     }
+
+    #[cfg(feature = "packet-timestamps")]
+    fn set_meta(&mut self, meta: PacketMeta) {
+        if meta.id != 0 {
+            self.0.request_timestamp(meta.id);
+        }
+    }
 }
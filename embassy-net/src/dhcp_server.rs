@@ -0,0 +1,795 @@
+//! DHCPv4 server.
+//!
+//! A small DHCPv4 server running on top of a [`UdpSocket`], intended for devices that
+//! act as the "router" of a tiny network, such as a WiFi access point or a USB Ethernet gadget.
+//!
+//! The stack the server runs on must have a static IPv4 configuration with an address
+//! in the subnet served.
+
+use core::cell::RefCell;
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use smoltcp::wire::{DhcpMessageType, DhcpPacket, DhcpRepr};
+pub use smoltcp::wire::{DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+
+use crate::udp::{BindError, UdpSocket};
+use crate::{EthernetAddress, IpEndpoint, Ipv4Address, Ipv4Cidr};
+
+/// Maximum size of a DHCP packet handled by the server.
+///
+/// This is the minimum size every DHCP client must be able to receive, per RFC 2131.
+const MAX_PACKET_LEN: usize = 576;
+
+/// Error returned by the lease table management methods of [`DhcpServer`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The lease table has no free entry left.
+    TableFull,
+    /// The address is already leased or reserved to another client.
+    AddressInUse,
+    /// The address is not in the served subnet.
+    InvalidAddress,
+}
+
+/// DHCP server configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Address of the server, and the subnet served.
+    ///
+    /// This should match the static IPv4 configuration of the stack.
+    pub address: Ipv4Cidr,
+    /// First address of the dynamic pool.
+    pub pool_start: Ipv4Address,
+    /// Last address of the dynamic pool, inclusive.
+    pub pool_end: Ipv4Address,
+    /// Lease duration handed out to clients.
+    pub lease_duration: Duration,
+    /// How long an offered address is held for a client before it can be offered to another one.
+    pub offer_duration: Duration,
+    /// Default gateway handed out to clients.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers handed out to clients.
+    pub dns_servers: Vec<Ipv4Address, 3>,
+    /// Server port. This is almost always 67. Do not change unless you know what you're doing.
+    pub server_port: u16,
+    /// Client port. This is almost always 68. Do not change unless you know what you're doing.
+    pub client_port: u16,
+}
+
+impl Config {
+    /// Create a new configuration serving `pool_start..=pool_end` from the server at `address`.
+    ///
+    /// The server address is also handed out as the default gateway.
+    pub fn new(address: Ipv4Cidr, pool_start: Ipv4Address, pool_end: Ipv4Address) -> Self {
+        Self {
+            address,
+            pool_start,
+            pool_end,
+            lease_duration: Duration::from_secs(24 * 60 * 60),
+            offer_duration: Duration::from_secs(60),
+            router: Some(address.address()),
+            dns_servers: Vec::new(),
+            server_port: DHCP_SERVER_PORT,
+            client_port: DHCP_CLIENT_PORT,
+        }
+    }
+}
+
+/// State of a [`Lease`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LeaseState {
+    /// The address is reserved for the client, but the client hasn't requested it yet.
+    Reserved,
+    /// The address has been offered to the client, which hasn't confirmed it yet.
+    Offered,
+    /// The address is bound to the client.
+    Bound,
+    /// The client reported the address as already in use on the network.
+    Declined,
+}
+
+/// An entry of the lease table.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Lease {
+    /// Hardware address of the client.
+    pub mac: EthernetAddress,
+    /// Address leased to the client.
+    pub address: Ipv4Address,
+    /// State of the lease.
+    pub state: LeaseState,
+    /// When the lease expires. `None` if it never does.
+    pub expires: Option<Instant>,
+    /// Whether this is a static reservation, which is kept when the lease expires or is released.
+    pub is_static: bool,
+}
+
+impl Lease {
+    /// An empty lease table entry.
+    pub const EMPTY: Option<Lease> = None;
+
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.expires, Some(t) if t <= now)
+    }
+
+    /// Whether the entry currently holds its address, i.e. the address can't be given to another client.
+    fn is_active(&self, now: Instant) -> bool {
+        self.is_static || !self.is_expired(now)
+    }
+
+    fn expire(&mut self) {
+        self.state = LeaseState::Reserved;
+        self.expires = None;
+    }
+}
+
+/// A DHCPv4 server.
+///
+/// Leases are kept in a table provided by the user, which also holds the static reservations.
+/// Its size bounds the amount of clients that can be served at the same time.
+pub struct DhcpServer<'a> {
+    socket: UdpSocket<'a>,
+    table: LeaseTable<'a>,
+}
+
+impl<'a> DhcpServer<'a> {
+    /// Create a new DHCP server using the provided socket and lease table.
+    ///
+    /// The socket is bound to the server port.
+    pub fn new(mut socket: UdpSocket<'a>, config: Config, leases: &'a mut [Option<Lease>]) -> Result<Self, BindError> {
+        socket.bind(config.server_port)?;
+
+        Ok(Self {
+            socket,
+            table: LeaseTable::new(config, leases),
+        })
+    }
+
+    /// Get the server configuration.
+    pub fn config(&self) -> &Config {
+        &self.table.config
+    }
+
+    /// Reserve `address` for the client with hardware address `mac`.
+    ///
+    /// The reserved address does not need to be in the dynamic pool, but must be in the served subnet.
+    /// Any existing lease of the client is replaced.
+    pub fn add_reservation(&self, mac: EthernetAddress, address: Ipv4Address) -> Result<(), Error> {
+        self.table.add_reservation(mac, address, Instant::now())
+    }
+
+    /// Remove the reservation of the client with hardware address `mac`.
+    ///
+    /// Returns whether there was a reservation. An active lease of the client is kept until it expires.
+    pub fn remove_reservation(&self, mac: EthernetAddress) -> bool {
+        self.table.remove_reservation(mac)
+    }
+
+    /// Get the lease of the client with hardware address `mac`.
+    pub fn lease_for_mac(&self, mac: EthernetAddress) -> Option<Lease> {
+        self.table.lease_for_mac(mac)
+    }
+
+    /// Get the lease of `address`.
+    pub fn lease_for_address(&self, address: Ipv4Address) -> Option<Lease> {
+        self.table.lease_for_address(address, Instant::now())
+    }
+
+    /// Call `f` for each entry of the lease table, including reservations and expired leases
+    /// that haven't been reclaimed yet.
+    pub fn for_each_lease(&self, f: impl FnMut(&Lease)) {
+        self.table.leases.borrow().iter().flatten().for_each(f)
+    }
+
+    /// Run the server.
+    ///
+    /// This handles DHCP requests forever. You must call this in a background task.
+    pub async fn run(&self) -> ! {
+        let mut rx_buf = [0; MAX_PACKET_LEN];
+        let mut tx_buf = [0; MAX_PACKET_LEN];
+
+        loop {
+            let (n, _meta) = match self.socket.recv_from(&mut rx_buf).await {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("dhcp server: receive failed: {:?}", e);
+                    continue;
+                }
+            };
+
+            let Some((len, dest)) = self.table.handle(&rx_buf[..n], &mut tx_buf, Instant::now()) else {
+                continue;
+            };
+            if let Err(e) = self.socket.send_to(&tx_buf[..len], dest).await {
+                warn!("dhcp server: send failed: {:?}", e);
+            }
+        }
+    }
+}
+
+/// The protocol state of the server: its configuration and the lease table.
+struct LeaseTable<'a> {
+    config: Config,
+    leases: RefCell<&'a mut [Option<Lease>]>,
+}
+
+impl<'a> LeaseTable<'a> {
+    fn new(config: Config, leases: &'a mut [Option<Lease>]) -> Self {
+        leases.fill(None);
+        Self {
+            config,
+            leases: RefCell::new(leases),
+        }
+    }
+
+    fn add_reservation(&self, mac: EthernetAddress, address: Ipv4Address, now: Instant) -> Result<(), Error> {
+        if !self.config.address.contains_addr(&address) || address == self.config.address.address() {
+            return Err(Error::InvalidAddress);
+        }
+
+        let mut leases = self.leases.borrow_mut();
+        if leases
+            .iter()
+            .flatten()
+            .any(|l| l.address == address && l.mac != mac && l.is_active(now))
+        {
+            return Err(Error::AddressInUse);
+        }
+
+        let lease = Lease {
+            mac,
+            address,
+            state: LeaseState::Reserved,
+            expires: None,
+            is_static: true,
+        };
+        let slot = Self::slot_for(&mut leases, now, |l| l.mac == mac || l.address == address);
+        match slot {
+            Some(slot) => {
+                *slot = Some(lease);
+                Ok(())
+            }
+            None => Err(Error::TableFull),
+        }
+    }
+
+    fn remove_reservation(&self, mac: EthernetAddress) -> bool {
+        let mut leases = self.leases.borrow_mut();
+        for slot in leases.iter_mut() {
+            if let Some(l) = slot {
+                if l.mac == mac && l.is_static {
+                    l.is_static = false;
+                    if l.expires.is_none() {
+                        *slot = None;
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn lease_for_mac(&self, mac: EthernetAddress) -> Option<Lease> {
+        self.leases.borrow().iter().flatten().find(|l| l.mac == mac).copied()
+    }
+
+    fn lease_for_address(&self, address: Ipv4Address, now: Instant) -> Option<Lease> {
+        self.leases
+            .borrow()
+            .iter()
+            .flatten()
+            .find(|l| l.address == address && l.is_active(now))
+            .copied()
+    }
+
+    /// Process a request, write the reply to `tx_buf`, if any.
+    ///
+    /// Returns the reply length and its destination.
+    fn handle(&self, rx_buf: &[u8], tx_buf: &mut [u8], now: Instant) -> Option<(usize, IpEndpoint)> {
+        let packet = DhcpPacket::new_checked(rx_buf).ok()?;
+        let request = match DhcpRepr::parse(&packet) {
+            Ok(r) => r,
+            Err(_) => {
+                debug!("dhcp server: invalid packet");
+                return None;
+            }
+        };
+
+        let server_ip = self.config.address.address();
+        let mac = request.client_hardware_address;
+
+        let (message_type, your_ip) = match request.message_type {
+            DhcpMessageType::Discover => {
+                let address = self.allocate(mac, request.requested_ip, now)?;
+                debug!("dhcp server: offering {:?} to {:?}", address, mac);
+                self.update(mac, address, LeaseState::Offered, now + self.config.offer_duration, now);
+                (DhcpMessageType::Offer, address)
+            }
+            DhcpMessageType::Request => {
+                let requested = request.requested_ip.or(match request.client_ip {
+                    Ipv4Address::UNSPECIFIED => None,
+                    ip => Some(ip),
+                });
+
+                match request.server_identifier {
+                    Some(server_id) if server_id != server_ip => {
+                        // The client picked another server, forget our offer.
+                        self.release(mac, LeaseState::Offered);
+                        return None;
+                    }
+                    Some(_) => {}
+                    // INIT-REBOOT, RENEWING or REBINDING: addresses on another network are NAKed,
+                    // but the request must be ignored if we have no record of the client (RFC 2131 4.3.2).
+                    None => {
+                        let on_network = requested.is_some_and(|ip| self.config.address.contains_addr(&ip));
+                        if on_network && !self.has_record(mac) {
+                            debug!(
+                                "dhcp server: ignoring request of {:?} from unknown {:?}",
+                                requested, mac
+                            );
+                            return None;
+                        }
+                    }
+                }
+
+                match requested.and_then(|ip| self.allocate(mac, Some(ip), now).filter(|a| *a == ip)) {
+                    Some(address) => {
+                        debug!("dhcp server: leasing {:?} to {:?}", address, mac);
+                        self.update(mac, address, LeaseState::Bound, now + self.config.lease_duration, now);
+                        (DhcpMessageType::Ack, address)
+                    }
+                    None => {
+                        debug!("dhcp server: rejecting request of {:?} from {:?}", requested, mac);
+                        (DhcpMessageType::Nak, Ipv4Address::UNSPECIFIED)
+                    }
+                }
+            }
+            DhcpMessageType::Release => {
+                debug!("dhcp server: {:?} released {:?}", mac, request.client_ip);
+                self.release(mac, LeaseState::Bound);
+                return None;
+            }
+            DhcpMessageType::Decline => {
+                let address = request.requested_ip?;
+                warn!("dhcp server: {:?} declined {:?}, already in use", mac, address);
+                self.decline(mac, address, now);
+                return None;
+            }
+            DhcpMessageType::Inform => (DhcpMessageType::Ack, Ipv4Address::UNSPECIFIED),
+            _ => return None,
+        };
+
+        let lease_secs = self.config.lease_duration.as_secs() as u32;
+        let is_nak = message_type == DhcpMessageType::Nak;
+        let is_lease = matches!(
+            request.message_type,
+            DhcpMessageType::Discover | DhcpMessageType::Request
+        );
+        let reply = DhcpRepr {
+            message_type,
+            transaction_id: request.transaction_id,
+            secs: 0,
+            client_hardware_address: mac,
+            client_ip: if is_nak {
+                Ipv4Address::UNSPECIFIED
+            } else {
+                request.client_ip
+            },
+            your_ip,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: self.config.router.filter(|_| !is_nak),
+            subnet_mask: (!is_nak).then(|| self.config.address.netmask()),
+            relay_agent_ip: request.relay_agent_ip,
+            broadcast: request.broadcast,
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(server_ip),
+            parameter_request_list: None,
+            dns_servers: (!is_nak && !self.config.dns_servers.is_empty()).then(|| self.config.dns_servers.clone()),
+            max_size: None,
+            lease_duration: (is_lease && !is_nak).then_some(lease_secs),
+            renew_duration: (is_lease && !is_nak).then_some(lease_secs / 2),
+            rebind_duration: (is_lease && !is_nak).then_some(lease_secs / 8 * 7),
+            additional_options: &[],
+        };
+
+        let len = reply.buffer_len();
+        if len > tx_buf.len() {
+            warn!("dhcp server: reply too long");
+            return None;
+        }
+        let mut packet = DhcpPacket::new_unchecked(&mut tx_buf[..len]);
+        if reply.emit(&mut packet).is_err() {
+            warn!("dhcp server: failed to emit reply");
+            return None;
+        }
+
+        // A client without an address can't answer ARP requests, so we can only reach it via broadcast.
+        // Naks are always broadcast, as the client might be using an address we don't consider valid.
+        let dest = if is_nak || request.client_ip == Ipv4Address::UNSPECIFIED {
+            Ipv4Address::BROADCAST
+        } else {
+            request.client_ip
+        };
+        Some((len, IpEndpoint::new(dest.into(), self.config.client_port)))
+    }
+
+    /// Whether the table holds a lease or reservation of `mac`.
+    fn has_record(&self, mac: EthernetAddress) -> bool {
+        self.leases
+            .borrow()
+            .iter()
+            .flatten()
+            .any(|l| l.mac == mac && l.state != LeaseState::Declined)
+    }
+
+    /// Pick an address for `mac`, preferring its reservation, its previous lease and then `requested`.
+    fn allocate(&self, mac: EthernetAddress, requested: Option<Ipv4Address>, now: Instant) -> Option<Ipv4Address> {
+        let leases = self.leases.borrow();
+
+        if let Some(lease) = leases
+            .iter()
+            .flatten()
+            .find(|l| l.mac == mac && l.state != LeaseState::Declined)
+        {
+            if lease.is_static || self.is_free(&leases, mac, lease.address, now) {
+                return Some(lease.address);
+            }
+        }
+
+        if let Some(ip) = requested {
+            if self.in_pool(ip) && self.is_free(&leases, mac, ip, now) {
+                return Some(ip);
+            }
+        }
+
+        let start = u32::from_be_bytes(self.config.pool_start.0);
+        let end = u32::from_be_bytes(self.config.pool_end.0);
+        let address = (start..=end)
+            .map(|a| Ipv4Address::from_bytes(&a.to_be_bytes()))
+            .filter(|a| *a != self.config.address.address())
+            .find(|a| self.is_free(&leases, mac, *a, now));
+        if address.is_none() {
+            warn!("dhcp server: pool exhausted");
+        }
+        address
+    }
+
+    fn in_pool(&self, address: Ipv4Address) -> bool {
+        let start = u32::from_be_bytes(self.config.pool_start.0);
+        let end = u32::from_be_bytes(self.config.pool_end.0);
+        let a = u32::from_be_bytes(address.0);
+        (start..=end).contains(&a) && address != self.config.address.address()
+    }
+
+    fn is_free(&self, leases: &[Option<Lease>], mac: EthernetAddress, address: Ipv4Address, now: Instant) -> bool {
+        !leases
+            .iter()
+            .flatten()
+            .any(|l| l.address == address && (l.mac != mac || l.state == LeaseState::Declined) && l.is_active(now))
+    }
+
+    /// Record a lease of `address` to `mac`, replacing any previous lease of the client.
+    fn update(&self, mac: EthernetAddress, address: Ipv4Address, state: LeaseState, expires: Instant, now: Instant) {
+        let mut leases = self.leases.borrow_mut();
+        let slot = Self::slot_for(&mut leases, now, |l| l.mac == mac && l.state != LeaseState::Declined);
+        match slot {
+            Some(slot) => {
+                let is_static = matches!(slot, Some(l) if l.is_static && l.address == address);
+                *slot = Some(Lease {
+                    mac,
+                    address,
+                    state,
+                    expires: Some(expires),
+                    is_static,
+                })
+            }
+            None => warn!("dhcp server: lease table full"),
+        }
+    }
+
+    /// Drop the lease of `mac` if it is in state `state`.
+    fn release(&self, mac: EthernetAddress, state: LeaseState) {
+        let mut leases = self.leases.borrow_mut();
+        for slot in leases.iter_mut() {
+            if let Some(l) = slot {
+                if l.mac == mac && l.state == state {
+                    if l.is_static {
+                        l.expire();
+                    } else {
+                        *slot = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Mark `address` as in use by an unknown host, so it isn't handed out for a lease duration.
+    fn decline(&self, mac: EthernetAddress, address: Ipv4Address, now: Instant) {
+        let expires = now + self.config.lease_duration;
+        let mut leases = self.leases.borrow_mut();
+        let slot = Self::slot_for(&mut leases, now, |l| l.mac == mac && l.address == address);
+        if let Some(slot) = slot {
+            *slot = Some(Lease {
+                mac,
+                address,
+                state: LeaseState::Declined,
+                expires: Some(expires),
+                is_static: false,
+            });
+        }
+    }
+
+    /// Find the table slot matching `f`, or a free one, reclaiming expired leases if needed.
+    fn slot_for(leases: &mut [Option<Lease>], now: Instant, f: impl Fn(&Lease) -> bool) -> Option<&mut Option<Lease>> {
+        let pos = leases
+            .iter()
+            .position(|l| matches!(l, Some(l) if f(l)))
+            .or_else(|| leases.iter().position(|l| l.is_none()))
+            .or_else(|| leases.iter().position(|l| matches!(l, Some(l) if !l.is_active(now))))?;
+        Some(&mut leases[pos])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const MAC: EthernetAddress = EthernetAddress([2, 0, 0, 0, 0, 1]);
+    const OTHER_MAC: EthernetAddress = EthernetAddress([2, 0, 0, 0, 0, 2]);
+
+    fn config() -> Config {
+        Config::new(
+            Ipv4Cidr::new(SERVER, 24),
+            Ipv4Address::new(192, 168, 1, 100),
+            Ipv4Address::new(192, 168, 1, 101),
+        )
+    }
+
+    fn message(message_type: DhcpMessageType, mac: EthernetAddress) -> DhcpRepr<'static> {
+        DhcpRepr {
+            message_type,
+            transaction_id: 0x1234,
+            secs: 0,
+            client_hardware_address: mac,
+            client_ip: Ipv4Address::UNSPECIFIED,
+            your_ip: Ipv4Address::UNSPECIFIED,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: None,
+            subnet_mask: None,
+            relay_agent_ip: Ipv4Address::UNSPECIFIED,
+            broadcast: false,
+            requested_ip: None,
+            client_identifier: Some(mac),
+            server_identifier: None,
+            parameter_request_list: None,
+            dns_servers: None,
+            max_size: None,
+            lease_duration: None,
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        }
+    }
+
+    /// Feed `request` to the server, return the reply type, the address offered and the destination.
+    fn handle(
+        table: &LeaseTable,
+        request: &DhcpRepr,
+        now: Instant,
+    ) -> Option<(DhcpMessageType, Ipv4Address, IpEndpoint)> {
+        let mut rx_buf = [0; MAX_PACKET_LEN];
+        let mut tx_buf = [0; MAX_PACKET_LEN];
+        let len = request.buffer_len();
+        request
+            .emit(&mut DhcpPacket::new_unchecked(&mut rx_buf[..len]))
+            .unwrap();
+
+        let (len, dest) = table.handle(&rx_buf[..len], &mut tx_buf, now)?;
+        let packet = DhcpPacket::new_checked(&tx_buf[..len]).unwrap();
+        let reply = DhcpRepr::parse(&packet).unwrap();
+        assert_eq!(reply.transaction_id, request.transaction_id);
+        assert_eq!(reply.server_identifier, Some(SERVER));
+        Some((reply.message_type, reply.your_ip, dest))
+    }
+
+    fn select(requested: Ipv4Address, mac: EthernetAddress) -> DhcpRepr<'static> {
+        DhcpRepr {
+            requested_ip: Some(requested),
+            server_identifier: Some(SERVER),
+            ..message(DhcpMessageType::Request, mac)
+        }
+    }
+
+    fn broadcast() -> IpEndpoint {
+        IpEndpoint::new(Ipv4Address::BROADCAST.into(), DHCP_CLIENT_PORT)
+    }
+
+    #[test]
+    fn discover_request() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+
+        let (ty, offered, dest) = handle(&table, &message(DhcpMessageType::Discover, MAC), now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Offer);
+        assert_eq!(offered, Ipv4Address::new(192, 168, 1, 100));
+        assert_eq!(dest, broadcast());
+        assert_eq!(table.lease_for_mac(MAC).unwrap().state, LeaseState::Offered);
+
+        // The offered address is held for the client.
+        let (_, other, _) = handle(&table, &message(DhcpMessageType::Discover, OTHER_MAC), now).unwrap();
+        assert_eq!(other, Ipv4Address::new(192, 168, 1, 101));
+
+        let (ty, leased, _) = handle(&table, &select(offered, MAC), now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Ack);
+        assert_eq!(leased, offered);
+        let lease = table.lease_for_mac(MAC).unwrap();
+        assert_eq!(lease.state, LeaseState::Bound);
+        assert_eq!(lease.expires, Some(now + table.config.lease_duration));
+    }
+
+    #[test]
+    fn discover_pool_exhausted() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+
+        for mac in [MAC, OTHER_MAC] {
+            handle(&table, &message(DhcpMessageType::Discover, mac), now).unwrap();
+        }
+        let third = EthernetAddress([2, 0, 0, 0, 0, 3]);
+        assert!(handle(&table, &message(DhcpMessageType::Discover, third), now).is_none());
+
+        // Offers expire.
+        let later = now + table.config.offer_duration;
+        let (_, offered, _) = handle(&table, &message(DhcpMessageType::Discover, third), later).unwrap();
+        assert_eq!(offered, Ipv4Address::new(192, 168, 1, 100));
+    }
+
+    #[test]
+    fn request_other_server() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+
+        let (_, offered, _) = handle(&table, &message(DhcpMessageType::Discover, MAC), now).unwrap();
+        let request = DhcpRepr {
+            server_identifier: Some(Ipv4Address::new(192, 168, 1, 2)),
+            ..select(offered, MAC)
+        };
+        assert!(handle(&table, &request, now).is_none());
+        assert_eq!(table.lease_for_mac(MAC), None);
+    }
+
+    #[test]
+    fn request_nak() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+
+        handle(&table, &select(Ipv4Address::new(192, 168, 1, 100), OTHER_MAC), now).unwrap();
+
+        // Address leased to another client.
+        let (ty, your_ip, dest) = handle(&table, &select(Ipv4Address::new(192, 168, 1, 100), MAC), now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Nak);
+        assert_eq!(your_ip, Ipv4Address::UNSPECIFIED);
+        assert_eq!(dest, broadcast());
+
+        // INIT-REBOOT on the wrong network.
+        let request = DhcpRepr {
+            requested_ip: Some(Ipv4Address::new(10, 0, 0, 5)),
+            ..message(DhcpMessageType::Request, MAC)
+        };
+        let (ty, _, _) = handle(&table, &request, now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Nak);
+
+        // INIT-REBOOT with another address than the one leased.
+        let request = DhcpRepr {
+            requested_ip: Some(Ipv4Address::new(192, 168, 1, 101)),
+            ..message(DhcpMessageType::Request, OTHER_MAC)
+        };
+        let (ty, _, _) = handle(&table, &request, now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Nak);
+    }
+
+    #[test]
+    fn init_reboot() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+
+        // No record of the client: stay silent.
+        let request = DhcpRepr {
+            requested_ip: Some(Ipv4Address::new(192, 168, 1, 100)),
+            ..message(DhcpMessageType::Request, MAC)
+        };
+        assert!(handle(&table, &request, now).is_none());
+        assert_eq!(table.lease_for_mac(MAC), None);
+
+        handle(&table, &select(Ipv4Address::new(192, 168, 1, 100), MAC), now).unwrap();
+        let (ty, leased, _) = handle(&table, &request, now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Ack);
+        assert_eq!(leased, Ipv4Address::new(192, 168, 1, 100));
+
+        // Renewing, unicast to the client.
+        let request = DhcpRepr {
+            client_ip: leased,
+            ..message(DhcpMessageType::Request, MAC)
+        };
+        let (ty, _, dest) = handle(&table, &request, now).unwrap();
+        assert_eq!(ty, DhcpMessageType::Ack);
+        assert_eq!(dest, IpEndpoint::new(leased.into(), DHCP_CLIENT_PORT));
+    }
+
+    #[test]
+    fn release() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+        let address = Ipv4Address::new(192, 168, 1, 100);
+
+        handle(&table, &select(address, MAC), now).unwrap();
+        let request = DhcpRepr {
+            client_ip: address,
+            ..message(DhcpMessageType::Release, MAC)
+        };
+        assert!(handle(&table, &request, now).is_none());
+        assert_eq!(table.lease_for_mac(MAC), None);
+        assert_eq!(table.lease_for_address(address, now), None);
+    }
+
+    #[test]
+    fn release_reservation() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+        let address = Ipv4Address::new(192, 168, 1, 50);
+
+        table.add_reservation(MAC, address, now).unwrap();
+        let (_, offered, _) = handle(&table, &message(DhcpMessageType::Discover, MAC), now).unwrap();
+        assert_eq!(offered, address);
+        handle(&table, &select(address, MAC), now).unwrap();
+
+        let request = DhcpRepr {
+            client_ip: address,
+            ..message(DhcpMessageType::Release, MAC)
+        };
+        assert!(handle(&table, &request, now).is_none());
+        let lease = table.lease_for_mac(MAC).unwrap();
+        assert_eq!(lease.state, LeaseState::Reserved);
+        assert!(lease.is_static);
+    }
+
+    #[test]
+    fn decline() {
+        let mut leases = [Lease::EMPTY; 4];
+        let table = LeaseTable::new(config(), &mut leases);
+        let now = Instant::from_secs(10);
+        let address = Ipv4Address::new(192, 168, 1, 100);
+
+        handle(&table, &select(address, MAC), now).unwrap();
+        let request = DhcpRepr {
+            requested_ip: Some(address),
+            server_identifier: Some(SERVER),
+            ..message(DhcpMessageType::Decline, MAC)
+        };
+        assert!(handle(&table, &request, now).is_none());
+        assert_eq!(
+            table.lease_for_address(address, now).unwrap().state,
+            LeaseState::Declined
+        );
+
+        // The declined address isn't handed out again until it expires.
+        let (_, offered, _) = handle(&table, &message(DhcpMessageType::Discover, MAC), now).unwrap();
+        assert_eq!(offered, Ipv4Address::new(192, 168, 1, 101));
+        let later = now + table.config.lease_duration;
+        let (_, offered, _) = handle(&table, &message(DhcpMessageType::Discover, OTHER_MAC), later).unwrap();
+        assert_eq!(offered, address);
+    }
+}
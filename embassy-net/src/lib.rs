@@ -12,14 +12,22 @@ compile_error!("You must enable at least one of the following features: proto-ip
 // This mod MUST go first, so that the others see its macros.
 pub(crate) mod fmt;
 
+#[cfg(feature = "packet-capture")]
+pub mod capture;
 mod device;
+#[cfg(feature = "dhcpv4-server")]
+pub mod dhcp_server;
 #[cfg(feature = "dns")]
 pub mod dns;
 #[cfg(feature = "raw")]
 pub mod raw;
+#[cfg(feature = "proto-ipv6-fragmentation")]
+mod reassembly;
 #[cfg(feature = "tcp")]
 pub mod tcp;
 mod time;
+#[cfg(feature = "packet-timestamps")]
+mod timestamp;
 #[cfg(feature = "udp")]
 pub mod udp;
 
@@ -29,6 +37,7 @@ use core::pin::pin;
 use core::task::{Context, Poll};
 
 pub use embassy_net_driver as driver;
+pub use embassy_net_driver::Timestamp;
 use embassy_net_driver::{Driver, LinkState};
 use embassy_sync::waitqueue::WakerRegistration;
 use embassy_time::{Instant, Timer};
@@ -66,8 +75,16 @@ const MAX_QUERIES: usize = 4;
 const MAX_HOSTNAME_LEN: usize = 32;
 
 /// Memory resources needed for a network stack.
+///
+/// This includes the buffers used for reassembling fragmented packets, when enabled.
 pub struct StackResources<const SOCK: usize> {
     sockets: [SocketStorage<'static>; SOCK],
+    // The interface holds smoltcp's reassembly buffers.
+    iface: Option<Interface>,
+    #[cfg(feature = "proto-ipv6-fragmentation")]
+    ipv6_reassembly: reassembly::Buffers,
+    #[cfg(feature = "packet-timestamps")]
+    timestamps: [Option<timestamp::Registration>; SOCK],
     #[cfg(feature = "dns")]
     queries: [Option<dns::DnsQuery>; MAX_QUERIES],
     #[cfg(feature = "dhcpv4-hostname")]
@@ -85,8 +102,15 @@ impl<const SOCK: usize> StackResources<SOCK> {
     pub const fn new() -> Self {
         #[cfg(feature = "dns")]
         const INIT: Option<dns::DnsQuery> = None;
+        #[cfg(feature = "packet-timestamps")]
+        const REGISTRATION_INIT: Option<timestamp::Registration> = None;
         Self {
             sockets: [SocketStorage::EMPTY; SOCK],
+            iface: None,
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            ipv6_reassembly: reassembly::EMPTY_BUFFERS,
+            #[cfg(feature = "packet-timestamps")]
+            timestamps: [REGISTRATION_INIT; SOCK],
             #[cfg(feature = "dns")]
             queries: [INIT; MAX_QUERIES],
             #[cfg(feature = "dhcpv4-hostname")]
@@ -172,6 +196,15 @@ pub struct Config {
     /// IPv6 configuration
     #[cfg(feature = "proto-ipv6")]
     pub ipv6: ConfigV6,
+    /// How long to wait for all fragments of a packet before dropping it.
+    ///
+    /// If not set, smoltcp's default of 60 seconds is used.
+    #[cfg(any(
+        feature = "proto-ipv4-fragmentation",
+        feature = "proto-ipv6-fragmentation",
+        feature = "proto-sixlowpan-fragmentation"
+    ))]
+    pub reassembly_timeout: Option<embassy_time::Duration>,
 }
 
 impl Config {
@@ -182,6 +215,12 @@ impl Config {
             ipv4: ConfigV4::Static(config),
             #[cfg(feature = "proto-ipv6")]
             ipv6: ConfigV6::None,
+            #[cfg(any(
+                feature = "proto-ipv4-fragmentation",
+                feature = "proto-ipv6-fragmentation",
+                feature = "proto-sixlowpan-fragmentation"
+            ))]
+            reassembly_timeout: None,
         }
     }
 
@@ -192,6 +231,12 @@ impl Config {
             #[cfg(feature = "proto-ipv4")]
             ipv4: ConfigV4::None,
             ipv6: ConfigV6::Static(config),
+            #[cfg(any(
+                feature = "proto-ipv4-fragmentation",
+                feature = "proto-ipv6-fragmentation",
+                feature = "proto-sixlowpan-fragmentation"
+            ))]
+            reassembly_timeout: None,
         }
     }
 
@@ -208,6 +253,12 @@ impl Config {
             ipv4: ConfigV4::Dhcp(config),
             #[cfg(feature = "proto-ipv6")]
             ipv6: ConfigV6::None,
+            #[cfg(any(
+                feature = "proto-ipv4-fragmentation",
+                feature = "proto-ipv6-fragmentation",
+                feature = "proto-sixlowpan-fragmentation"
+            ))]
+            reassembly_timeout: None,
         }
     }
 }
@@ -261,12 +312,18 @@ struct Inner<D: Driver> {
     dns_waker: WakerRegistration,
     #[cfg(feature = "dhcpv4-hostname")]
     hostname: &'static mut core::cell::UnsafeCell<HostnameResources>,
+    #[cfg(feature = "packet-capture")]
+    capture: Option<&'static dyn capture::Capture>,
 }
 
 pub(crate) struct SocketStack {
     pub(crate) sockets: SocketSet<'static>,
-    pub(crate) iface: Interface,
+    pub(crate) iface: &'static mut Interface,
     pub(crate) waker: WakerRegistration,
+    #[cfg(feature = "packet-timestamps")]
+    pub(crate) timestamps: timestamp::Timestamps,
+    #[cfg(feature = "proto-ipv6-fragmentation")]
+    pub(crate) ipv6_reassembly: reassembly::Reassembler,
     next_local_port: u16,
 }
 
@@ -302,15 +359,40 @@ impl<D: Driver> Stack<D> {
         let mut iface_cfg = smoltcp::iface::Config::new(hardware_addr);
         iface_cfg.random_seed = random_seed;
 
-        let iface = Interface::new(
+        #[cfg(feature = "packet-timestamps")]
+        let mut timestamps = timestamp::Timestamps::new(device.capabilities().timestamps.tx, &mut resources.timestamps);
+
+        #[cfg(feature = "proto-ipv6-fragmentation")]
+        let mut ipv6_reassembly = reassembly::Reassembler::new(&mut resources.ipv6_reassembly, medium);
+
+        let iface = resources.iface.insert(Interface::new(
             iface_cfg,
             &mut DriverAdapter {
                 inner: &mut device,
                 cx: None,
                 medium,
+                #[cfg(feature = "packet-capture")]
+                capture: None,
+                #[cfg(feature = "packet-timestamps")]
+                timestamps: &mut timestamps,
+                #[cfg(feature = "proto-ipv6-fragmentation")]
+                ipv6_reassembly: &mut ipv6_reassembly,
             },
             instant_to_smoltcp(Instant::now()),
-        );
+        ));
+
+        #[cfg(any(
+            feature = "proto-ipv4-fragmentation",
+            feature = "proto-ipv6-fragmentation",
+            feature = "proto-sixlowpan-fragmentation"
+        ))]
+        if let Some(timeout) = config.reassembly_timeout {
+            let timeout = crate::time::duration_to_smoltcp(timeout);
+            #[cfg(any(feature = "proto-ipv4-fragmentation", feature = "proto-sixlowpan-fragmentation"))]
+            iface.set_reassembly_timeout(timeout);
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            ipv6_reassembly.set_timeout(timeout);
+        }
 
         let sockets = SocketSet::new(&mut resources.sockets[..]);
 
@@ -321,6 +403,10 @@ impl<D: Driver> Stack<D> {
             sockets,
             iface,
             waker: WakerRegistration::new(),
+            #[cfg(feature = "packet-timestamps")]
+            timestamps,
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            ipv6_reassembly,
             next_local_port,
         };
 
@@ -343,6 +429,8 @@ impl<D: Driver> Stack<D> {
             dns_waker: WakerRegistration::new(),
             #[cfg(feature = "dhcpv4-hostname")]
             hostname: &mut resources.hostname,
+            #[cfg(feature = "packet-capture")]
+            capture: None,
         };
 
         #[cfg(feature = "proto-ipv4")]
@@ -486,6 +574,15 @@ impl<D: Driver> Stack<D> {
         })
     }
 
+    /// Set the packet capture hook.
+    ///
+    /// The hook is called with every frame received or transmitted by the device. Pass `None`
+    /// to stop capturing.
+    #[cfg(feature = "packet-capture")]
+    pub fn set_capture(&self, capture: Option<&'static dyn capture::Capture>) {
+        self.with_mut(|_, i| i.capture = capture)
+    }
+
     /// Run the network stack.
     ///
     /// You must call this in a background task, to process network events.
@@ -627,6 +724,12 @@ impl<D: Driver> Stack<D> {
                 cx: Some(cx),
                 inner: &mut i.device,
                 medium,
+                #[cfg(feature = "packet-capture")]
+                capture: i.capture,
+                #[cfg(feature = "packet-timestamps")]
+                timestamps: &mut s.timestamps,
+                #[cfg(feature = "proto-ipv6-fragmentation")]
+                ipv6_reassembly: &mut s.ipv6_reassembly,
             };
 
             match s
@@ -666,6 +769,12 @@ impl<D: Driver> Stack<D> {
                 cx: Some(cx),
                 inner: &mut i.device,
                 medium,
+                #[cfg(feature = "packet-capture")]
+                capture: i.capture,
+                #[cfg(feature = "packet-timestamps")]
+                timestamps: &mut s.timestamps,
+                #[cfg(feature = "proto-ipv6-fragmentation")]
+                ipv6_reassembly: &mut s.ipv6_reassembly,
             };
 
             match s
@@ -855,9 +964,20 @@ impl<D: Driver> Inner<D> {
             cx: Some(cx),
             inner: &mut self.device,
             medium,
+            #[cfg(feature = "packet-capture")]
+            capture: self.capture,
+            #[cfg(feature = "packet-timestamps")]
+            timestamps: &mut s.timestamps,
+            #[cfg(feature = "proto-ipv6-fragmentation")]
+            ipv6_reassembly: &mut s.ipv6_reassembly,
         };
         s.iface.poll(timestamp, &mut smoldev, &mut s.sockets);
 
+        #[cfg(feature = "packet-timestamps")]
+        while let Some((id, timestamp)) = self.device.transmit_timestamp(cx) {
+            s.timestamps.push_tx(id, timestamp);
+        }
+
         // Update link up
         let old_link_up = self.link_up;
         self.link_up = self.device.link_state(cx) == LinkState::Up;
@@ -60,7 +60,7 @@ impl<'a> RawSocket<'a> {
     fn with_mut<R>(&self, f: impl FnOnce(&mut raw::Socket, &mut Interface) -> R) -> R {
         let s = &mut *self.stack.borrow_mut();
         let socket = s.sockets.get_mut::<raw::Socket>(self.handle);
-        let res = f(socket, &mut s.iface);
+        let res = f(socket, s.iface);
         s.waker.wake();
         res
     }
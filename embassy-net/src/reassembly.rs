@@ -0,0 +1,399 @@
+//! IPv6 fragment reassembly.
+//!
+//! smoltcp reassembles IPv4 and 6LoWPAN fragments, but not IPv6 ones. These are put back together
+//! here, from the received frames, before the whole packet is handed to smoltcp.
+//!
+//! The buffers are part of the [`StackResources`](crate::StackResources). Their size and count are set
+//! through the `reassembly-buffer-size-*` and `reassembly-buffer-count-*` features of smoltcp, like
+//! the ones smoltcp uses for the other protocols.
+
+use smoltcp::config::{REASSEMBLY_BUFFER_COUNT, REASSEMBLY_BUFFER_SIZE};
+use smoltcp::phy::Medium;
+use smoltcp::storage::Assembler;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{IpProtocol, Ipv6Address};
+
+#[cfg(feature = "medium-ethernet")]
+const ETHERNET_HEADER_LEN: usize = 14;
+#[cfg(feature = "medium-ethernet")]
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPV6_HEADER_LEN: usize = 40;
+const FRAGMENT_HEADER_LEN: usize = 8;
+
+/// Identifies the fragments of one packet.
+#[derive(PartialEq, Eq, Clone, Copy)]
+struct Key {
+    src: Ipv6Address,
+    dst: Ipv6Address,
+    id: u32,
+}
+
+/// A received fragment, as offsets into its frame.
+struct Fragment {
+    key: Key,
+    /// Start of the IPv6 header.
+    ip_start: usize,
+    /// Length of the unfragmentable part: the link-layer header, the IPv6 header and the
+    /// extension headers preceding the Fragment header.
+    header_len: usize,
+    /// Offset of the "next header" field pointing at the Fragment header.
+    next_header_pos: usize,
+    /// The "next header" field of the Fragment header.
+    next_header: u8,
+    /// Offset of the fragment data in the packet's fragmentable part.
+    offset: usize,
+    /// Whether more fragments follow.
+    more: bool,
+    /// Position of the fragment data in the frame.
+    data: core::ops::Range<usize>,
+}
+
+impl Fragment {
+    fn parse(medium: Medium, frame: &[u8]) -> Option<Self> {
+        let ip_start = match medium {
+            #[cfg(feature = "medium-ethernet")]
+            Medium::Ethernet => {
+                let ethertype = frame.get(12..ETHERNET_HEADER_LEN)?;
+                if u16::from_be_bytes([ethertype[0], ethertype[1]]) != ETHERTYPE_IPV6 {
+                    return None;
+                }
+                ETHERNET_HEADER_LEN
+            }
+            #[cfg(feature = "medium-ip")]
+            Medium::Ip => 0,
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        };
+
+        let ip = frame.get(ip_start..)?;
+        if ip.len() < IPV6_HEADER_LEN || ip[0] >> 4 != 6 {
+            return None;
+        }
+        let payload_len = u16::from_be_bytes([ip[4], ip[5]]) as usize;
+        // Strip the link-layer padding, if any.
+        let ip = ip.get(..IPV6_HEADER_LEN + payload_len)?;
+
+        // The Fragment header can only be preceded by the headers that are processed on the way.
+        let mut next_header_pos = 6;
+        let mut pos = IPV6_HEADER_LEN;
+        loop {
+            match IpProtocol::from(ip[next_header_pos]) {
+                IpProtocol::HopByHop | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                    let len = (*ip.get(pos + 1)? as usize + 1) * 8;
+                    next_header_pos = pos;
+                    pos += len;
+                }
+                IpProtocol::Ipv6Frag => break,
+                _ => return None,
+            }
+        }
+
+        let header = ip.get(pos..pos + FRAGMENT_HEADER_LEN)?;
+        let offset_and_flags = u16::from_be_bytes([header[2], header[3]]);
+        Some(Self {
+            key: Key {
+                src: Ipv6Address::from_bytes(&ip[8..24]),
+                dst: Ipv6Address::from_bytes(&ip[24..40]),
+                id: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+            },
+            ip_start,
+            header_len: ip_start + pos,
+            next_header_pos: ip_start + next_header_pos,
+            next_header: header[0],
+            offset: (offset_and_flags & !0b111) as usize,
+            more: offset_and_flags & 1 != 0,
+            data: ip_start + pos + FRAGMENT_HEADER_LEN..ip_start + ip.len(),
+        })
+    }
+}
+
+/// Buffer for reassembling one packet.
+pub(crate) struct Buffer {
+    key: Option<Key>,
+    expires_at: Instant,
+    assembler: Assembler,
+    /// Start of the IPv6 header.
+    ip_start: usize,
+    /// Length of the unfragmentable part, see [`Fragment::header_len`].
+    header_len: usize,
+    /// Length of the fragmentable part, known once the last fragment is received.
+    total_len: Option<usize>,
+    data: [u8; REASSEMBLY_BUFFER_SIZE],
+}
+
+impl Buffer {
+    const EMPTY: Self = Self {
+        key: None,
+        expires_at: Instant::ZERO,
+        assembler: Assembler::new(),
+        ip_start: 0,
+        header_len: 0,
+        total_len: None,
+        data: [0; REASSEMBLY_BUFFER_SIZE],
+    };
+
+    fn reset(&mut self) {
+        self.key = None;
+        self.assembler.clear();
+        self.total_len = None;
+    }
+
+    fn is_free(&self, now: Instant) -> bool {
+        self.key.is_none() || self.expires_at <= now
+    }
+}
+
+/// Reassembly buffers, stored in the [`StackResources`](crate::StackResources).
+pub(crate) type Buffers = [Buffer; REASSEMBLY_BUFFER_COUNT];
+
+pub(crate) const EMPTY_BUFFERS: Buffers = [Buffer::EMPTY; REASSEMBLY_BUFFER_COUNT];
+
+/// What became of a received frame.
+pub(crate) enum Received<'a> {
+    /// The frame is not an IPv6 fragment, it must be processed as is.
+    NotFragment,
+    /// The frame was a fragment, and has been buffered or dropped.
+    Consumed,
+    /// The frame completed a packet.
+    Complete(&'a mut [u8]),
+}
+
+/// IPv6 reassembler.
+pub(crate) struct Reassembler {
+    buffers: &'static mut [Buffer],
+    medium: Medium,
+    timeout: Duration,
+}
+
+impl Reassembler {
+    pub(crate) fn new(buffers: &'static mut [Buffer], medium: Medium) -> Self {
+        Self {
+            buffers,
+            medium,
+            // Same default as smoltcp.
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Process a received frame.
+    pub(crate) fn receive(&mut self, frame: &[u8], now: Instant) -> Received<'_> {
+        let Some(fragment) = Fragment::parse(self.medium, frame) else {
+            return Received::NotFragment;
+        };
+
+        let buffer = match self.buffers.iter().position(|b| b.key == Some(fragment.key)) {
+            Some(i) if self.buffers[i].expires_at > now => &mut self.buffers[i],
+            _ => match self.buffers.iter_mut().find(|b| b.is_free(now)) {
+                Some(buffer) => {
+                    buffer.reset();
+                    buffer.key = Some(fragment.key);
+                    buffer.expires_at = now + self.timeout;
+                    buffer.ip_start = fragment.ip_start;
+                    buffer.header_len = fragment.header_len;
+                    buffer
+                }
+                None => {
+                    debug!("ipv6 reassembly: no free buffer, dropping fragment");
+                    return Received::Consumed;
+                }
+            },
+        };
+
+        let data = &frame[fragment.data.clone()];
+        let start = fragment.header_len + fragment.offset;
+        let end = start + data.len();
+        let len = fragment.offset + data.len();
+        if fragment.header_len != buffer.header_len
+            || end > buffer.data.len()
+            || (!fragment.more && matches!(buffer.total_len, Some(total) if total != len))
+        {
+            debug!("ipv6 reassembly: invalid or too large packet, dropping it");
+            buffer.reset();
+            return Received::Consumed;
+        }
+
+        // The unfragmentable part is taken from the first fragment received, and replaced by the one
+        // of the fragment at offset 0 as required by RFC 8200. The Fragment header is left out.
+        if fragment.offset == 0 || buffer.assembler.is_empty() {
+            buffer.data[..fragment.header_len].copy_from_slice(&frame[..fragment.header_len]);
+            buffer.data[fragment.next_header_pos] = fragment.next_header;
+        }
+        buffer.data[start..end].copy_from_slice(data);
+        if !fragment.more {
+            buffer.total_len = Some(len);
+        }
+        if buffer.assembler.add(fragment.offset, data.len()).is_err() {
+            debug!("ipv6 reassembly: too many holes, dropping packet");
+            buffer.reset();
+            return Received::Consumed;
+        }
+
+        match buffer.total_len {
+            Some(total) if buffer.assembler.peek_front() >= total => {
+                let payload_len = buffer.header_len - buffer.ip_start - IPV6_HEADER_LEN + total;
+                let Ok(payload_len) = u16::try_from(payload_len) else {
+                    buffer.reset();
+                    return Received::Consumed;
+                };
+                let ip_start = buffer.ip_start;
+                buffer.data[ip_start + 4..ip_start + 6].copy_from_slice(&payload_len.to_be_bytes());
+                let len = buffer.header_len + total;
+                buffer.reset();
+                Received::Complete(&mut buffer.data[..len])
+            }
+            _ => Received::Consumed,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "medium-ip"))]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const SRC: [u8; 16] = [0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const DST: [u8; 16] = [0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+    fn buffers() -> &'static mut Buffers {
+        std::boxed::Box::leak(std::boxed::Box::new(EMPTY_BUFFERS))
+    }
+
+    fn ipv6_header(next_header: u8, payload_len: usize) -> Vec<u8> {
+        let mut header = Vec::from([0x60, 0, 0, 0]);
+        header.extend_from_slice(&(payload_len as u16).to_be_bytes());
+        header.extend_from_slice(&[next_header, 64]);
+        header.extend_from_slice(&SRC);
+        header.extend_from_slice(&DST);
+        header
+    }
+
+    /// An IPv6 fragment of a UDP packet, with optional extension headers before the Fragment header.
+    fn fragment(ext: &[u8], id: u32, offset: usize, more: bool, data: &[u8]) -> Vec<u8> {
+        let next_header = if ext.is_empty() { 44 } else { 0 };
+        let mut frame = ipv6_header(next_header, ext.len() + FRAGMENT_HEADER_LEN + data.len());
+        frame.extend_from_slice(ext);
+        frame.extend_from_slice(&[17, 0]);
+        frame.extend_from_slice(&(offset as u16 | more as u16).to_be_bytes());
+        frame.extend_from_slice(&id.to_be_bytes());
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn payload() -> Vec<u8> {
+        (0..64).collect()
+    }
+
+    fn complete(received: Received) -> Vec<u8> {
+        match received {
+            Received::Complete(packet) => packet.to_vec(),
+            _ => panic!("packet not complete"),
+        }
+    }
+
+    fn consumed(received: Received) {
+        assert!(matches!(received, Received::Consumed));
+    }
+
+    #[test]
+    fn not_fragment() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let mut packet = ipv6_header(17, 8);
+        packet.extend_from_slice(&[0; 8]);
+        assert!(matches!(
+            reassembler.receive(&packet, Instant::ZERO),
+            Received::NotFragment
+        ));
+        assert!(matches!(
+            reassembler.receive(&[0x45, 0], Instant::ZERO),
+            Received::NotFragment
+        ));
+    }
+
+    #[test]
+    fn reassemble_out_of_order() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let data = payload();
+        let now = Instant::from_secs(1);
+
+        consumed(reassembler.receive(&fragment(&[], 7, 48, false, &data[48..]), now));
+        consumed(reassembler.receive(&fragment(&[], 7, 0, true, &data[..24]), now));
+        let packet = complete(reassembler.receive(&fragment(&[], 7, 24, true, &data[24..48]), now));
+
+        let mut expected = ipv6_header(17, data.len());
+        expected.extend_from_slice(&data);
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn reassemble_with_extension_header() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let data = payload();
+        // Hop-by-hop options, followed by the Fragment header.
+        let ext = [44, 0, 1, 4, 0, 0, 0, 0];
+        let now = Instant::from_secs(1);
+
+        consumed(reassembler.receive(&fragment(&ext, 1, 0, true, &data[..32]), now));
+        let packet = complete(reassembler.receive(&fragment(&ext, 1, 32, false, &data[32..]), now));
+
+        let mut expected = ipv6_header(0, ext.len() + data.len());
+        expected.extend_from_slice(&[17, 0, 1, 4, 0, 0, 0, 0]);
+        expected.extend_from_slice(&data);
+        assert_eq!(packet, expected);
+    }
+
+    #[test]
+    fn atomic_fragment() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let data = payload();
+        let packet = complete(reassembler.receive(&fragment(&[], 1, 0, false, &data), Instant::ZERO));
+        assert_eq!(packet[IPV6_HEADER_LEN..], data);
+    }
+
+    #[test]
+    fn interleaved_packets() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let data = payload();
+        let now = Instant::from_secs(1);
+
+        consumed(reassembler.receive(&fragment(&[], 1, 0, true, &data[..32]), now));
+        if REASSEMBLY_BUFFER_COUNT > 1 {
+            consumed(reassembler.receive(&fragment(&[], 2, 0, true, &data[..32]), now));
+            complete(reassembler.receive(&fragment(&[], 2, 32, false, &data[32..]), now));
+        } else {
+            // No buffer left for the second packet.
+            consumed(reassembler.receive(&fragment(&[], 2, 32, false, &data[32..]), now));
+        }
+        complete(reassembler.receive(&fragment(&[], 1, 32, false, &data[32..]), now));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        reassembler.set_timeout(Duration::from_secs(5));
+        let data = payload();
+
+        consumed(reassembler.receive(&fragment(&[], 1, 0, true, &data[..32]), Instant::from_secs(0)));
+        // The first fragment expired, the packet is never completed.
+        consumed(reassembler.receive(&fragment(&[], 1, 32, false, &data[32..]), Instant::from_secs(5)));
+        // The buffer of an expired packet is reused.
+        consumed(reassembler.receive(&fragment(&[], 2, 0, true, &data[..32]), Instant::from_secs(10)));
+        complete(reassembler.receive(&fragment(&[], 2, 32, false, &data[32..]), Instant::from_secs(10)));
+    }
+
+    #[test]
+    fn too_large() {
+        let mut reassembler = Reassembler::new(buffers(), Medium::Ip);
+        let data = payload();
+        let offset = REASSEMBLY_BUFFER_SIZE & !0b111;
+        consumed(reassembler.receive(&fragment(&[], 1, offset, false, &data[..8]), Instant::ZERO));
+        // The buffer was freed.
+        complete(reassembler.receive(&fragment(&[], 2, 0, false, &data), Instant::ZERO));
+    }
+}
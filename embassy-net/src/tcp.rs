@@ -14,7 +14,7 @@ use core::mem;
 use core::task::Poll;
 
 use embassy_net_driver::Driver;
-use embassy_time::Duration;
+use embassy_time::{with_timeout, Duration, TimeoutError};
 use smoltcp::iface::{Interface, SocketHandle};
 use smoltcp::socket::tcp;
 pub use smoltcp::socket::tcp::State;
@@ -83,6 +83,17 @@ impl<'a> TcpReader<'a> {
         self.io.read(buf).await
     }
 
+    /// Read data from the socket, waiting at most `timeout` for data to be available.
+    ///
+    /// See [`TcpSocket::read_with_timeout`].
+    pub async fn read_with_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<Result<usize, Error>, TimeoutError> {
+        with_timeout(timeout, self.io.read(buf)).await
+    }
+
     /// Call `f` with the largest contiguous slice of octets in the receive buffer,
     /// and dequeue the amount of elements returned by `f`.
     ///
@@ -109,6 +120,17 @@ impl<'a> TcpWriter<'a> {
         self.io.write(buf).await
     }
 
+    /// Write data to the socket, waiting at most `timeout` for buffer space to be available.
+    ///
+    /// See [`TcpSocket::write_with_timeout`].
+    pub async fn write_with_timeout(
+        &mut self,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<Result<usize, Error>, TimeoutError> {
+        with_timeout(timeout, self.io.write(buf)).await
+    }
+
     /// Flushes the written data to the socket.
     ///
     /// This waits until all data has been sent, and ACKed by the remote host. For a connection
@@ -117,6 +139,20 @@ impl<'a> TcpWriter<'a> {
         self.io.flush().await
     }
 
+    /// Close the write half of the socket, and wait until the remote host has ACKed all written data.
+    ///
+    /// See [`TcpSocket::shutdown_write`].
+    pub async fn shutdown(&mut self) -> Result<(), Error> {
+        self.io.shutdown_write().await
+    }
+
+    /// Wait until all written data, and the FIN if the write half is closed, has been ACKed by the remote host.
+    ///
+    /// See [`TcpSocket::wait_write_flushed_and_acked`].
+    pub async fn wait_write_flushed_and_acked(&mut self) -> Result<(), Error> {
+        self.io.wait_write_flushed_and_acked().await
+    }
+
     /// Call `f` with the largest contiguous slice of octets in the transmit buffer,
     /// and enqueue the amount of elements returned by `f`.
     ///
@@ -253,6 +289,18 @@ impl<'a> TcpSocket<'a> {
         self.io.read(buf).await
     }
 
+    /// Read data from the socket, waiting at most `timeout` for data to be available.
+    ///
+    /// Returns [`TimeoutError`] if no data arrived in time. The connection itself is not affected,
+    /// and the read can be retried.
+    pub async fn read_with_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<Result<usize, Error>, TimeoutError> {
+        with_timeout(timeout, self.io.read(buf)).await
+    }
+
     /// Write data to the socket.
     ///
     /// Returns how many bytes were written, or an error. If the socket is not ready to
@@ -261,6 +309,18 @@ impl<'a> TcpSocket<'a> {
         self.io.write(buf).await
     }
 
+    /// Write data to the socket, waiting at most `timeout` for buffer space to be available.
+    ///
+    /// Returns [`TimeoutError`] if no space was available in time. The connection itself is not
+    /// affected, and the write can be retried.
+    pub async fn write_with_timeout(
+        &mut self,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<Result<usize, Error>, TimeoutError> {
+        with_timeout(timeout, self.io.write(buf)).await
+    }
+
     /// Flushes the written data to the socket.
     ///
     /// This waits until all data has been sent, and ACKed by the remote host. For a connection
@@ -269,10 +329,24 @@ impl<'a> TcpSocket<'a> {
         self.io.flush().await
     }
 
+    /// Wait until all written data, and the FIN if the write half is closed, has been ACKed by the remote host.
+    ///
+    /// Unlike [`flush()`](TcpSocket::flush), this returns [`Error::ConnectionReset`] if the connection is
+    /// reset or times out before everything was ACKed, so callers can tell the data was lost. This is also
+    /// the case if the socket was already closed with written data still pending when this is called.
+    /// A FIN lost to a reset before this is called can't be told apart from a completed close, though.
+    pub async fn wait_write_flushed_and_acked(&mut self) -> Result<(), Error> {
+        self.io.wait_write_flushed_and_acked().await
+    }
+
     /// Set the timeout for the socket.
     ///
     /// If the timeout is set, the socket will be closed if no data is received for the
     /// specified duration.
+    ///
+    /// This is the idle timeout of the whole connection. To bound the time a single read or
+    /// write waits, see [`read_with_timeout()`](TcpSocket::read_with_timeout) and
+    /// [`write_with_timeout()`](TcpSocket::write_with_timeout).
     pub fn set_timeout(&mut self, duration: Option<Duration>) {
         self.io
             .with_mut(|s, _| s.set_timeout(duration.map(duration_to_smoltcp)))
@@ -324,6 +398,15 @@ impl<'a> TcpSocket<'a> {
         self.io.with_mut(|s, _| s.close())
     }
 
+    /// Close the write half of the socket, and wait until the remote host has ACKed all written data.
+    ///
+    /// Like [`close()`](TcpSocket::close), this sends a FIN after the pending data, and the read half
+    /// remains open. Unlike it, this waits for the FIN to be ACKed, and reports whether all data
+    /// made it to the remote host.
+    pub async fn shutdown_write(&mut self) -> Result<(), Error> {
+        self.io.shutdown_write().await
+    }
+
     /// Forcibly close the socket.
     ///
     /// This instantly closes both the read and write halves of the socket. Any pending data
@@ -374,13 +457,13 @@ impl<'d> TcpIo<'d> {
     fn with<R>(&self, f: impl FnOnce(&tcp::Socket, &Interface) -> R) -> R {
         let s = &*self.stack.borrow();
         let socket = s.sockets.get::<tcp::Socket>(self.handle);
-        f(socket, &s.iface)
+        f(socket, s.iface)
     }
 
     fn with_mut<R>(&mut self, f: impl FnOnce(&mut tcp::Socket, &mut Interface) -> R) -> R {
         let s = &mut *self.stack.borrow_mut();
         let socket = s.sockets.get_mut::<tcp::Socket>(self.handle);
-        let res = f(socket, &mut s.iface);
+        let res = f(socket, s.iface);
         s.waker.wake();
         res
     }
@@ -512,6 +595,41 @@ impl<'d> TcpIo<'d> {
         .await
     }
 
+    async fn wait_write_flushed_and_acked(&mut self) -> Result<(), Error> {
+        let mut prev_state = None;
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| {
+                let state = s.state();
+                let last_state = prev_state.replace(state);
+                match state {
+                    // smoltcp keeps the send buffer when the connection is reset or times out, so
+                    // unsent or unACKed data means it was lost, even if the socket closed before the first poll.
+                    tcp::State::Closed if s.send_queue() > 0 => Poll::Ready(Err(Error::ConnectionReset)),
+                    // Otherwise, reaching `Closed` from anything but `LastAck` means the FIN was never ACKed.
+                    tcp::State::Closed => match last_state {
+                        Some(tcp::State::Closed | tcp::State::LastAck) | None => Poll::Ready(Ok(())),
+                        Some(_) => Poll::Ready(Err(Error::ConnectionReset)),
+                    },
+                    tcp::State::FinWait1 | tcp::State::Closing | tcp::State::LastAck => {
+                        s.register_send_waker(cx.waker());
+                        Poll::Pending
+                    }
+                    _ if s.send_queue() > 0 => {
+                        s.register_send_waker(cx.waker());
+                        Poll::Pending
+                    }
+                    _ => Poll::Ready(Ok(())),
+                }
+            })
+        })
+        .await
+    }
+
+    async fn shutdown_write(&mut self) -> Result<(), Error> {
+        self.with_mut(|s, _| s.close());
+        self.wait_write_flushed_and_acked().await
+    }
+
     fn recv_capacity(&self) -> usize {
         self.with(|s, _| s.recv_capacity())
     }
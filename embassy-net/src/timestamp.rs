@@ -0,0 +1,423 @@
+//! Packet timestamps.
+//!
+//! Timestamps reported by the driver are matched to the packets they belong to through the
+//! smoltcp `PacketMeta` id. Received packets with a timestamp are given a fresh id when they
+//! come in, and their timestamp is stored right away with the UDP socket they are addressed to,
+//! next to the socket's packet metadata. Sockets request transmit timestamps by sending with a
+//! fresh id.
+
+use core::task::{Context, Poll};
+
+use embassy_net_driver::Timestamp;
+use embassy_sync::waitqueue::WakerRegistration;
+use smoltcp::iface::SocketHandle;
+use smoltcp::phy::Medium;
+use smoltcp::wire::{IpAddress, IpListenEndpoint, IpProtocol};
+
+/// Number of transmit timestamps held until the sender picks them up.
+const TX_QUEUE_LEN: usize = 16;
+
+/// Storage for the receive timestamp of a datagram.
+///
+/// See [`UdpSocket::new_timestamped`](crate::udp::UdpSocket::new_timestamped).
+#[derive(Debug, Clone, Copy)]
+pub struct TimestampMetadata {
+    /// Id of the packet, 0 if the slot is free.
+    id: u32,
+    timestamp: Timestamp,
+}
+
+impl TimestampMetadata {
+    /// Empty timestamp metadata.
+    pub const EMPTY: Self = Self {
+        id: 0,
+        timestamp: Timestamp {
+            seconds: 0,
+            nanoseconds: 0,
+        },
+    };
+}
+
+/// Whether packet id `a` was given out before `b`, accounting for wraparound.
+fn is_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// A UDP socket keeping the timestamps of the datagrams it receives.
+pub(crate) struct Registration {
+    handle: SocketHandle,
+    endpoint: IpListenEndpoint,
+    slots: &'static mut [TimestampMetadata],
+}
+
+impl Registration {
+    /// Mirrors the check smoltcp does to pick the socket a datagram is delivered to.
+    fn accepts(&self, dst_addr: IpAddress, dst_port: u16) -> bool {
+        if self.endpoint.port != dst_port {
+            return false;
+        }
+        match self.endpoint.addr {
+            None => true,
+            Some(addr) => addr == dst_addr || dst_addr.is_broadcast() || dst_addr.is_multicast(),
+        }
+    }
+
+    fn push(&mut self, id: u32, timestamp: Timestamp) {
+        // There is one slot per datagram the socket can hold. If none is free, the socket's
+        // buffer is full, and smoltcp drops the datagram too.
+        match self.slots.iter_mut().find(|s| s.id == 0) {
+            Some(slot) => *slot = TimestampMetadata { id, timestamp },
+            None => debug!("no free timestamp slot, dropping timestamp"),
+        }
+    }
+
+    fn take(&mut self, id: u32) -> Option<Timestamp> {
+        let mut timestamp = None;
+        for slot in self.slots.iter_mut().filter(|s| s.id != 0) {
+            if slot.id == id {
+                timestamp = Some(slot.timestamp);
+                slot.id = 0;
+            } else if is_before(slot.id, id) {
+                // Datagrams are received in order, so this one was dropped by smoltcp,
+                // for example because the payload buffer was full.
+                slot.id = 0;
+            }
+        }
+        timestamp
+    }
+}
+
+struct TxQueue {
+    entries: [Option<(u32, Timestamp)>; TX_QUEUE_LEN],
+    next: usize,
+}
+
+impl TxQueue {
+    const fn new() -> Self {
+        Self {
+            entries: [None; TX_QUEUE_LEN],
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, id: u32, timestamp: Timestamp) {
+        // When full, the oldest timestamp is dropped. Its sender stopped waiting for it.
+        self.entries[self.next] = Some((id, timestamp));
+        self.next = (self.next + 1) % TX_QUEUE_LEN;
+    }
+
+    fn take(&mut self, id: u32) -> Option<Timestamp> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| matches!(e, Some((i, _)) if *i == id))?;
+        entry.take().map(|(_, timestamp)| timestamp)
+    }
+}
+
+pub(crate) struct Timestamps {
+    next_id: u32,
+    tx_supported: bool,
+    sockets: &'static mut [Option<Registration>],
+    tx: TxQueue,
+    tx_waker: WakerRegistration,
+}
+
+impl Timestamps {
+    pub(crate) fn new(tx_supported: bool, sockets: &'static mut [Option<Registration>]) -> Self {
+        Self {
+            next_id: 1,
+            tx_supported,
+            sockets,
+            tx: TxQueue::new(),
+            tx_waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Allocate a packet id. Id 0 means "no timestamp", so it's never returned.
+    pub(crate) fn next_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    /// Keep the timestamps of the datagrams received by the socket `handle` in `slots`.
+    pub(crate) fn register(&mut self, handle: SocketHandle, slots: &'static mut [TimestampMetadata]) {
+        // There's a registration slot per socket, so there's always one free.
+        let registration = unwrap!(self.sockets.iter_mut().find(|r| r.is_none()));
+        *registration = Some(Registration {
+            handle,
+            endpoint: IpListenEndpoint::default(),
+            slots,
+        });
+    }
+
+    pub(crate) fn unregister(&mut self, handle: SocketHandle) {
+        if let Some(registration) = self
+            .sockets
+            .iter_mut()
+            .find(|r| matches!(r, Some(r) if r.handle == handle))
+        {
+            *registration = None;
+        }
+    }
+
+    fn registration(&mut self, handle: SocketHandle) -> Option<&mut Registration> {
+        self.sockets.iter_mut().flatten().find(|r| r.handle == handle)
+    }
+
+    /// Record the endpoint the socket `handle` is bound to, to find the datagrams it receives.
+    pub(crate) fn set_endpoint(&mut self, handle: SocketHandle, endpoint: IpListenEndpoint) {
+        if let Some(registration) = self.registration(handle) {
+            registration.endpoint = endpoint;
+            for slot in registration.slots.iter_mut() {
+                slot.id = 0;
+            }
+        }
+    }
+
+    /// Record the timestamp of the received packet tagged with `id`, with the socket it's addressed to.
+    pub(crate) fn push_rx(&mut self, medium: Medium, packet: &[u8], id: u32, timestamp: Timestamp) {
+        let Some((dst_addr, dst_port)) = udp_destination(medium, packet) else {
+            return;
+        };
+        if let Some(registration) = self
+            .sockets
+            .iter_mut()
+            .flatten()
+            .find(|r| r.accepts(dst_addr, dst_port))
+        {
+            registration.push(id, timestamp);
+        }
+    }
+
+    /// Take the timestamp of the packet tagged with `id`, received by the socket `handle`.
+    pub(crate) fn take_rx(&mut self, handle: SocketHandle, id: u32) -> Option<Timestamp> {
+        if id == 0 {
+            return None;
+        }
+        self.registration(handle)?.take(id)
+    }
+
+    /// Record the timestamp of the transmitted packet tagged with `id`.
+    pub(crate) fn push_tx(&mut self, id: u32, timestamp: Timestamp) {
+        self.tx.push(id, timestamp);
+        self.tx_waker.wake();
+    }
+
+    /// Wait for the timestamp of the transmitted packet tagged with `id`.
+    ///
+    /// Returns `None` right away if the driver doesn't timestamp transmitted packets.
+    pub(crate) fn poll_tx(&mut self, id: u32, cx: &mut Context<'_>) -> Poll<Option<Timestamp>> {
+        if !self.tx_supported {
+            return Poll::Ready(None);
+        }
+        match self.tx.take(id) {
+            Some(timestamp) => Poll::Ready(Some(timestamp)),
+            None => {
+                self.tx_waker.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Get the destination of a UDP packet.
+///
+/// Fragmented packets are not handled: smoltcp reassembles IPv4 ones later on, and doesn't
+/// tag the reassembled packet with the id of the fragment.
+fn udp_destination(medium: Medium, packet: &[u8]) -> Option<(IpAddress, u16)> {
+    let ip = match medium {
+        #[cfg(feature = "medium-ethernet")]
+        Medium::Ethernet => {
+            let frame = smoltcp::wire::EthernetFrame::new_checked(packet).ok()?;
+            match frame.ethertype() {
+                #[cfg(feature = "proto-ipv4")]
+                smoltcp::wire::EthernetProtocol::Ipv4 => {}
+                #[cfg(feature = "proto-ipv6")]
+                smoltcp::wire::EthernetProtocol::Ipv6 => {}
+                _ => return None,
+            }
+            frame.payload()
+        }
+        #[cfg(feature = "medium-ip")]
+        Medium::Ip => packet,
+        #[allow(unreachable_patterns)]
+        _ => return None,
+    };
+
+    let (dst_addr, udp) = match ip.first()? >> 4 {
+        #[cfg(feature = "proto-ipv4")]
+        4 => {
+            let ip = smoltcp::wire::Ipv4Packet::new_checked(ip).ok()?;
+            if ip.next_header() != IpProtocol::Udp || ip.more_frags() || ip.frag_offset() != 0 {
+                return None;
+            }
+            (IpAddress::Ipv4(ip.dst_addr()), ip.payload())
+        }
+        #[cfg(feature = "proto-ipv6")]
+        6 => {
+            let ip = smoltcp::wire::Ipv6Packet::new_checked(ip).ok()?;
+            if ip.next_header() != IpProtocol::Udp {
+                return None;
+            }
+            (IpAddress::Ipv6(ip.dst_addr()), ip.payload())
+        }
+        _ => return None,
+    };
+
+    let udp = smoltcp::wire::UdpPacket::new_checked(udp).ok()?;
+    Some((dst_addr, udp.dst_port()))
+}
+
+#[cfg(all(test, feature = "medium-ip", feature = "proto-ipv4"))]
+mod tests {
+    use smoltcp::iface::{SocketSet, SocketStorage};
+    use smoltcp::socket::udp;
+    use smoltcp::wire::Ipv4Address;
+
+    use super::*;
+
+    fn udp_packet(dst: [u8; 4], dst_port: u16) -> [u8; 32] {
+        let mut packet = [0; 32];
+        // IPv4 header: version/IHL, total length, TTL, protocol, addresses.
+        packet[0] = 0x45;
+        packet[3] = 32;
+        packet[8] = 64;
+        packet[9] = 17;
+        packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        packet[16..20].copy_from_slice(&dst);
+        // UDP header: source port, destination port, length.
+        packet[20..22].copy_from_slice(&1234u16.to_be_bytes());
+        packet[22..24].copy_from_slice(&dst_port.to_be_bytes());
+        packet[24..26].copy_from_slice(&12u16.to_be_bytes());
+        packet
+    }
+
+    fn timestamp(nanos: u64) -> Timestamp {
+        Timestamp::from_nanos(nanos)
+    }
+
+    extern crate std;
+    use std::vec;
+    use std::vec::Vec;
+
+    fn timestamps<const N: usize>() -> (Timestamps, [SocketHandle; N]) {
+        // Handles can only be made by adding sockets to a set.
+        let mut sockets = SocketSet::new(Vec::leak((0..N).map(|_| SocketStorage::EMPTY).collect()));
+        let handles = [(); N].map(|_| {
+            let rx = udp::PacketBuffer::new(Vec::leak(vec![udp::PacketMetadata::EMPTY]), Vec::leak(vec![0]));
+            let tx = udp::PacketBuffer::new(Vec::leak(vec![udp::PacketMetadata::EMPTY]), Vec::leak(vec![0]));
+            sockets.add(udp::Socket::new(rx, tx))
+        });
+        let registrations = Vec::leak((0..N).map(|_| None).collect());
+        (Timestamps::new(false, registrations), handles)
+    }
+
+    fn register(timestamps: &mut Timestamps, handle: SocketHandle, slots: usize, endpoint: IpListenEndpoint) {
+        timestamps.register(handle, Vec::leak(vec![TimestampMetadata::EMPTY; slots]));
+        timestamps.set_endpoint(handle, endpoint);
+    }
+
+    #[test]
+    fn destination() {
+        let packet = udp_packet([10, 0, 0, 2], 319);
+        assert_eq!(
+            udp_destination(Medium::Ip, &packet),
+            Some((IpAddress::Ipv4(Ipv4Address::new(10, 0, 0, 2)), 319))
+        );
+
+        let mut tcp = packet;
+        tcp[9] = 6;
+        assert_eq!(udp_destination(Medium::Ip, &tcp), None);
+
+        let mut fragment = packet;
+        fragment[6] = 0x20;
+        assert_eq!(udp_destination(Medium::Ip, &fragment), None);
+
+        assert_eq!(udp_destination(Medium::Ip, &packet[..24]), None);
+    }
+
+    #[test]
+    fn keeps_every_queued_timestamp() {
+        let (mut ts, [h]) = timestamps::<1>();
+        register(&mut ts, h, 32, 319.into());
+
+        let packet = udp_packet([10, 0, 0, 2], 319);
+        let ids: Vec<u32> = (0..32).map(|_| ts.next_id()).collect();
+        for (n, &id) in ids.iter().enumerate() {
+            ts.push_rx(Medium::Ip, &packet, id, timestamp(n as u64));
+        }
+        for (n, &id) in ids.iter().enumerate() {
+            assert_eq!(ts.take_rx(h, id), Some(timestamp(n as u64)));
+        }
+    }
+
+    #[test]
+    fn routes_by_destination() {
+        let (mut ts, [a, b, c]) = timestamps::<3>();
+        register(&mut ts, a, 4, 319.into());
+        register(&mut ts, b, 4, (Ipv4Address::new(10, 0, 0, 3), 320).into());
+        register(&mut ts, c, 4, 320.into());
+
+        let to_a = ts.next_id();
+        ts.push_rx(Medium::Ip, &udp_packet([10, 0, 0, 2], 319), to_a, timestamp(1));
+        let to_c = ts.next_id();
+        ts.push_rx(Medium::Ip, &udp_packet([10, 0, 0, 2], 320), to_c, timestamp(2));
+        let to_b = ts.next_id();
+        ts.push_rx(Medium::Ip, &udp_packet([10, 0, 0, 3], 320), to_b, timestamp(3));
+        let unbound = ts.next_id();
+        ts.push_rx(Medium::Ip, &udp_packet([10, 0, 0, 2], 321), unbound, timestamp(4));
+
+        assert_eq!(ts.take_rx(b, to_a), None);
+        assert_eq!(ts.take_rx(a, to_a), Some(timestamp(1)));
+        assert_eq!(ts.take_rx(b, to_b), Some(timestamp(3)));
+        assert_eq!(ts.take_rx(c, to_c), Some(timestamp(2)));
+        assert_eq!(ts.take_rx(c, unbound), None);
+    }
+
+    #[test]
+    fn drops_timestamps_of_dropped_datagrams() {
+        let (mut ts, [h]) = timestamps::<1>();
+        register(&mut ts, h, 2, 319.into());
+        let packet = udp_packet([10, 0, 0, 2], 319);
+
+        // The first datagram never makes it to the socket.
+        let dropped = ts.next_id();
+        ts.push_rx(Medium::Ip, &packet, dropped, timestamp(1));
+        let received = ts.next_id();
+        ts.push_rx(Medium::Ip, &packet, received, timestamp(2));
+        assert_eq!(ts.take_rx(h, received), Some(timestamp(2)));
+
+        // Its slot was freed along the way.
+        let ids = [ts.next_id(), ts.next_id()];
+        for id in ids {
+            ts.push_rx(Medium::Ip, &packet, id, timestamp(u64::from(id)));
+        }
+        for id in ids {
+            assert_eq!(ts.take_rx(h, id), Some(timestamp(u64::from(id))));
+        }
+    }
+
+    #[test]
+    fn unregister() {
+        let (mut ts, [h]) = timestamps::<1>();
+        register(&mut ts, h, 1, 319.into());
+        ts.unregister(h);
+
+        let id = ts.next_id();
+        ts.push_rx(Medium::Ip, &udp_packet([10, 0, 0, 2], 319), id, timestamp(1));
+        assert_eq!(ts.take_rx(h, id), None);
+
+        // The registration can be reused.
+        register(&mut ts, h, 1, 319.into());
+    }
+
+    #[test]
+    fn ids_wrap_around() {
+        assert!(is_before(1, 2));
+        assert!(!is_before(2, 1));
+        assert!(is_before(u32::MAX, 1));
+        assert!(!is_before(1, u32::MAX));
+    }
+}
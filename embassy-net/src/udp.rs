@@ -11,6 +11,10 @@ use smoltcp::socket::udp;
 pub use smoltcp::socket::udp::{PacketMetadata, UdpMetadata};
 use smoltcp::wire::IpListenEndpoint;
 
+#[cfg(feature = "packet-timestamps")]
+pub use crate::timestamp::TimestampMetadata;
+#[cfg(feature = "packet-timestamps")]
+use crate::Timestamp;
 use crate::{SocketStack, Stack};
 
 /// Error returned by [`UdpSocket::bind`].
@@ -73,6 +77,32 @@ impl<'a> UdpSocket<'a> {
         }
     }
 
+    /// Create a new UDP socket using the provided stack and buffers, keeping the time datagrams
+    /// are received at in `rx_timestamps`.
+    ///
+    /// `rx_timestamps` needs a slot per datagram the socket can hold, so it should be as long as
+    /// `rx_meta`. The timestamps are returned by [`recv_from_timestamped`](Self::recv_from_timestamped).
+    #[cfg(feature = "packet-timestamps")]
+    pub fn new_timestamped<D: Driver>(
+        stack: &'a Stack<D>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+        rx_timestamps: &'a mut [TimestampMetadata],
+    ) -> Self {
+        let socket = Self::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+
+        let rx_timestamps: &'static mut [TimestampMetadata] = unsafe { mem::transmute(rx_timestamps) };
+        stack
+            .socket
+            .borrow_mut()
+            .timestamps
+            .register(socket.handle, rx_timestamps);
+
+        socket
+    }
+
     /// Bind the socket to a local endpoint.
     pub fn bind<T>(&mut self, endpoint: T) -> Result<(), BindError>
     where
@@ -86,7 +116,11 @@ impl<'a> UdpSocket<'a> {
         }
 
         match self.with_mut(|s, _| s.bind(endpoint)) {
-            Ok(()) => Ok(()),
+            Ok(()) => {
+                #[cfg(feature = "packet-timestamps")]
+                self.stack.borrow_mut().timestamps.set_endpoint(self.handle, endpoint);
+                Ok(())
+            }
             Err(udp::BindError::InvalidState) => Err(BindError::InvalidState),
             Err(udp::BindError::Unaddressable) => Err(BindError::NoRoute),
         }
@@ -95,13 +129,13 @@ impl<'a> UdpSocket<'a> {
     fn with<R>(&self, f: impl FnOnce(&udp::Socket, &Interface) -> R) -> R {
         let s = &*self.stack.borrow();
         let socket = s.sockets.get::<udp::Socket>(self.handle);
-        f(socket, &s.iface)
+        f(socket, s.iface)
     }
 
     fn with_mut<R>(&self, f: impl FnOnce(&mut udp::Socket, &mut Interface) -> R) -> R {
         let s = &mut *self.stack.borrow_mut();
         let socket = s.sockets.get_mut::<udp::Socket>(self.handle);
-        let res = f(socket, &mut s.iface);
+        let res = f(socket, s.iface);
         s.waker.wake();
         res
     }
@@ -127,6 +161,49 @@ impl<'a> UdpSocket<'a> {
         buf: &mut [u8],
         cx: &mut Context<'_>,
     ) -> Poll<Result<(usize, UdpMetadata), RecvError>> {
+        let res = self.poll_recv_slice(buf, cx);
+        // Also frees the slots of the timestamps of earlier datagrams that were dropped.
+        #[cfg(feature = "packet-timestamps")]
+        if let Poll::Ready(Ok((_, meta))) = &res {
+            self.take_rx_timestamp(meta);
+        }
+        res
+    }
+
+    /// Receive a datagram, and the time it was received at.
+    ///
+    /// This method will wait until a datagram is received.
+    ///
+    /// Returns the number of bytes received, the remote endpoint, and the receive timestamp.
+    /// The timestamp is `None` if the socket wasn't created with [`new_timestamped`](Self::new_timestamped),
+    /// or the driver doesn't timestamp received packets.
+    #[cfg(feature = "packet-timestamps")]
+    pub async fn recv_from_timestamped(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<(usize, UdpMetadata, Option<Timestamp>), RecvError> {
+        poll_fn(move |cx| self.poll_recv_from_timestamped(buf, cx)).await
+    }
+
+    /// Receive a datagram, and the time it was received at.
+    ///
+    /// When no datagram is available, this method will return `Poll::Pending` and
+    /// register the current task to be notified when a datagram is received.
+    ///
+    /// When a datagram is received, this method will return `Poll::Ready` with the
+    /// number of bytes received, the remote endpoint, and the receive timestamp.
+    #[cfg(feature = "packet-timestamps")]
+    #[allow(clippy::type_complexity)]
+    pub fn poll_recv_from_timestamped(
+        &self,
+        buf: &mut [u8],
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(usize, UdpMetadata, Option<Timestamp>), RecvError>> {
+        self.poll_recv_slice(buf, cx)
+            .map_ok(|(n, meta)| (n, meta, self.take_rx_timestamp(&meta)))
+    }
+
+    fn poll_recv_slice(&self, buf: &mut [u8], cx: &mut Context<'_>) -> Poll<Result<(usize, UdpMetadata), RecvError>> {
         self.with_mut(|s, _| match s.recv_slice(buf) {
             Ok((n, meta)) => Poll::Ready(Ok((n, meta))),
             // No data ready
@@ -138,6 +215,11 @@ impl<'a> UdpSocket<'a> {
         })
     }
 
+    #[cfg(feature = "packet-timestamps")]
+    fn take_rx_timestamp(&self, meta: &UdpMetadata) -> Option<Timestamp> {
+        self.stack.borrow_mut().timestamps.take_rx(self.handle, meta.meta.id)
+    }
+
     /// Send a datagram to the specified remote endpoint.
     ///
     /// This method will wait until the datagram has been sent.
@@ -163,7 +245,29 @@ impl<'a> UdpSocket<'a> {
     where
         T: Into<UdpMetadata>,
     {
-        self.with_mut(|s, _| match s.send_slice(buf, remote_endpoint) {
+        self.poll_send_meta(buf, remote_endpoint.into(), cx)
+    }
+
+    /// Send a datagram to the specified remote endpoint, and return the time it was sent at.
+    ///
+    /// This method will wait until the datagram has been sent, and the driver has reported
+    /// its transmit timestamp. Wrap it in a timeout if the datagram may never be sent, for
+    /// example because the remote address can't be resolved.
+    ///
+    /// Returns `Ok(None)` if the driver doesn't timestamp transmitted packets.
+    #[cfg(feature = "packet-timestamps")]
+    pub async fn send_to_timestamped<T>(&self, buf: &[u8], remote_endpoint: T) -> Result<Option<Timestamp>, SendError>
+    where
+        T: Into<UdpMetadata>,
+    {
+        let mut meta: UdpMetadata = remote_endpoint.into();
+        meta.meta.id = self.stack.borrow_mut().timestamps.next_id();
+        poll_fn(|cx| self.poll_send_meta(buf, meta, cx)).await?;
+        Ok(poll_fn(|cx| self.stack.borrow_mut().timestamps.poll_tx(meta.meta.id, cx)).await)
+    }
+
+    fn poll_send_meta(&self, buf: &[u8], meta: UdpMetadata, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        self.with_mut(|s, _| match s.send_slice(buf, meta) {
             // Entire datagram has been sent
             Ok(()) => Poll::Ready(Ok(())),
             Err(udp::SendError::BufferFull) => {
@@ -194,7 +298,12 @@ impl<'a> UdpSocket<'a> {
 
     /// Close the socket.
     pub fn close(&mut self) {
-        self.with_mut(|s, _| s.close())
+        self.with_mut(|s, _| s.close());
+        #[cfg(feature = "packet-timestamps")]
+        self.stack
+            .borrow_mut()
+            .timestamps
+            .set_endpoint(self.handle, IpListenEndpoint::default());
     }
 
     /// Returns whether the socket is ready to send data, i.e. it has enough buffer space to hold a packet.
@@ -235,6 +344,9 @@ impl<'a> UdpSocket<'a> {
 
 impl Drop for UdpSocket<'_> {
     fn drop(&mut self) {
-        self.stack.borrow_mut().sockets.remove(self.handle);
+        let s = &mut *self.stack.borrow_mut();
+        s.sockets.remove(self.handle);
+        #[cfg(feature = "packet-timestamps")]
+        s.timestamps.unregister(self.handle);
     }
 }
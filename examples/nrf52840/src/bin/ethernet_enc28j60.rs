@@ -5,8 +5,8 @@ use defmt::*;
 use embassy_executor::Spawner;
 use embassy_net::tcp::TcpSocket;
 use embassy_net::{Stack, StackResources};
-use embassy_net_enc28j60::Enc28j60;
-use embassy_nrf::gpio::{Level, Output, OutputDrive};
+use embassy_net_enc28j60::{self as enc28j60, Device};
+use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pull};
 use embassy_nrf::rng::Rng;
 use embassy_nrf::spim::Spim;
 use embassy_nrf::{bind_interrupts, peripherals, spim};
@@ -22,11 +22,19 @@ bind_interrupts!(struct Irqs {
 });
 
 #[embassy_executor::task]
-async fn net_task(
-    stack: &'static Stack<
-        Enc28j60<ExclusiveDevice<Spim<'static, peripherals::SPI3>, Output<'static>, Delay>, Output<'static>>,
+async fn ethernet_task(
+    runner: enc28j60::Runner<
+        'static,
+        ExclusiveDevice<Spim<'static, peripherals::SPI3>, Output<'static>, Delay>,
+        Input<'static>,
+        Output<'static>,
     >,
 ) -> ! {
+    runner.run().await
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack<Device<'static>>) -> ! {
     stack.run().await
 }
 
@@ -40,7 +48,7 @@ async fn main(spawner: Spawner) {
     let eth_miso = p.P0_24;
     let eth_cs = p.P0_15;
     let eth_rst = p.P0_13;
-    let _eth_irq = p.P0_12;
+    let eth_int = p.P0_12;
 
     let mut config = spim::Config::default();
     config.frequency = spim::Frequency::M16;
@@ -48,9 +56,13 @@ async fn main(spawner: Spawner) {
     let cs = Output::new(eth_cs, Level::High, OutputDrive::Standard);
     let spi = ExclusiveDevice::new(spi, cs, Delay);
 
+    let int = Input::new(eth_int, Pull::Up);
     let rst = Output::new(eth_rst, Level::High, OutputDrive::Standard);
     let mac_addr = [2, 3, 4, 5, 6, 7];
-    let device = Enc28j60::new(spi, Some(rst), mac_addr);
+    static STATE: StaticCell<enc28j60::State<8, 8>> = StaticCell::new();
+    let state = STATE.init(enc28j60::State::<8, 8>::new());
+    let (device, runner) = enc28j60::new(mac_addr, state, spi, int, Some(rst)).await;
+    unwrap!(spawner.spawn(ethernet_task(runner)));
 
     let config = embassy_net::Config::dhcpv4(Default::default());
     // let config = embassy_net::Config::ipv4_static(embassy_net::StaticConfigV4 {
@@ -67,9 +79,7 @@ async fn main(spawner: Spawner) {
 
     // Init network stack
     static RESOURCES: StaticCell<StackResources<2>> = StaticCell::new();
-    static STACK: StaticCell<
-        Stack<Enc28j60<ExclusiveDevice<Spim<'static, peripherals::SPI3>, Output<'static>, Delay>, Output<'static>>>,
-    > = StaticCell::new();
+    static STACK: StaticCell<Stack<Device<'static>>> = StaticCell::new();
     let stack = STACK.init(Stack::new(
         device,
         config,
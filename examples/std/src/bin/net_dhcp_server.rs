@@ -0,0 +1,118 @@
+use clap::Parser;
+use embassy_executor::{Executor, Spawner};
+use embassy_net::dhcp_server::{self, DhcpServer, Lease};
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{Config, EthernetAddress, Ipv4Address, Ipv4Cidr, Stack, StackResources};
+use embassy_net_tuntap::TunTapDevice;
+use embassy_time::{Duration, Timer};
+use heapless::Vec;
+use log::*;
+use rand_core::{OsRng, RngCore};
+use static_cell::StaticCell;
+
+#[derive(Parser)]
+#[clap(version = "1.0")]
+struct Opts {
+    /// TAP device name
+    #[clap(long, default_value = "tap0")]
+    tap: String,
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack<TunTapDevice>) -> ! {
+    stack.run().await
+}
+
+#[embassy_executor::task]
+async fn dhcp_task(server: &'static DhcpServer<'static>) -> ! {
+    server.run().await
+}
+
+#[embassy_executor::task]
+async fn main_task(spawner: Spawner) {
+    let opts: Opts = Opts::parse();
+
+    // Init network device
+    let device = TunTapDevice::new(&opts.tap).unwrap();
+
+    // The DHCP server needs a static IP
+    let address = Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 1), 24);
+    let config = Config::ipv4_static(embassy_net::StaticConfigV4 {
+        address,
+        dns_servers: Vec::new(),
+        gateway: None,
+    });
+
+    // Generate random seed
+    let mut seed = [0; 8];
+    OsRng.fill_bytes(&mut seed);
+    let seed = u64::from_le_bytes(seed);
+
+    // Init network stack
+    static STACK: StaticCell<Stack<TunTapDevice>> = StaticCell::new();
+    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
+    let stack = &*STACK.init(Stack::new(
+        device,
+        config,
+        RESOURCES.init(StackResources::<3>::new()),
+        seed,
+    ));
+
+    // Launch network task
+    spawner.spawn(net_task(stack)).unwrap();
+
+    // Init DHCP server
+    static RX_META: StaticCell<[PacketMetadata; 4]> = StaticCell::new();
+    static RX_BUFFER: StaticCell<[u8; 2048]> = StaticCell::new();
+    static TX_META: StaticCell<[PacketMetadata; 4]> = StaticCell::new();
+    static TX_BUFFER: StaticCell<[u8; 2048]> = StaticCell::new();
+    let socket = UdpSocket::new(
+        stack,
+        RX_META.init([PacketMetadata::EMPTY; 4]),
+        RX_BUFFER.init([0; 2048]),
+        TX_META.init([PacketMetadata::EMPTY; 4]),
+        TX_BUFFER.init([0; 2048]),
+    );
+
+    let mut server_config = dhcp_server::Config::new(
+        address,
+        Ipv4Address::new(192, 168, 69, 100),
+        Ipv4Address::new(192, 168, 69, 199),
+    );
+    server_config.lease_duration = Duration::from_secs(600);
+
+    static LEASES: StaticCell<[Option<Lease>; 16]> = StaticCell::new();
+    static SERVER: StaticCell<DhcpServer<'static>> = StaticCell::new();
+    let server = &*SERVER.init(DhcpServer::new(socket, server_config, LEASES.init([Lease::EMPTY; 16])).unwrap());
+
+    // Always hand out the same address to this client
+    server
+        .add_reservation(
+            EthernetAddress([0x02, 0x00, 0x00, 0x00, 0x00, 0x01]),
+            Ipv4Address::new(192, 168, 69, 2),
+        )
+        .unwrap();
+
+    spawner.spawn(dhcp_task(server)).unwrap();
+
+    loop {
+        Timer::after_secs(10).await;
+        info!("leases:");
+        server.for_each_lease(|l| info!("  {} -> {} {:?} {:?}", l.mac, l.address, l.state, l.expires));
+    }
+}
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .filter_module("async_io", log::LevelFilter::Info)
+        .format_timestamp_nanos()
+        .init();
+
+    let executor = EXECUTOR.init(Executor::new());
+    executor.run(|spawner| {
+        spawner.spawn(main_task(spawner)).unwrap();
+    });
+}
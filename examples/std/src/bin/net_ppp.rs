@@ -2,10 +2,11 @@
 //!
 //!     echo myuser $(hostname) mypass 192.168.7.10 >> /etc/ppp/pap-secrets
 //!     socat -v -x PTY,link=pty1,rawer PTY,link=pty2,rawer
-//!     sudo pppd $PWD/pty1 115200 192.168.7.1: ms-dns 8.8.4.4 ms-dns 8.8.8.8 nodetach debug local persist silent noproxyarp
+//!     sudo pppd $PWD/pty1 115200 192.168.7.1: ms-dns 8.8.4.4 ms-dns 8.8.8.8 nodetach debug local persist silent noproxyarp +ipv6
 //!     RUST_LOG=trace cargo run --bin net_ppp -- --device pty2
 //!     ping 192.168.7.10
 //!     nc 192.168.7.10 1234
+//!     ping fe80::200:0:0:1%ppp0
 
 #![allow(async_fn_in_trait)]
 
@@ -16,7 +17,9 @@ use async_io::Async;
 use clap::Parser;
 use embassy_executor::{Executor, Spawner};
 use embassy_net::tcp::TcpSocket;
-use embassy_net::{Config, ConfigV4, Ipv4Address, Ipv4Cidr, Stack, StackResources};
+use embassy_net::{
+    Config, ConfigV4, ConfigV6, Ipv4Address, Ipv4Cidr, Ipv6Address, Ipv6Cidr, Stack, StackResources, StaticConfigV6,
+};
 use embassy_net_ppp::Runner;
 use embedded_io_async::Write;
 use futures::io::BufReader;
@@ -51,13 +54,26 @@ async fn ppp_task(
     let port = BufReader::new(port);
     let port = adapter::FromFutures::new(port);
 
-    let config = embassy_net_ppp::Config {
-        username: b"myuser",
-        password: b"mypass",
-    };
+    let mut config = embassy_net_ppp::Config::default();
+    config.username = b"myuser";
+    config.password = b"mypass";
+    config.ipv6 = true;
+    config.interface_identifier = [0x02, 0, 0, 0, 0, 0, 0, 0x01];
 
     runner
-        .run(port, config, |ipv4| {
+        .run(port, config, |status| {
+            if let Some(ipv6) = status.ipv6 {
+                stack.set_config_v6(ConfigV6::Static(StaticConfigV6 {
+                    address: Ipv6Cidr::new(Ipv6Address::from_bytes(&ipv6.link_local_address()), 64),
+                    gateway: Some(Ipv6Address::from_bytes(&ipv6.peer_link_local_address())),
+                    dns_servers: Vec::new(),
+                }));
+            }
+
+            let Some(ipv4) = status.ipv4 else {
+                warn!("PPP did not negotiate IPv4.");
+                return;
+            };
             let Some(addr) = ipv4.address else {
                 warn!("PPP did not provide an IP address.");
                 return;